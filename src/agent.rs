@@ -0,0 +1,130 @@
+use crate::config::Config;
+use crate::output;
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use ssh2::{Agent, PublicKey, Session};
+use ssh_key::HashAlg;
+use std::env;
+
+/// agent 里的一把密钥
+pub struct AgentKey {
+    pub fingerprint: String,
+    pub algorithm: String,
+    pub comment: String,
+}
+
+/// clap 的 value_parser：接受 "SHA256:..." 或省略前缀，空字符串表示去掉固定
+pub fn parse_fingerprint(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(String::new());
+    }
+    let hash = text.strip_prefix("SHA256:").unwrap_or(text);
+    if hash.len() != 43 || !hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/') {
+        return Err(format!("'{}' is not a SHA256 key fingerprint (see 'rssh agent list')", text));
+    }
+    Ok(format!("SHA256:{}", hash))
+}
+
+fn connect(sess: &Session) -> Result<Agent> {
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Err(anyhow!("SSH_AUTH_SOCK is not set; no ssh-agent is running for this shell."));
+    }
+    let mut agent = sess.agent().context("Failed to initialize the ssh-agent client")?;
+    agent.connect().context("Failed to connect to ssh-agent")?;
+    agent.list_identities().context("Failed to list the keys in ssh-agent")?;
+    Ok(agent)
+}
+
+fn describe(identity: &PublicKey) -> AgentKey {
+    match ssh_key::PublicKey::from_bytes(identity.blob()) {
+        Ok(key) => AgentKey {
+            fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+            algorithm: key.algorithm().as_str().to_string(),
+            comment: identity.comment().to_string(),
+        },
+        // 不认识的密钥类型（如证书）也列出来
+        Err(_) => AgentKey {
+            fingerprint: "?".to_string(),
+            algorithm: "unknown".to_string(),
+            comment: identity.comment().to_string(),
+        },
+    }
+}
+
+/// 用 agent 认证；fingerprint 给出时只提供那一把密钥，不逐个尝试，避免触发服务器的 MaxAuthTries
+pub fn userauth(sess: &Session, user: &str, fingerprint: Option<&str>) -> Result<()> {
+    let Some(fingerprint) = fingerprint else {
+        return sess.userauth_agent(user).map_err(|e| anyhow!("No agent key was accepted: {}", e));
+    };
+    let agent = connect(sess)?;
+    let identity = agent
+        .identities()?
+        .into_iter()
+        .find(|identity| describe(identity).fingerprint == fingerprint)
+        .ok_or_else(|| anyhow!("Key {} is not loaded in ssh-agent (see 'rssh agent list')", fingerprint))?;
+    agent.userauth(user, &identity).map_err(|e| {
+        // ssh-add -c 加载的密钥需要在 agent 那边确认，拒绝确认时也是这个错误
+        anyhow!(
+            "Agent key {} was not accepted: {} (if it was added with 'ssh-add -c', the confirmation may have been declined)",
+            fingerprint,
+            e
+        )
+    })
+}
+
+/// agent list：列出 agent 里的密钥，以及固定了它的别名
+pub fn handle_list(config: &Config) -> Result<()> {
+    let sess = Session::new()?;
+    let agent = connect(&sess)?;
+    let keys: Vec<AgentKey> = agent.identities()?.iter().map(describe).collect();
+    let pinned_by = |fingerprint: &str| -> Vec<String> {
+        let mut aliases: Vec<String> = config
+            .connections
+            .iter()
+            .filter(|(_, conn)| conn.agent_key_fingerprint.as_deref() == Some(fingerprint))
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        aliases.sort();
+        aliases
+    };
+
+    if output::is_json() {
+        let items: Vec<_> = keys
+            .iter()
+            .map(|key| {
+                json!({
+                    "fingerprint": key.fingerprint,
+                    "algorithm": key.algorithm,
+                    "comment": key.comment,
+                    "aliases": pinned_by(&key.fingerprint),
+                })
+            })
+            .collect();
+        output::emit("agent_keys", json!({ "keys": items }));
+        return Ok(());
+    }
+    if keys.is_empty() {
+        println!("ssh-agent holds no keys. Add one with 'ssh-add <private key>'.");
+        return Ok(());
+    }
+    for key in &keys {
+        println!("{}  {:<20}  {}", key.fingerprint, key.algorithm, key.comment);
+        let aliases = pinned_by(&key.fingerprint);
+        if !aliases.is_empty() {
+            println!("    pinned by: {}", aliases.join(", "));
+        }
+    }
+    // 固定的密钥不在 agent 里时提醒，否则登录时才发现
+    let mut missing: Vec<(&String, &str)> = config
+        .connections
+        .iter()
+        .filter_map(|(alias, conn)| conn.agent_key_fingerprint.as_deref().map(|fingerprint| (alias, fingerprint)))
+        .filter(|(_, fingerprint)| !keys.iter().any(|key| key.fingerprint == *fingerprint))
+        .collect();
+    missing.sort();
+    for (alias, fingerprint) in missing {
+        eprintln!("Warning: '{}' is pinned to {}, which is not in the agent.", alias, fingerprint);
+    }
+    Ok(())
+}
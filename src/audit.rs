@@ -0,0 +1,166 @@
+use crate::config::{state_dir, Config};
+use crate::output;
+use crate::remote_fs::format_size;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Local, NaiveDate, TimeZone};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+const AUDIT_FILE: &str = "audit.log";
+
+pub fn audit_path(config: &Config) -> Result<PathBuf> {
+    match &config.audit_log {
+        Some(path) => Ok(path.clone()),
+        None => Ok(state_dir()?.join(AUDIT_FILE)),
+    }
+}
+
+/// 追加一条审计记录；写入失败只给出警告，不影响操作本身
+pub fn record(
+    config: &Config,
+    action: &str,
+    alias: &str,
+    port: u16,
+    started: Instant,
+    error: Option<&anyhow::Error>,
+    details: Value,
+) {
+    let target = config.connections.get(alias).map(|c| c.target.as_str());
+    let mut entry = json!({
+        "ts": Local::now().to_rfc3339(),
+        "user": local_user(),
+        "action": action,
+        "alias": alias,
+        "target": target,
+        "port": port,
+        "ok": error.is_none(),
+        "duration_ms": started.elapsed().as_millis() as u64,
+    });
+    if let Some(e) = error {
+        entry["error"] = json!(format!("{:#}", e));
+    }
+    if let (Some(entry), Value::Object(details)) = (entry.as_object_mut(), details) {
+        entry.extend(details);
+    }
+
+    if let Err(e) = append(config, &entry) {
+        eprintln!("Warning: failed to write audit log: {:#}", e);
+    }
+}
+
+fn append(config: &Config, entry: &Value) -> Result<()> {
+    let path = audit_path(config)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create audit log directory")?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .context(format!("Failed to open audit log {:?}", path))?;
+    writeln!(file, "{}", entry)?;
+    Ok(())
+}
+
+fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn handle_show(config: &Config, since: Option<&str>, alias: Option<&str>) -> Result<()> {
+    let since = since.map(parse_since).transpose()?;
+    let path = audit_path(config)?;
+    if !path.exists() {
+        output::status("The audit log is empty.");
+        return Ok(());
+    }
+    let file = fs::File::open(&path).context(format!("Failed to open audit log {:?}", path))?;
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        // 跳过损坏的行，而不是让整个命令失败
+        let Ok(entry) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let ts = entry["ts"]
+            .as_str()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok());
+        if let (Some(since), Some(ts)) = (since, ts)
+            && ts < since
+        {
+            continue;
+        }
+        if alias.is_some_and(|alias| entry["alias"].as_str() != Some(alias)) {
+            continue;
+        }
+
+        if output::is_json() {
+            output::emit("audit_entry", entry);
+        } else {
+            println!("{}", format_entry(&entry, ts));
+        }
+    }
+    Ok(())
+}
+
+fn format_entry(entry: &Value, ts: Option<DateTime<FixedOffset>>) -> String {
+    let when = ts
+        .map(|ts| ts.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let status = if entry["ok"].as_bool() == Some(true) { "ok" } else { "FAILED" };
+    let mut line = format!(
+        "{}  {:<10} {:<9} {} ({}:{})  {}",
+        when,
+        entry["user"].as_str().unwrap_or("-"),
+        entry["action"].as_str().unwrap_or("-"),
+        entry["alias"].as_str().unwrap_or("-"),
+        entry["target"].as_str().unwrap_or("-"),
+        entry["port"],
+        status
+    );
+    if let Some(bytes) = entry["bytes"].as_u64() {
+        line.push_str(&format!("  {}", format_size(bytes)));
+    }
+    if let Some(code) = entry["exit_code"].as_i64() {
+        line.push_str(&format!("  exit {}", code));
+    }
+    if let (Some(from), Some(to)) = (entry["from"].as_str(), entry["to"].as_str()) {
+        line.push_str(&format!("  {} -> {}", from, to));
+    }
+    if let Some(error) = entry["error"].as_str() {
+        line.push_str(&format!("  ({})", error));
+    }
+    line
+}
+
+// 支持 "30m"、"12h"、"7d"、"2w" 这样的相对时间，或 "2024-01-31" 这样的日期
+fn parse_since(since: &str) -> Result<DateTime<FixedOffset>> {
+    let invalid = || anyhow!("Invalid --since value '{}'. Use e.g. 30m, 12h, 7d, 2w or 2024-01-31.", since);
+
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        let local = Local.from_local_datetime(&midnight).earliest().ok_or_else(invalid)?;
+        return Ok(local.fixed_offset());
+    }
+
+    let split = since.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = since.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => ChronoDuration::minutes(amount),
+        "h" => ChronoDuration::hours(amount),
+        "d" => ChronoDuration::days(amount),
+        "w" => ChronoDuration::weeks(amount),
+        _ => return Err(invalid()),
+    };
+    Ok((Local::now() - duration).fixed_offset())
+}
@@ -0,0 +1,413 @@
+use crate::agent;
+use crate::biometric;
+use crate::config::Connection;
+use crate::credentials::{delete_password, get_password, may_store, set_password, store_name};
+use crate::error::{self, RsshError};
+use crate::keys;
+use crate::output;
+use crate::perms;
+use crate::pkcs11;
+use crate::prompt;
+use crate::trace;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use inquire::{Confirm, InquireError, Password, Text};
+use serde::{Deserialize, Serialize};
+use ssh2::{KeyboardInteractivePrompt, Prompt, Session};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// 并发连接多台主机时，一次只让一个连接向终端提问
+static PROMPT: Mutex<()> = Mutex::new(());
+
+fn prompt_lock() -> MutexGuard<'static, ()> {
+    PROMPT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    /// Keys held by a running ssh-agent
+    Agent,
+    /// Keys on a smartcard or hardware token, loaded into ssh-agent
+    Pkcs11,
+    /// Private key files
+    Publickey,
+    /// Saved or typed password
+    Password,
+    /// Server-driven prompts (e.g. one-time codes)
+    KeyboardInteractive,
+}
+
+// 密码被拒绝后最多重新输入的次数
+const PASSWORD_ATTEMPTS: u32 = 3;
+
+// 不能询问时（如 CI）从环境变量取登录密码和私钥口令
+pub const PASSWORD_ENV: &str = "RSSH_PASSWORD";
+pub const PASSPHRASE_ENV: &str = "RSSH_PASSPHRASE";
+
+// 没有配置 auth_methods 时的默认顺序
+const DEFAULT_AUTH_METHODS: [AuthMethod; 3] = [
+    AuthMethod::Publickey,
+    AuthMethod::Password,
+    AuthMethod::KeyboardInteractive,
+];
+
+impl AuthMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            AuthMethod::Agent => "agent",
+            AuthMethod::Pkcs11 => "pkcs11",
+            AuthMethod::Publickey => "publickey",
+            AuthMethod::Password => "password",
+            AuthMethod::KeyboardInteractive => "keyboard-interactive",
+        }
+    }
+
+    // 服务器在 auth_methods 里使用的名字
+    fn server_name(self) -> &'static str {
+        match self {
+            AuthMethod::Agent | AuthMethod::Pkcs11 | AuthMethod::Publickey => "publickey",
+            AuthMethod::Password => "password",
+            AuthMethod::KeyboardInteractive => "keyboard-interactive",
+        }
+    }
+}
+
+/// 按别名配置的顺序尝试服务器允许的认证方式，一种失败后继续下一种
+pub fn authenticate(
+    sess: &Session,
+    alias: &str,
+    conn: &Connection,
+    user: &str,
+    identity_path: Option<&Path>,
+) -> Result<()> {
+    // 发送 "none" 认证请求以获取服务器允许的方式
+    let offered = sess.auth_methods(user)?.to_string();
+    if sess.authenticated() {
+        return Ok(());
+    }
+    let offered: Vec<&str> = offered.split(',').collect();
+    trace::log(format!("server allows: {}", offered.join(",")));
+
+    let mut preference = conn.auth_methods.clone();
+    if preference.is_empty() {
+        // 配置了 PKCS#11 时默认先用硬件令牌
+        if conn.pkcs11_provider.is_some() {
+            preference.push(AuthMethod::Pkcs11);
+        }
+        // 固定了 agent 密钥时先用它
+        if conn.agent_key_fingerprint.is_some() {
+            preference.push(AuthMethod::Agent);
+        }
+        preference.extend(DEFAULT_AUTH_METHODS);
+    }
+    let stored_password = get_password(alias).ok();
+
+    let mut failures = Vec::new();
+    for &method in &preference {
+        if !offered.contains(&method.server_name()) {
+            continue;
+        }
+        trace::log(format!("trying {}", method.name()));
+        let result = match method {
+            AuthMethod::Agent => try_agent(sess, user, conn.agent_key_fingerprint.as_deref()),
+            AuthMethod::Pkcs11 => try_pkcs11(sess, user, conn.pkcs11_provider.as_deref()),
+            AuthMethod::Publickey => match identity_path {
+                Some(path) => try_identity_file(sess, user, path, true),
+                // 有保存的密码时不去猜默认私钥
+                None if stored_password.is_none() => try_identity_files(sess, user, &candidate_identities(conn)),
+                None => Ok(false),
+            },
+            AuthMethod::Password => {
+                let stored = stored_password.as_deref().filter(|_| confirm_saved_password(alias, conn));
+                try_password(sess, alias, &conn.target, user, stored, may_store(Some(conn)))
+            }
+            AuthMethod::KeyboardInteractive => try_keyboard_interactive(sess, user),
+        };
+        match result {
+            Ok(true) if sess.authenticated() => return Ok(()),
+            Ok(_) => {}
+            Err(e) if is_cancelled(&e) => return Err(e),
+            // 需要询问却不能询问时直接失败，给出具体原因
+            Err(e) if matches!(error::classify(&e), Some(RsshError::Input(_))) => return Err(e),
+            Err(e) => failures.push(format!("{}: {:#}", method.name(), e)),
+        }
+    }
+
+    let tried: Vec<&str> = preference
+        .iter()
+        .filter(|m| offered.contains(&m.server_name()))
+        .map(|m| m.name())
+        .collect();
+    let mut message = format!(
+        "Authentication failed for {}. Server allows: {}; tried: {}.",
+        conn.target,
+        offered.join(", "),
+        if tried.is_empty() { "none".to_string() } else { tried.join(", ") }
+    );
+    for failure in failures {
+        message.push_str(&format!("\n  {}", failure));
+    }
+    Err(anyhow!(message))
+}
+
+// 用户按了 Esc / Ctrl+C 时直接中止，而不是尝试下一种方式
+fn is_cancelled(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<InquireError>(),
+        Some(InquireError::OperationCanceled | InquireError::OperationInterrupted)
+    )
+}
+
+fn try_agent(sess: &Session, user: &str, fingerprint: Option<&str>) -> Result<bool> {
+    agent::userauth(sess, user, fingerprint)?;
+    output::status("Authenticated with ssh-agent");
+    Ok(true)
+}
+
+// 令牌上的密钥还没加载到 agent 时，先通过 ssh-add 加载（会提示输入 PIN）
+fn try_pkcs11(sess: &Session, user: &str, provider: Option<&Path>) -> Result<bool> {
+    let provider = provider.ok_or_else(|| anyhow!("No pkcs11_provider is configured for this connection"))?;
+    if sess.userauth_agent(user).is_ok() {
+        output::status("Authenticated with a hardware token key");
+        return Ok(true);
+    }
+    pkcs11::load_provider(provider)?;
+    sess.userauth_agent(user)
+        .map_err(|e| anyhow!("No key from {:?} was accepted: {}", provider, e))?;
+    output::status("Authenticated with a hardware token key");
+    Ok(true)
+}
+
+// FIDO2 密钥经由 ssh-agent 签名，需要用户触摸设备
+fn try_security_key(sess: &Session, user: &str, path: &Path) -> Result<bool> {
+    keys::add_security_key(path)?;
+    output::status("Touch your security key to confirm the login...");
+    sess.userauth_agent(user)
+        .map_err(|e| anyhow!("Security key {:?} was not accepted: {}", path, e))?;
+    Ok(true)
+}
+
+// 别名配置的 identities 优先，然后是 ~/.ssh 下的默认私钥
+pub fn candidate_identities(conn: &Connection) -> Vec<PathBuf> {
+    let mut candidates = conn.identities.clone();
+    for path in keys::default_identity_files() {
+        if !candidates.contains(&path) {
+            candidates.push(path);
+        }
+    }
+    candidates.retain(|path| path.exists());
+    candidates
+}
+
+fn try_identity_files(sess: &Session, user: &str, identities: &[PathBuf]) -> Result<bool> {
+    for path in identities {
+        if try_identity_file(sess, user, path, false)? {
+            output::status(format!("Authenticated with {}", path.display()));
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// 需要口令的私钥会提示输入；`explicit` 为 false 时留空即跳过，失败也不报错
+fn try_identity_file(sess: &Session, user: &str, path: &Path, explicit: bool) -> Result<bool> {
+    if keys::is_security_key(path) {
+        return try_security_key(sess, user, path);
+    }
+    perms::warn_if_exposed(path);
+    let error = match sess.userauth_pubkey_file(user, None, path, None) {
+        Ok(_) => return Ok(true),
+        Err(e) => e,
+    };
+    if !error.to_string().contains("passphrase") {
+        if explicit {
+            return Err(anyhow!("Authentication failed with key: {}", error));
+        }
+        return Ok(false);
+    }
+    // 加密的私钥可能已经加载到 agent 里，先试 agent，免得输入口令
+    let has_agent = env::var_os("SSH_AUTH_SOCK").is_some();
+    if has_agent && sess.userauth_agent(user).is_ok() {
+        output::status(format!("{} is encrypted; authenticated with ssh-agent instead", path.display()));
+        return Ok(true);
+    }
+
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV)
+        && sess.userauth_pubkey_file(user, None, path, Some(&passphrase)).is_ok()
+    {
+        return Ok(true);
+    }
+    if !prompt::can_ask() {
+        if explicit {
+            return Err(prompt::unavailable(format!(
+                "{} is encrypted and no passphrase can be asked for. Set {}, or load the key into ssh-agent.",
+                path.display(),
+                PASSPHRASE_ENV
+            )));
+        }
+        return Ok(false);
+    }
+
+    let _prompt = prompt_lock();
+    let prompt = if explicit {
+        "Enter passphrase for key:".to_string()
+    } else {
+        format!("Enter passphrase for {} (leave empty to skip):", path.display())
+    };
+    let passphrase = match Password::new(&prompt)
+        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .prompt()
+    {
+        Ok(passphrase) => passphrase,
+        Err(e) if explicit => return Err(e.into()),
+        Err(_) => return Ok(false),
+    };
+    if passphrase.is_empty() && !explicit {
+        return Ok(false);
+    }
+    match sess.userauth_pubkey_file(user, None, path, Some(&passphrase)) {
+        Ok(_) => {
+            if has_agent {
+                offer_agent_add(path, &passphrase);
+            }
+            Ok(true)
+        }
+        Err(e) if explicit => Err(anyhow!("Authentication failed with key: {}", e)),
+        Err(_) => Ok(false),
+    }
+}
+
+// 口令输入正确后询问是否加到 agent，下次就不用再输；失败只警告
+fn offer_agent_add(path: &Path, passphrase: &str) {
+    if !prompt::can_ask() || output::is_json() {
+        return;
+    }
+    let question = format!("Add {} to ssh-agent so the passphrase isn't asked again?", path.display());
+    if !Confirm::new(&question).with_default(true).prompt().unwrap_or(false) {
+        return;
+    }
+    if let Err(e) = keys::add_to_agent(path, passphrase) {
+        eprintln!("Warning: {:#}", e);
+    }
+}
+
+/// 别名要求生物识别时，使用保存的密码前先确认；确认失败则改为手动输入
+pub fn confirm_saved_password(alias: &str, conn: &Connection) -> bool {
+    if !conn.require_biometrics {
+        return true;
+    }
+    if prompt::is_non_interactive() {
+        eprintln!("Not using the saved password for '{}': it needs Touch ID, which --non-interactive rules out.", alias);
+        return false;
+    }
+    match biometric::confirm(&format!("use the saved password for '{}'", alias)) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Not using the saved password for '{}': {:#}", alias, e);
+            false
+        }
+    }
+}
+
+fn try_password(
+    sess: &Session,
+    alias: &str,
+    target: &str,
+    user: &str,
+    stored: Option<&str>,
+    store: bool,
+) -> Result<bool> {
+    if let Some(stored) = stored {
+        if sess.userauth_password(user, stored).is_ok() {
+            return Ok(true);
+        }
+        eprintln!("The saved password for '{}' was rejected.", alias);
+    }
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        if sess.userauth_password(user, &password).is_ok() {
+            return Ok(true);
+        }
+        eprintln!("The password in {} was rejected.", PASSWORD_ENV);
+    }
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable(format!(
+            "'{}' needs a password and none can be asked for. Save it beforehand, or set {}.",
+            alias, PASSWORD_ENV
+        )));
+    }
+
+    let _prompt = prompt_lock();
+    for attempt in 1..=PASSWORD_ATTEMPTS {
+        let pass = Password::new(&format!("Enter password for {}:", target))
+            .with_display_mode(inquire::PasswordDisplayMode::Masked)
+            .without_confirmation()
+            .prompt()?;
+        if sess.userauth_password(user, &pass).is_ok() {
+            // 只在密码验证通过后才保存
+            let question = if stored.is_some() {
+                format!("Update the saved password in the {}?", store_name())
+            } else {
+                format!("Save password to {}?", store_name())
+            };
+            if store && Confirm::new(&question).with_default(true).prompt()? {
+                set_password(alias, &pass)?;
+            }
+            return Ok(true);
+        }
+        if attempt < PASSWORD_ATTEMPTS {
+            eprintln!("Permission denied, please try again.");
+        }
+    }
+
+    if stored.is_some()
+        && Confirm::new(&format!("Delete the saved password for '{}'?", alias))
+            .with_default(false)
+            .prompt()?
+    {
+        delete_password(alias)?;
+    }
+    Err(anyhow!(
+        "Authentication failed after {} attempts. Please check your username/password.",
+        PASSWORD_ATTEMPTS
+    ))
+}
+
+// 把服务器的 keyboard-interactive 提示逐个交给用户回答
+struct TerminalPrompter;
+
+impl KeyboardInteractivePrompt for TerminalPrompter {
+    fn prompt<'a>(&mut self, _username: &str, instructions: &str, prompts: &[Prompt<'a>]) -> Vec<String> {
+        let _prompt = prompt_lock();
+        if !instructions.is_empty() {
+            eprintln!("{}", instructions);
+        }
+        prompts
+            .iter()
+            .map(|prompt| {
+                let text = prompt.text.trim();
+                let answer = if prompt.echo {
+                    Text::new(text).prompt()
+                } else {
+                    Password::new(text)
+                        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+                        .without_confirmation()
+                        .prompt()
+                };
+                answer.unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+fn try_keyboard_interactive(sess: &Session, user: &str) -> Result<bool> {
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("The server asks keyboard-interactive questions, which can't be answered without a prompt."));
+    }
+    sess.userauth_keyboard_interactive(user, &mut TerminalPrompter)
+        .map_err(|e| anyhow!("Keyboard-interactive authentication failed: {}", e))?;
+    Ok(true)
+}
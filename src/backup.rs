@@ -0,0 +1,162 @@
+use crate::config::{state_dir, config_path, lock_config, write_atomic, Config};
+use crate::output;
+use crate::perms;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDateTime};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 保留最近的备份数
+const MAX_BACKUPS: usize = 20;
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+// undo 之前的配置也会备份，但带上这个后缀，undo 不会再跳回去
+const UNDO_SUFFIX: &str = "-undo";
+
+struct Backup {
+    timestamp: String,
+    undo: bool,
+    path: PathBuf,
+}
+
+impl Backup {
+    fn display_time(&self) -> String {
+        NaiveDateTime::parse_from_str(&self.timestamp, TIMESTAMP_FORMAT)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| self.timestamp.clone())
+    }
+}
+
+fn backup_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("backups"))
+}
+
+/// 覆盖配置文件前调用（已持有配置锁）：把当前内容存为带时间戳的备份，并清理旧备份
+pub fn save_backup(path: &Path, new_content: &[u8]) -> Result<()> {
+    save_backup_as(path, new_content, false)
+}
+
+fn save_backup_as(path: &Path, new_content: &[u8], undo: bool) -> Result<()> {
+    let Ok(current) = fs::read(path) else {
+        return Ok(());
+    };
+    // 内容没变的保存不产生备份
+    if current == new_content {
+        return Ok(());
+    }
+    let dir = backup_dir()?;
+    fs::create_dir_all(&dir).context("Failed to create backup directory")?;
+    let suffix = if undo { UNDO_SUFFIX } else { "" };
+    let name = format!("config-{}{}.json", Local::now().format(TIMESTAMP_FORMAT), suffix);
+    write_atomic(&dir.join(name), &current).context("Failed to write config backup")?;
+
+    let backups = list_backups()?;
+    if backups.len() > MAX_BACKUPS {
+        for backup in &backups[..backups.len() - MAX_BACKUPS] {
+            let _ = fs::remove_file(&backup.path);
+        }
+    }
+    Ok(())
+}
+
+// 按时间从旧到新排列
+fn list_backups() -> Result<Vec<Backup>> {
+    let dir = backup_dir()?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let stem = name.strip_prefix("config-")?.strip_suffix(".json")?;
+            let (timestamp, undo) = match stem.strip_suffix(UNDO_SUFFIX) {
+                Some(timestamp) => (timestamp.to_string(), true),
+                None => (stem.to_string(), false),
+            };
+            Some(Backup { timestamp, undo, path: entry.path() })
+        })
+        .collect();
+    backups.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(backups)
+}
+
+pub fn handle_list() -> Result<()> {
+    let backups = list_backups()?;
+    if output::is_json() {
+        let entries: Vec<_> = backups
+            .iter()
+            .map(|backup| json!({
+                "timestamp": backup.timestamp,
+                "before_undo": backup.undo,
+                "connections": connection_count(&backup.path),
+                "path": backup.path,
+            }))
+            .collect();
+        output::emit("config_backups", json!({ "backups": entries }));
+        return Ok(());
+    }
+    if backups.is_empty() {
+        println!("No config backups yet.");
+        return Ok(());
+    }
+    println!("{:<22} {:<21} {:>11}", "TIMESTAMP", "SAVED", "CONNECTIONS");
+    for backup in backups.iter().rev() {
+        let count = connection_count(&backup.path).map_or("?".to_string(), |n| n.to_string());
+        let note = if backup.undo { "  (before undo)" } else { "" };
+        println!("{:<22} {:<21} {:>11}{}", backup.timestamp, backup.display_time(), count, note);
+    }
+    Ok(())
+}
+
+/// 恢复最近一次保存之前的配置；连续执行可以一步步往回退
+pub fn handle_undo() -> Result<()> {
+    let backup = list_backups()?
+        .into_iter()
+        .rev()
+        .find(|backup| !backup.undo)
+        .context("There is nothing to undo.")?;
+    restore(&backup, true)?;
+    // 用过的备份删掉，下一次 undo 再往前退一步
+    let _ = fs::remove_file(&backup.path);
+    output::status(format!("Restored the config saved before {}.", backup.display_time()));
+    output::event("config_restored", json!({ "timestamp": backup.timestamp, "undo": true }));
+    Ok(())
+}
+
+/// 恢复指定时间戳（或其唯一前缀）的备份
+pub fn handle_restore(timestamp: &str) -> Result<()> {
+    let matches: Vec<Backup> = list_backups()?
+        .into_iter()
+        .filter(|backup| backup.timestamp.starts_with(timestamp))
+        .collect();
+    let backup = match matches.len() {
+        0 => return Err(anyhow!("No config backup matches '{}'. Run 'rssh config backups' to list them.", timestamp)),
+        1 => &matches[0],
+        n => return Err(anyhow!("'{}' matches {} backups; give more of the timestamp.", timestamp, n)),
+    };
+    restore(backup, false)?;
+    output::status(format!("Restored the config backup from {}.", backup.display_time()));
+    output::event("config_restored", json!({ "timestamp": backup.timestamp, "undo": false }));
+    Ok(())
+}
+
+// 当前配置先备份，恢复本身也可以撤回
+fn restore(backup: &Backup, undo: bool) -> Result<()> {
+    let content = fs::read(&backup.path).context(format!("Failed to read backup {:?}", backup.path))?;
+    serde_json::from_slice::<Config>(&content).context("The backup is not a valid config file")?;
+
+    let path = config_path()?;
+    let parent = path.parent().unwrap();
+    perms::create_private_dir(parent).context("Failed to create config directory")?;
+    let _lock = lock_config(parent)?;
+    save_backup_as(&path, &content, undo)?;
+    write_atomic(&path, &content).context("Failed to write config file")?;
+    Ok(())
+}
+
+fn connection_count(path: &Path) -> Option<usize> {
+    let content = fs::read(path).ok()?;
+    let config: Config = serde_json::from_slice(&content).ok()?;
+    Some(config.connections.len())
+}
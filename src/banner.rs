@@ -0,0 +1,87 @@
+use clap::ValueEnum;
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use crossterm::{queue, terminal};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BannerColor {
+    #[default]
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl BannerColor {
+    fn background(self) -> Color {
+        match self {
+            BannerColor::Red => Color::DarkRed,
+            BannerColor::Yellow => Color::DarkYellow,
+            BannerColor::Green => Color::DarkGreen,
+            BannerColor::Blue => Color::DarkBlue,
+            BannerColor::Magenta => Color::DarkMagenta,
+            BannerColor::Cyan => Color::DarkCyan,
+        }
+    }
+
+    // 同样的颜色在 PS1 里用的 SGR 参数（粗体白字 + 背景色）
+    fn sgr(self) -> &'static str {
+        match self {
+            BannerColor::Red => "1;97;41",
+            BannerColor::Yellow => "1;97;43",
+            BannerColor::Green => "1;97;42",
+            BannerColor::Blue => "1;97;44",
+            BannerColor::Magenta => "1;97;45",
+            BannerColor::Cyan => "1;97;46",
+        }
+    }
+}
+
+/// 进入交互会话前在本地显示的横幅，提醒当前连的是哪台机器
+#[derive(Clone)]
+pub struct Banner {
+    pub text: String,
+    pub color: BannerColor,
+    // 同时把横幅文字加到远程 shell 的提示符前面
+    pub prompt: bool,
+}
+
+impl Banner {
+    /// 整行背景色，文字居中
+    pub fn print(&self) -> io::Result<()> {
+        let width = terminal::size().map(|(width, _)| width as usize).unwrap_or(80);
+        let text = format!(" {} ", self.text);
+        let len = text.chars().count();
+        let left = width.saturating_sub(len) / 2;
+        let right = width.saturating_sub(len + left);
+        let line = format!("{}{}{}", " ".repeat(left), text, " ".repeat(right));
+
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            SetBackgroundColor(self.color.background()),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold),
+            Print(line),
+            SetAttribute(Attribute::Reset),
+            ResetColor,
+            Print("\n"),
+        )?;
+        stdout.flush()
+    }
+
+    /// 在远程 shell 里执行的命令：给 PS1 加上带颜色的横幅文字
+    /// 以空格开头，HISTCONTROL=ignorespace 时不进历史；只适用于 bash 等认 \[ \] 的 shell
+    pub fn prompt_command(&self) -> String {
+        let label = self.text.replace(['"', '\\', '$', '`'], "");
+        format!(
+            " PS1=\"\\[\\033[{}m\\] {} \\[\\033[0m\\] $PS1\"\n",
+            self.color.sgr(),
+            label
+        )
+    }
+}
@@ -0,0 +1,159 @@
+use crate::output;
+use crate::remote_fs::format_size;
+use crate::transfer::{copy_buffered, DEFAULT_BUFFER_SIZE};
+use crate::ssh::{self, shell_quote};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde_json::json;
+use ssh2::Session;
+use ssh_key::rand_core::{OsRng, RngCore};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+pub const DEFAULT_SIZE: &str = "100M";
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    /// Local to remote
+    Up,
+    /// Remote to local
+    Down,
+    /// Both directions
+    Both,
+}
+
+struct BenchResult {
+    method: &'static str,
+    direction: &'static str,
+    bytes: u64,
+    secs: f64,
+}
+
+impl BenchResult {
+    fn mb_per_sec(&self) -> f64 {
+        self.bytes as f64 / 1_000_000.0 / self.secs.max(f64::EPSILON)
+    }
+}
+
+/// 分别测量 SFTP 和 exec 通道的吞吐量，用来区分是 SFTP、网络还是磁盘慢
+pub fn handle_bench(sess: Session, size: u64, direction: Direction) -> Result<()> {
+    let mut chunk = vec![0u8; DEFAULT_BUFFER_SIZE];
+    // 随机数据，避免压缩影响结果
+    OsRng.fill_bytes(&mut chunk);
+
+    let remote_file = remote_temp_file(&sess)?;
+    let up = direction != Direction::Down;
+    let down = direction != Direction::Up;
+    let mut results = Vec::new();
+
+    let outcome = (|| -> Result<()> {
+        let sftp = sess.sftp().context("Failed to create SFTP session")?;
+        if up {
+            output::status(format!("SFTP upload of {}...", format_size(size)));
+            let started = Instant::now();
+            let mut file = sftp.create(Path::new(&remote_file))?;
+            write_generated(&mut file, &chunk, size)?;
+            drop(file);
+            results.push(BenchResult { method: "sftp", direction: "up", bytes: size, secs: started.elapsed().as_secs_f64() });
+        } else {
+            // 只测下载时先在远程生成测试文件，不计时
+            exec_discard(&sess, &format!("head -c {} /dev/urandom > {}", size, shell_quote(&remote_file)))?;
+        }
+        if down {
+            output::status(format!("SFTP download of {}...", format_size(size)));
+            let started = Instant::now();
+            let mut file = sftp.open(Path::new(&remote_file))?;
+            let bytes = copy_buffered(&mut file, &mut io::sink(), DEFAULT_BUFFER_SIZE)?;
+            results.push(BenchResult { method: "sftp", direction: "down", bytes, secs: started.elapsed().as_secs_f64() });
+        }
+
+        if up {
+            output::status(format!("exec 'cat > /dev/null' upload of {}...", format_size(size)));
+            let started = Instant::now();
+            let mut channel = ssh::bulk_channel(&sess)?;
+            channel.exec("cat > /dev/null")?;
+            write_generated(&mut channel, &chunk, size)?;
+            channel.send_eof()?;
+            // 等远程 cat 读完并退出才算结束
+            io::copy(&mut channel, &mut io::sink())?;
+            channel.wait_close()?;
+            results.push(BenchResult { method: "exec", direction: "up", bytes: size, secs: started.elapsed().as_secs_f64() });
+        }
+        if down {
+            output::status(format!("exec 'cat' download of {}...", format_size(size)));
+            let started = Instant::now();
+            let mut channel = ssh::bulk_channel(&sess)?;
+            channel.exec(&format!("cat {}", shell_quote(&remote_file)))?;
+            let bytes = copy_buffered(&mut channel, &mut io::sink(), DEFAULT_BUFFER_SIZE)?;
+            channel.wait_close()?;
+            results.push(BenchResult { method: "exec", direction: "down", bytes, secs: started.elapsed().as_secs_f64() });
+        }
+        Ok(())
+    })();
+
+    let _ = exec_discard(&sess, &format!("rm -f {}", shell_quote(&remote_file)));
+    outcome?;
+
+    if output::is_json() {
+        let results: Vec<_> = results
+            .iter()
+            .map(|r| json!({
+                "method": r.method,
+                "direction": r.direction,
+                "bytes": r.bytes,
+                "seconds": r.secs,
+                "mb_per_sec": r.mb_per_sec(),
+            }))
+            .collect();
+        output::emit("bench", json!({ "size": size, "results": results }));
+        return Ok(());
+    }
+
+    println!();
+    println!("{:<6} {:<5} {:>10} {:>9} {:>10}", "METHOD", "DIR", "SIZE", "TIME", "MB/s");
+    for r in &results {
+        println!(
+            "{:<6} {:<5} {:>10} {:>8.2}s {:>10.1}",
+            r.method,
+            r.direction,
+            format_size(r.bytes),
+            r.secs,
+            r.mb_per_sec()
+        );
+    }
+    println!();
+    println!("exec numbers are close to the raw SSH throughput. If SFTP is much slower, the SFTP protocol or the remote disk is the bottleneck.");
+    Ok(())
+}
+
+fn write_generated<W: Write>(writer: &mut W, chunk: &[u8], size: u64) -> Result<()> {
+    let mut remaining = size;
+    while remaining > 0 {
+        let n = remaining.min(chunk.len() as u64) as usize;
+        writer.write_all(&chunk[..n])?;
+        remaining -= n as u64;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// 远程临时文件，放在 /tmp 下以免测到用户家目录所在的慢盘
+fn remote_temp_file(sess: &Session) -> Result<String> {
+    let mut channel = sess.channel_session()?;
+    channel.exec("mktemp /tmp/rssh-bench.XXXXXX")?;
+    let mut path = String::new();
+    channel.read_to_string(&mut path)?;
+    channel.wait_close()?;
+    match path.trim() {
+        "" => Err(anyhow!("Could not create a temporary file on the remote host")),
+        path => Ok(path.to_string()),
+    }
+}
+
+fn exec_discard(sess: &Session, command: &str) -> Result<()> {
+    match ssh::exec_status(sess, command)? {
+        0 => Ok(()),
+        code => Err(anyhow!("Remote command '{}' failed with status {}", command, code)),
+    }
+}
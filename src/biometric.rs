@@ -0,0 +1,161 @@
+use anyhow::Result;
+
+/// 使用保存的密码前请求本机的生物识别确认（macOS 上为 Touch ID）
+pub fn confirm(reason: &str) -> Result<()> {
+    platform::confirm(reason)
+}
+
+// 通过 Objective-C 运行时调用 LocalAuthentication 的 LAContext
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use std::ffi::{c_char, c_long, c_void, CStr, CString};
+    use std::ptr;
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::Mutex;
+
+    type Id = *mut c_void;
+    type Sel = *mut c_void;
+
+    // LAPolicyDeviceOwnerAuthenticationWithBiometrics
+    const POLICY_BIOMETRICS: c_long = 1;
+    const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+    #[link(name = "objc")]
+    unsafe extern "C" {
+        fn objc_getClass(name: *const c_char) -> Id;
+        fn sel_registerName(name: *const c_char) -> Sel;
+        fn objc_msgSend();
+        static _NSConcreteGlobalBlock: c_void;
+    }
+
+    #[link(name = "Foundation", kind = "framework")]
+    unsafe extern "C" {}
+
+    #[link(name = "LocalAuthentication", kind = "framework")]
+    unsafe extern "C" {}
+
+    // Objective-C block 的内存布局，回调不捕获变量，按全局 block 处理
+    #[repr(C)]
+    struct BlockDescriptor {
+        reserved: usize,
+        size: usize,
+    }
+
+    #[repr(C)]
+    struct Block {
+        isa: *const c_void,
+        flags: i32,
+        reserved: i32,
+        invoke: unsafe extern "C" fn(*mut Block, u8, Id),
+        descriptor: *const BlockDescriptor,
+    }
+
+    // 回调在系统的队列上执行，通过通道把结果送回调用线程
+    static REPLY: Mutex<Option<Sender<Result<(), String>>>> = Mutex::new(None);
+
+    unsafe extern "C" fn reply(_block: *mut Block, success: u8, error: Id) {
+        let result = if success != 0 { Ok(()) } else { Err(unsafe { describe(error) }) };
+        if let Some(sender) = REPLY.lock().unwrap().take() {
+            let _ = sender.send(result);
+        }
+    }
+
+    unsafe fn class(name: &str) -> Id {
+        let name = CString::new(name).unwrap();
+        unsafe { objc_getClass(name.as_ptr()) }
+    }
+
+    unsafe fn sel(name: &str) -> Sel {
+        let name = CString::new(name).unwrap();
+        unsafe { sel_registerName(name.as_ptr()) }
+    }
+
+    unsafe fn send(receiver: Id, selector: &str) -> Id {
+        let msg_send: unsafe extern "C" fn(Id, Sel) -> Id =
+            unsafe { std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(Id, Sel) -> Id>(objc_msgSend) };
+        unsafe { msg_send(receiver, sel(selector)) }
+    }
+
+    unsafe fn ns_string(text: &str) -> Id {
+        let text = CString::new(text.replace('\0', "")).unwrap();
+        let msg_send: unsafe extern "C" fn(Id, Sel, *const c_char) -> Id = unsafe {
+            std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(Id, Sel, *const c_char) -> Id>(objc_msgSend)
+        };
+        unsafe { msg_send(class("NSString"), sel("stringWithUTF8String:"), text.as_ptr()) }
+    }
+
+    unsafe fn describe(error: Id) -> String {
+        if error.is_null() {
+            return "unknown error".to_string();
+        }
+        unsafe {
+            let description = send(error, "localizedDescription");
+            let utf8 = send(description, "UTF8String") as *const c_char;
+            if utf8.is_null() {
+                return "unknown error".to_string();
+            }
+            CStr::from_ptr(utf8).to_string_lossy().into_owned()
+        }
+    }
+
+    pub fn confirm(reason: &str) -> Result<()> {
+        unsafe {
+            let context = send(send(class("LAContext"), "alloc"), "init");
+            if context.is_null() {
+                return Err(anyhow!("LocalAuthentication is not available"));
+            }
+            let mut error: Id = ptr::null_mut();
+            let can_evaluate: unsafe extern "C" fn(Id, Sel, c_long, *mut Id) -> u8 = std::mem::transmute::<
+                unsafe extern "C" fn(),
+                unsafe extern "C" fn(Id, Sel, c_long, *mut Id) -> u8,
+            >(objc_msgSend);
+            if can_evaluate(context, sel("canEvaluatePolicy:error:"), POLICY_BIOMETRICS, &mut error) == 0 {
+                let message = describe(error);
+                send(context, "release");
+                return Err(anyhow!("Touch ID is not available: {}", message));
+            }
+
+            let (sender, receiver) = mpsc::channel();
+            *REPLY.lock().unwrap() = Some(sender);
+            let descriptor = Box::leak(Box::new(BlockDescriptor {
+                reserved: 0,
+                size: std::mem::size_of::<Block>(),
+            }));
+            let block = Box::leak(Box::new(Block {
+                isa: &raw const _NSConcreteGlobalBlock,
+                flags: BLOCK_IS_GLOBAL,
+                reserved: 0,
+                invoke: reply,
+                descriptor,
+            }));
+            let evaluate: unsafe extern "C" fn(Id, Sel, c_long, Id, *mut Block) = std::mem::transmute::<
+                unsafe extern "C" fn(),
+                unsafe extern "C" fn(Id, Sel, c_long, Id, *mut Block),
+            >(objc_msgSend);
+            evaluate(
+                context,
+                sel("evaluatePolicy:localizedReason:reply:"),
+                POLICY_BIOMETRICS,
+                ns_string(reason),
+                block,
+            );
+            let result = receiver.recv();
+            send(context, "release");
+            match result {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(message)) => Err(anyhow!("Touch ID confirmation failed: {}", message)),
+                Err(_) => Err(anyhow!("Touch ID confirmation was interrupted")),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use anyhow::{anyhow, Result};
+
+    pub fn confirm(_reason: &str) -> Result<()> {
+        Err(anyhow!("biometric confirmation is only available on macOS"))
+    }
+}
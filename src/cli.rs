@@ -1,62 +1,1122 @@
-use clap::{Parser, Subcommand};
+use crate::agent;
+use crate::auth::AuthMethod;
+use crate::banner::BannerColor;
+use crate::bench::Direction;
+use crate::completion::Shell;
+use crate::config::HookFailure;
+use crate::deploy::{self, Put};
+use crate::docker;
+use crate::guard::ConfirmMode;
+use crate::hooks::HookEvent;
+use crate::keys::KeyType;
+use crate::osc52::Osc52Mode;
+use crate::output::OutputFormat;
+use crate::progress::ProgressMode;
+use crate::recipe;
+use crate::secret_env::{self, SecretEnv};
+use crate::show::ShowAs;
+use crate::ssh;
+use crate::svc::SvcAction;
+use crate::term::SignalMode;
+use crate::transfer::{SymlinkPolicy, WalkOptions};
+use crate::tunnel::{self, Forward};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "rssh", version = "1.0", about = "A secure SSH login management tool")]
 pub struct Cli {
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text, help = "Output format for results and events")]
+    pub format: OutputFormat,
+    #[arg(short, long, global = true, help = "Print connection timings and other diagnostics")]
+    pub verbose: bool,
+    #[arg(long, global = true, help = "Never save passwords or cache the vault key; nothing typed is persisted")]
+    pub no_store: bool,
+    #[arg(long, global = true, help = "Don't ask for confirmation before using protected hosts (for scripts)")]
+    pub allow_protected: bool,
+    #[arg(long, global = true, help = "Never prompt (for CI): fail with exit code 66 when a password, passphrase or confirmation would be needed, and only connect to hosts with a pinned key")]
+    pub non_interactive: bool,
+    #[arg(long, global = true, value_name = "FILE", help = "Write libssh2 protocol traces and the timing of each connection phase to FILE, for bug reports")]
+    pub debug_ssh: Option<PathBuf>,
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+// 只在启动时解析一次，变体大小无关紧要
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     /// Add a new SSH connection
     Add {
         #[arg(help = "A unique alias for the connection")]
         alias: String,
-        #[arg(help = "Connection string in user@host format")]
+        #[arg(help = "Connection string in user@host or user@host:port format")]
         connection_string: String,
+        #[arg(long, help = "MAC address to send a Wake-on-LAN packet to before connecting")]
+        wol_mac: Option<String>,
+        #[arg(long, requires = "wol_mac", help = "Broadcast address for the Wake-on-LAN packet [default: 255.255.255.255]")]
+        wol_broadcast: Option<String>,
+        #[arg(long, value_delimiter = ',', help = "Comma-separated private keys to try before the default ~/.ssh keys")]
+        identities: Vec<PathBuf>,
+        #[arg(long, value_enum, value_delimiter = ',', help = "Comma-separated order of authentication methods to try")]
+        auth_methods: Vec<AuthMethod>,
+        #[arg(long, help = "PKCS#11 library for keys on a smartcard or hardware token (e.g. /usr/lib/opensc-pkcs11.so)")]
+        pkcs11_provider: Option<PathBuf>,
+        #[arg(long, value_name = "FINGERPRINT", value_parser = agent::parse_fingerprint, help = "Only offer this ssh-agent key (SHA256 fingerprint from 'rssh agent list') to the host (empty removes it)")]
+        agent_key: Option<String>,
+        #[arg(long, value_delimiter = ',', help = "Comma-separated other addresses (host or host:port) to try in order when the main one is unreachable")]
+        fallback_hosts: Vec<String>,
+        #[arg(long, help = "Seconds to wait for each address to accept the connection [default: 5 with fallbacks]")]
+        connect_timeout: Option<u64>,
+        #[arg(long, help = "Verify unpinned host keys against SSHFP records in DNS")]
+        sshfp: bool,
+        #[arg(long, help = "Disconnect interactive sessions after this many idle seconds (0 disables)")]
+        idle_timeout: Option<u64>,
+        #[arg(long, value_name = "SECS", help = "Send a keepalive after this many idle seconds so NAT and firewalls keep the connection (0 disables, also over a default)")]
+        keepalive: Option<u32>,
+        #[arg(long, help = "Lines of local scrollback to keep for copy mode (Ctrl+] then [) in interactive sessions (0 disables)")]
+        scrollback: Option<usize>,
+        #[arg(long, value_enum, help = "Handle OSC 52 clipboard sequences from remote programs in interactive sessions")]
+        osc52: Option<Osc52Mode>,
+        #[arg(long, help = "Largest clipboard content remote programs may set through OSC 52 (e.g. 512K) [default: 1M]")]
+        osc52_max_size: Option<String>,
+        #[arg(long, value_enum, help = "Where Ctrl+C and Ctrl+Z go in interactive sessions; toggle during a session with Ctrl+] then c [default: remote]")]
+        signals: Option<SignalMode>,
+        #[arg(long, help = "TERM to request for interactive sessions, e.g. vt100 or xterm [default: xterm-256color]")]
+        term: Option<String>,
+        #[arg(long, help = "LANG to set for sessions, e.g. en_US.UTF-8 (the server must accept it)")]
+        lang: Option<String>,
+        #[arg(long, help = "LC_ALL to set for sessions (the server must accept it)")]
+        lc_all: Option<String>,
+        #[arg(long, help = "Text of a colored banner shown before interactive sessions, e.g. \"PRODUCTION — db-primary\" (empty removes it)")]
+        banner: Option<String>,
+        #[arg(long, value_enum, help = "Background color of the banner [default: red]")]
+        banner_color: Option<BannerColor>,
+        #[arg(long, value_name = "BOOL", help = "Also put the banner text in front of the remote shell prompt (bash-compatible shells)")]
+        prompt_banner: Option<bool>,
+        #[arg(long, value_name = "BOOL", help = "Show a short summary of the server (uptime, load, disk, last login) before interactive sessions")]
+        show_info: Option<bool>,
+        #[arg(long, value_name = "BOOL", help = "Don't print rssh's connection messages, and hide the server's MOTD and last-login lines in interactive sessions")]
+        quiet_login: Option<bool>,
+        #[arg(long, value_enum, help = "Ask before connecting to, uploading to or running commands on this host: type the alias, or answer yes/no")]
+        confirm_connect: Option<ConfirmMode>,
+        #[arg(long, help = "Ask for Touch ID before using the saved password (macOS only)")]
+        require_biometrics: bool,
+        #[arg(long, value_name = "BOOL", help = "Set to false to never offer saving passwords typed for this alias")]
+        store_credentials: Option<bool>,
+        #[arg(short, long, help = "Copy port, identity, jump host and tags from a saved template")]
+        template: Option<String>,
+        #[command(flatten)]
+        settings: ConnectionSettings,
+        #[command(flatten)]
+        crypto: CryptoSettings,
     },
     /// List all saved SSH connections
-    List,
-    /// Remove a saved SSH connection
+    List {
+        #[arg(long, help = "Check whether each host is reachable and whether saved keys or passwords still log in, without asking for anything")]
+        probe: bool,
+        #[arg(long, conflicts_with = "probe", help = "Also show the OS, kernel, architecture and hostname recorded at the last interactive login")]
+        details: bool,
+        #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..), requires = "probe", help = "Number of hosts to probe at the same time")]
+        jobs: u16,
+    },
+    /// Show or change the connection used when no alias is given
+    SetDefault {
+        #[arg(help = "The alias to connect to by default")]
+        alias: Option<String>,
+        #[arg(long, conflicts_with = "alias", help = "Remove the default alias")]
+        clear: bool,
+    },
+    /// Print a connection's full settings and an equivalent ssh command line
+    Show {
+        #[arg(help = "The alias of the connection to show")]
+        alias: String,
+        #[arg(long = "as", value_enum, help = "Print only this form, e.g. for scripts")]
+        as_: Option<ShowAs>,
+        #[arg(long, help = "Copy the ssh command (or the form chosen with --as) to the clipboard")]
+        copy: bool,
+    },
+    /// Check the permissions of the config directory, its files and private keys
+    Doctor {
+        #[arg(long, help = "Remove group and other permissions that are too open")]
+        fix_perms: bool,
+    },
+    /// Run the first-time setup: check password storage, import hosts from ~/.ssh/config and add a connection
+    Setup,
+    /// Remove saved SSH connections (pick them interactively when none are given)
     Remove {
-        #[arg(help = "The alias of the connection to remove")]
+        #[arg(help = "The aliases of the connections to remove")]
+        aliases: Vec<String>,
+        #[arg(long, help = "Also remove every connection with this tag")]
+        tag: Option<String>,
+        #[arg(short, long, help = "Don't ask for confirmation when removing several connections")]
+        yes: bool,
+    },
+    /// Copy a saved connection to a new alias, optionally changing some settings
+    Clone {
+        #[arg(help = "The alias to copy")]
+        source: String,
+        #[arg(help = "The alias for the copy")]
         alias: String,
+        #[arg(long, help = "Host or host:port for the copy (keeps the user)")]
+        host: Option<String>,
+        #[arg(long, help = "User for the copy")]
+        user: Option<String>,
+        #[command(flatten)]
+        settings: ConnectionSettings,
     },
     /// Connect to a server using a saved alias
     Connect {
+        #[arg(help = "The alias of the connection to use [default: the default alias]")]
+        alias: Option<String>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+        #[arg(long, help = "Write remote stderr to stdout instead of local stderr")]
+        merge_stderr: bool,
+        #[arg(long, help = "TERM to request for this session, overriding the saved one")]
+        term: Option<String>,
+        #[arg(long, help = "Show a short summary of the server before the shell starts")]
+        info: bool,
+        #[arg(long, value_name = "PORT", help = "Mirror the session to watchers connecting to 127.0.0.1:PORT (read-only)")]
+        share: Option<u16>,
+        #[arg(long, requires = "share", help = "Let watchers type after sending the token printed at the start")]
+        share_input: bool,
+        #[arg(long, value_enum, help = "Where Ctrl+C and Ctrl+Z go in this session, overriding the saved setting")]
+        signals: Option<SignalMode>,
+    },
+    /// Run a command on a server and exit with its status
+    Exec {
+        #[arg(help = "The alias of the connection to use, or a pattern such as 'web-*' to run it on each matching host")]
+        alias: String,
+        #[arg(required_unless_present_any = ["last", "history"], trailing_var_arg = true, allow_hyphen_values = true, help = "The command to run")]
+        command: Vec<String>,
+        #[arg(long, conflicts_with_all = ["command", "history"], help = "Run the last command run on this alias again")]
+        last: bool,
+        #[arg(long, conflicts_with = "command", help = "Pick a previous command for this alias from a list and run it again")]
+        history: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+        #[arg(long, help = "Write remote stderr to stdout instead of local stderr")]
+        merge_stderr: bool,
+        #[arg(long, help = "Run the command through sudo on a PTY and answer its password prompt")]
+        sudo: bool,
+        #[arg(long, help = "Run the command as this user through sudo (implies --sudo)")]
+        sudo_user: Option<String>,
+        #[arg(long, value_name = "NAME=SOURCE", value_parser = secret_env::parse, conflicts_with_all = ["sudo", "sudo_user"], help = "Set an environment variable on the remote command from a saved secret (keyring:<name>) or a prompt (prompt), without putting the value on any command line")]
+        secret_env: Vec<SecretEnv>,
+        #[arg(long, value_name = "SECS", help = "Give up on the command after this many seconds and exit with code 124")]
+        timeout: Option<u64>,
+        #[arg(long, value_name = "NAME", value_parser = ssh::parse_signal, requires = "timeout", conflicts_with_all = ["sudo", "sudo_user"], help = "Signal sent to the remote command when --timeout expires, e.g. TERM or KILL")]
+        signal: Option<String>,
+        #[arg(short, long, help = "Don't ask before running on every host an alias pattern matches")]
+        yes: bool,
+    },
+    /// Run the local docker command against the Docker daemon on a server, through a forwarded socket
+    Docker {
         #[arg(help = "The alias of the connection to use")]
         alias: String,
-        #[arg(short, long, help = "The port to connect to", default_value_t = 22)]
-        port: u16,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true, help = "Arguments for docker, e.g. ps or logs -f web")]
+        args: Vec<String>,
+        #[arg(long, default_value = docker::DEFAULT_SOCKET, help = "The Docker socket on the server")]
+        socket: String,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
         #[arg(short, long, help = "Path to the private key file")]
         identity: Option<PathBuf>,
     },
-    /// Upload a file to a remote directory
-    Upload {
+    /// Check, restart or read the logs of a systemd service on a server
+    Svc {
         #[arg(help = "The alias of the connection to use")]
         alias: String,
-        #[arg(help = "Local file to upload")]
-        local_path: PathBuf,
-        #[arg(help = "Remote directory to save the file in")]
+        #[arg(value_enum, help = "What to do with the service")]
+        action: SvcAction,
+        #[arg(help = "The systemd unit, e.g. nginx or nginx.service")]
+        unit: String,
+        #[arg(long, help = "Read the journal through sudo (for users outside the systemd-journal and adm groups)")]
+        sudo: bool,
+        #[arg(long, help = "Restart without sudo, e.g. when polkit allows it")]
+        no_sudo: bool,
+        #[arg(short = 'n', long, default_value_t = 50, help = "Number of journal lines to show")]
+        lines: u32,
+        #[arg(short, long, help = "Keep printing new journal lines until Ctrl+C")]
+        follow: bool,
+        #[arg(long, help = "Only show journal lines since this time, e.g. '1 hour ago' or 'today'")]
+        since: Option<String>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Upload files and then run commands over a single connection
+    Deploy {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(long, value_name = "LOCAL:REMOTE_DIR", value_parser = deploy::parse_put, required_unless_present = "run", help = "Upload a local file (or directory with --recursive) into a remote directory; repeat for more, all run before any command")]
+        put: Vec<Put>,
+        #[arg(long, value_name = "COMMAND", help = "Run a command after the uploads; repeat for more, stopping at the first that fails")]
+        run: Vec<String>,
+        #[arg(long, help = "Preserve modes and modification times of the original file")]
+        preserve: bool,
+        #[arg(short, long, help = "Overwrite existing files without asking")]
+        force: bool,
+        #[arg(short, long, help = "Never overwrite existing files", conflicts_with_all = ["force", "backup"])]
+        no_clobber: bool,
+        #[arg(short, long, help = "Rename existing files to '<name>.bak' before overwriting", conflicts_with = "force")]
+        backup: bool,
+        #[arg(short, long, help = "Transfer directories recursively")]
+        recursive: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Run a SOCKS5 proxy that goes out through a server, or with --reverse one on the server that goes out through this machine
+    Proxy {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(long, default_value_t = 1080, conflicts_with = "reverse", help = "Local port to listen on")]
+        listen: u16,
+        #[arg(long, value_name = "PORT", help = "Listen on this port on the server instead, so programs there can reach the network through this machine")]
+        reverse: Option<u16>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Forward ports on the server to this machine; with --auto, pick them from the ports listening there
+    Tunnel {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(short = 'L', long = "forward", value_name = "[LOCAL:][HOST:]PORT|LOCAL:/SOCKET", value_parser = tunnel::parse_forward, required_unless_present = "auto", help = "Forward a local port or Unix socket (a path) to PORT on the server, to HOST:PORT as seen from the server, or to a Unix socket on the server (repeatable)")]
+        forwards: Vec<Forward>,
+        #[arg(long, help = "List the TCP ports listening on the server (via ss or netstat) and choose which to forward")]
+        auto: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Check whether a port on the server is already in use, and suggest the next free one
+    PortCheck {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(value_name = "REMOTE_PORT", help = "The port on the server to check")]
+        remote_port: u16,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Bridge stdin/stdout to the connection's SSH port, or to HOST:PORT through the server (for ProxyCommand)
+    Stdio {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(value_name = "HOST:PORT", help = "Open a tunnel to this address through the server instead of passing the SSH connection itself")]
+        target: Option<String>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Show uptime, load, memory and disk usage of several hosts in one table
+    Status {
+        #[arg(help = "Aliases of the hosts to check")]
+        aliases: Vec<String>,
+        #[arg(long, help = "Also check every connection with this tag")]
+        tag: Option<String>,
+        #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..), help = "Number of hosts to check at the same time")]
+        jobs: u16,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Check which key exchange, host key, cipher and MAC algorithms hosts offer, and list weak or deprecated ones
+    AuditCrypto {
+        #[arg(help = "Aliases of the hosts to check")]
+        aliases: Vec<String>,
+        #[arg(long, conflicts_with_all = ["aliases", "tag"], help = "Check every saved connection")]
+        all: bool,
+        #[arg(long, help = "Also check every connection with this tag")]
+        tag: Option<String>,
+        #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..), help = "Number of hosts to check at the same time")]
+        jobs: u16,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+    },
+    /// Run a local script on several hosts, save each host's output and print a pass/fail table
+    Fanout {
+        #[arg(help = "Aliases of the hosts to run it on")]
+        aliases: Vec<String>,
+        #[arg(long, help = "Also run it on every connection with this tag")]
+        tag: Option<String>,
+        #[arg(short, long, help = "Local script to run (a shebang line picks the interpreter, default sh)")]
+        script: PathBuf,
+        #[arg(long, value_name = "DIR", help = "Save <alias>.stdout and <alias>.stderr for each host in this directory")]
+        collect: Option<PathBuf>,
+        #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..), help = "Number of hosts to run it on at the same time")]
+        jobs: u16,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Open tiled shells on several hosts and type into all of them at once
+    Multiconnect {
+        #[arg(help = "Aliases of the connections to open")]
+        aliases: Vec<String>,
+        #[arg(long, help = "Also open every connection with this tag")]
+        tag: Option<String>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Upload a file (or a directory with --recursive) to a remote directory
+    Upload {
+        #[arg(help = "The alias of the connection to use, or a pattern such as 'db-0[1-3]' to upload to each matching host")]
+        alias: String,
+        #[arg(required = true, num_args = 1.., help = "Local files or directories to upload")]
+        local_paths: Vec<PathBuf>,
+        #[arg(help = "Remote directory to save the files in")]
         remote_path: PathBuf,
-        #[arg(short, long, help = "The port to connect to", default_value_t = 22)]
-        port: u16,
+        #[arg(long, value_name = "REMOTE_DIR", help = "Also upload the files to this remote directory over the same connection (repeatable)")]
+        also: Vec<PathBuf>,
+        #[arg(long, conflicts_with = "recursive", help = "Unpack each uploaded archive (.tar.gz, .tgz, .tar.bz2, .tar.xz, .tar.zst, .tar or .zip) in the remote directory")]
+        extract: bool,
+        #[arg(long, requires = "extract", help = "Delete the archive on the server once it has been unpacked")]
+        remove_archive: bool,
+        #[arg(short, long, help = "Don't ask before uploading to every host an alias pattern matches")]
+        yes: bool,
+        #[arg(long, help = "Preserve modes and modification times of the original file")]
+        preserve: bool,
+        #[arg(short, long, help = "Overwrite existing files without asking, and upload even if the remote disk looks too full")]
+        force: bool,
+        #[arg(short, long, help = "Never overwrite existing files", conflicts_with_all = ["force", "backup"])]
+        no_clobber: bool,
+        #[arg(short, long, help = "Rename existing files to '<name>.bak' before overwriting", conflicts_with = "force")]
+        backup: bool,
+        #[arg(short, long, help = "Transfer directories recursively")]
+        recursive: bool,
+        #[command(flatten)]
+        walk: WalkSettings,
+        #[arg(long, requires = "recursive", help = "Stream directories as a tar archive over an exec channel")]
+        tar: bool,
+        #[arg(long, conflicts_with = "tar", help = "Skip files whose content (SHA-256) already matches the destination, whatever their modification times")]
+        checksum: bool,
+        #[arg(long = "continue", conflicts_with = "tar", help = "Resume an interrupted transfer: skip files finished last time and continue partly transferred ones")]
+        resume: bool,
+        #[arg(long, conflicts_with = "tar", help = "Send changed files whole instead of only the blocks that differ from the existing copy")]
+        whole_file: bool,
+        #[arg(long, help = "Upload even if the remote disk looks too full")]
+        skip_space_check: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(long, default_value = "256K", help = "Size of the copy buffer (e.g. 64K, 1M); larger values keep more SFTP requests in flight")]
+        buffer_size: String,
+        #[command(flatten)]
+        queue: QueueSettings,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
         #[arg(short, long, help = "Path to the private key file")]
         identity: Option<PathBuf>,
     },
-    /// Download a file to a local directory
+    /// Download a file (or a directory with --recursive) to a local directory
     Download {
         #[arg(help = "The alias of the connection to use")]
         alias: String,
-        #[arg(help = "Remote file to download")]
-        remote_path: PathBuf,
-        #[arg(help = "Local directory to save the file in")]
+        #[arg(required = true, num_args = 1.., help = "Remote files or directories to download")]
+        remote_paths: Vec<PathBuf>,
+        #[arg(help = "Local directory to save the files in")]
         local_path: PathBuf,
-        #[arg(short, long, help = "The port to connect to", default_value_t = 22)]
-        port: u16,
+        #[arg(long, help = "Preserve modes and modification times of the original file")]
+        preserve: bool,
+        #[arg(short, long, help = "Overwrite existing files without asking")]
+        force: bool,
+        #[arg(short, long, help = "Never overwrite existing files", conflicts_with_all = ["force", "backup"])]
+        no_clobber: bool,
+        #[arg(short, long, help = "Rename existing files to '<name>.bak' before overwriting", conflicts_with = "force")]
+        backup: bool,
+        #[arg(short, long, help = "Transfer directories recursively")]
+        recursive: bool,
+        #[command(flatten)]
+        walk: WalkSettings,
+        #[arg(long, requires = "recursive", help = "Stream directories as a tar archive over an exec channel")]
+        tar: bool,
+        #[arg(long, conflicts_with = "tar", help = "Skip files whose content (SHA-256) already matches the destination, whatever their modification times")]
+        checksum: bool,
+        #[arg(long = "continue", conflicts_with = "tar", help = "Resume an interrupted transfer: skip files finished last time and continue partly transferred ones")]
+        resume: bool,
+        #[arg(long, conflicts_with = "tar", help = "Send changed files whole instead of only the blocks that differ from the existing copy")]
+        whole_file: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(long, default_value = "256K", help = "Size of the copy buffer (e.g. 64K, 1M); larger values keep more SFTP requests in flight")]
+        buffer_size: String,
+        #[command(flatten)]
+        queue: QueueSettings,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
         #[arg(short, long, help = "Path to the private key file")]
         identity: Option<PathBuf>,
     },
+    /// Append a local file (or stdin with -) to a remote file, or write it at an offset with --offset
+    Append {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Local file to send, or - for stdin")]
+        local_path: PathBuf,
+        #[arg(help = "Remote file to append to; it is created if missing")]
+        remote_path: PathBuf,
+        #[arg(long, help = "Write at this byte offset instead of the end, keeping the rest of the file")]
+        offset: Option<u64>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Copy a file or directory from one saved host to another through this machine
+    Copy {
+        #[arg(help = "Source as <alias>:<path>")]
+        source: String,
+        #[arg(help = "Destination directory as <alias>:<path>")]
+        destination: String,
+        #[arg(long, help = "Preserve modes and modification times of the original file")]
+        preserve: bool,
+        #[arg(short, long, help = "Overwrite existing files without asking")]
+        force: bool,
+        #[arg(short, long, help = "Never overwrite existing files", conflicts_with_all = ["force", "backup"])]
+        no_clobber: bool,
+        #[arg(short, long, help = "Rename existing files to '<name>.bak' before overwriting", conflicts_with = "force")]
+        backup: bool,
+        #[arg(short, long, help = "Copy directories recursively")]
+        recursive: bool,
+        #[command(flatten)]
+        walk: WalkSettings,
+        #[arg(long, help = "First try running scp on the source host to send the data straight to the destination")]
+        direct: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(long, default_value = "256K", help = "Size of the copy buffer (e.g. 64K, 1M); larger values keep more SFTP requests in flight")]
+        buffer_size: String,
+    },
+    /// Copy with scp-style paths; the side with an <alias>: prefix decides the direction
+    Cp {
+        #[arg(required = true, num_args = 2.., value_name = "[ALIAS:]PATH", help = "Sources followed by the destination directory; remote sources may use wildcards in the last part, e.g. 'web:/var/log/*.log'")]
+        paths: Vec<String>,
+        #[arg(long, help = "Preserve modes and modification times of the original file")]
+        preserve: bool,
+        #[arg(short, long, help = "Overwrite existing files without asking")]
+        force: bool,
+        #[arg(short, long, help = "Never overwrite existing files", conflicts_with_all = ["force", "backup"])]
+        no_clobber: bool,
+        #[arg(short, long, help = "Rename existing files to '<name>.bak' before overwriting", conflicts_with = "force")]
+        backup: bool,
+        #[arg(short, long, help = "Copy directories recursively")]
+        recursive: bool,
+        #[command(flatten)]
+        walk: WalkSettings,
+        #[arg(long, requires = "recursive", help = "Stream directories as a tar archive over an exec channel (uploads and downloads)")]
+        tar: bool,
+        #[arg(long, conflicts_with = "tar", help = "Skip files whose content (SHA-256) already matches the destination, whatever their modification times")]
+        checksum: bool,
+        #[arg(long = "continue", conflicts_with = "tar", help = "Resume an interrupted transfer: skip files finished last time and continue partly transferred ones")]
+        resume: bool,
+        #[arg(long, conflicts_with = "tar", help = "Send changed files whole instead of only the blocks that differ from the existing copy")]
+        whole_file: bool,
+        #[arg(long, help = "Copy to a server even if its disk looks too full")]
+        skip_space_check: bool,
+        #[arg(long, help = "Between two hosts, first try running scp on the source host to send the data straight to the destination")]
+        direct: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(long, default_value = "256K", help = "Size of the copy buffer (e.g. 64K, 1M); larger values keep more SFTP requests in flight")]
+        buffer_size: String,
+        #[command(flatten)]
+        queue: QueueSettings,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// List a remote directory in long format
+    Ls {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Remote path to list", default_value = ".")]
+        path: PathBuf,
+        #[arg(short, long, help = "Include entries starting with '.'")]
+        all: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Remove a remote file or directory
+    Rm {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Remote path to remove")]
+        path: PathBuf,
+        #[arg(short, long, help = "Remove directories and their contents recursively")]
+        recursive: bool,
+        #[arg(long, help = "Move it into ~/.rssh-trash on the server instead of deleting it")]
+        trash: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Create a remote directory
+    Mkdir {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Remote directory to create")]
+        path: PathBuf,
+        #[arg(long, help = "Create parent directories as needed")]
+        parents: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Move or rename a remote file or directory
+    Mv {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Remote source path")]
+        from: PathBuf,
+        #[arg(help = "Remote destination path")]
+        to: PathBuf,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Show details about a remote file
+    Stat {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Remote path to inspect")]
+        path: PathBuf,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Show the server's host key fingerprints without logging in
+    Fingerprint {
+        #[arg(help = "The alias of the connection to inspect")]
+        alias: String,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+    },
+    /// Trust the server's current host key and reject any other key in future connections
+    Pin {
+        #[arg(help = "The alias of the connection to pin")]
+        alias: String,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+    },
+    /// List, remove or export trusted host keys
+    Hostkey {
+        #[command(subcommand)]
+        action: HostkeyCommand,
+    },
+    /// Generate a new SSH key pair
+    Keygen {
+        #[arg(short = 't', long = "type", value_enum, default_value_t = KeyType::Ed25519, help = "Type of key to generate")]
+        key_type: KeyType,
+        #[arg(short, long, help = "Where to write the private key [default: ~/.ssh/id_<type>]")]
+        output: Option<PathBuf>,
+        #[arg(short = 'C', long, help = "Comment stored in the public key")]
+        comment: Option<String>,
+        #[arg(short = 'N', long, help = "Don't protect the private key with a passphrase")]
+        no_passphrase: bool,
+    },
+    /// Install your public key on a server and switch the alias to key login
+    CopyId {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(short, long, help = "Public (or private) key to install [default: ~/.ssh/id_ed25519.pub, id_ecdsa.pub or id_rsa.pub]")]
+        key: Option<PathBuf>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file used to log in")]
+        identity: Option<PathBuf>,
+    },
+    /// Measure connection setup times and round-trip latency to a host
+    Ping {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(short, long, default_value_t = 10, help = "Number of samples to take")]
+        count: u32,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Measure SFTP and raw SSH throughput to a host
+    Bench {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(long, default_value = crate::bench::DEFAULT_SIZE, help = "Amount of data to send in each test (e.g. 512K, 100M, 1G)")]
+        size: String,
+        #[arg(long, value_enum, default_value_t = Direction::Both, help = "Which direction to measure")]
+        direction: Direction,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Send a Wake-on-LAN packet to a saved host
+    Wake {
+        #[arg(help = "The alias of the connection to wake")]
+        alias: String,
+        #[arg(long, help = "Wait until the SSH port accepts connections")]
+        wait: bool,
+        #[arg(long, requires = "wait", default_value_t = crate::wol::DEFAULT_WAIT_SECS, help = "Seconds to wait for the host to come up")]
+        timeout: u64,
+        #[arg(short, long, help = "The port to wait for [default: 22]")]
+        port: Option<u16>,
+    },
+    /// Undo the last change to the saved connections
+    Undo,
+    /// Manage config backups
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Keep passwords in an encrypted local vault instead of the system keychain
+    Vault {
+        #[command(subcommand)]
+        action: VaultCommand,
+    },
+    /// List and clean up saved passwords
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsCommand,
+    },
+    /// Review the audit log of connections and transfers
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommand,
+    },
+    /// Manage connection templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateCommand,
+    },
+    /// Manage local commands run before connecting and after disconnecting
+    Hook {
+        #[command(subcommand)]
+        action: HookCommand,
+    },
+    /// Save frequently used remote commands and run them by name
+    Cmd {
+        #[command(subcommand)]
+        action: CmdCommand,
+    },
+    /// Inspect the keys held by ssh-agent
+    Agent {
+        #[command(subcommand)]
+        action: AgentCommand,
+    },
+    /// Run multi-step upload / exec / download recipes saved in the config
+    Recipe {
+        #[command(subcommand)]
+        action: RecipeCommand,
+    },
+    /// List, restore or empty files moved to the trash by 'rm --trash'
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommand,
+    },
+    /// Print a shell completion script, e.g. `source <(rssh completions bash)`
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Candidates for the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[command(subcommand)]
+        command: CompleteCommand,
+    },
+}
+
+impl Commands {
+    /// 会写配置文件的命令，从加载到保存都要持有配置锁
+    pub fn changes_config(&self) -> bool {
+        matches!(
+            self,
+            Commands::Add { .. }
+                | Commands::SetDefault { alias: Some(_), .. }
+                | Commands::SetDefault { clear: true, .. }
+                | Commands::Setup
+                | Commands::Remove { .. }
+                | Commands::Clone { .. }
+                | Commands::CopyId { .. }
+                | Commands::Config { action: ConfigCommand::HistorySize { size: Some(_) } }
+                | Commands::Config { action: ConfigCommand::ConfirmTags { tags: Some(_) } }
+                | Commands::Audit { action: AuditCommand::Path { path: Some(_) } }
+                | Commands::Template { action: TemplateCommand::Add { .. } | TemplateCommand::Remove { .. } }
+                | Commands::Cmd { action: CmdCommand::Add { .. } | CmdCommand::Edit { .. } | CmdCommand::Remove { .. } }
+                | Commands::Hook { .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub enum CompleteCommand {
+    /// Saved aliases, one per line
+    Aliases,
+    /// Remote paths starting with PREFIX
+    Remote {
+        alias: String,
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+/// 连接和模板共用的设置参数
+#[derive(Args)]
+pub struct ConnectionSettings {
+    #[arg(short, long, help = "Default port for the connection")]
+    pub port: Option<u16>,
+    #[arg(short, long, help = "Default private key file for the connection")]
+    pub identity: Option<PathBuf>,
+    #[arg(short, long, help = "Alias of a saved connection to use as a jump host")]
+    pub jump: Option<String>,
+    #[arg(long, value_delimiter = ',', help = "Comma-separated tags")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Subcommand)]
+pub enum TemplateCommand {
+    /// Save a template of common connection settings
+    Add {
+        #[arg(help = "A unique name for the template")]
+        name: String,
+        #[command(flatten)]
+        settings: ConnectionSettings,
+    },
+    /// List saved templates
+    List,
+    /// Remove a saved template
+    Remove {
+        #[arg(help = "The name of the template to remove")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+    /// Print audit log entries
+    Show {
+        #[arg(long, help = "Only show entries newer than this (e.g. 30m, 12h, 7d, 2w or 2024-01-31)")]
+        since: Option<String>,
+        #[arg(long, help = "Only show entries for this alias")]
+        alias: Option<String>,
+    },
+    /// Show or change where the audit log is written
+    Path {
+        #[arg(help = "New location for the audit log")]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// List config backups, newest first
+    Backups,
+    /// Replace the config with a backup
+    Restore {
+        #[arg(help = "Timestamp of the backup, or a unique prefix of it")]
+        timestamp: String,
+    },
+    /// Show or set how many exec commands are remembered per alias (0 turns the history off)
+    HistorySize {
+        #[arg(help = "Number of commands to keep per alias")]
+        size: Option<usize>,
+    },
+    /// Show or set the tags whose hosts need their alias typed before connecting, uploading or exec
+    ConfirmTags {
+        #[arg(value_delimiter = ',', help = "Comma-separated tags, e.g. prod,live (\"\" clears them)")]
+        tags: Option<Vec<String>>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Create the vault and move saved passwords from the keychain into it
+    Init,
+    /// Unlock the vault for the rest of the login session
+    Unlock {
+        #[arg(long, value_name = "MINUTES", help = "Lock again after this many minutes without use")]
+        timeout: Option<u64>,
+    },
+    /// Forget the unlocked vault key
+    Lock,
+    /// Show whether a vault is in use and whether it is unlocked
+    Status,
+    /// Change the master password
+    Passwd,
+    /// Move the passwords back to the keychain and delete the vault
+    Disable,
+}
+
+#[derive(Subcommand)]
+pub enum SecretsCommand {
+    /// Show which aliases have saved passwords, without revealing them
+    List,
+    /// Save a value for 'exec --secret-env NAME=keyring:<name>'
+    Set {
+        #[arg(help = "Name of the secret")]
+        name: String,
+    },
+    /// Delete the saved passwords of an alias
+    Delete {
+        #[arg(help = "Alias whose passwords to delete (it doesn't need to exist any more)")]
+        alias: String,
+    },
+    /// Delete saved passwords
+    Purge {
+        #[arg(long, help = "Only delete passwords of aliases that are no longer in the config")]
+        orphaned: bool,
+        #[arg(long, help = "Don't ask before deleting every saved password")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HostkeyCommand {
+    /// List trusted host keys with their fingerprints
+    List {
+        #[arg(help = "Only show keys for this alias or host")]
+        host: Option<String>,
+        #[arg(short, long, help = "Port of the host [default: 22]")]
+        port: Option<u16>,
+        #[arg(long, help = "known_hosts file to use [default: known_hosts in the rssh config directory]")]
+        file: Option<PathBuf>,
+    },
+    /// Remove the trusted keys of a host
+    Remove {
+        #[arg(help = "Alias, host name or [host]:port")]
+        host: String,
+        #[arg(short, long, help = "Port of the host [default: 22]")]
+        port: Option<u16>,
+        #[arg(long, help = "known_hosts file to use [default: known_hosts in the rssh config directory]")]
+        file: Option<PathBuf>,
+    },
+    /// Print trusted keys in OpenSSH known_hosts format
+    Export {
+        #[arg(help = "Only export keys for this alias or host")]
+        host: Option<String>,
+        #[arg(short, long, help = "Port of the host [default: 22]")]
+        port: Option<u16>,
+        #[arg(long, help = "known_hosts file to use [default: known_hosts in the rssh config directory]")]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TrashCommand {
+    /// List what is in the trash on a host
+    List {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Move an item back to where it was deleted from
+    Restore {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "The ID shown by 'rssh trash list'")]
+        id: String,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+    /// Permanently delete everything in the trash on a host
+    Empty {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(short, long, help = "Don't ask for confirmation")]
+        yes: bool,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CmdCommand {
+    /// Save a command for all hosts, or with --alias for one host (replaces one with the same name)
+    Add {
+        #[arg(help = "Name to run the command by, e.g. restart-app")]
+        name: String,
+        #[arg(help = "The remote shell command, e.g. \"systemctl restart myapp\"")]
+        command: String,
+        #[arg(short, long, help = "Only save it for this connection")]
+        alias: Option<String>,
+    },
+    /// Change a saved command in an editable prompt
+    Edit {
+        #[arg(help = "Name of the saved command")]
+        name: String,
+        #[arg(short, long, help = "Edit the command saved for this connection")]
+        alias: Option<String>,
+    },
+    /// Remove a saved command
+    Remove {
+        #[arg(help = "Name of the saved command")]
+        name: String,
+        #[arg(short, long, help = "Remove the command saved for this connection")]
+        alias: Option<String>,
+    },
+    /// List saved commands, or only those available on one host
+    List {
+        #[arg(help = "Only show commands that can run on this connection")]
+        alias: Option<String>,
+    },
+    /// Run a saved command on a host and exit with its status
+    Run {
+        #[arg(help = "The alias of the connection to use")]
+        alias: String,
+        #[arg(help = "Name of the saved command")]
+        name: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, help = "Extra arguments appended to the command (quoted)")]
+        args: Vec<String>,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+        #[arg(long, help = "Write remote stderr to stdout instead of local stderr")]
+        merge_stderr: bool,
+        #[arg(long, value_name = "SECS", help = "Give up on the command after this many seconds and exit with code 124")]
+        timeout: Option<u64>,
+        #[arg(long, value_name = "NAME", value_parser = ssh::parse_signal, requires = "timeout", help = "Signal sent to the remote command when --timeout expires, e.g. TERM or KILL")]
+        signal: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AgentCommand {
+    /// List the keys in ssh-agent with their fingerprints and the aliases pinned to them
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum RecipeCommand {
+    /// List saved recipes with their variables and steps
+    List,
+    /// Run a recipe's steps in order over one connection, stopping at the first that fails
+    Run {
+        #[arg(help = "Name of the recipe")]
+        name: String,
+        #[arg(short, long, help = "The alias of the connection to run it on")]
+        alias: String,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = recipe::parse_var, help = "Set a variable used as {{NAME}} in the steps, overriding the recipe's default")]
+        vars: Vec<(String, String)>,
+        #[arg(short, long, help = "Overwrite existing files without asking")]
+        force: bool,
+        #[arg(long, help = "Print the steps with variables filled in, without connecting")]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t = ProgressMode::Bar, help = "How to report transfer progress")]
+        progress: ProgressMode,
+        #[arg(short, long, help = "The port to connect to [default: 22]")]
+        port: Option<u16>,
+        #[arg(short, long, help = "Path to the private key file")]
+        identity: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HookCommand {
+    /// Set a hook for a connection
+    Set {
+        #[arg(help = "The alias of the connection")]
+        alias: String,
+        #[arg(value_enum, help = "When the hook runs")]
+        event: HookEvent,
+        #[arg(help = "Local shell command to run")]
+        command: String,
+        #[arg(long, help = "Seconds to wait before the hook is killed [default: 30]")]
+        timeout: Option<u64>,
+        #[arg(long, value_enum, default_value_t = HookFailure::Abort, help = "What to do when the hook fails or times out")]
+        on_failure: HookFailure,
+    },
+    /// Remove a hook from a connection
+    Clear {
+        #[arg(help = "The alias of the connection")]
+        alias: String,
+        #[arg(value_enum, help = "Which hook to remove")]
+        event: HookEvent,
+    },
+}
+
+/// 多个源路径时的并发和重试设置
+#[derive(Args)]
+pub struct QueueSettings {
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), help = "Number of sources to transfer at once, each over its own connection")]
+    pub jobs: u16,
+    #[arg(long, default_value_t = 3, help = "How many times to retry a source after a dropped connection or timeout")]
+    pub retries: u32,
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0, help = "Wait before the first retry; doubles on each further retry")]
+    pub retry_delay: f64,
+}
+
+/// 递归传输时对隐藏文件、符号链接和挂载点的处理
+#[derive(Args)]
+pub struct WalkSettings {
+    #[arg(long, overrides_with = "exclude_hidden", help = "Include files and directories whose names start with '.' (the default)")]
+    pub include_hidden: bool,
+    #[arg(long, overrides_with = "include_hidden", help = "Leave out files and directories whose names start with '.'")]
+    pub exclude_hidden: bool,
+    #[arg(long, conflicts_with = "preserve_symlinks", help = "Copy what every symlink points to, including linked directories")]
+    pub follow_symlinks: bool,
+    #[arg(long, help = "Recreate symlinks as symlinks instead of copying what they point to")]
+    pub preserve_symlinks: bool,
+    #[arg(long, help = "Don't descend into directories on other filesystems")]
+    pub one_file_system: bool,
+}
+
+impl WalkSettings {
+    pub fn options(&self) -> WalkOptions {
+        let symlinks = if self.follow_symlinks {
+            SymlinkPolicy::Follow
+        } else if self.preserve_symlinks {
+            SymlinkPolicy::Preserve
+        } else {
+            SymlinkPolicy::FilesOnly
+        };
+        WalkOptions { hidden: !self.exclude_hidden, symlinks, one_file_system: self.one_file_system }
+    }
+}
+
+/// 握手算法偏好
+#[derive(Args)]
+pub struct CryptoSettings {
+    #[arg(long, help = "Comma-separated key exchange algorithms, in order of preference")]
+    pub kex: Option<String>,
+    #[arg(long, help = "Comma-separated ciphers, in order of preference")]
+    pub ciphers: Option<String>,
+    #[arg(long, help = "Comma-separated MAC algorithms, in order of preference")]
+    pub macs: Option<String>,
+    #[arg(long, help = "Comma-separated host key algorithms, in order of preference")]
+    pub host_key_algorithms: Option<String>,
+    #[arg(long, help = "Also allow old algorithms (SHA-1 key exchange, CBC ciphers, ssh-rsa/ssh-dss) for legacy devices")]
+    pub insecure_legacy: bool,
 }
@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// 把文本放到本地剪贴板，返回使用的方式
+/// 优先用系统自带的剪贴板命令，都没有时用 OSC 52 交给本地终端处理
+pub fn copy(text: &str) -> Result<&'static str> {
+    for (program, args) in clipboard_commands() {
+        if let Ok(()) = pipe_to(program, args, text) {
+            return Ok(program);
+        }
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()?;
+    Ok("OSC 52")
+}
+
+/// 读取本地剪贴板的文本
+pub fn paste() -> Result<String> {
+    let commands: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbpaste", &[])]
+    } else if cfg!(windows) {
+        &[("powershell", &["-NoProfile", "-Command", "Get-Clipboard"])]
+    } else {
+        &[
+            ("wl-paste", &["--no-newline"]),
+            ("xclip", &["-selection", "clipboard", "-o"]),
+            ("xsel", &["--clipboard", "--output"]),
+        ]
+    };
+    for (program, args) in commands {
+        if let Ok(output) = Command::new(program).args(*args).stderr(Stdio::null()).output()
+            && output.status.success()
+        {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+    }
+    Err(anyhow!("No clipboard tool found (pbpaste, wl-paste, xclip or xsel)"))
+}
+
+fn clipboard_commands() -> Vec<(&'static str, &'static [&'static str])> {
+    let mut commands: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    if cfg!(target_os = "macos") {
+        commands.push(("pbcopy", &[]));
+    } else if cfg!(windows) {
+        commands.push(("clip", &[]));
+    } else {
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            commands.push(("wl-copy", &[]));
+        }
+        if env::var_os("DISPLAY").is_some() {
+            commands.push(("xclip", &["-selection", "clipboard"]));
+            commands.push(("xsel", &["--clipboard", "--input"]));
+        }
+    }
+    commands
+}
+
+fn pipe_to(program: &str, args: &[&str], text: &str) -> Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context(format!("Failed to run {}", program))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use crate::config::Config;
+use crate::error;
+use crate::output;
+use crate::prompt;
+use crate::ssh::shell_quote;
+use anyhow::{anyhow, Context, Result};
+use inquire::Text;
+use serde_json::json;
+use std::collections::HashMap;
+
+// 给 --alias 时操作别名自己的命令，否则操作全局命令
+fn scope<'a>(config: &'a mut Config, alias: Option<&str>) -> Result<&'a mut HashMap<String, String>> {
+    match alias {
+        Some(alias) => Ok(&mut config
+            .connections
+            .get_mut(alias)
+            .ok_or_else(|| error::alias_not_found(alias))?
+            .commands),
+        None => Ok(&mut config.commands),
+    }
+}
+
+fn scope_name(alias: Option<&str>) -> String {
+    match alias {
+        Some(alias) => format!("'{}'", alias),
+        None => "all hosts".to_string(),
+    }
+}
+
+pub fn handle_add(config: &mut Config, name: &str, command: &str, alias: Option<&str>) -> Result<()> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(anyhow!("Command names can't be empty or contain spaces."));
+    }
+    let replaced = scope(config, alias)?.insert(name.to_string(), command.to_string()).is_some();
+    config.save()?;
+    let verb = if replaced { "Updated" } else { "Saved" };
+    output::status(format!("{} command '{}' for {}.", verb, name, scope_name(alias)));
+    output::event("command_saved", json!({ "name": name, "command": command, "alias": alias, "replaced": replaced }));
+    Ok(())
+}
+
+/// 在提示里预先填好原来的命令，修改后保存
+pub fn handle_edit(config: &mut Config, name: &str, alias: Option<&str>) -> Result<()> {
+    let current = scope(config, alias)?
+        .get(name)
+        .cloned()
+        .context(format!("No saved command '{}' for {}.", name, scope_name(alias)))?;
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("'rssh cmd edit' needs a terminal; use 'rssh cmd add' to replace the command."));
+    }
+    let command = Text::new(&format!("Command for '{}':", name))
+        .with_initial_value(&current)
+        .prompt()?;
+    if command.trim().is_empty() {
+        return Err(anyhow!("The command can't be empty; use 'rssh cmd remove' to delete it."));
+    }
+    handle_add(config, name, &command, alias)
+}
+
+pub fn handle_remove(config: &mut Config, name: &str, alias: Option<&str>) -> Result<()> {
+    if scope(config, alias)?.remove(name).is_none() {
+        return Err(anyhow!("No saved command '{}' for {}.", name, scope_name(alias)));
+    }
+    config.save()?;
+    output::status(format!("Removed command '{}' for {}.", name, scope_name(alias)));
+    output::event("command_removed", json!({ "name": name, "alias": alias }));
+    Ok(())
+}
+
+/// 列出全局命令和各别名的命令；给出别名时只列出这台主机可以用的
+pub fn handle_list(config: &Config, alias: Option<&str>) -> Result<()> {
+    let mut scopes: Vec<(Option<&str>, &HashMap<String, String>)> = Vec::new();
+    match alias {
+        Some(alias) => {
+            let conn = config.connections.get(alias).ok_or_else(|| error::alias_not_found(alias))?;
+            scopes.push((Some(alias), &conn.commands));
+        }
+        None => {
+            let mut aliases: Vec<_> = config.connections.iter().filter(|(_, conn)| !conn.commands.is_empty()).collect();
+            aliases.sort_by_key(|(alias, _)| *alias);
+            scopes.extend(aliases.into_iter().map(|(alias, conn)| (Some(alias.as_str()), &conn.commands)));
+        }
+    }
+    scopes.insert(0, (None, &config.commands));
+
+    if output::is_json() {
+        let entries: Vec<_> = scopes
+            .iter()
+            .flat_map(|(alias, commands)| {
+                commands
+                    .iter()
+                    .map(move |(name, command)| json!({ "name": name, "command": command, "alias": alias }))
+            })
+            .collect();
+        output::emit("commands", json!({ "commands": entries }));
+        return Ok(());
+    }
+    if scopes.iter().all(|(_, commands)| commands.is_empty()) {
+        println!("No saved commands. Use 'rssh cmd add <name> <command>' to add one.");
+        return Ok(());
+    }
+    for (alias, commands) in scopes.iter().filter(|(_, commands)| !commands.is_empty()) {
+        println!("{}:", match alias {
+            Some(alias) => format!("Only on '{}'", alias),
+            None => "All hosts".to_string(),
+        });
+        let mut names: Vec<_> = commands.iter().collect();
+        names.sort();
+        for (name, command) in names {
+            println!("  {:<20} {}", name, command);
+        }
+    }
+    Ok(())
+}
+
+/// 别名自己的命令优先于同名的全局命令；额外的参数加引号后接在后面
+pub fn resolve(config: &Config, alias: &str, name: &str, args: &[String]) -> Result<String> {
+    let conn = config.connections.get(alias).ok_or_else(|| error::alias_not_found(alias))?;
+    let command = conn
+        .commands
+        .get(name)
+        .or_else(|| config.commands.get(name))
+        .with_context(|| format!("No saved command '{}' for '{}'. See 'rssh cmd list {}'.", name, alias, alias))
+        .map_err(error::config)?;
+    let mut command = command.clone();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&shell_quote(arg));
+    }
+    Ok(command)
+}
@@ -0,0 +1,222 @@
+use crate::cli::Cli;
+use crate::config::{state_dir, write_atomic, Config};
+use crate::output;
+use crate::ssh;
+use anyhow::{Context, Result};
+use clap::{CommandFactory, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 补全要跟得上打字：连接加列目录最多等这么久，结果缓存一小段时间
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(3);
+const CACHE_TTL_SECS: u64 = 30;
+const CACHE_FILE: &str = "completion-cache.json";
+// 第二个位置参数起是远程路径的子命令
+const REMOTE_PATH_COMMANDS: &[&str] = &["download", "ls", "rm", "mkdir", "mv", "stat"];
+// 带值的选项，数位置参数时跳过它后面的值
+const VALUE_OPTIONS: &str = "-p|--port|-i|--identity|-j|--jobs|--retries|--retry-delay|--progress|--buffer-size|--user|--timeout";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// 输出补全脚本；子命令和接受别名的子命令从 clap 的定义里取
+pub fn print_script(shell: Shell) {
+    let command = Cli::command();
+    let visible: Vec<_> = command.get_subcommands().filter(|sub| !sub.is_hide_set()).collect();
+    let commands: Vec<&str> = visible.iter().map(|sub| sub.get_name()).collect();
+    let alias_commands: Vec<&str> = visible
+        .iter()
+        .filter(|sub| {
+            sub.get_positionals()
+                .next()
+                .is_some_and(|arg| matches!(arg.get_id().as_str(), "alias" | "aliases" | "source"))
+        })
+        .map(|sub| sub.get_name())
+        .collect();
+    let (commands, alias_commands, remote_commands) =
+        (commands.join(" "), alias_commands.join(" "), REMOTE_PATH_COMMANDS.join(" "));
+    match shell {
+        Shell::Bash => print!("{}", bash_script(&commands, &alias_commands, &remote_commands)),
+        Shell::Zsh => print!(
+            "#compdef rssh\nautoload -U +X bashcompinit && bashcompinit\n{}",
+            bash_script(&commands, &alias_commands, &remote_commands)
+        ),
+        Shell::Fish => print!("{}", fish_script(&commands, &alias_commands, &remote_commands)),
+    }
+}
+
+fn bash_script(commands: &str, alias_commands: &str, remote_commands: &str) -> String {
+    format!(
+        r#"# rssh completion: source <(rssh completions bash)
+_rssh() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur"))
+        return
+    fi
+    [[ $cur == -* ]] && return
+    local cmd=${{COMP_WORDS[1]}} target= arg=0 i word
+    for ((i = 2; i < COMP_CWORD; i++)); do
+        word=${{COMP_WORDS[i]}}
+        case $word in
+            {value_options}) ((i++)) ;;
+            -*) ;;
+            *) [[ $arg -eq 0 ]] && target=$word; ((arg++)) ;;
+        esac
+    done
+    if [[ $arg -eq 0 && " {alias_commands} " == *" $cmd "* ]]; then
+        COMPREPLY=($(compgen -W "$(rssh __complete aliases 2>/dev/null)" -- "$cur"))
+        return
+    fi
+    if [[ $arg -ge 1 && " {remote_commands} " == *" $cmd "* ]]; then
+        # download 的最后一个参数是本地目录，以 . 或 ~ 开头时按本地路径补全
+        if [[ $cmd == download && $arg -ge 2 && $cur == [.~]* ]]; then
+            return
+        fi
+        local IFS=$'\n'
+        compopt -o nospace 2>/dev/null
+        COMPREPLY=($(rssh __complete remote "$target" "$cur" </dev/null 2>/dev/null))
+    fi
+}}
+complete -o default -F _rssh rssh
+"#,
+        value_options = VALUE_OPTIONS
+    )
+}
+
+fn fish_script(commands: &str, alias_commands: &str, remote_commands: &str) -> String {
+    format!(
+        r#"# rssh completion: rssh completions fish | source
+complete -c rssh -n __fish_use_subcommand -f -a "{commands}"
+complete -c rssh -n "__fish_seen_subcommand_from {alias_commands}; and test (count (commandline -opc)) -eq 2" -f -a "(rssh __complete aliases 2>/dev/null)"
+complete -c rssh -n "__fish_seen_subcommand_from {remote_commands}; and test (count (commandline -opc)) -ge 3" -a "(rssh __complete remote (commandline -opc)[3] (commandline -ct) </dev/null 2>/dev/null)"
+"#
+    )
+}
+
+pub fn complete_aliases(config: &Config) {
+    for alias in config.connections.keys() {
+        println!("{}", alias);
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Cache {
+    // 别名 -> 目录 -> 列表
+    #[serde(default)]
+    listings: BTreeMap<String, BTreeMap<String, Listing>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Listing {
+    time: u64,
+    entries: Vec<(String, bool)>,
+}
+
+/// 列出 prefix 所在的远程目录，输出以 prefix 开头的路径，目录带 '/'
+/// 连接不能弹出提示，也不执行钩子；超时或失败时什么都不输出
+pub fn complete_remote(config: Config, alias: &str, prefix: &str) -> Result<()> {
+    output::use_stderr();
+    let (dir, partial) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+    let entries = match cached(alias, dir) {
+        Some(entries) => entries,
+        None => {
+            let entries = list_with_timeout(config, alias, remote_dir(dir))?;
+            store(alias, dir, &entries);
+            entries
+        }
+    };
+    for (name, is_dir) in entries {
+        // 和 shell 一样，没输入 '.' 时不列隐藏文件
+        if !name.starts_with(partial) || (name.starts_with('.') && !partial.starts_with('.')) {
+            continue;
+        }
+        println!("{}{}{}", dir, name, if is_dir { "/" } else { "" });
+    }
+    Ok(())
+}
+
+// SFTP 的相对路径从家目录算起，"~/" 去掉即可
+fn remote_dir(dir: &str) -> PathBuf {
+    match dir {
+        "" | "~/" => PathBuf::from("."),
+        _ => PathBuf::from(dir.strip_prefix("~/").unwrap_or(dir)),
+    }
+}
+
+fn list_with_timeout(config: Config, alias: &str, dir: PathBuf) -> Result<Vec<(String, bool)>> {
+    let (sender, receiver) = mpsc::channel();
+    let alias = alias.to_string();
+    // 超时后直接返回，连接线程随进程结束
+    thread::spawn(move || {
+        let result = ssh::create_session(&config, &alias, None, None).and_then(|sess| {
+            let sftp = sess.sftp().context("Failed to create SFTP session")?;
+            let mut entries: Vec<(String, bool)> = sftp
+                .readdir(&dir)
+                .context(format!("Failed to list {:?}", dir))?
+                .into_iter()
+                .filter_map(|(path, stat)| Some((path.file_name()?.to_string_lossy().into_owned(), stat.is_dir())))
+                .collect();
+            entries.sort();
+            Ok(entries)
+        });
+        let _ = sender.send(result);
+    });
+    receiver
+        .recv_timeout(COMPLETION_TIMEOUT)
+        .context("Timed out listing the remote directory")?
+}
+
+fn cache_path() -> Option<PathBuf> {
+    state_dir().ok().map(|dir| dir.join(CACHE_FILE))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn load_cache() -> Cache {
+    cache_path()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn cached(alias: &str, dir: &str) -> Option<Vec<(String, bool)>> {
+    let listing = load_cache().listings.get(alias)?.get(dir)?.clone();
+    (now().saturating_sub(listing.time) < CACHE_TTL_SECS).then_some(listing.entries)
+}
+
+// 写入时顺便清掉过期的目录；缓存写不进去不影响补全
+fn store(alias: &str, dir: &str, entries: &[(String, bool)]) {
+    let Some(path) = cache_path() else { return };
+    let mut cache = load_cache();
+    let now = now();
+    for listings in cache.listings.values_mut() {
+        listings.retain(|_, listing| now.saturating_sub(listing.time) < CACHE_TTL_SECS);
+    }
+    cache.listings.retain(|_, listings| !listings.is_empty());
+    cache
+        .listings
+        .entry(alias.to_string())
+        .or_default()
+        .insert(dir.to_string(), Listing { time: now, entries: entries.to_vec() });
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(content) = serde_json::to_vec(&cache) {
+        let _ = write_atomic(&path, &content);
+    }
+}
@@ -1,38 +1,951 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
-use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use crate::auth::AuthMethod;
+use crate::backup;
+use crate::banner::BannerColor;
+use crate::error;
+use crate::guard::ConfirmMode;
+use crate::output;
+use crate::perms;
+use serde_json::{json, Value};
+use crate::osc52::Osc52Mode;
+use crate::term::SignalMode;
+use clap::ValueEnum;
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct Config {
-    // 使用 HashMap 存储: alias -> user@host
-    pub connections: HashMap<String, String>,
+    // 使用 HashMap 存储: alias -> 连接信息
+    #[serde(default, deserialize_with = "deserialize_connections")]
+    pub connections: HashMap<String, Connection>,
+    // 可复用的连接模板: name -> 设置
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub templates: HashMap<String, Template>,
+    // 审计日志路径，默认在配置目录下
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+    // 所有主机都可以用的常用命令: name -> command
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub commands: HashMap<String, String>,
+    // 带这些标签的主机在连接、上传和执行命令前需要输入别名确认
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confirm_tags: Vec<String>,
+    // 按顺序执行的上传 / 命令 / 下载步骤: name -> recipe，手动编辑
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub recipes: HashMap<String, Recipe>,
+    // 不带别名运行 rssh 或 rssh connect 时直接连接的主机
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_alias: Option<String>,
+    // exec 命令历史每个别名保留的条数，0 表示不记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_size: Option<usize>,
+    // 所有连接共用的设置，连接自己设置了的项不受影响
+    #[serde(default, skip_serializing_if = "Defaults::is_empty")]
+    pub defaults: Defaults,
+    // 额外读取连接的文件（如生成的或团队共享的主机清单），按顺序叠加，后面的覆盖前面的和本文件里的同名连接
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+    // 来自 include 文件的连接，只读，保存时不写回本文件
+    #[serde(skip)]
+    included: HashMap<String, Included>,
+    // 加载时磁盘上的内容，保存时用来合并其他进程的修改
+    #[serde(skip)]
+    loaded: Mutex<Option<Value>>,
+    // load_for_update 拿到的配置锁，一直持有到本进程退出
+    #[serde(skip)]
+    lock: Option<File>,
+}
+
+/// include 文件里的一个连接：来源、加载时的内容和被它覆盖的本地连接
+struct Included {
+    source: PathBuf,
+    original: Value,
+    shadowed: Option<Value>,
+}
+
+/// include 文件只读取其中的连接
+#[derive(Deserialize)]
+struct IncludeFile {
+    #[serde(default, deserialize_with = "deserialize_connections")]
+    connections: HashMap<String, Connection>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Connection {
+    // user@host
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<PathBuf>,
+    // 没有指定 identity 时依次尝试的私钥
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identities: Vec<PathBuf>,
+    // 认证方式的尝试顺序，为空时使用默认顺序
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_methods: Vec<AuthMethod>,
+    // 只向服务器提供 agent 里这把密钥（SHA256 指纹），避免逐个尝试触发 MaxAuthTries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_key_fingerprint: Option<String>,
+    // 智能卡 / 硬件令牌的 PKCS#11 库路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pkcs11_provider: Option<PathBuf>,
+    // 主地址连不上时按顺序尝试的其他地址（host 或 host:port）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_hosts: Vec<String>,
+    // 每个地址的 TCP 连接超时秒数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    // 未固定主机密钥时用 DNS 中的 SSHFP 记录验证
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sshfp: bool,
+    // 握手时的算法偏好，用于只支持旧算法的设备
+    #[serde(default, skip_serializing_if = "CryptoPrefs::is_empty")]
+    pub crypto: CryptoPrefs,
+    // 经由另一个已保存的别名跳转
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jump_host: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // 只在这台主机上用的常用命令，同名时优先于全局命令
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub commands: HashMap<String, String>,
+    // 交互会话空闲超时秒数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u64>,
+    // 交互会话本地保留的回滚行数，用于复制模式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback: Option<usize>,
+    // 是否处理远程程序发出的 OSC 52 剪贴板序列，以及写入剪贴板的大小上限
+    #[serde(default, skip_serializing_if = "Osc52Mode::is_off")]
+    pub osc52: Osc52Mode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc52_max_bytes: Option<u64>,
+    // 交互会话里 Ctrl+C / Ctrl+Z 发给远程程序还是由本地处理
+    #[serde(default, skip_serializing_if = "SignalMode::is_remote")]
+    pub signals: SignalMode,
+    // 请求 PTY 时的 TERM，部分设备不认识 xterm-256color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    // 空闲时每隔多少秒发送一次 keepalive，防止 NAT / 防火墙断开连接；0 表示关闭
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<u32>,
+    // 会话的 LANG / LC_ALL，需要服务器的 AcceptEnv 允许
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lc_all: Option<String>,
+    // 交互会话开始前显示的横幅，提醒这是哪台机器（如生产环境）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner_color: Option<BannerColor>,
+    // 把横幅文字也加到远程提示符前面
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prompt_banner: bool,
+    // 进入 shell 前显示服务器概况
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub show_info: bool,
+    // 连接和 exec 时不打印 rssh 的状态信息，交互会话不显示远程的 MOTD 和上次登录信息
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quiet_login: bool,
+    // 连接、上传和执行命令前要求确认，防止误操作生产环境
+    #[serde(default, skip_serializing_if = "ConfirmMode::is_off")]
+    pub confirm_connect: ConfirmMode,
+    // 使用保存的密码前需要 Touch ID 确认（仅 macOS）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_biometrics: bool,
+    // 为 false 时输入的密码从不保存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_credentials: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_connect: Option<Hook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_disconnect: Option<Hook>,
+    // Wake-on-LAN 的 MAC 地址和广播地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wol_mac: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wol_broadcast: Option<String>,
+}
+
+impl Connection {
+    pub fn new(target: String) -> Self {
+        Connection {
+            target,
+            ..Default::default()
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout.filter(|&secs| secs > 0).map(Duration::from_secs)
+    }
+
+    /// 请求 PTY 前要设置的环境变量
+    pub fn locale_env(&self) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+        if let Some(lang) = &self.lang {
+            env.push(("LANG".to_string(), lang.clone()));
+        }
+        if let Some(lc_all) = &self.lc_all {
+            env.push(("LC_ALL".to_string(), lc_all.clone()));
+        }
+        env
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout.filter(|&secs| secs > 0).map(Duration::from_secs)
+    }
+
+    pub fn keepalive(&self) -> Option<u32> {
+        self.keepalive.filter(|&secs| secs > 0)
+    }
+
+    /// 用模板中的设置填充；模板没有设置的项保持连接原有的值
+    pub fn apply_template(&mut self, template: &Template) {
+        if template.port.is_some() {
+            self.port = template.port;
+        }
+        if template.identity.is_some() {
+            self.identity = template.identity.clone();
+        }
+        if template.jump_host.is_some() {
+            self.jump_host = template.jump_host.clone();
+        }
+        if !template.tags.is_empty() {
+            self.tags = template.tags.clone();
+        }
+    }
+}
+
+/// 新连接可以继承的通用设置
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Template {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jump_host: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// 配置文件里的 defaults：连接没有设置某一项时使用，设置了的以连接为准
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Defaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    // 连接既没有 identity 也没有 identities 时使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<PathBuf>,
+    // 没有标签的连接使用这些标签
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+}
+
+impl Defaults {
+    fn is_empty(&self) -> bool {
+        self.port.is_none()
+            && self.identity.is_none()
+            && self.tags.is_empty()
+            && self.keepalive.is_none()
+            && self.term.is_none()
+    }
+
+    /// 用默认值填充连接没有设置的项
+    pub fn apply(&self, conn: &Connection) -> Connection {
+        let mut conn = conn.clone();
+        conn.port = conn.port.or(self.port);
+        if conn.identity.is_none() && conn.identities.is_empty() {
+            conn.identity = self.identity.clone();
+        }
+        if conn.tags.is_empty() {
+            conn.tags = self.tags.clone();
+        }
+        conn.keepalive = conn.keepalive.or(self.keepalive);
+        conn.term = conn.term.or_else(|| self.term.clone());
+        conn
+    }
+}
+
+/// 在同一个连接上按顺序执行的步骤；字符串里的 {{name}} 换成变量的值
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Recipe {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // 变量的默认值，运行时可以用 --var 覆盖
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    pub steps: Vec<RecipeStep>,
+}
+
+/// {"exec": "..."}、{"upload": {"from": ..., "to": ...}} 或 {"download": {...}}
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipeStep {
+    Exec(String),
+    /// 本地文件或目录上传到远程目录
+    Upload {
+        from: String,
+        to: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        recursive: bool,
+    },
+    /// 远程文件或目录下载到本地目录
+    Download {
+        from: String,
+        to: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        recursive: bool,
+    },
+}
+
+/// 逗号分隔的算法列表，按优先级排列，未设置的使用 libssh2 默认值
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CryptoPrefs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphers: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub macs: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key_algorithms: Option<String>,
+}
+
+impl CryptoPrefs {
+    pub fn is_empty(&self) -> bool {
+        self.kex.is_none() && self.ciphers.is_none() && self.macs.is_none() && self.host_key_algorithms.is_none()
+    }
+
+    /// 在现代算法之后追加 SHA-1 / CBC 等旧算法，只应用于确实需要的老设备
+    pub fn insecure_legacy() -> Self {
+        CryptoPrefs {
+            kex: Some(
+                "curve25519-sha256,ecdh-sha2-nistp256,diffie-hellman-group14-sha256,\
+                 diffie-hellman-group-exchange-sha256,diffie-hellman-group14-sha1,\
+                 diffie-hellman-group-exchange-sha1,diffie-hellman-group1-sha1"
+                    .to_string(),
+            ),
+            ciphers: Some("aes128-ctr,aes256-ctr,aes128-cbc,aes256-cbc,3des-cbc".to_string()),
+            macs: Some("hmac-sha2-256,hmac-sha2-512,hmac-sha1".to_string()),
+            host_key_algorithms: Some("ssh-ed25519,ecdsa-sha2-nistp256,rsa-sha2-256,rsa-sha2-512,ssh-rsa,ssh-dss".to_string()),
+        }
+    }
+}
+
+/// 连接前后在本地执行的 shell 命令
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Hook {
+    pub command: String,
+    // 超时秒数，默认 30 秒
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub on_failure: HookFailure,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Stop with an error
+    #[default]
+    Abort,
+    /// Print a warning and continue
+    Warn,
+    /// Continue silently
+    Ignore,
+}
+
+// 兼容旧版配置：值可以直接是 "user@host" 字符串
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConnectionEntry {
+    Target(String),
+    Full(Box<Connection>),
+}
+
+fn deserialize_connections<'de, D>(deserializer: D) -> Result<HashMap<String, Connection>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = HashMap::<String, ConnectionEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|(alias, entry)| {
+            let mut conn = match entry {
+                ConnectionEntry::Target(target) => Connection::new(target),
+                ConnectionEntry::Full(conn) => *conn,
+            };
+            // 手改的配置可能把端口写在 target 里
+            if let Ok((target, Some(port))) = split_port(&conn.target) {
+                conn.target = target;
+                conn.port.get_or_insert(port);
+            }
+            (alias, conn)
+        })
+        .collect())
+}
+
+pub const DEFAULT_PORT: u16 = 22;
+pub const DEFAULT_ALIAS_ENV: &str = "RSSH_DEFAULT_ALIAS";
+
+/// 拆出连接字符串末尾的端口："user@host:2222"、"user@[::1]:2222"
+/// 没加方括号的 IPv6 地址不当作带端口
+pub fn split_port(target: &str) -> Result<(String, Option<u16>)> {
+    let (user, host) = match target.split_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, target),
+    };
+    let (host, port) = if let Some(rest) = host.strip_prefix('[') {
+        let (inner, after) = rest
+            .split_once(']')
+            .context(format!("Invalid address '{}': missing ']'", target))?;
+        match after {
+            "" => (inner, None),
+            _ => match after.strip_prefix(':') {
+                Some(port) => (inner, Some(port)),
+                None => return Err(anyhow!("Invalid address '{}'", target)),
+            },
+        }
+    } else {
+        match host.split_once(':') {
+            Some((name, port)) if !port.contains(':') => (name, Some(port)),
+            _ => (host, None),
+        }
+    };
+    let port = port
+        .map(|port| port.parse::<u16>().context(format!("Invalid port in '{}'", target)))
+        .transpose()?;
+    // IPv6 地址去掉方括号保存，连接时直接按 (host, port) 解析
+    let target = match user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    };
+    Ok((target, port))
+}
+
+/// split_port 的反向：user@host:port，IPv6 地址加方括号
+pub fn format_endpoint(target: &str, port: u16) -> String {
+    let (user, host) = match target.split_once('@') {
+        Some((user, host)) => (format!("{}@", user), host),
+        None => (String::new(), target),
+    };
+    if host.contains(':') {
+        format!("{}[{}]:{}", user, host, port)
+    } else {
+        format!("{}{}:{}", user, host, port)
+    }
 }
 
 impl Config {
+    /// 命令行显式指定的端口优先，其次是连接里保存的端口，再次是 defaults
+    pub fn effective_port(&self, alias: &str, port: Option<u16>) -> u16 {
+        port.or_else(|| self.connections.get(alias).and_then(|c| c.port))
+            .or(self.defaults.port)
+            .unwrap_or(DEFAULT_PORT)
+    }
+
+    /// 填充了 defaults 的连接设置；连接时和显示设置时用它，保存时仍然写连接自己的设置
+    pub fn resolved(&self, alias: &str) -> Option<Connection> {
+        self.connections.get(alias).map(|conn| self.defaults.apply(conn))
+    }
+
+    /// 连接的标签，没有时是 defaults 里的标签
+    pub fn effective_tags<'a>(&'a self, conn: &'a Connection) -> &'a [String] {
+        match conn.tags.is_empty() {
+            true => &self.defaults.tags,
+            false => &conn.tags,
+        }
+    }
+
+    /// 不带别名时连接的主机：环境变量 RSSH_DEFAULT_ALIAS 优先，其次是 default_alias
+    pub fn effective_default_alias(&self) -> Option<String> {
+        std::env::var(DEFAULT_ALIAS_ENV)
+            .ok()
+            .filter(|alias| !alias.is_empty())
+            .or_else(|| self.default_alias.clone())
+    }
+
+    /// 从最外层跳板机到目标的完整别名链，检测循环和不存在的别名
+    pub fn jump_chain(&self, alias: &str) -> Result<Vec<String>> {
+        let mut chain = vec![alias.to_string()];
+        let mut current = alias;
+        while let Some(jump) = self
+            .connections
+            .get(current)
+            .ok_or_else(|| error::alias_not_found(current))?
+            .jump_host
+            .as_deref()
+        {
+            if chain.iter().any(|seen| seen == jump) {
+                chain.reverse();
+                return Err(error::config(anyhow!(
+                    "Jump hosts form a loop: {} -> {}",
+                    jump,
+                    chain.join(" -> ")
+                )));
+            }
+            chain.push(jump.to_string());
+            current = jump;
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
     pub fn load() -> Result<Self> {
+        Self::read().map_err(error::config)
+    }
+
+    /// 要修改配置的命令用这个加载：先拿配置锁再读取，锁一直持有到保存之后，
+    /// 其他 rssh 进程的修改要等这次读-改-写完成
+    pub fn load_for_update() -> Result<Self> {
+        let lock = Self::lock().map_err(error::config)?;
+        let mut config = Self::load()?;
+        config.lock = Some(lock);
+        Ok(config)
+    }
+
+    fn lock() -> Result<File> {
+        migrate_legacy_dir()?;
+        let dir = config_dir()?;
+        perms::create_private_dir(&dir).context("Failed to create config directory")?;
+        lock_config(&dir)
+    }
+
+    fn read() -> Result<Self> {
+        migrate_legacy_dir()?;
         let path = config_path()?;
-        if !path.exists() {
+        let Some(value) = read_config_value(&path)? else {
             return Ok(Config::default());
-        }
-        let content = fs::read_to_string(path).context("Failed to read config file")?;
-        let config: Config = serde_json::from_str(&content).context("Failed to parse config file")?;
+        };
+        let mut config: Config = serde_json::from_value(value.clone()).context("Failed to parse config file")?;
+        *config.loaded.lock().unwrap() = Some(value);
+        config.read_includes(&config_dir()?)?;
         Ok(config)
     }
 
+    // 依次读取 include 的文件；找不到的文件只警告，方便同一份配置在没有共享清单的机器上使用。
+    // 和 config.json 一样只支持 JSON；其他扩展名也只警告并跳过，免得 TOML 等文件被当成 JSON 解析，
+    // 而报错会让包括修改配置在内的所有命令都用不了
+    fn read_includes(&mut self, dir: &Path) -> Result<()> {
+        for path in self.include.clone() {
+            let path = include_path(&path, dir);
+            if path.extension().is_none_or(|ext| ext != "json") {
+                eprintln!(
+                    "Warning: skipping included config {}; include files must be JSON like config.json.",
+                    path.display()
+                );
+                continue;
+            }
+            if !path.exists() {
+                eprintln!("Warning: included config {} does not exist.", path.display());
+                continue;
+            }
+            let content = fs::read_to_string(&path).context(format!("Failed to read included config {}", path.display()))?;
+            let file: IncludeFile =
+                serde_json::from_str(&content).context(format!("Failed to parse included config {}", path.display()))?;
+            for (alias, conn) in file.connections {
+                let original = serde_json::to_value(&conn)?;
+                let previous = self.connections.insert(alias.clone(), conn);
+                // 被更早的 include 覆盖过的，保留最初的本地连接
+                let shadowed = match self.included.remove(&alias) {
+                    Some(earlier) => earlier.shadowed,
+                    None => previous.map(|conn| serde_json::to_value(&conn)).transpose()?,
+                };
+                self.included.insert(alias, Included { source: path.clone(), original, shadowed });
+            }
+        }
+        Ok(())
+    }
+
+    /// 连接来自哪个 include 文件；本地连接返回 None
+    pub fn included_from(&self, alias: &str) -> Option<&Path> {
+        self.included.get(alias).map(|included| included.source.as_path())
+    }
+
+    /// 修改或删除连接前调用：include 进来的连接只读
+    pub fn check_editable(&self, alias: &str) -> Result<()> {
+        match self.included_from(alias) {
+            Some(source) => Err(read_only(alias, source)),
+            None => Ok(()),
+        }
+    }
+
+    // 保存前把 include 进来的连接换回被覆盖的本地连接（没有就去掉）；它们被改过时拒绝保存
+    fn strip_included(&self, content: &mut Value) -> Result<()> {
+        let Some(connections) = content.get_mut("connections").and_then(Value::as_object_mut) else {
+            return Ok(());
+        };
+        for (alias, included) in &self.included {
+            if connections.get(alias) != Some(&included.original) {
+                return Err(read_only(alias, &included.source));
+            }
+            match &included.shadowed {
+                Some(local) => connections.insert(alias.clone(), local.clone()),
+                None => connections.remove(alias),
+            };
+        }
+        Ok(())
+    }
+
+    /// 加锁后写入临时文件再原子替换；如果加载之后有其他 rssh 进程改过配置，
+    /// 只把本进程的修改合并到磁盘上的最新内容里，而不是整体覆盖
     pub fn save(&self) -> Result<()> {
+        self.write().map_err(error::config)
+    }
+
+    fn write(&self) -> Result<()> {
         let path = config_path()?;
         let parent = path.parent().unwrap();
-        fs::create_dir_all(parent).context("Failed to create config directory")?;
-        let content = serde_json::to_string_pretty(self).context("Failed to serialize config")?;
-        fs::write(path, content).context("Failed to write config file")?;
+        perms::create_private_dir(parent).context("Failed to create config directory")?;
+        // load_for_update 已经持有锁时不能再锁一次，同一进程里的第二把 flock 会一直等下去
+        let _lock = match self.lock {
+            Some(_) => None,
+            None => Some(lock_config(parent)?),
+        };
+
+        let mut content = serde_json::to_value(self).context("Failed to serialize config")?;
+        self.strip_included(&mut content)?;
+        let base = self.loaded.lock().unwrap().clone().unwrap_or_else(|| json!({}));
+        if let Some(current) = read_config_value(&path)?
+            && current != base
+        {
+            content = merge(&base, current, &content);
+            if output::is_verbose() {
+                eprintln!("Config was changed by another rssh process; merged the changes.");
+            }
+        }
+        let text = serde_json::to_string_pretty(&content).context("Failed to serialize config")?;
+        backup::save_backup(&path, text.as_bytes())?;
+        write_atomic(&path, text.as_bytes()).context("Failed to write config file")?;
+        *self.loaded.lock().unwrap() = Some(content);
         Ok(())
     }
 }
 
+fn read_only(alias: &str, source: &Path) -> anyhow::Error {
+    error::config(anyhow!(
+        "'{}' comes from the included file {}, which rssh does not change; edit that file instead.",
+        alias,
+        source.display()
+    ))
+}
+
+// include 的路径可以以 ~/ 开头，相对路径相对于配置目录
+fn include_path(path: &Path, dir: &Path) -> PathBuf {
+    if let (Ok(rest), Some(home)) = (path.strip_prefix("~"), dirs::home_dir()) {
+        return home.join(rest);
+    }
+    dir.join(path)
+}
+
+fn read_config_value(path: &Path) -> Result<Option<Value>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).context("Failed to read config file")?;
+    let value = serde_json::from_str(&content).context("Failed to parse config file")?;
+    Ok(Some(value))
+}
+
+// 配置目录下的锁文件，保证同一时间只有一个进程在读-合并-写
+pub fn lock_config(dir: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(dir.join("config.lock"))
+        .context("Failed to open config lock file")?;
+    file.lock().context("Failed to lock config file")?;
+    Ok(file)
+}
+
+// 先写同目录下的临时文件，再重命名，读者不会看到写了一半的文件；新文件只有自己可读写
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("config");
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+    let result = (|| {
+        let mut file = perms::private_file().open(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// 三方合并：以其他进程写入的最新内容为准，只应用本进程相对于加载时的修改；
+// 同一项两边都改了时以本进程为准，但对方已经删掉的项不会因为本进程改过而复活
+fn merge(base: &Value, theirs: Value, ours: &Value) -> Value {
+    match (base, theirs, ours) {
+        (Value::Object(base), Value::Object(mut theirs), Value::Object(ours)) => {
+            let keys: BTreeSet<&String> = base.keys().chain(ours.keys()).collect();
+            for key in keys {
+                match (base.get(key), ours.get(key)) {
+                    (Some(b), Some(o)) if b == o => {}
+                    (Some(b), Some(o)) => {
+                        if let Some(t) = theirs.remove(key) {
+                            theirs.insert(key.clone(), merge(b, t, o));
+                        }
+                    }
+                    (Some(_), None) => {
+                        theirs.remove(key);
+                    }
+                    (None, Some(o)) => {
+                        theirs.insert(key.clone(), o.clone());
+                    }
+                    (None, None) => {}
+                }
+            }
+            Value::Object(theirs)
+        }
+        (base, theirs, ours) => {
+            if ours == base {
+                theirs
+            } else {
+                ours.clone()
+            }
+        }
+    }
+}
+
+const APP_DIR: &str = "rssh";
+// 旧版本使用的目录，首次运行时迁移到新位置
+const LEGACY_DIR: &str = ".rss_ssh";
+
+// 辅助函数，获取配置目录：$XDG_CONFIG_HOME/rssh，其他平台用 dirs 给出的对应目录
+pub fn config_dir() -> Result<PathBuf> {
+    let base = xdg_env("XDG_CONFIG_HOME")
+        .or_else(dirs::config_dir)
+        .context("Could not find config directory")?;
+    Ok(base.join(APP_DIR))
+}
+
+// 状态目录（审计日志、配置备份）：$XDG_STATE_HOME/rssh，没有对应目录的平台用本地数据目录
+pub fn state_dir() -> Result<PathBuf> {
+    let base = xdg_env("XDG_STATE_HOME")
+        .or_else(dirs::state_dir)
+        .or_else(dirs::data_local_dir)
+        .context("Could not find state directory")?;
+    Ok(base.join(APP_DIR))
+}
+
+// 按 XDG 规范，相对路径视为未设置
+fn xdg_env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// 把 ~/.rss_ssh 下的文件移到新的配置和状态目录；新位置已有的文件不覆盖
+fn migrate_legacy_dir() -> Result<()> {
+    let Some(legacy) = dirs::home_dir().map(|home| home.join(LEGACY_DIR)) else {
+        return Ok(());
+    };
+    if !legacy.is_dir() {
+        return Ok(());
+    }
+    let config = config_dir()?;
+    let state = state_dir()?;
+    let targets = [
+        ("config.json", &config),
+        ("known_hosts", &config),
+        ("audit.log", &state),
+        ("backups", &state),
+    ];
+
+    let mut moved = Vec::new();
+    let mut kept = Vec::new();
+    for (name, dir) in targets {
+        let from = legacy.join(name);
+        if !from.exists() {
+            continue;
+        }
+        let to = dir.join(name);
+        if to.exists() {
+            kept.push(name);
+            continue;
+        }
+        fs::create_dir_all(dir).context(format!("Failed to create {:?}", dir))?;
+        move_path(&from, &to).context(format!("Failed to move {:?} to {:?}", from, to))?;
+        moved.push(name);
+    }
+    // 锁文件不需要迁移
+    let _ = fs::remove_file(legacy.join("config.lock"));
+    let removed = fs::remove_dir(&legacy).is_ok();
+
+    if !moved.is_empty() && !output::is_json() {
+        eprintln!(
+            "Moved {} from {:?} to {:?}{}.",
+            moved.join(", "),
+            legacy,
+            config,
+            if state != config { format!(" and {:?}", state) } else { String::new() }
+        );
+    }
+    if !removed && (!moved.is_empty() || output::is_verbose()) {
+        let note = if kept.is_empty() {
+            String::new()
+        } else {
+            format!(" ({} already exist in the new location)", kept.join(", "))
+        };
+        eprintln!("Warning: {:?} still contains files{}; rssh no longer reads it.", legacy, note);
+    }
+    Ok(())
+}
+
+// 先尝试重命名，跨文件系统时改为复制后删除
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    }
+}
+
 // 辅助函数，获取配置文件路径
 pub fn config_path() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().context("Could not find home directory")?;
-    Ok(home_dir.join(".rss_ssh/config.json"))
+    Ok(config_dir()?.join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_paths_are_relative_to_the_config_dir() {
+        let dir = Path::new("/etc/rssh");
+        assert_eq!(include_path(Path::new("work.json"), dir), Path::new("/etc/rssh/work.json"));
+        assert_eq!(include_path(Path::new("team/hosts.json"), dir), Path::new("/etc/rssh/team/hosts.json"));
+        assert_eq!(include_path(Path::new("/srv/hosts.json"), dir), Path::new("/srv/hosts.json"));
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(include_path(Path::new("~/team/hosts.json"), dir), home.join("team/hosts.json"));
+        }
+        // 只有单独的 ~ 才表示家目录
+        assert_eq!(include_path(Path::new("~team/hosts.json"), dir), Path::new("/etc/rssh/~team/hosts.json"));
+    }
+
+    #[test]
+    fn read_includes_skips_files_that_are_not_json() {
+        let dir = std::env::temp_dir().join(format!("rssh-test-include-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hosts.toml"), "[connections]\nweb = \"deploy@web\"\n").unwrap();
+        fs::write(dir.join("team.json"), r#"{ "connections": { "db": "root@db" } }"#).unwrap();
+
+        let mut config = Config {
+            include: vec![PathBuf::from("hosts.toml"), PathBuf::from("missing.json"), PathBuf::from("team.json")],
+            ..Default::default()
+        };
+        config.read_includes(&dir).unwrap();
+        let aliases: BTreeSet<&String> = config.connections.keys().collect();
+        assert_eq!(aliases, BTreeSet::from([&"db".to_string()]));
+        assert_eq!(config.included_from("db"), Some(dir.join("team.json").as_path()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn split_port_reads_ports_after_hosts_and_bracketed_ipv6() {
+        let split = |target: &str| split_port(target).unwrap();
+        assert_eq!(split("deploy@web"), ("deploy@web".to_string(), None));
+        assert_eq!(split("deploy@web:2222"), ("deploy@web".to_string(), Some(2222)));
+        assert_eq!(split("web:22"), ("web".to_string(), Some(22)));
+        assert_eq!(split("root@[::1]:2222"), ("root@::1".to_string(), Some(2222)));
+        assert_eq!(split("[fe80::1]"), ("fe80::1".to_string(), None));
+        // 不带方括号的 IPv6 地址里的冒号不是端口
+        assert_eq!(split("root@fe80::1"), ("root@fe80::1".to_string(), None));
+        assert_eq!(split("web:65535"), ("web".to_string(), Some(65535)));
+    }
+
+    #[test]
+    fn split_port_rejects_bad_ports_and_brackets() {
+        for target in ["web:", "web:ssh", "web:65536", "web:-1", "root@[::1", "[::1]2222", "[::1]:"] {
+            assert!(split_port(target).is_err(), "{} should be rejected", target);
+        }
+    }
+
+    #[test]
+    fn format_endpoint_brackets_ipv6_hosts() {
+        assert_eq!(format_endpoint("deploy@web", 22), "deploy@web:22");
+        assert_eq!(format_endpoint("web", 2222), "web:2222");
+        assert_eq!(format_endpoint("root@::1", 2222), "root@[::1]:2222");
+        assert_eq!(format_endpoint("fe80::1", 22), "[fe80::1]:22");
+        for (target, port) in [("deploy@web", 2222), ("root@::1", 22)] {
+            assert_eq!(split_port(&format_endpoint(target, port)).unwrap(), (target.to_string(), Some(port)));
+        }
+    }
+
+    #[test]
+    fn apply_template_keeps_fields_the_template_leaves_unset() {
+        let mut conn = Connection::new("deploy@web".to_string());
+        conn.port = Some(2222);
+        conn.identity = Some(PathBuf::from("~/.ssh/web"));
+        conn.tags = vec!["prod".to_string()];
+        let template = Template {
+            jump_host: Some("bastion".to_string()),
+            ..Default::default()
+        };
+        conn.apply_template(&template);
+        assert_eq!(conn.port, Some(2222));
+        assert_eq!(conn.identity, Some(PathBuf::from("~/.ssh/web")));
+        assert_eq!(conn.jump_host.as_deref(), Some("bastion"));
+        assert_eq!(conn.tags, ["prod"]);
+
+        let template = Template {
+            port: Some(22),
+            tags: vec!["staging".to_string()],
+            ..Default::default()
+        };
+        conn.apply_template(&template);
+        assert_eq!(conn.port, Some(22));
+        assert_eq!(conn.tags, ["staging"]);
+        assert_eq!(conn.jump_host.as_deref(), Some("bastion"));
+    }
+
+    #[test]
+    fn merge_keeps_changes_from_both_sides() {
+        let base = json!({ "connections": { "web": { "target": "deploy@web" }, "db": { "target": "root@db" } } });
+        let theirs = json!({ "connections": { "web": { "target": "deploy@web", "port": 2222 }, "db": { "target": "root@db" } } });
+        let ours = json!({ "connections": { "web": { "target": "deploy@web" }, "db": { "target": "admin@db" } } });
+        assert_eq!(
+            merge(&base, theirs, &ours),
+            json!({ "connections": { "web": { "target": "deploy@web", "port": 2222 }, "db": { "target": "admin@db" } } })
+        );
+    }
+
+    #[test]
+    fn merge_does_not_bring_back_removed_connections() {
+        let base = json!({ "connections": { "web": { "target": "deploy@web" }, "db": { "target": "root@db" } } });
+        let theirs = json!({ "connections": { "db": { "target": "root@db" } } });
+        let ours = json!({ "connections": { "web": { "target": "deploy@web", "port": 2222 }, "db": { "target": "root@db" } } });
+        assert_eq!(merge(&base, theirs, &ours), json!({ "connections": { "db": { "target": "root@db" } } }));
+
+        // 本进程删掉的也不会被对方留下
+        let theirs = json!({ "connections": { "web": { "target": "deploy@web" }, "db": { "target": "root@db" } } });
+        let ours = json!({ "connections": { "db": { "target": "root@db" } } });
+        assert_eq!(merge(&base, theirs, &ours), json!({ "connections": { "db": { "target": "root@db" } } }));
+    }
 }
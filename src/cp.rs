@@ -0,0 +1,119 @@
+use crate::config::Config;
+use crate::progress::TransferStats;
+use anyhow::{anyhow, Context, Result};
+use ssh2::Sftp;
+use std::path::{Path, PathBuf};
+
+/// cp 的一端：本地路径，或 <alias>:<path>
+pub enum Location {
+    Local(PathBuf),
+    Remote { alias: String, path: PathBuf },
+}
+
+/// 和 scp 一样按冒号前是否为已保存的别名判断；含 '/' 的前缀和 Windows 上的盘符按本地路径处理
+pub fn parse_location(config: &Config, arg: &str) -> Result<Location> {
+    let Some((prefix, path)) = arg.split_once(':') else {
+        return Ok(Location::Local(PathBuf::from(arg)));
+    };
+    let drive = cfg!(windows) && prefix.len() == 1 && prefix.chars().all(|c| c.is_ascii_alphabetic());
+    if prefix.is_empty() || prefix.contains('/') || prefix.contains('\\') || drive {
+        return Ok(Location::Local(PathBuf::from(arg)));
+    }
+    if !config.connections.contains_key(prefix) {
+        return Err(anyhow!(
+            "'{}' is not a saved alias. For a local path containing ':', write it as ./{}",
+            prefix,
+            arg
+        ));
+    }
+    // "alias:" 和 scp 一样指家目录
+    let path = if path.is_empty() { "." } else { path };
+    Ok(Location::Remote {
+        alias: prefix.to_string(),
+        path: PathBuf::from(path),
+    })
+}
+
+/// 路径的最后一段含 * ? [ 时视为通配符
+pub fn has_glob(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(['*', '?', '[']))
+}
+
+/// 在远程展开最后一段的通配符；不含通配符时原样返回，没有匹配时报错
+pub fn expand_remote(sftp: &Sftp, path: &Path) -> Result<Vec<PathBuf>> {
+    if !has_glob(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let pattern = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut matches: Vec<PathBuf> = sftp
+        .readdir(dir)
+        .context(format!("Failed to list remote directory {:?}", dir))?
+        .into_iter()
+        .filter_map(|(entry, _)| {
+            let name = entry.file_name()?.to_string_lossy().into_owned();
+            // 和 shell 一样，模式不以 '.' 开头时不匹配隐藏文件
+            let hidden = name.starts_with('.') && !pattern.starts_with('.');
+            (!hidden && glob_match(&pattern, &name)).then(|| dir.join(name))
+        })
+        .collect();
+    if matches.is_empty() {
+        return Err(anyhow!("No remote files match {:?}", path));
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// 对每个匹配的路径执行一次传输，合计结果
+pub fn for_each_match<F>(sftp: &Sftp, path: &Path, mut transfer: F) -> Result<TransferStats>
+where
+    F: FnMut(&Path) -> Result<TransferStats>,
+{
+    let mut total = TransferStats::default();
+    for path in expand_remote(sftp, path)? {
+        total.add(transfer(&path)?);
+    }
+    Ok(total)
+}
+
+/// 支持 *、? 和 [abc] / [a-z] / [!a]
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == ']').map(|i| i + 2) else {
+                return name.first() == Some(&'[') && match_from(&pattern[1..], &name[1..]);
+            };
+            let Some(&c) = name.first() else { return false };
+            let (negated, set) = match pattern[1] {
+                '!' | '^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    found |= set[i] <= c && c <= set[i + 2];
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && match_from(&pattern[end + 1..], &name[1..])
+        }
+        Some(&c) => name.first() == Some(&c) && match_from(&pattern[1..], &name[1..]),
+    }
+}
@@ -1,30 +1,159 @@
 use keyring::Entry;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use crate::config::{config_dir, Connection};
+use crate::secrets;
+use crate::vault;
+use std::sync::OnceLock;
 
 const SERVICE_NAME: &str = "rssh";
+// --secret-env 用的值按 secret:<name> 保存
+pub const SECRET_PREFIX: &str = "secret:";
 
-// 保存密码到系统的 keychain
+static NO_STORE: OnceLock<bool> = OnceLock::new();
+
+/// --no-store：本次运行不保存任何密码
+pub fn set_no_store(no_store: bool) {
+    let _ = NO_STORE.set(no_store);
+}
+
+pub fn is_no_store() -> bool {
+    NO_STORE.get().copied().unwrap_or(false)
+}
+
+/// 是否可以询问保存输入的密码：--no-store 或别名的 store_credentials = false 时不询问
+pub fn may_store(conn: Option<&Connection>) -> bool {
+    !is_no_store() && conn.is_none_or(|conn| conn.store_credentials != Some(false))
+}
+
+// 保存密码：创建了密码库时存到密码库，否则存到系统的 keychain
 pub fn set_password(alias: &str, password: &str) -> Result<()> {
-    let entry = Entry::new(SERVICE_NAME, alias)?;
-    entry.set_password(password)
-        .context(format!("Failed to save password for '{}'", alias))?;
-    Ok(())
+    if is_no_store() {
+        return Err(anyhow!("Not saving the password for '{}' because --no-store is set.", alias));
+    }
+    if vault::is_enabled() {
+        return vault::set(alias, password);
+    }
+    keychain_set(alias, password)
 }
 
-// 从系统的 keychain 获取密码
+// 获取密码
 pub fn get_password(alias: &str) -> Result<String> {
-    let entry = Entry::new(SERVICE_NAME, alias)?;
-    entry.get_password()
-        .context(format!("Failed to retrieve password for '{}'. Please run 'connect' command to set it first.", alias))
+    if vault::is_enabled() {
+        return vault::get(alias)?
+            .context(format!("Failed to retrieve password for '{}'. Please run 'connect' command to set it first.", alias));
+    }
+    keychain_get(alias)
 }
 
 // 删除密码
 pub fn delete_password(alias: &str) -> Result<()> {
-    let entry = Entry::new(SERVICE_NAME, alias)?;
-    match entry.delete_password() {
-        Ok(_) => Ok(()),
-        // 如果密码不存在，也视为成功
-        Err(keyring::Error::NoEntry) => Ok(()),
+    if vault::is_enabled() {
+        return vault::delete(alias);
+    }
+    keychain_delete(alias)
+}
+
+/// 提示信息里使用的存储名称
+pub fn store_name() -> &'static str {
+    if vault::is_enabled() { "vault" } else { "keychain" }
+}
+
+pub fn keychain_set(account: &str, password: &str) -> Result<()> {
+    let entry = Entry::new(namespace(), account)?;
+    entry.set_password(password)
+        .context(format!("Failed to save password for '{}'", account))?;
+    secrets::remember(account);
+    Ok(())
+}
+
+pub fn keychain_get(account: &str) -> Result<String> {
+    let entry = Entry::new(namespace(), account)?;
+    let password = match entry.get_password() {
+        Err(keyring::Error::NoEntry) => migrate_legacy(account)?.ok_or(keyring::Error::NoEntry),
+        result => result,
+    };
+    password.context(format!("Failed to retrieve password for '{}'. Please run 'connect' command to set it first.", account))
+}
+
+pub fn keychain_delete(account: &str) -> Result<()> {
+    // 旧命名空间里没迁移过的同名条目也一起删除
+    for service in [namespace(), SERVICE_NAME] {
+        let entry = Entry::new(service, account)?;
+        match entry.delete_password() {
+            Ok(_) => {}
+            // 如果密码不存在，也视为成功
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    secrets::forget(account);
+    Ok(())
+}
+
+/// keychain 中是否有这一项，不读出内容以外的信息
+pub fn keychain_exists(account: &str) -> Result<bool> {
+    let entry = Entry::new(namespace(), account)?;
+    match entry.get_password() {
+        Ok(_) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(migrate_legacy(account)?.is_some()),
         Err(e) => Err(e.into()),
     }
 }
+
+/// 写入、读回再删除一个测试条目，检查系统的 keychain 是否可用
+pub fn keychain_probe() -> Result<()> {
+    let entry = Entry::new(namespace(), "rssh-probe")?;
+    entry.set_password("probe")?;
+    let read = entry.get_password();
+    let _ = entry.delete_password();
+    read?;
+    Ok(())
+}
+
+/// keychain 条目的 service 名称，按配置目录区分：
+/// 不同配置目录（如 XDG_CONFIG_HOME 不同）里的同名别名不会共用密码
+pub fn namespace() -> &'static str {
+    static NAMESPACE: OnceLock<String> = OnceLock::new();
+    NAMESPACE.get_or_init(|| match config_dir() {
+        Ok(dir) => format!("{}:{}", SERVICE_NAME, dir.display()),
+        Err(_) => SERVICE_NAME.to_string(),
+    })
+}
+
+// 旧版本把所有密码都存在 "rssh" 下；第一次用到时移到当前配置的命名空间
+fn migrate_legacy(account: &str) -> Result<Option<String>> {
+    let legacy = Entry::new(SERVICE_NAME, account)?;
+    let password = match legacy.get_password() {
+        Ok(password) => password,
+        Err(keyring::Error::NoEntry) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Entry::new(namespace(), account)?
+        .set_password(&password)
+        .context(format!("Failed to move the password for '{}' to {}", account, namespace()))?;
+    let _ = legacy.delete_password();
+    secrets::remember(account);
+    Ok(Some(password))
+}
+
+// sudo 密码和登录密码分开保存
+pub fn sudo_account(alias: &str) -> String {
+    format!("{}#sudo", alias)
+}
+
+// 和别名的密码分开保存
+pub fn secret_account(name: &str) -> String {
+    format!("{}{}", SECRET_PREFIX, name)
+}
+
+pub fn set_sudo_password(alias: &str, password: &str) -> Result<()> {
+    set_password(&sudo_account(alias), password)
+}
+
+pub fn get_sudo_password(alias: &str) -> Result<String> {
+    get_password(&sudo_account(alias))
+}
+
+pub fn delete_sudo_password(alias: &str) -> Result<()> {
+    delete_password(&sudo_account(alias))
+}
@@ -0,0 +1,348 @@
+use crate::config::Config;
+use crate::error;
+use crate::output;
+use crate::ssh;
+use anyhow::{anyhow, Context, Result};
+use crossterm::style::Stylize;
+use serde_json::json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// 服务器迟迟不发版本号或 KEXINIT 时放弃
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// 版本号之前服务器可以先发几行其他文字
+const MAX_PREAMBLE_LINES: usize = 50;
+// 合法的 KEXINIT 包不会超过这个长度
+const MAX_PACKET_LEN: usize = 35000;
+const SSH_MSG_KEXINIT: u8 = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    /// 已被弃用，还能用但应尽快替换
+    Legacy,
+    /// 有已知的攻击，应该关闭
+    Weak,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Legacy => "legacy",
+            Severity::Weak => "weak",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Category {
+    Kex,
+    HostKey,
+    Cipher,
+    Mac,
+}
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Kex => "kex",
+            Category::HostKey => "host key",
+            Category::Cipher => "cipher",
+            Category::Mac => "mac",
+        }
+    }
+}
+
+// 算法名（* 开头时按后缀、* 结尾时按前缀匹配）、严重程度和原因；先匹配到的生效
+const RULES: &[(&str, Severity, &str)] = &[
+    ("diffie-hellman-group1-sha1", Severity::Weak, "1024-bit group and SHA-1"),
+    ("rsa1024-sha1", Severity::Weak, "1024-bit RSA and SHA-1"),
+    ("diffie-hellman-group14-sha1", Severity::Legacy, "SHA-1 exchange hash"),
+    ("diffie-hellman-group-exchange-sha1", Severity::Legacy, "SHA-1 exchange hash"),
+    ("gss-gex-sha1-*", Severity::Legacy, "SHA-1 exchange hash"),
+    ("gss-group1-sha1-*", Severity::Weak, "1024-bit group and SHA-1"),
+    ("gss-group14-sha1-*", Severity::Legacy, "SHA-1 exchange hash"),
+    ("ssh-dss*", Severity::Weak, "DSA keys are limited to 1024 bits"),
+    ("ssh-rsa-cert-v01@openssh.com", Severity::Legacy, "SHA-1 signatures"),
+    ("ssh-rsa", Severity::Legacy, "SHA-1 signatures"),
+    ("none", Severity::Weak, "no encryption or integrity protection"),
+    ("arcfour*", Severity::Weak, "RC4 is broken"),
+    ("des-cbc*", Severity::Weak, "56-bit DES"),
+    ("3des-cbc", Severity::Weak, "64-bit block size (Sweet32)"),
+    ("blowfish-cbc", Severity::Weak, "64-bit block size (Sweet32)"),
+    ("cast128-cbc", Severity::Weak, "64-bit block size (Sweet32)"),
+    ("*-cbc", Severity::Legacy, "CBC mode allows plaintext recovery attacks"),
+    ("rijndael-cbc@lysator.liu.se", Severity::Legacy, "CBC mode allows plaintext recovery attacks"),
+    ("hmac-md5*", Severity::Weak, "MD5"),
+    ("hmac-sha1-96*", Severity::Weak, "SHA-1 truncated to 96 bits"),
+    ("hmac-sha1*", Severity::Legacy, "SHA-1"),
+    ("hmac-ripemd160*", Severity::Legacy, "RIPEMD-160"),
+    ("umac-64*", Severity::Legacy, "64-bit tag"),
+];
+
+/// 一个算法的评估结果
+struct Finding {
+    category: Category,
+    algorithm: String,
+    severity: Severity,
+    reason: &'static str,
+}
+
+/// 服务器在 KEXINIT 里提供的算法，加密和 MAC 合并了两个方向
+struct Offer {
+    host: String,
+    port: u16,
+    banner: String,
+    kex: Vec<String>,
+    host_keys: Vec<String>,
+    ciphers: Vec<String>,
+    macs: Vec<String>,
+    compression: Vec<String>,
+}
+
+impl Offer {
+    fn findings(&self) -> Vec<Finding> {
+        let lists = [
+            (Category::Kex, &self.kex),
+            (Category::HostKey, &self.host_keys),
+            (Category::Cipher, &self.ciphers),
+            (Category::Mac, &self.macs),
+        ];
+        let mut findings = Vec::new();
+        for (category, algorithms) in lists {
+            for algorithm in algorithms {
+                if let Some((severity, reason)) = classify(algorithm) {
+                    findings.push(Finding { category, algorithm: algorithm.clone(), severity, reason });
+                }
+            }
+        }
+        // SSH-1.99 表示服务器还接受 SSH 协议 1
+        if self.banner.starts_with("SSH-1.") {
+            findings.push(Finding {
+                category: Category::Kex,
+                algorithm: "SSH protocol 1".to_string(),
+                severity: Severity::Weak,
+                reason: "protocol 1 is broken",
+            });
+        }
+        findings
+    }
+}
+
+fn classify(algorithm: &str) -> Option<(Severity, &'static str)> {
+    RULES.iter().find_map(|&(pattern, severity, reason)| {
+        let matched = match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+            (Some(suffix), _) => algorithm.ends_with(suffix),
+            (_, Some(prefix)) => algorithm.starts_with(prefix),
+            _ => algorithm == pattern,
+        };
+        matched.then_some((severity, reason))
+    })
+}
+
+/// audit-crypto：不登录，只读取每台主机握手时提供的算法，列出弱的和已弃用的
+pub fn handle_audit_crypto(config: &Config, aliases: &[String], port: Option<u16>, jobs: usize) -> Result<()> {
+    if !output::is_verbose() {
+        output::quiet();
+    }
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, Result<Offer>)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, aliases.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(alias) = aliases.get(index) else { break };
+                    let result = read_offer(config, alias, port);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+
+    let unreachable = results.iter().filter(|(_, result)| result.is_err()).count();
+    let weak = results
+        .iter()
+        .filter(|(_, result)| {
+            result
+                .as_ref()
+                .is_ok_and(|offer| offer.findings().iter().any(|finding| finding.severity == Severity::Weak))
+        })
+        .count();
+    if output::is_json() {
+        let hosts: Vec<_> = results
+            .iter()
+            .map(|(index, result)| host_json(&aliases[*index], result))
+            .collect();
+        output::emit("crypto_audit", json!({ "hosts": hosts, "unreachable": unreachable, "weak": weak }));
+    } else {
+        print_report(aliases, &results);
+    }
+    match (unreachable, weak) {
+        (0, 0) => Ok(()),
+        (0, weak) => Err(anyhow!("{} of {} hosts offer weak algorithms", weak, aliases.len())),
+        (unreachable, _) => Err(anyhow!("{} of {} hosts could not be checked", unreachable, aliases.len())),
+    }
+}
+
+// 连到别名的 SSH 端口（经跳板机时也一样），交换版本号后读服务器的 KEXINIT
+fn read_offer(config: &Config, alias: &str, port: Option<u16>) -> Result<Offer> {
+    let conn = config.connections.get(alias).ok_or_else(|| error::alias_not_found(alias))?;
+    let host = conn.target.split_once('@').map_or(conn.target.as_str(), |(_, host)| host).to_string();
+    let port = config.effective_port(alias, port);
+    let stream = ssh::connect_raw(config, alias, Some(port))?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let (banner, payload) = kexinit(stream).context("Failed to read the server's algorithm offer")?;
+    let mut lists = parse_kexinit(&payload)?.into_iter();
+    let mut next = || lists.next().unwrap_or_default();
+    let kex = next();
+    let host_keys = next();
+    let ciphers = merge(next(), next());
+    let macs = merge(next(), next());
+    let compression = merge(next(), next());
+    Ok(Offer { host, port, banner, kex, host_keys, ciphers, macs, compression })
+}
+
+fn kexinit(mut stream: TcpStream) -> Result<(String, Vec<u8>)> {
+    stream.write_all(format!("SSH-2.0-rssh_{}\r\n", env!("CARGO_PKG_VERSION")).as_bytes())?;
+    let mut reader = BufReader::new(stream);
+    let mut banner = None;
+    for _ in 0..MAX_PREAMBLE_LINES {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("the server closed the connection"));
+        }
+        if line.starts_with("SSH-") {
+            banner = Some(line.trim_end().to_string());
+            break;
+        }
+    }
+    let banner = banner.ok_or_else(|| anyhow!("this doesn't look like an SSH server"))?;
+
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if !(5..=MAX_PACKET_LEN).contains(&length) {
+        return Err(anyhow!("invalid packet length {}", length));
+    }
+    let mut packet = vec![0; length];
+    reader.read_exact(&mut packet)?;
+    let padding = packet[0] as usize;
+    let payload = packet
+        .get(1..length.saturating_sub(padding))
+        .filter(|payload| payload.first() == Some(&SSH_MSG_KEXINIT))
+        .ok_or_else(|| anyhow!("the server's first packet is not a key exchange offer"))?;
+    Ok((banner, payload.to_vec()))
+}
+
+// 消息号和 16 字节 cookie 之后是 10 个逗号分隔的名字列表
+fn parse_kexinit(payload: &[u8]) -> Result<Vec<Vec<String>>> {
+    let mut rest = payload.get(17..).ok_or_else(|| anyhow!("truncated key exchange offer"))?;
+    let mut lists = Vec::new();
+    for _ in 0..10 {
+        let (length, tail) = rest.split_first_chunk::<4>().ok_or_else(|| anyhow!("truncated key exchange offer"))?;
+        let length = u32::from_be_bytes(*length) as usize;
+        let names = tail.get(..length).ok_or_else(|| anyhow!("truncated key exchange offer"))?;
+        lists.push(
+            String::from_utf8_lossy(names)
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+        );
+        rest = &tail[length..];
+    }
+    Ok(lists)
+}
+
+// 客户端到服务器的列表在前，另一个方向多出来的追加在后面
+fn merge(mut first: Vec<String>, second: Vec<String>) -> Vec<String> {
+    for name in second {
+        if !first.contains(&name) {
+            first.push(name);
+        }
+    }
+    first
+}
+
+fn print_report(aliases: &[String], results: &[(usize, Result<Offer>)]) {
+    let width = aliases.iter().map(|alias| alias.chars().count()).max().unwrap_or(0).max(4);
+    for (index, result) in results {
+        let alias = &aliases[*index];
+        let offer = match result {
+            Ok(offer) => offer,
+            Err(e) => {
+                let message: Vec<String> = format!("{:#}", e).lines().map(|line| line.trim().to_string()).collect();
+                println!("{:<width$}  {}", alias, format!("unreachable: {}", message.join("; ")).red());
+                continue;
+            }
+        };
+        let mut findings = offer.findings();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+        let count = |severity| findings.iter().filter(|finding| finding.severity == severity).count();
+        let summary = match (count(Severity::Weak), count(Severity::Legacy)) {
+            (0, 0) => "ok".green().to_string(),
+            (0, legacy) => format!("{} legacy", legacy).yellow().to_string(),
+            (weak, legacy) => format!("{} weak, {} legacy", weak, legacy).red().bold().to_string(),
+        };
+        println!("{:<width$}  {}:{}  {}  {}", alias, offer.host, offer.port, offer.banner, summary);
+        for finding in &findings {
+            let severity = format!("{:<6}", finding.severity.name());
+            let severity = match finding.severity {
+                Severity::Weak => severity.red().to_string(),
+                Severity::Legacy => severity.yellow().to_string(),
+            };
+            println!(
+                "    {}  {:<8}  {:<40}  {}",
+                severity,
+                finding.category.name(),
+                finding.algorithm,
+                finding.reason
+            );
+        }
+        if output::is_verbose() {
+            println!("    offered kex:          {}", offer.kex.join(","));
+            println!("    offered host keys:    {}", offer.host_keys.join(","));
+            println!("    offered ciphers:      {}", offer.ciphers.join(","));
+            println!("    offered macs:         {}", offer.macs.join(","));
+            println!("    offered compression:  {}", offer.compression.join(","));
+        }
+    }
+}
+
+fn host_json(alias: &str, result: &Result<Offer>) -> serde_json::Value {
+    match result {
+        Ok(offer) => {
+            let findings: Vec<_> = offer
+                .findings()
+                .iter()
+                .map(|finding| {
+                    json!({
+                        "category": finding.category.name(),
+                        "algorithm": finding.algorithm,
+                        "severity": finding.severity.name(),
+                        "reason": finding.reason,
+                    })
+                })
+                .collect();
+            json!({
+                "alias": alias,
+                "host": offer.host,
+                "port": offer.port,
+                "banner": offer.banner,
+                "kex": offer.kex,
+                "host_keys": offer.host_keys,
+                "ciphers": offer.ciphers,
+                "macs": offer.macs,
+                "compression": offer.compression,
+                "findings": findings,
+            })
+        }
+        Err(e) => json!({ "alias": alias, "error": format!("{:#}", e) }),
+    }
+}
@@ -0,0 +1,496 @@
+use crate::interrupt;
+use crate::ssh::shell_quote;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::Path;
+
+// 增量传输（rsync 的做法）：服务器按块给文件算签名，弱校验用 cksum 的 CRC，强校验用 SHA-256；
+// 本地用同样的 CRC 在自己的文件上逐字节滚动，找出对方已有的块，只有其余部分经过网络
+
+/// 两边都小于这个大小时直接整个传，签名省不下多少
+pub const MIN_SIZE: u64 = 1024 * 1024;
+
+// 服务器上每块要起几个进程，块不能太小；文件越大块越大，块数大致不超过 TARGET_BLOCKS
+const MIN_BLOCK: u64 = 64 * 1024;
+const MAX_BLOCK: u64 = 4 * 1024 * 1024;
+const TARGET_BLOCKS: u64 = 2048;
+
+/// 重组后的文件和源文件的 SHA-256 对不上时脚本的退出码
+pub const MISMATCH: i32 = 3;
+
+// 每次从本地文件读这么多
+const READ_CHUNK: usize = 1024 * 1024;
+
+// POSIX cksum 的 CRC-32：高位在前，初值为 0
+const POLY: u32 = 0x04C1_1DB7;
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ POLY } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc_update(crc: u32, byte: u8) -> u32 {
+    (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+}
+
+fn crc_raw(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| crc_update(crc, byte))
+}
+
+// cksum 在数据之后再算上长度（低字节在前，只取需要的字节数），最后取反
+fn cksum_finish(mut crc: u32, mut len: u64) -> u32 {
+    while len > 0 {
+        crc = crc_update(crc, len as u8);
+        len >>= 8;
+    }
+    !crc
+}
+
+// GF(2) 上模 POLY 的乘法
+fn mul_mod(a: u32, b: u32) -> u32 {
+    let mut product = 0u32;
+    for bit in (0..32).rev() {
+        product = if product & 0x8000_0000 != 0 { (product << 1) ^ POLY } else { product << 1 };
+        if a & (1 << bit) != 0 {
+            product ^= b;
+        }
+    }
+    product
+}
+
+// 窗口滑出的字节对 CRC 的贡献：这个字节后面跟着 len 个零字节时的 CRC，
+// 即 crc_update(0, byte) · x^(8·len)
+fn outgoing_table(len: u64) -> [u32; 256] {
+    let mut shift = 1u32;
+    let mut base = 2u32;
+    let mut exponent = 8 * len;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            shift = mul_mod(shift, base);
+        }
+        base = mul_mod(base, base);
+        exponent >>= 1;
+    }
+    let mut table = [0u32; 256];
+    for (byte, entry) in table.iter_mut().enumerate() {
+        *entry = mul_mod(crc_update(0, byte as u8), shift);
+    }
+    table
+}
+
+/// 按文件大小选块大小
+pub fn block_size_for(size: u64) -> u64 {
+    (size / TARGET_BLOCKS).next_power_of_two().clamp(MIN_BLOCK, MAX_BLOCK)
+}
+
+/// 在服务器上给文件分块算签名：每块一行 cksum（CRC 和长度）、一行 sha256sum。
+/// 缺少需要的命令时以 127 退出
+pub fn signature_command(path: &Path, block_size: u64) -> String {
+    format!(
+        "command -v cksum >/dev/null && command -v sha256sum >/dev/null || exit 127\n\
+         f={}; b={}; n=0\n\
+         size=$(wc -c < \"$f\") || exit 1\n\
+         while [ $((n * b)) -lt $((size)) ]; do\n\
+         dd if=\"$f\" bs=$b skip=$n count=1 2>/dev/null | cksum\n\
+         dd if=\"$f\" bs=$b skip=$n count=1 2>/dev/null | sha256sum\n\
+         n=$((n + 1))\n\
+         done\n",
+        shell_quote(&path.to_string_lossy()),
+        block_size
+    )
+}
+
+struct Block {
+    weak: u32,
+    strong: [u8; 32],
+}
+
+/// 服务器上文件的块签名；末尾不满一块的部分不参与匹配
+pub struct Signature {
+    pub block_size: u64,
+    blocks: Vec<Block>,
+}
+
+impl Signature {
+    pub fn parse(output: &str, block_size: u64) -> Result<Signature> {
+        let mut blocks = Vec::new();
+        let mut short = false;
+        let mut lines = output.lines();
+        while let Some(line) = lines.next() {
+            let mut fields = line.split_whitespace();
+            let weak = fields.next().and_then(|f| f.parse::<u32>().ok());
+            let len = fields.next().and_then(|f| f.parse::<u64>().ok());
+            let strong = lines.next().and_then(|l| l.split_whitespace().next()).and_then(parse_sha256);
+            let (Some(weak), Some(len), Some(strong)) = (weak, len, strong) else {
+                return Err(anyhow!("Unexpected block signature from the server: {:?}", line));
+            };
+            if short || len > block_size {
+                return Err(anyhow!("Unexpected block length {} in the signature from the server", len));
+            }
+            match len == block_size {
+                true => blocks.push(Block { weak, strong }),
+                false => short = true,
+            }
+        }
+        Ok(Signature { block_size, blocks })
+    }
+}
+
+fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// 本地文件 offset 处的一块和服务器文件的第 block 块相同
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub offset: u64,
+    pub block: usize,
+}
+
+/// 扫描结果：互不重叠、按偏移排好的匹配，以及整个本地文件的 SHA-256
+pub struct Scan {
+    pub matches: Vec<Match>,
+    pub sha256: String,
+}
+
+impl Scan {
+    pub fn matched_bytes(&self, block_size: u64) -> u64 {
+        self.matches.len() as u64 * block_size
+    }
+}
+
+// 本地文件上的滑动窗口，读入的数据顺便算整个文件的 SHA-256
+struct Window<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    hasher: Sha256,
+}
+
+impl<R: Read> Window<R> {
+    // 保证窗口起点之后至少有 n 字节；文件剩下的不够时返回 false
+    fn available(&mut self, n: usize) -> io::Result<bool> {
+        while self.buf.len() - self.pos < n && !self.eof {
+            interrupt::check()?;
+            if self.pos >= READ_CHUNK {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            let len = self.buf.len();
+            self.buf.resize(len + READ_CHUNK, 0);
+            let read = loop {
+                match self.reader.read(&mut self.buf[len..]) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result,
+                }
+            };
+            let read = read.inspect_err(|_| self.buf.truncate(len))?;
+            self.buf.truncate(len + read);
+            self.hasher.update(&self.buf[len..]);
+            self.eof = read == 0;
+        }
+        Ok(self.buf.len() - self.pos >= n)
+    }
+
+    fn bytes(&self, n: usize) -> &[u8] {
+        &self.buf[self.pos..self.pos + n]
+    }
+
+    fn byte(&self, i: usize) -> u8 {
+        self.buf[self.pos + i]
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.pos += n;
+    }
+
+    fn finish(mut self) -> io::Result<String> {
+        while !self.eof {
+            self.pos = self.buf.len();
+            self.available(1)?;
+        }
+        Ok(self.hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// 在本地文件里找服务器文件的块：弱校验命中后再比 SHA-256，命中就跳过整块，否则前进一个字节
+pub fn find_matches(reader: impl Read, signature: &Signature) -> io::Result<Scan> {
+    let block = signature.block_size as usize;
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    // 大多数位置弱校验都对不上，先查一张位图，少做哈希表查找
+    let mut seen = vec![false; 1 << 16];
+    for (i, b) in signature.blocks.iter().enumerate() {
+        index.entry(b.weak).or_default().push(i);
+        seen[(b.weak & 0xffff) as usize] = true;
+    }
+    let outgoing = outgoing_table(signature.block_size);
+
+    let mut window = Window { reader, buf: Vec::new(), pos: 0, eof: false, hasher: Sha256::new() };
+    let mut matches = Vec::new();
+    let mut offset = 0u64;
+    // 上一次匹配的下一块，内容没有移动时优先选它
+    let mut next = None;
+    let mut crc = match window.available(block)? && !index.is_empty() {
+        true => crc_raw(window.bytes(block)),
+        false => return Ok(Scan { matches, sha256: window.finish()? }),
+    };
+    loop {
+        let weak = cksum_finish(crc, signature.block_size);
+        if seen[(weak & 0xffff) as usize]
+            && let Some(candidates) = index.get(&weak)
+        {
+            let strong: [u8; 32] = Sha256::digest(window.bytes(block)).into();
+            let found = next
+                .filter(|k| candidates.contains(k) && signature.blocks[*k].strong == strong)
+                .or_else(|| candidates.iter().copied().find(|&k| signature.blocks[k].strong == strong));
+            if let Some(k) = found {
+                matches.push(Match { offset, block: k });
+                next = Some(k + 1);
+                window.advance(block);
+                offset += block as u64;
+                if !window.available(block)? {
+                    break;
+                }
+                crc = crc_raw(window.bytes(block));
+                continue;
+            }
+        }
+        if !window.available(block + 1)? {
+            break;
+        }
+        crc = crc_update(crc, window.byte(block)) ^ outgoing[window.byte(0) as usize];
+        window.advance(1);
+        offset += 1;
+    }
+    Ok(Scan { matches, sha256: window.finish()? })
+}
+
+/// 新文件的一段：从服务器上的旧文件复制连续的若干块，或者是一段新数据。
+/// 新数据在暂存文件里从第 slot 块开始放，每段都按块对齐
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Piece {
+    Copy { block: usize, count: usize },
+    Literal { offset: u64, len: u64, slot: u64 },
+}
+
+/// 由匹配结果排出重组新文件的顺序，相邻的块合并成一段
+pub fn plan(matches: &[Match], file_size: u64, block_size: u64) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut slot = 0;
+    let mut literal = |pieces: &mut Vec<Piece>, offset: u64, end: u64| {
+        if end > offset {
+            let len = end - offset;
+            pieces.push(Piece::Literal { offset, len, slot });
+            slot += len.div_ceil(block_size);
+        }
+    };
+    let mut position = 0;
+    for m in matches {
+        literal(&mut pieces, position, m.offset);
+        match pieces.last_mut() {
+            Some(Piece::Copy { block, count }) if position == m.offset && *block + *count == m.block => *count += 1,
+            _ => pieces.push(Piece::Copy { block: m.block, count: 1 }),
+        }
+        position = m.offset + block_size;
+    }
+    literal(&mut pieces, position, file_size);
+    pieces
+}
+
+/// 服务器上按 plan 拼出新文件，校验 SHA-256 后写回目标文件（写回而不是改名，目标的权限和属主不变）
+pub fn assemble_script(target: &Path, staged: &Path, assembled: &Path, pieces: &[Piece], block_size: u64, sha256: &str) -> String {
+    let target = shell_quote(&target.to_string_lossy());
+    let staged = shell_quote(&staged.to_string_lossy());
+    let assembled = shell_quote(&assembled.to_string_lossy());
+    let mut script = format!("staged={staged}; assembled={assembled}\ntrap 'rm -f -- \"$staged\" \"$assembled\"' EXIT\n{{\n");
+    for piece in pieces {
+        script.push_str(&match *piece {
+            Piece::Copy { block, count } => {
+                format!("dd if={target} bs={block_size} skip={block} count={count} 2>/dev/null\n")
+            }
+            Piece::Literal { len, slot, .. } => format!(
+                "dd if=\"$staged\" bs={block_size} skip={slot} count={} 2>/dev/null | head -c {len}\n",
+                len.div_ceil(block_size)
+            ),
+        });
+    }
+    script.push_str(&format!(
+        "}} > \"$assembled\" || exit 1\n\
+         [ \"$(sha256sum < \"$assembled\" | cut -c1-64)\" = {sha256} ] || exit {MISMATCH}\n\
+         cat -- \"$assembled\" > {target}\n"
+    ));
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 和 `cksum` 命令输出的第一列相同
+    fn cksum(data: &[u8]) -> u32 {
+        cksum_finish(crc_raw(data), data.len() as u64)
+    }
+
+    fn data(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn signature_of(data: &[u8], block_size: u64) -> Signature {
+        let output: String = data
+            .chunks(block_size as usize)
+            .map(|chunk| {
+                let strong: String = Sha256::digest(chunk).iter().map(|b| format!("{:02x}", b)).collect();
+                format!("{} {}\n{}  -\n", cksum(chunk), chunk.len(), strong)
+            })
+            .collect();
+        Signature::parse(&output, block_size).unwrap()
+    }
+
+    // 按 plan 在本地模拟服务器上的重组
+    fn assemble(old: &[u8], new: &[u8], pieces: &[Piece], block_size: u64) -> Vec<u8> {
+        let block_size = block_size as usize;
+        let mut out = Vec::new();
+        for piece in pieces {
+            match *piece {
+                Piece::Copy { block, count } => out.extend_from_slice(&old[block * block_size..(block + count) * block_size]),
+                Piece::Literal { offset, len, .. } => out.extend_from_slice(&new[offset as usize..(offset + len) as usize]),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn cksum_matches_posix_cksum() {
+        // 和 `printf ... | cksum` 的输出相同
+        assert_eq!(cksum(b""), 4_294_967_295);
+        assert_eq!(cksum(b"hello"), 3_287_646_509);
+        assert_eq!(cksum(b"The quick brown fox jumps over the lazy dog"), 2_074_844_392);
+        assert_eq!(cksum(&[0u8; 300_000]), 2_913_298_395);
+    }
+
+    #[test]
+    fn rolling_crc_matches_direct_crc() {
+        let bytes = data(4096, 1);
+        let len = 100;
+        let outgoing = outgoing_table(len as u64);
+        let mut crc = crc_raw(&bytes[..len]);
+        for start in 1..bytes.len() - len {
+            crc = crc_update(crc, bytes[start + len - 1]) ^ outgoing[bytes[start - 1] as usize];
+            assert_eq!(crc, crc_raw(&bytes[start..start + len]), "window at {}", start);
+        }
+    }
+
+    #[test]
+    fn block_size_grows_with_the_file() {
+        assert_eq!(block_size_for(0), MIN_BLOCK);
+        assert_eq!(block_size_for(MIN_SIZE), MIN_BLOCK);
+        assert_eq!(block_size_for(1 << 30), 1 << 19);
+        assert_eq!(block_size_for(u64::MAX / 2), MAX_BLOCK);
+    }
+
+    #[test]
+    fn parse_keeps_full_blocks_only() {
+        let sig = signature_of(&data(250, 2), 100);
+        assert_eq!(sig.blocks.len(), 2);
+        assert!(Signature::parse("123 100\nnot-a-hash  -\n", 100).is_err());
+        assert!(Signature::parse("123 100\n", 100).is_err());
+        assert_eq!(Signature::parse("", 100).unwrap().blocks.len(), 0);
+    }
+
+    #[test]
+    fn parse_rejects_a_short_block_in_the_middle() {
+        let hash = "0".repeat(64);
+        let output = format!("1 50\n{hash}  -\n2 100\n{hash}  -\n");
+        assert!(Signature::parse(&output, 100).is_err());
+    }
+
+    #[test]
+    fn finds_blocks_after_an_insertion() {
+        let block = 64;
+        let old = data(64 * 20, 3);
+        let mut new = old.clone();
+        new.splice(300..300, b"inserted text".iter().copied());
+        new.truncate(new.len() - 10);
+
+        let sig = signature_of(&old, block);
+        let scan = find_matches(&new[..], &sig).unwrap();
+        // 插入点所在的块和被截短的最后一块对不上，其余的都能找到
+        assert_eq!(scan.matches.len(), 18);
+        assert_eq!(scan.sha256, Sha256::digest(&new).iter().map(|b| format!("{:02x}", b)).collect::<String>());
+
+        let pieces = plan(&scan.matches, new.len() as u64, block);
+        assert_eq!(assemble(&old, &new, &pieces, block), new);
+        let literal: u64 = pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal { len, .. } => *len,
+                Piece::Copy { .. } => 0,
+            })
+            .sum();
+        assert_eq!(literal, new.len() as u64 - scan.matched_bytes(block));
+    }
+
+    #[test]
+    fn plan_merges_consecutive_blocks_and_aligns_literals() {
+        let matches = [
+            Match { offset: 0, block: 0 },
+            Match { offset: 10, block: 1 },
+            Match { offset: 30, block: 5 },
+            Match { offset: 40, block: 6 },
+        ];
+        let pieces = plan(&matches, 75, 10);
+        assert_eq!(
+            pieces,
+            [
+                Piece::Copy { block: 0, count: 2 },
+                Piece::Literal { offset: 20, len: 10, slot: 0 },
+                Piece::Copy { block: 5, count: 2 },
+                Piece::Literal { offset: 50, len: 25, slot: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_files_have_no_matches() {
+        let sig = signature_of(&data(1000, 4), 100);
+        let scan = find_matches(&data(1000, 5)[..], &sig).unwrap();
+        assert!(scan.matches.is_empty());
+        assert_eq!(plan(&scan.matches, 1000, 100), [Piece::Literal { offset: 0, len: 1000, slot: 0 }]);
+    }
+
+    #[test]
+    fn file_shorter_than_a_block_is_still_hashed() {
+        let sig = signature_of(&data(1000, 6), 100);
+        let scan = find_matches(&b"short"[..], &sig).unwrap();
+        assert!(scan.matches.is_empty());
+        assert_eq!(scan.sha256, Sha256::digest(b"short").iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    }
+}
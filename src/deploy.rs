@@ -0,0 +1,41 @@
+use crate::output;
+use crate::ssh;
+use crate::transfer::{self, SftpSession, TransferOptions};
+use anyhow::Result;
+use ssh2::Session;
+use std::path::PathBuf;
+
+/// --put 的一项：LOCAL:REMOTE_DIR
+#[derive(Clone)]
+pub struct Put {
+    pub local: PathBuf,
+    pub remote: PathBuf,
+}
+
+/// clap 的 value_parser；在第一个冒号处分开，和 <alias>:<path> 的写法一致
+pub fn parse_put(spec: &str) -> Result<Put, String> {
+    match spec.split_once(':') {
+        Some((local, remote)) if !local.is_empty() && !remote.is_empty() => Ok(Put {
+            local: PathBuf::from(local),
+            remote: PathBuf::from(remote),
+        }),
+        _ => Err(format!("expected LOCAL:REMOTE_DIR, got '{}'", spec)),
+    }
+}
+
+/// 在同一个连接上先按顺序上传所有文件，再按顺序执行命令；
+/// 上传失败时不再执行命令，命令失败时不再执行后面的，返回它的退出码
+pub fn handle_deploy(sess: Session, alias: &str, puts: &[Put], commands: &[String], opts: TransferOptions) -> Result<i32> {
+    let conn = SftpSession::new(sess);
+    for put in puts {
+        transfer::handle_upload(&conn, alias, &put.local, &put.remote, opts)?;
+    }
+    for command in commands {
+        output::status(format!("==> {}", command));
+        match ssh::handle_exec(conn.sess.clone(), command, false, &[], None)? {
+            0 => {}
+            code => return Ok(code),
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use ssh2::Session;
+
+pub const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// docker：把服务器上的 Docker 套接字转发到临时的本地套接字，用本机的 docker 命令访问，docker 退出后拆除；
+/// 返回 docker 的退出码
+#[cfg(unix)]
+pub fn handle_docker(sess: Session, alias: &str, socket: &str, args: &[String]) -> Result<i32> {
+    use std::fs;
+
+    // 先打开一次，Docker 没有运行或没有权限时在启动 docker 前就报告
+    let mut probe = sess.channel_direct_streamlocal(socket, None).map_err(|e| {
+        anyhow!(
+            "Could not open {} on '{}': {} (is Docker running there, may your user use the socket, and is AllowStreamLocalForwarding on?)",
+            socket,
+            alias,
+            e
+        )
+    })?;
+    let _ = probe.close();
+
+    let dir = platform::temp_dir()?;
+    let result = platform::run(&sess, alias, socket, &dir, args);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+#[cfg(not(unix))]
+pub fn handle_docker(_sess: Session, _alias: &str, _socket: &str, _args: &[String]) -> Result<i32> {
+    Err(anyhow!("'rssh docker' forwards the Docker socket to a local Unix socket, which this platform does not support."))
+}
+
+#[cfg(unix)]
+mod platform {
+    use crate::interrupt;
+    use crate::output;
+    use crate::proxy::{self, ForwardListener, Target};
+    use crate::tunnel;
+    use anyhow::{anyhow, Context, Result};
+    use ssh2::Session;
+    use ssh_key::rand_core::{OsRng, RngCore};
+    use std::env;
+    use std::fs::DirBuilder;
+    use std::io;
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, ExitStatus};
+
+    // 只有自己能进入的临时目录，套接字放在里面
+    pub fn temp_dir() -> Result<PathBuf> {
+        let dir = env::temp_dir().join(format!("rssh-docker-{:08x}", OsRng.next_u32()));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .context(format!("Failed to create {}", dir.display()))?;
+        Ok(dir)
+    }
+
+    pub fn run(sess: &Session, alias: &str, socket: &str, dir: &Path, args: &[String]) -> Result<i32> {
+        let path = dir.join("docker.sock");
+        let listener = tunnel::bind_socket(&path)?;
+        if output::is_verbose() {
+            eprintln!("Forwarding {} on '{}' to {}", socket, alias, path.display());
+        }
+        let mut child = Command::new("docker")
+            .args(args)
+            .env("DOCKER_HOST", format!("unix://{}", path.display()))
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => anyhow!("The docker command was not found on this machine; install the Docker CLI first."),
+                _ => anyhow!("Failed to start docker: {}", e),
+            })?;
+
+        // Ctrl+C 交给 docker 处理（如结束 logs -f），docker 退出后再拆除转发
+        let _interrupt = interrupt::Guard::install();
+        let mut status = None;
+        let forwards = vec![(ForwardListener::Unix(listener), Target::Socket(socket.to_string()))];
+        let forwarded = proxy::handle_forwards_while(sess, forwards, || match child.try_wait() {
+            Ok(Some(exited)) => {
+                status = Some(exited);
+                false
+            }
+            Ok(None) => true,
+            Err(_) => false,
+        });
+        if let Err(e) = forwarded {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+        let status = match status {
+            Some(status) => status,
+            None => child.wait()?,
+        };
+        Ok(exit_code(status))
+    }
+
+    // 被信号结束时和 shell 一样用 128 + 信号值
+    fn exit_code(status: ExitStatus) -> i32 {
+        status.code().unwrap_or_else(|| status.signal().map_or(1, |signal| 128 + signal))
+    }
+}
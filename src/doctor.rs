@@ -0,0 +1,88 @@
+use crate::config::{config_dir, config_path, Config};
+use crate::hostkey::known_hosts_path;
+use crate::keys;
+use crate::output;
+use crate::perms::{self, PRIVATE_MASK, WRITE_MASK};
+use crate::vault;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::path::PathBuf;
+
+struct Issue {
+    path: PathBuf,
+    mode: u32,
+    mask: u32,
+    fixed: Option<Result<(), String>>,
+}
+
+/// 检查配置目录、配置文件、密码库和私钥的权限；fix 时去掉多余的权限位
+pub fn handle_doctor(config: &Config, fix: bool) -> Result<()> {
+    if !cfg!(unix) {
+        output::status("Permission checks only apply on Unix.");
+        return Ok(());
+    }
+    let mut issues = Vec::new();
+    for (path, mask) in checked_paths(config)? {
+        let Some(mode) = perms::exposed_bits(&path, mask) else {
+            continue;
+        };
+        let fixed = fix.then(|| perms::restrict(&path, mask).map_err(|e| e.to_string()));
+        issues.push(Issue { path, mode, mask, fixed });
+    }
+
+    let remaining = issues.iter().filter(|issue| !matches!(issue.fixed, Some(Ok(())))).count();
+    if output::is_json() {
+        let items: Vec<_> = issues
+            .iter()
+            .map(|issue| {
+                json!({
+                    "path": issue.path,
+                    "mode": format!("{:04o}", issue.mode),
+                    "expected": format!("{:04o}", issue.mode & !issue.mask),
+                    "fixed": matches!(issue.fixed, Some(Ok(()))),
+                    "error": issue.fixed.as_ref().and_then(|fixed| fixed.as_ref().err()),
+                })
+            })
+            .collect();
+        output::emit("doctor", json!({ "permissions": items }));
+    } else if issues.is_empty() {
+        println!("Permissions of the config directory, its files and private keys look fine.");
+    } else {
+        for issue in &issues {
+            let expected = issue.mode & !issue.mask;
+            match &issue.fixed {
+                None => println!("{}: mode {:04o}, should be {:04o}", issue.path.display(), issue.mode, expected),
+                Some(Ok(())) => println!("{}: changed mode {:04o} to {:04o}", issue.path.display(), issue.mode, expected),
+                Some(Err(e)) => println!("{}: could not change mode {:04o}: {}", issue.path.display(), issue.mode, e),
+            }
+        }
+    }
+    match (remaining, fix) {
+        (0, _) => Ok(()),
+        (n, false) => Err(anyhow!("{} path(s) have permissions that are too open. Run 'rssh doctor --fix-perms' to fix them.", n)),
+        (n, true) => Err(anyhow!("Could not fix the permissions of {} path(s).", n)),
+    }
+}
+
+// 要检查的路径和不允许的权限位；不存在的路径在检查时跳过
+fn checked_paths(config: &Config) -> Result<Vec<(PathBuf, u32)>> {
+    let mut paths = vec![
+        (config_dir()?, PRIVATE_MASK),
+        (config_path()?, PRIVATE_MASK),
+        (vault::vault_path()?, PRIVATE_MASK),
+        (known_hosts_path()?, WRITE_MASK),
+    ];
+    let mut keys: Vec<PathBuf> = keys::default_identity_files();
+    for conn in config.connections.values() {
+        keys.extend(conn.identity.iter().cloned());
+        keys.extend(conn.identities.iter().cloned());
+    }
+    for template in config.templates.values() {
+        keys.extend(template.identity.iter().cloned());
+    }
+    keys.extend(config.defaults.identity.iter().cloned());
+    keys.sort();
+    keys.dedup();
+    paths.extend(keys.into_iter().map(|key| (key, PRIVATE_MASK)));
+    Ok(paths)
+}
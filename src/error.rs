@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::fmt;
+
+/// 按失败原因区分的错误，决定进程退出码；其他错误仍用 anyhow，退出码为 1
+#[derive(Debug)]
+pub enum RsshError {
+    /// 配置文件、别名或参数有问题
+    Config(anyhow::Error),
+    /// 认证失败或主机密钥不符
+    Auth(anyhow::Error),
+    /// 连不上服务器或连接中断
+    Network(anyhow::Error),
+    /// 上传、下载或复制失败
+    Transfer(anyhow::Error),
+    /// 需要输入密码、口令或确认，但不能询问（--non-interactive 或没有终端）
+    Input(anyhow::Error),
+    /// 远程命令没有在 --timeout 之内结束
+    Timeout(anyhow::Error),
+    /// 远程命令以非零状态退出
+    RemoteExit(i32),
+}
+
+// 取自 sysexits.h，避开 1（其他错误）和 130（中断）
+pub const EXIT_CONFIG: i32 = 78;
+pub const EXIT_AUTH: i32 = 77;
+pub const EXIT_NETWORK: i32 = 69;
+pub const EXIT_TRANSFER: i32 = 74;
+pub const EXIT_INPUT: i32 = 66;
+// 和 timeout(1) 相同
+pub const EXIT_TIMEOUT: i32 = 124;
+pub const EXIT_OTHER: i32 = 1;
+
+impl RsshError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RsshError::Config(_) => EXIT_CONFIG,
+            RsshError::Auth(_) => EXIT_AUTH,
+            RsshError::Network(_) => EXIT_NETWORK,
+            RsshError::Transfer(_) => EXIT_TRANSFER,
+            RsshError::Input(_) => EXIT_INPUT,
+            RsshError::Timeout(_) => EXIT_TIMEOUT,
+            RsshError::RemoteExit(code) => *code,
+        }
+    }
+
+    /// JSON 错误事件里的 kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RsshError::Config(_) => "config",
+            RsshError::Auth(_) => "auth",
+            RsshError::Network(_) => "network",
+            RsshError::Transfer(_) => "transfer",
+            RsshError::Input(_) => "input",
+            RsshError::Timeout(_) => "timeout",
+            RsshError::RemoteExit(_) => "remote_exit",
+        }
+    }
+
+    fn inner(&self) -> Option<&anyhow::Error> {
+        match self {
+            RsshError::Config(e)
+            | RsshError::Auth(e)
+            | RsshError::Network(e)
+            | RsshError::Transfer(e)
+            | RsshError::Input(e)
+            | RsshError::Timeout(e) => Some(e),
+            RsshError::RemoteExit(_) => None,
+        }
+    }
+}
+
+// 只是给错误加上类别，显示时和原来的错误一样
+impl fmt::Display for RsshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner() {
+            Some(e) => write!(f, "{}", e),
+            None => write!(f, "Remote command exited with status {}", self.exit_code()),
+        }
+    }
+}
+
+impl Error for RsshError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.inner().and_then(|e| e.source())
+    }
+}
+
+/// 错误链里最外层的 RsshError；已经分过类的错误不会被外层覆盖
+pub fn classify(error: &anyhow::Error) -> Option<&RsshError> {
+    error.chain().find_map(|cause| cause.downcast_ref::<RsshError>())
+}
+
+/// 和 anyhow::Error::chain 一样，但会展开 RsshError 包着的原始错误，便于 downcast
+pub fn chain(error: &anyhow::Error) -> Vec<&(dyn Error + 'static)> {
+    let mut causes = Vec::new();
+    for cause in error.chain() {
+        if let Some(inner) = cause.downcast_ref::<RsshError>().and_then(RsshError::inner) {
+            // RsshError 之后的部分就是 inner 的 source，不再重复
+            causes.extend(chain(inner));
+            break;
+        }
+        causes.push(cause);
+    }
+    causes
+}
+
+fn tag(error: anyhow::Error, wrap: fn(anyhow::Error) -> RsshError) -> anyhow::Error {
+    if classify(&error).is_some() {
+        return error;
+    }
+    anyhow::Error::new(wrap(error))
+}
+
+pub fn config(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Config)
+}
+
+pub fn auth(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Auth)
+}
+
+pub fn network(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Network)
+}
+
+pub fn transfer(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Transfer)
+}
+
+pub fn input(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Input)
+}
+
+pub fn timeout(error: anyhow::Error) -> anyhow::Error {
+    tag(error, RsshError::Timeout)
+}
+
+pub fn alias_not_found(alias: &str) -> anyhow::Error {
+    config(anyhow::anyhow!("Alias '{}' not found.", alias))
+}
@@ -0,0 +1,206 @@
+use crate::audit;
+use crate::config::Config;
+use crate::guard;
+use crate::hooks::{self, HookEvent};
+use crate::output;
+use crate::ssh::{self, Captured};
+use anyhow::{anyhow, Context, Result};
+use crossterm::style::Stylize;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 脚本先存到远程临时文件再执行，带 shebang 的 Python 等脚本也能运行；结束后删除
+const RUNNER: &str = r#"f=$(mktemp) && cat > "$f" && chmod 700 "$f" && "$f"; s=$?; rm -f "$f"; exit $s"#;
+
+struct HostResult {
+    result: Result<Captured>,
+    duration: Duration,
+}
+
+impl HostResult {
+    fn status(&self) -> &'static str {
+        match &self.result {
+            Ok(captured) if captured.status == 0 => "pass",
+            Ok(_) => "fail",
+            Err(_) => "unreachable",
+        }
+    }
+}
+
+/// 在每台主机上执行同一个本地脚本，按主机保存输出，最后打印通过 / 失败表
+pub fn handle_fanout(
+    config: &Config,
+    aliases: &[String],
+    script: &Path,
+    collect: Option<&Path>,
+    jobs: usize,
+    port: Option<u16>,
+    identity: Option<&Path>,
+) -> Result<()> {
+    let content = fs::read(script).context(format!("Failed to read script {:?}", script))?;
+    // 受保护的主机在开始前逐个确认
+    for alias in aliases {
+        guard::confirm(config, alias, "run a script on")?;
+    }
+    if let Some(dir) = collect {
+        fs::create_dir_all(dir).context(format!("Failed to create {:?}", dir))?;
+    }
+    if !output::is_verbose() {
+        output::quiet();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, HostResult)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, aliases.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(alias) = aliases.get(index) else { break };
+                    let result = run_host(config, alias, port, identity, script, &content);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut files = Vec::new();
+    for (index, host) in &results {
+        files.push(match collect {
+            Some(dir) => Some(save_output(dir, &aliases[*index], host)?),
+            None => None,
+        });
+    }
+
+    let failed = results.iter().filter(|(_, host)| host.status() != "pass").count();
+    if output::is_json() {
+        let hosts: Vec<_> = results
+            .iter()
+            .zip(&files)
+            .map(|((index, host), files)| host_json(&aliases[*index], host, files.as_ref()))
+            .collect();
+        output::emit("fanout", json!({ "script": script, "hosts": hosts, "failed": failed }));
+    } else {
+        if collect.is_none() {
+            print_outputs(aliases, &results);
+        }
+        print_matrix(aliases, &results);
+        if let Some(dir) = collect {
+            println!("Output saved in {}", dir.display());
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(anyhow!("{} of {} hosts did not pass", n, aliases.len())),
+    }
+}
+
+fn run_host(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>, script: &Path, content: &[u8]) -> HostResult {
+    let started = Instant::now();
+    let hook_port = config.effective_port(alias, port);
+    let result = hooks::run(config, alias, hook_port, HookEvent::PreConnect).and_then(|_| {
+        let result = ssh::create_session(config, alias, port, identity).and_then(|sess| ssh::exec_capture(&sess, RUNNER, content));
+        if let Err(e) = hooks::run(config, alias, hook_port, HookEvent::PostDisconnect) {
+            eprintln!("Warning: {:#}", e);
+        }
+        result
+    });
+    audit::record(
+        config,
+        "fanout",
+        alias,
+        hook_port,
+        started,
+        result.as_ref().err(),
+        json!({ "script": script, "exit_code": result.as_ref().ok().map(|captured| captured.status) }),
+    );
+    HostResult { result, duration: started.elapsed() }
+}
+
+// <alias>.stdout 和 <alias>.stderr；连不上时写 <alias>.error
+fn save_output(dir: &Path, alias: &str, host: &HostResult) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    let mut write = |suffix: &str, content: &[u8]| -> Result<()> {
+        let path = dir.join(format!("{}.{}", alias, suffix));
+        fs::write(&path, content).context(format!("Failed to write {:?}", path))?;
+        written.push(path);
+        Ok(())
+    };
+    match &host.result {
+        Ok(captured) => {
+            write("stdout", &captured.stdout)?;
+            write("stderr", &captured.stderr)?;
+        }
+        Err(e) => write("error", format!("{:#}\n", e).as_bytes())?,
+    }
+    Ok(written)
+}
+
+fn print_outputs(aliases: &[String], results: &[(usize, HostResult)]) {
+    for (index, host) in results {
+        let Ok(captured) = &host.result else { continue };
+        if captured.stdout.is_empty() && captured.stderr.is_empty() {
+            continue;
+        }
+        println!("{}", format!("── {} ──", aliases[*index]).bold());
+        print!("{}", String::from_utf8_lossy(&captured.stdout));
+        if !captured.stderr.is_empty() {
+            print!("{}", String::from_utf8_lossy(&captured.stderr).dark_grey());
+        }
+        if !captured.stdout.ends_with(b"\n") && !captured.stderr.ends_with(b"\n") {
+            println!();
+        }
+    }
+}
+
+fn print_matrix(aliases: &[String], results: &[(usize, HostResult)]) {
+    let width = aliases.iter().map(|alias| alias.chars().count()).max().unwrap_or(0).max(4);
+    println!("{:<width$}  {:<11}  {:>4}  {:>7}", "HOST", "RESULT", "EXIT", "TIME");
+    for (index, host) in results {
+        let time = format!("{:.1}s", host.duration.as_secs_f64());
+        let (exit, note) = match &host.result {
+            Ok(captured) => (captured.status.to_string(), String::new()),
+            // 多行错误压成一行，不打乱表格
+            Err(e) => {
+                let message: Vec<String> = format!("{:#}", e).lines().map(|line| line.trim().to_string()).collect();
+                ("-".to_string(), format!("  {}", message.join("; ")))
+            }
+        };
+        let status = format!("{:<11}", host.status());
+        let status = match host.status() {
+            "pass" => status.green().to_string(),
+            _ => status.red().bold().to_string(),
+        };
+        println!("{:<width$}  {}  {:>4}  {:>7}{}", aliases[*index], status, exit, time, note.red());
+    }
+}
+
+fn host_json(alias: &str, host: &HostResult, files: Option<&Vec<PathBuf>>) -> serde_json::Value {
+    let mut entry = json!({
+        "alias": alias,
+        "status": host.status(),
+        "duration_ms": host.duration.as_millis() as u64,
+    });
+    match &host.result {
+        Ok(captured) => {
+            entry["exit_code"] = json!(captured.status);
+            // 保存到文件时不再重复输出内容
+            if files.is_none() {
+                entry["stdout"] = json!(String::from_utf8_lossy(&captured.stdout));
+                entry["stderr"] = json!(String::from_utf8_lossy(&captured.stderr));
+            }
+        }
+        Err(e) => entry["error"] = json!(format!("{:#}", e)),
+    }
+    if let Some(files) = files {
+        entry["files"] = json!(files);
+    }
+    entry
+}
@@ -0,0 +1,76 @@
+use crate::config::Config;
+use crate::output;
+use crate::prompt;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use inquire::{Confirm, Text};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// 连接受保护的主机前要怎样确认
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfirmMode {
+    /// No confirmation
+    #[default]
+    Off,
+    /// Answer a yes/no question
+    Yes,
+    /// Type the alias name
+    Alias,
+}
+
+impl ConfirmMode {
+    pub fn is_off(&self) -> bool {
+        *self == ConfirmMode::Off
+    }
+}
+
+static ALLOW_PROTECTED: OnceLock<bool> = OnceLock::new();
+
+/// --allow-protected：脚本中跳过确认
+pub fn set_allow_protected(allow: bool) {
+    let _ = ALLOW_PROTECTED.set(allow);
+}
+
+/// 别名自己的 confirm_connect 优先；带有 confirm_tags 中任一标签的主机需要输入别名
+pub fn mode(config: &Config, alias: &str) -> ConfirmMode {
+    let Some(conn) = config.connections.get(alias) else {
+        return ConfirmMode::Off;
+    };
+    if !conn.confirm_connect.is_off() {
+        return conn.confirm_connect;
+    }
+    if config.effective_tags(conn).iter().any(|tag| config.confirm_tags.contains(tag)) {
+        return ConfirmMode::Alias;
+    }
+    ConfirmMode::Off
+}
+
+/// 连接、上传或执行命令前确认受保护的主机；action 如 "connect to"、"upload to"
+pub fn confirm(config: &Config, alias: &str, action: &str) -> Result<()> {
+    let mode = mode(config, alias);
+    if mode.is_off() || ALLOW_PROTECTED.get().copied().unwrap_or(false) {
+        return Ok(());
+    }
+    if !prompt::can_ask() || output::is_json() {
+        return Err(prompt::unavailable(format!(
+            "'{}' is a protected host. Pass --allow-protected to {} it without confirming.",
+            alias, action
+        )));
+    }
+    let confirmed = match mode {
+        ConfirmMode::Off => true,
+        ConfirmMode::Yes => Confirm::new(&format!("'{}' is a protected host. Really {} it?", alias, action))
+            .with_default(false)
+            .prompt()?,
+        ConfirmMode::Alias => {
+            let typed = Text::new(&format!("'{}' is a protected host. Type its alias to {} it:", alias, action)).prompt()?;
+            typed.trim() == alias
+        }
+    };
+    if !confirmed {
+        return Err(anyhow!("Cancelled; nothing was done on '{}'.", alias));
+    }
+    Ok(())
+}
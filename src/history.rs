@@ -0,0 +1,116 @@
+use crate::config::{state_dir, write_atomic, Config};
+use crate::hostmeta;
+use crate::prompt;
+use crate::trace;
+use anyhow::{anyhow, Context, Result};
+use inquire::Select;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HISTORY_FILE: &str = "history.json";
+// 每个别名默认保留的命令数
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+/// exec 执行过的一条命令
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub command: String,
+    // 最后一次执行的时间（Unix 秒）
+    pub time: u64,
+}
+
+fn history_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join(HISTORY_FILE))
+}
+
+// 别名 -> 命令，旧的在前；文件不存在或损坏时为空
+fn load_all() -> BTreeMap<String, Vec<Entry>> {
+    history_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_all(all: &BTreeMap<String, Vec<Entry>>) -> Result<()> {
+    let path = history_path()?;
+    fs::create_dir_all(path.parent().unwrap()).context("Failed to create state directory")?;
+    // 命令里可能有敏感内容，write_atomic 写出的文件只有自己可读写
+    write_atomic(&path, &serde_json::to_vec_pretty(all)?).context("Failed to write command history")?;
+    Ok(())
+}
+
+fn limit(config: &Config) -> usize {
+    config.history_size.unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
+/// 记下别名上执行的命令；重复的命令移到最后，超过上限时丢掉最旧的。history_size 为 0 时不记录
+pub fn record(config: &Config, alias: &str, command: &str) {
+    let limit = limit(config);
+    if limit == 0 {
+        return;
+    }
+    let mut all = load_all();
+    let entries = all.entry(alias.to_string()).or_default();
+    entries.retain(|entry| entry.command != command);
+    entries.push(Entry { command: command.to_string(), time: now() });
+    let excess = entries.len().saturating_sub(limit);
+    entries.drain(..excess);
+    // 记不下历史不影响命令本身
+    if let Err(e) = write_all(&all) {
+        trace::log(format!("could not record command history for {}: {:#}", alias, e));
+    }
+}
+
+/// 别名上最近执行的命令
+pub fn last(alias: &str) -> Result<String> {
+    load_all()
+        .remove(alias)
+        .and_then(|mut entries| entries.pop())
+        .map(|entry| entry.command)
+        .ok_or_else(|| anyhow!("No commands recorded for '{}' yet.", alias))
+}
+
+/// 从别名的历史里选一条命令，最近的在最上面
+pub fn pick(alias: &str) -> Result<String> {
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("--history needs a terminal to pick from; use --last or pass the command."));
+    }
+    let mut entries = load_all().remove(alias).unwrap_or_default();
+    if entries.is_empty() {
+        return Err(anyhow!("No commands recorded for '{}' yet.", alias));
+    }
+    entries.reverse();
+    let labels: Vec<String> = entries
+        .iter()
+        .map(|entry| format!("{}  ({})", entry.command, hostmeta::ago(entry.time)))
+        .collect();
+    let picked = Select::new(&format!("Run again on {}:", alias), labels).raw_prompt()?;
+    Ok(entries.swap_remove(picked.index).command)
+}
+
+/// 连接删除后忘掉它的历史
+pub fn forget(alias: &str) {
+    let mut all = load_all();
+    if all.remove(alias).is_some() {
+        let _ = write_all(&all);
+    }
+}
+
+/// 历史上限改小后立即截断已有的记录；0 时删除所有记录
+pub fn truncate(limit: usize) -> Result<()> {
+    let mut all = load_all();
+    for entries in all.values_mut() {
+        let excess = entries.len().saturating_sub(limit);
+        entries.drain(..excess);
+    }
+    all.retain(|_, entries| !entries.is_empty());
+    write_all(&all)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
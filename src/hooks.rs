@@ -0,0 +1,124 @@
+use crate::config::{Config, Hook, HookFailure};
+use crate::output;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde_json::json;
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HOOK_TIMEOUT: u64 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookEvent {
+    /// Before the SSH connection is opened
+    PreConnect,
+    /// After the session has been closed
+    PostDisconnect,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::PreConnect => "pre_connect",
+            HookEvent::PostDisconnect => "post_disconnect",
+        }
+    }
+}
+
+/// 执行别名上配置的钩子，按照 on_failure 策略处理失败
+pub fn run(config: &Config, alias: &str, port: u16, event: HookEvent) -> Result<()> {
+    let Some(conn) = config.connections.get(alias) else {
+        return Ok(());
+    };
+    let hook = match event {
+        HookEvent::PreConnect => &conn.pre_connect,
+        HookEvent::PostDisconnect => &conn.post_disconnect,
+    };
+    let Some(hook) = hook else {
+        return Ok(());
+    };
+
+    let (user, host) = conn.target.split_once('@').unwrap_or(("", conn.target.as_str()));
+    let envs = [
+        ("RSSH_HOOK", event.name().to_string()),
+        ("RSSH_ALIAS", alias.to_string()),
+        ("RSSH_TARGET", conn.target.clone()),
+        ("RSSH_USER", user.to_string()),
+        ("RSSH_HOST", host.to_string()),
+        ("RSSH_PORT", port.to_string()),
+    ];
+
+    let started = Instant::now();
+    let result = run_command(hook, &envs);
+    output::event(
+        "hook",
+        json!({
+            "hook": event.name(),
+            "alias": alias,
+            "command": hook.command,
+            "ok": result.is_ok(),
+            "duration_ms": started.elapsed().as_millis() as u64,
+        }),
+    );
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => match hook.on_failure {
+            HookFailure::Abort => Err(e.context(format!("{} hook failed for '{}'", event.name(), alias))),
+            HookFailure::Warn => {
+                eprintln!("Warning: {} hook failed for '{}': {:#}", event.name(), alias, e);
+                Ok(())
+            }
+            HookFailure::Ignore => Ok(()),
+        },
+    }
+}
+
+fn run_command(hook: &Hook, envs: &[(&str, String)]) -> Result<()> {
+    let mut command = shell_command(&hook.command);
+    command.envs(envs.iter().map(|(k, v)| (*k, v)));
+    // stdout 留给 JSON 事件或命令输出时，钩子输出改走 stderr
+    if output::stdout_reserved() {
+        command.stdout(Stdio::from(io::stderr()));
+    }
+    let mut child = command
+        .spawn()
+        .context(format!("Failed to run hook command '{}'", hook.command))?;
+
+    let timeout = Duration::from_secs(hook.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT));
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if status.success() {
+                return Ok(());
+            }
+            return Err(anyhow!("Hook command '{}' exited with {}", hook.command, status));
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!(
+                "Hook command '{}' timed out after {}s",
+                hook.command,
+                timeout.as_secs()
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(windows)]
+fn shell_command(script: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(script);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell_command(script: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    command
+}
@@ -0,0 +1,462 @@
+use crate::config::{config_dir, Config};
+use crate::error;
+use crate::output;
+use crate::perms;
+use crate::prompt;
+use crate::ssh;
+use crate::sshfp;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha1::Sha1;
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, KnownHosts, Session};
+use ssh_key::{HashAlg, PublicKey};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// rssh 自己的信任库，格式与 OpenSSH known_hosts 相同
+pub fn known_hosts_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("known_hosts"))
+}
+
+/// 服务器主机密钥的类型和指纹
+pub struct HostKeyInfo {
+    pub key_type: &'static str,
+    pub sha256: String,
+    pub md5: String,
+}
+
+pub fn host_key_info(sess: &Session) -> Result<HostKeyInfo> {
+    let (_, key_type) = sess.host_key().context("Server did not present a host key")?;
+    let sha256 = sess
+        .host_key_hash(HashType::Sha256)
+        .context("Failed to hash the host key")?;
+    let md5 = sess
+        .host_key_hash(HashType::Md5)
+        .context("Failed to hash the host key")?;
+    Ok(HostKeyInfo {
+        key_type: key_type_name(key_type),
+        sha256: format!("SHA256:{}", STANDARD_NO_PAD.encode(sha256)),
+        md5: format!(
+            "MD5:{}",
+            md5.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+        ),
+    })
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+// known_hosts 里非默认端口的主机写作 "[host]:port"
+fn host_entry_name(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn load_known_hosts(sess: &Session) -> Result<KnownHosts> {
+    let mut known_hosts = sess.known_hosts()?;
+    let path = known_hosts_path()?;
+    if path.exists() {
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .context(format!("Failed to read {:?}", path))?;
+    }
+    Ok(known_hosts)
+}
+
+/// 不询问、不输出地对照 known_hosts 检查主机密钥
+pub fn check(sess: &Session, host: &str, port: u16) -> Result<CheckResult> {
+    let (key, _) = sess.host_key().context("Server did not present a host key")?;
+    Ok(load_known_hosts(sess)?.check_port(host, port, key))
+}
+
+/// 握手后调用：主机已被固定时必须与固定的密钥一致；`sshfp` 为 true 时未固定的主机再查 DNS
+pub fn verify(sess: &Session, host: &str, port: u16, sshfp: bool) -> Result<()> {
+    let (key, key_type) = sess.host_key().context("Server did not present a host key")?;
+    let known_hosts = load_known_hosts(sess)?;
+    let trust = match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => "known_hosts",
+        CheckResult::NotFound if sshfp => verify_sshfp(sess, host, key_type)?,
+        CheckResult::NotFound => "tofu",
+        CheckResult::Mismatch => {
+            let info = host_key_info(sess)?;
+            return Err(anyhow!(
+                "Host key for {} has changed! The server presented {} {}, which does not match the pinned key. \
+                 If this change is expected, run 'rssh pin' again.",
+                host_entry_name(host, port),
+                info.key_type,
+                info.sha256
+            ));
+        }
+        CheckResult::Failure => return Err(anyhow!("Failed to check the host key for {}", host)),
+    };
+
+    // 非交互模式下不做首次信任，主机必须事先固定
+    if trust == "tofu" && prompt::is_non_interactive() {
+        return Err(error::auth(anyhow!(
+            "Host key for {} is not pinned, and --non-interactive only trusts pinned keys. Run 'rssh pin' first.",
+            host_entry_name(host, port)
+        )));
+    }
+    let message = match trust {
+        "known_hosts" => "Host key matches the pinned key",
+        "dnssec" => "Host key verified by DNSSEC-signed SSHFP records",
+        _ => "Host key not pinned, trusting it for this connection",
+    };
+    output::status(message);
+    output::event("host_key_trust", json!({ "host": host_entry_name(host, port), "source": trust }));
+    Ok(())
+}
+
+// 只有经过 DNSSEC 验证的 SSHFP 记录才作为信任依据，否则仅提示并回退到 TOFU
+fn verify_sshfp(sess: &Session, host: &str, key_type: HostKeyType) -> Result<&'static str> {
+    let answer = match sshfp::lookup(host) {
+        Ok(Some(answer)) => answer,
+        Ok(None) => return Ok("tofu"),
+        Err(e) => {
+            eprintln!("Warning: SSHFP lookup for {} failed: {:#}", host, e);
+            return Ok("tofu");
+        }
+    };
+    let algorithm = sshfp::algorithm_number(key_type);
+    let records: Vec<_> = answer
+        .records
+        .iter()
+        .filter(|record| Some(record.algorithm) == algorithm)
+        .collect();
+    if records.is_empty() {
+        return Ok("tofu");
+    }
+
+    let matched = records.iter().any(|record| {
+        let hash = match record.fp_type {
+            sshfp::FP_SHA1 => sess.host_key_hash(HashType::Sha1),
+            sshfp::FP_SHA256 => sess.host_key_hash(HashType::Sha256),
+            _ => None,
+        };
+        hash == Some(record.fingerprint.as_slice())
+    });
+    match (matched, answer.dnssec) {
+        (true, true) => Ok("dnssec"),
+        (true, false) => {
+            output::status("Host key matches SSHFP records in DNS, but the answer was not DNSSEC-validated");
+            Ok("tofu")
+        }
+        (false, true) => Err(anyhow!(
+            "Host key for {} does not match its DNSSEC-signed SSHFP records!",
+            host
+        )),
+        (false, false) => {
+            eprintln!("Warning: host key for {} does not match its (unvalidated) SSHFP records", host);
+            Ok("tofu")
+        }
+    }
+}
+
+// 建立连接但不认证，用于读取主机密钥
+fn connect_unauthenticated(config: &Config, alias: &str, port: Option<u16>) -> Result<(Session, String, u16)> {
+    config.jump_chain(alias)?;
+    let conn = config
+        .connections
+        .get(alias)
+        .ok_or_else(|| error::alias_not_found(alias))?;
+    let host = conn
+        .target
+        .split_once('@')
+        .map(|(_, host)| host.to_string())
+        .context("Invalid connection string format. Use 'user@host'.")?;
+    let port = config.effective_port(alias, port);
+    let transport = ssh::connect_endpoints(config, conn, &host, port)?;
+    Ok((transport.sess, transport.host, transport.port))
+}
+
+pub fn handle_fingerprint(config: &Config, alias: &str, port: Option<u16>) -> Result<()> {
+    let (sess, host, port) = connect_unauthenticated(config, alias, port)?;
+    let info = host_key_info(&sess)?;
+    let (key, _) = sess.host_key().context("Server did not present a host key")?;
+    let pinned = load_known_hosts(&sess)?.check_port(&host, port, key);
+
+    if output::is_json() {
+        output::emit(
+            "fingerprint",
+            json!({
+                "alias": alias,
+                "host": host,
+                "port": port,
+                "key_type": info.key_type,
+                "sha256": info.sha256,
+                "md5": info.md5,
+                "pinned": matches!(pinned, CheckResult::Match),
+                "mismatch": matches!(pinned, CheckResult::Mismatch),
+            }),
+        );
+        return Ok(());
+    }
+
+    println!("Host key for {}:", host_entry_name(&host, port));
+    println!("  Type:   {}", info.key_type);
+    println!("  SHA256: {}", info.sha256.trim_start_matches("SHA256:"));
+    println!("  MD5:    {}", info.md5.trim_start_matches("MD5:"));
+    match pinned {
+        CheckResult::Match => println!("  Pinned: yes"),
+        CheckResult::Mismatch => println!("  Pinned: MISMATCH (the pinned key is different)"),
+        _ => println!("  Pinned: no"),
+    }
+    Ok(())
+}
+
+pub fn handle_pin(config: &Config, alias: &str, port: Option<u16>) -> Result<()> {
+    let (sess, host, port) = connect_unauthenticated(config, alias, port)?;
+    let (key, key_type) = sess.host_key().context("Server did not present a host key")?;
+    let info = host_key_info(&sess)?;
+    let entry_name = host_entry_name(&host, port);
+
+    let mut known_hosts = load_known_hosts(&sess)?;
+    // 先移除这个主机已有的记录
+    let stale: Vec<_> = known_hosts
+        .iter()?
+        .into_iter()
+        .filter(|entry| entry.name() == Some(entry_name.as_str()))
+        .collect();
+    for entry in &stale {
+        known_hosts.remove(entry)?;
+    }
+    known_hosts.add(&entry_name, key, alias, key_type.into())?;
+
+    let path = known_hosts_path()?;
+    if let Some(parent) = path.parent() {
+        perms::create_private_dir(parent).context("Failed to create config directory")?;
+    }
+    known_hosts
+        .write_file(&path, KnownHostFileKind::OpenSSH)
+        .context(format!("Failed to write {:?}", path))?;
+
+    output::status(format!(
+        "Pinned {} key {} for {}.",
+        info.key_type, info.sha256, entry_name
+    ));
+    output::event(
+        "pinned",
+        json!({ "alias": alias, "host": entry_name, "key_type": info.key_type, "sha256": info.sha256 }),
+    );
+    Ok(())
+}
+
+/// known_hosts 中的一条记录
+struct KnownHostEntry<'a> {
+    line: &'a str,
+    // @cert-authority / @revoked
+    marker: Option<&'a str>,
+    hosts: &'a str,
+    key_type: &'a str,
+    key: &'a str,
+    comment: &'a str,
+}
+
+impl<'a> KnownHostEntry<'a> {
+    // 空行和注释返回 None
+    fn parse(line: &'a str) -> Option<Self> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+        let mut fields = trimmed.splitn(2, char::is_whitespace);
+        let mut marker = None;
+        let mut hosts = fields.next()?;
+        let mut rest = fields.next()?.trim_start();
+        if hosts.starts_with('@') {
+            marker = Some(hosts);
+            let mut fields = rest.splitn(2, char::is_whitespace);
+            hosts = fields.next()?;
+            rest = fields.next()?.trim_start();
+        }
+        let mut fields = rest.splitn(3, char::is_whitespace);
+        Some(KnownHostEntry {
+            line,
+            marker,
+            hosts,
+            key_type: fields.next()?,
+            key: fields.next()?,
+            comment: fields.next().unwrap_or("").trim(),
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.hosts.split(',').any(|pattern| match pattern.strip_prefix("|1|") {
+            Some(hashed) => hashed_host_matches(hashed, name),
+            None => pattern == name,
+        })
+    }
+
+    fn is_hashed(&self) -> bool {
+        self.hosts.starts_with("|1|")
+    }
+
+    fn fingerprint(&self) -> String {
+        match PublicKey::from_openssh(&format!("{} {}", self.key_type, self.key)) {
+            Ok(key) => key.fingerprint(HashAlg::Sha256).to_string(),
+            Err(_) => "(invalid key)".to_string(),
+        }
+    }
+
+    // 哈希过的主机名无法还原，只有按名字查询并匹配时才显示
+    fn display_host(&self, name: Option<&str>) -> String {
+        match name {
+            Some(name) if self.is_hashed() => name.to_string(),
+            _ if self.is_hashed() => "(hashed)".to_string(),
+            _ => self.hosts.to_string(),
+        }
+    }
+
+    fn to_json(&self, name: Option<&str>) -> serde_json::Value {
+        json!({
+            "host": self.display_host(name),
+            "hashed": self.is_hashed(),
+            "marker": self.marker,
+            "key_type": self.key_type,
+            "fingerprint": self.fingerprint(),
+            "comment": self.comment,
+        })
+    }
+}
+
+// OpenSSH 的 HashKnownHosts 格式: |1|base64(salt)|base64(HMAC-SHA1(salt, host))
+fn hashed_host_matches(hashed: &str, name: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (STANDARD.decode(salt), STANDARD.decode(hash)) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+        return false;
+    };
+    mac.update(name.as_bytes());
+    mac.verify_slice(&hash).is_ok()
+}
+
+fn read_known_hosts(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    fs::read_to_string(path).context(format!("Failed to read {:?}", path))
+}
+
+fn known_hosts_file(file: Option<PathBuf>) -> Result<PathBuf> {
+    match file {
+        Some(file) => Ok(file),
+        None => known_hosts_path(),
+    }
+}
+
+// 参数可以是已保存的别名、"[host]:port" 或主机名
+fn resolve_entry_name(config: &Config, host: &str, port: Option<u16>) -> String {
+    if let Some(conn) = config.connections.get(host)
+        && let Some((_, target_host)) = conn.target.split_once('@')
+    {
+        return host_entry_name(target_host, config.effective_port(host, port));
+    }
+    if host.starts_with('[') {
+        return host.to_string();
+    }
+    host_entry_name(host, port.unwrap_or(crate::config::DEFAULT_PORT))
+}
+
+pub fn handle_list(config: &Config, host: Option<&str>, port: Option<u16>, file: Option<PathBuf>) -> Result<()> {
+    let path = known_hosts_file(file)?;
+    let content = read_known_hosts(&path)?;
+    let name = host.map(|host| resolve_entry_name(config, host, port));
+    let entries: Vec<_> = content
+        .lines()
+        .filter_map(KnownHostEntry::parse)
+        .filter(|entry| name.as_deref().is_none_or(|name| entry.matches(name)))
+        .collect();
+
+    if output::is_json() {
+        let entries: Vec<_> = entries.iter().map(|entry| entry.to_json(name.as_deref())).collect();
+        output::emit("host_keys", json!({ "file": path, "entries": entries }));
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No host keys found in {}.", path.display());
+        return Ok(());
+    }
+    println!("Host keys in {}:", path.display());
+    for entry in &entries {
+        let mut line = format!(
+            "  {}  {}  {}",
+            entry.display_host(name.as_deref()),
+            entry.key_type,
+            entry.fingerprint()
+        );
+        if let Some(marker) = entry.marker {
+            line.push_str(&format!("  {}", marker));
+        }
+        if !entry.comment.is_empty() {
+            line.push_str(&format!("  ({})", entry.comment));
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+pub fn handle_remove(config: &Config, host: &str, port: Option<u16>, file: Option<PathBuf>) -> Result<()> {
+    let path = known_hosts_file(file)?;
+    let content = read_known_hosts(&path)?;
+    let name = resolve_entry_name(config, host, port);
+
+    let mut removed = 0;
+    let mut kept = String::new();
+    for line in content.lines() {
+        if KnownHostEntry::parse(line).is_some_and(|entry| entry.matches(&name)) {
+            removed += 1;
+        } else {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    if removed == 0 {
+        return Err(anyhow!("No host keys for {} found in {:?}.", name, path));
+    }
+    fs::write(&path, kept).context(format!("Failed to write {:?}", path))?;
+
+    output::status(format!("Removed {} host key(s) for {} from {}.", removed, name, path.display()));
+    output::event("host_keys_removed", json!({ "host": name, "removed": removed, "file": path }));
+    Ok(())
+}
+
+/// 按 OpenSSH known_hosts 格式输出，可直接追加到 ~/.ssh/known_hosts
+pub fn handle_export(config: &Config, host: Option<&str>, port: Option<u16>, file: Option<PathBuf>) -> Result<()> {
+    let path = known_hosts_file(file)?;
+    let content = read_known_hosts(&path)?;
+    let name = host.map(|host| resolve_entry_name(config, host, port));
+    let entries: Vec<_> = content
+        .lines()
+        .filter_map(KnownHostEntry::parse)
+        .filter(|entry| name.as_deref().is_none_or(|name| entry.matches(name)))
+        .collect();
+
+    if output::is_json() {
+        let lines: Vec<_> = entries.iter().map(|entry| entry.line.trim()).collect();
+        output::emit("host_keys_export", json!({ "file": path, "lines": lines }));
+        return Ok(());
+    }
+    for entry in &entries {
+        println!("{}", entry.line.trim());
+    }
+    Ok(())
+}
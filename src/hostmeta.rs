@@ -0,0 +1,124 @@
+use crate::config::{state_dir, write_atomic};
+use crate::sysinfo;
+use crate::trace;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use ssh2::Session;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const META_FILE: &str = "hosts.json";
+// 超过这么久的记录在下次交互登录时重新读取
+const MAX_AGE_SECS: u64 = 7 * 24 * 3600;
+
+// 只用 POSIX sh 和常见命令，缺少的项输出为空
+const META_SCRIPT: &str = r#"
+echo "hostname=$(hostname 2>/dev/null || uname -n)"
+echo "os=$( (. /etc/os-release 2>/dev/null && echo "$PRETTY_NAME") || uname -s)"
+echo "kernel=$(uname -sr 2>/dev/null)"
+echo "arch=$(uname -m 2>/dev/null)"
+"#;
+
+/// 登录时记下的远程系统信息，用于 list --details 和选择连接的列表
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct HostMeta {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub os: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub kernel: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub arch: String,
+    // 读取时间（Unix 秒）
+    pub updated: u64,
+}
+
+impl HostMeta {
+    /// "Ubuntu 22.04.4 LTS, x86_64"，用在选择列表里
+    pub fn summary(&self) -> String {
+        [self.os.as_str(), self.arch.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 距上次读取过了多久，如 "3d ago"
+    pub fn age(&self) -> String {
+        ago(self.updated)
+    }
+}
+
+/// Unix 秒表示的时间距现在多久，如 "5m ago"、"3d ago"
+pub fn ago(time: u64) -> String {
+    let secs = now().saturating_sub(time);
+    match secs {
+        0..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn meta_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join(META_FILE))
+}
+
+/// 所有别名的记录；文件不存在或损坏时为空
+pub fn load_all() -> BTreeMap<String, HostMeta> {
+    meta_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_all(all: &BTreeMap<String, HostMeta>) -> Result<()> {
+    let path = meta_path()?;
+    fs::create_dir_all(path.parent().unwrap()).context("Failed to create state directory")?;
+    write_atomic(&path, &serde_json::to_vec_pretty(all)?).context("Failed to write host metadata")?;
+    Ok(())
+}
+
+/// 没有记录或记录已过期时读取并保存；失败只记到调试日志，不影响登录
+pub fn refresh_if_stale(sess: &Session, alias: &str) {
+    let mut all = load_all();
+    if all.get(alias).is_some_and(|meta| now().saturating_sub(meta.updated) < MAX_AGE_SECS) {
+        return;
+    }
+    let result = read(sess).and_then(|meta| {
+        all.insert(alias.to_string(), meta);
+        write_all(&all)
+    });
+    if let Err(e) = result {
+        trace::log(format!("could not update host metadata for {}: {:#}", alias, e));
+    }
+}
+
+/// 连接删除后忘掉它的记录
+pub fn forget(alias: &str) {
+    let mut all = load_all();
+    if all.remove(alias).is_some() {
+        let _ = write_all(&all);
+    }
+}
+
+fn read(sess: &Session) -> Result<HostMeta> {
+    let mut meta = HostMeta { updated: now(), ..Default::default() };
+    for (key, value) in sysinfo::run_script(sess, META_SCRIPT)? {
+        match key.as_str() {
+            "hostname" => meta.hostname = value,
+            "os" => meta.os = value,
+            "kernel" => meta.kernel = value,
+            "arch" => meta.arch = value,
+            _ => {}
+        }
+    }
+    Ok(meta)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,171 @@
+use crossterm::event::{self, Event};
+use ssh2::Session;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+// 输入线程检查停止标志的间隔；等待期间阻塞在 crossterm 内部的 poll 上，不占 CPU
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 在单独线程里读取终端事件，主循环可以同时等待 SSH socket 和键盘输入
+pub struct InputThread {
+    events: Receiver<io::Result<Event>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    // 输入线程每收到一个事件就写一个字节，唤醒主循环的 poll
+    #[cfg(unix)]
+    wake: UnixStream,
+    #[cfg(unix)]
+    notify: UnixStream,
+}
+
+/// 让其他线程（如 --share 的观看者输入）也能唤醒主循环
+pub struct Waker {
+    #[cfg(unix)]
+    notify: UnixStream,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        #[cfg(unix)]
+        let _ = (&self.notify).write(&[1]);
+    }
+}
+
+impl InputThread {
+    pub fn spawn() -> io::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        let (wake, notify) = UnixStream::pair()?;
+        #[cfg(unix)]
+        wake.set_nonblocking(true)?;
+
+        let thread_stop = stop.clone();
+        #[cfg(unix)]
+        let thread_notify = notify.try_clone()?;
+        let handle = thread::spawn(move || {
+            #[cfg(unix)]
+            let mut notify = thread_notify;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let event = match event::poll(STOP_CHECK_INTERVAL) {
+                    Ok(false) => continue,
+                    Ok(true) => event::read(),
+                    Err(e) => Err(e),
+                };
+                let failed = event.is_err();
+                if sender.send(event).is_err() || failed {
+                    break;
+                }
+                #[cfg(unix)]
+                let _ = notify.write(&[1]);
+            }
+        });
+
+        Ok(InputThread {
+            events,
+            stop,
+            handle: Some(handle),
+            #[cfg(unix)]
+            wake,
+            #[cfg(unix)]
+            notify,
+        })
+    }
+
+    pub fn waker(&self) -> io::Result<Waker> {
+        Ok(Waker {
+            #[cfg(unix)]
+            notify: self.notify.try_clone()?,
+        })
+    }
+
+    /// 取出一个已到达的事件，不阻塞
+    pub fn try_next(&self) -> Option<io::Result<Event>> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::other("Terminal input thread stopped"))),
+        }
+    }
+
+    /// 阻塞直到 socket 可读、有终端事件或超时
+    #[cfg(unix)]
+    pub fn wait(&mut self, sess: &Session, timeout: Option<Duration>) -> io::Result<()> {
+        poll_readable(&[sess.as_raw_fd(), self.wake.as_raw_fd()], timeout)?;
+        // 清空唤醒字节
+        let mut buf = [0u8; 64];
+        while matches!(self.wake.read(&mut buf), Ok(n) if n > 0) {}
+        Ok(())
+    }
+
+    // 没有 poll(2) 的平台上退回到短间隔等待
+    #[cfg(not(unix))]
+    pub fn wait(&mut self, _sess: &Session, timeout: Option<Duration>) -> io::Result<()> {
+        let interval = Duration::from_millis(10);
+        thread::sleep(timeout.map_or(interval, |t| t.min(interval)));
+        Ok(())
+    }
+}
+
+/// 非阻塞会话上等待 socket 可读或超时；上次是写被挡住时也等可写
+#[cfg(unix)]
+pub fn wait_session(sess: &Session, timeout: Option<Duration>) -> io::Result<()> {
+    let events = match sess.block_directions() {
+        ssh2::BlockDirections::Outbound | ssh2::BlockDirections::Both => libc::POLLIN | libc::POLLOUT,
+        _ => libc::POLLIN,
+    };
+    poll_fds(&[sess.as_raw_fd()], events, timeout)
+}
+
+#[cfg(not(unix))]
+pub fn wait_session(_sess: &Session, timeout: Option<Duration>) -> io::Result<()> {
+    let interval = Duration::from_millis(10);
+    thread::sleep(timeout.map_or(interval, |t| t.min(interval)));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn poll_readable(fds: &[std::os::unix::io::RawFd], timeout: Option<Duration>) -> io::Result<()> {
+    poll_fds(fds, libc::POLLIN, timeout)
+}
+
+#[cfg(unix)]
+fn poll_fds(fds: &[std::os::unix::io::RawFd], events: libc::c_short, timeout: Option<Duration>) -> io::Result<()> {
+    let mut fds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd { fd, events, revents: 0 })
+        .collect();
+    let timeout_ms = match timeout {
+        Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+        None => -1,
+    };
+    let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+    if ret < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+impl Drop for InputThread {
+    // 等输入线程退出，避免它抢走之后交互提示的按键
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
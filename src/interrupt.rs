@@ -0,0 +1,79 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// 退出码与 shell 中被 SIGINT 结束的进程一致
+pub const EXIT_CODE: i32 = 130;
+
+/// 存在期间 Ctrl+C / SIGTERM 只设置标志，由传输循环检查后正常退出并清理；再按一次立即退出
+pub struct Guard;
+
+impl Guard {
+    pub fn install() -> Guard {
+        if DEPTH.fetch_add(1, Ordering::SeqCst) == 0 {
+            platform::install();
+        }
+        Guard
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if DEPTH.fetch_sub(1, Ordering::SeqCst) == 1 {
+            platform::restore();
+        }
+    }
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// 不经过信号也记为中断（如交互会话里按 Ctrl+C 断开），退出码同样是 130
+pub fn mark() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// 已收到中断时返回错误，放在读写循环里
+pub fn check() -> io::Result<()> {
+    if is_interrupted() {
+        return Err(io::Error::other("Interrupted"));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{EXIT_CODE, INTERRUPTED};
+    use std::sync::atomic::Ordering;
+
+    extern "C" fn on_signal(_signal: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+    }
+
+    pub fn install() {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
+
+    pub fn restore() {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    pub fn install() {}
+
+    pub fn restore() {}
+}
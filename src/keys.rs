@@ -0,0 +1,262 @@
+use crate::config::Config;
+use crate::error;
+use crate::output;
+use crate::pkcs11;
+use crate::prompt;
+use crate::ssh::{exec_status, shell_quote};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use inquire::{Confirm, Password};
+use serde_json::json;
+use ssh2::Session;
+use ssh_key::rand_core::OsRng;
+use ssh_key::{Algorithm, EcdsaCurve, HashAlg, LineEnding, PrivateKey};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// 没有指定公钥时按顺序查找
+const DEFAULT_KEY_NAMES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyType {
+    Ed25519,
+    Ecdsa,
+    Rsa,
+}
+
+impl KeyType {
+    fn algorithm(self) -> Algorithm {
+        match self {
+            KeyType::Ed25519 => Algorithm::Ed25519,
+            KeyType::Ecdsa => Algorithm::Ecdsa {
+                curve: EcdsaCurve::NistP256,
+            },
+            KeyType::Rsa => Algorithm::Rsa { hash: None },
+        }
+    }
+
+    fn default_file_name(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "id_ed25519",
+            KeyType::Ecdsa => "id_ecdsa",
+            KeyType::Rsa => "id_rsa",
+        }
+    }
+}
+
+pub fn ssh_dir() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().context("Could not find home directory")?;
+    Ok(home_dir.join(".ssh"))
+}
+
+/// ~/.ssh 下的默认私钥，按 OpenSSH 的尝试顺序
+pub fn default_identity_files() -> Vec<PathBuf> {
+    match ssh_dir() {
+        Ok(dir) => DEFAULT_KEY_NAMES.iter().map(|name| dir.join(name)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn public_key_path(private_key: &Path) -> PathBuf {
+    let mut name = private_key.as_os_str().to_owned();
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+/// 是否为 FIDO2 安全密钥（sk-ssh-ed25519 / sk-ecdsa-sha2-nistp256）
+pub fn is_security_key(private_key: &Path) -> bool {
+    if let Ok(public) = fs::read_to_string(public_key_path(private_key)) {
+        return public.trim_start().starts_with("sk-");
+    }
+    // 没有 .pub 文件时从私钥里读算法（加密的私钥也能读出）
+    match PrivateKey::read_openssh_file(private_key) {
+        Ok(key) => matches!(key.algorithm(), Algorithm::SkEd25519 | Algorithm::SkEcdsaSha2NistP256),
+        Err(_) => false,
+    }
+}
+
+/// libssh2 不能直接和 FIDO2 设备交互，把安全密钥加载到 ssh-agent 后由 agent 完成触摸 / PIN 确认
+pub fn add_security_key(private_key: &Path) -> Result<()> {
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Err(anyhow!(
+            "Security keys are used through ssh-agent, but SSH_AUTH_SOCK is not set. Start one with 'eval $(ssh-agent)'."
+        ));
+    }
+
+    // 已经加载过就不再重复添加
+    if let Ok(public) = fs::read_to_string(public_key_path(private_key))
+        && let Some(blob) = public.split_whitespace().nth(1)
+        && let Ok(loaded) = Command::new("ssh-add").arg("-L").output()
+        && String::from_utf8_lossy(&loaded.stdout).contains(blob)
+    {
+        return Ok(());
+    }
+
+    output::status(format!("Adding security key {} to ssh-agent", private_key.display()));
+    let status = Command::new("ssh-add")
+        .arg(private_key)
+        .status()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    if !status.success() {
+        return Err(anyhow!("ssh-add could not load {:?}", private_key));
+    }
+    Ok(())
+}
+
+/// 把手动解密过的私钥加载到 ssh-agent；口令经 SSH_ASKPASS 交给 ssh-add，不用再输一次
+pub fn add_to_agent(private_key: &Path, passphrase: &str) -> Result<()> {
+    let askpass = env::current_exe().context("Failed to locate the rssh executable")?;
+    let output = Command::new("ssh-add")
+        .arg(private_key)
+        .env("SSH_ASKPASS", askpass)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env(pkcs11::ASKPASS_SECRET_ENV, passphrase)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ssh-add could not load {:?}: {}",
+            private_key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    output::status(format!("Added {} to ssh-agent", private_key.display()));
+    Ok(())
+}
+
+pub fn handle_keygen(key_type: KeyType, output_path: Option<PathBuf>, comment: Option<String>, no_passphrase: bool) -> Result<()> {
+    let private_path = match output_path {
+        Some(path) => path,
+        None => ssh_dir()?.join(key_type.default_file_name()),
+    };
+    let public_path = public_key_path(&private_path);
+
+    if (private_path.exists() || public_path.exists()) && !prompt::can_ask() {
+        return Err(prompt::unavailable(format!("{:?} already exists.", private_path)));
+    }
+    if (private_path.exists() || public_path.exists())
+        && !Confirm::new(&format!("{} already exists. Overwrite?", private_path.display()))
+            .with_default(false)
+            .prompt()?
+    {
+        return Err(anyhow!("Aborted: {:?} already exists.", private_path));
+    }
+
+    let passphrase = if no_passphrase {
+        String::new()
+    } else if !prompt::can_ask() {
+        return Err(prompt::unavailable("Can't ask for a passphrase; pass -N for a key without one."));
+    } else {
+        Password::new("Enter passphrase (empty for no passphrase):")
+            .with_display_mode(inquire::PasswordDisplayMode::Masked)
+            .prompt()?
+    };
+
+    let mut key = PrivateKey::random(&mut OsRng, key_type.algorithm())
+        .context("Failed to generate key")?;
+    key.set_comment(comment.unwrap_or_else(default_comment));
+    let key = if passphrase.is_empty() {
+        key
+    } else {
+        key.encrypt(&mut OsRng, passphrase.as_bytes())
+            .context("Failed to encrypt key")?
+    };
+
+    if let Some(parent) = private_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).context(format!("Failed to create {:?}", parent))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if parent.ends_with(".ssh") {
+                fs::set_permissions(parent, fs::Permissions::from_mode(0o700))?;
+            }
+        }
+    }
+    // 私钥文件权限为 0600
+    key.write_openssh_file(&private_path, LineEnding::LF)
+        .context(format!("Failed to write {:?}", private_path))?;
+    key.public_key()
+        .write_openssh_file(&public_path)
+        .context(format!("Failed to write {:?}", public_path))?;
+
+    let fingerprint = key.public_key().fingerprint(HashAlg::Sha256).to_string();
+    output::status(format!("Private key saved to {}", private_path.display()));
+    output::status(format!("Public key saved to {}", public_path.display()));
+    output::status(format!("Fingerprint: {}", fingerprint));
+    output::event(
+        "key_generated",
+        json!({ "private_key": private_path, "public_key": public_path, "fingerprint": fingerprint }),
+    );
+    Ok(())
+}
+
+fn default_comment() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "rssh".to_string());
+    format!("{}@rssh", user)
+}
+
+/// 找到要部署的公钥：显式给出的、别名配置的私钥对应的 .pub，或 ~/.ssh 下的默认公钥
+pub fn resolve_public_key(config: &Config, alias: &str, key: Option<&Path>) -> Result<PathBuf> {
+    if let Some(key) = key {
+        // 允许直接传私钥路径
+        if key.extension().is_some_and(|ext| ext == "pub") {
+            return Ok(key.to_path_buf());
+        }
+        return Ok(public_key_path(key));
+    }
+    if let Some(identity) = config.resolved(alias).and_then(|c| c.identity) {
+        let public = public_key_path(&identity);
+        if public.exists() {
+            return Ok(public);
+        }
+    }
+    let ssh_dir = ssh_dir()?;
+    DEFAULT_KEY_NAMES
+        .iter()
+        .map(|name| ssh_dir.join(format!("{}.pub", name)))
+        .find(|path| path.exists())
+        .context("No public key found. Run 'rssh keygen' first or pass --key.")
+}
+
+/// 把公钥追加到远程 ~/.ssh/authorized_keys，已存在时不重复添加
+pub fn copy_id(sess: &Session, public_key_path: &Path) -> Result<bool> {
+    let public_key = fs::read_to_string(public_key_path)
+        .context(format!("Failed to read public key {:?}", public_key_path))?;
+    let public_key = public_key.trim();
+    if public_key.is_empty() || public_key.contains('\n') {
+        return Err(anyhow!("{:?} does not look like a single public key.", public_key_path));
+    }
+
+    let key = shell_quote(public_key);
+    let command = format!(
+        "umask 077; mkdir -p ~/.ssh && touch ~/.ssh/authorized_keys && \
+         if grep -qxF {key} ~/.ssh/authorized_keys; then exit 3; fi && \
+         printf '%s\\n' {key} >> ~/.ssh/authorized_keys",
+        key = key
+    );
+    match exec_status(sess, &command)? {
+        0 => Ok(true),
+        3 => Ok(false),
+        status => Err(anyhow!("Failed to update remote authorized_keys (exit status {})", status)),
+    }
+}
+
+/// 部署成功后，让别名改用对应的私钥登录
+pub fn use_identity(config: &mut Config, alias: &str, public_key_path: &Path) -> Result<Option<PathBuf>> {
+    let private = public_key_path.with_extension("");
+    if !private.exists() {
+        return Ok(None);
+    }
+    let conn = config
+        .connections
+        .get_mut(alias)
+        .ok_or_else(|| error::alias_not_found(alias))?;
+    conn.identity = Some(private.clone());
+    config.save()?;
+    Ok(Some(private))
+}
@@ -1,85 +1,1323 @@
+mod agent;
+mod audit;
+mod auth;
+mod backup;
+mod banner;
+mod bench;
+mod biometric;
+mod clipboard;
 mod cli;
+mod cmds;
+mod completion;
+mod cp;
 mod config;
 mod credentials;
+mod crypto_audit;
+mod delta;
+mod docker;
+mod doctor;
+mod deploy;
+mod error;
+mod fanout;
+mod guard;
+mod hooks;
+mod hostkey;
+mod history;
+mod hostmeta;
+mod input;
+mod interrupt;
+mod keys;
+mod manifest;
+mod multi;
+mod osc52;
+mod output;
+mod perms;
+mod ping;
+mod pkcs11;
+mod portcheck;
+mod progress;
+mod prompt;
+mod queue;
+mod probe;
+mod proxy;
+mod recipe;
+mod remote_fs;
 mod ssh;
+mod scrollback;
+mod secret_env;
+mod secrets;
+mod setup;
+mod share;
+mod show;
+mod sshfp;
+mod status;
+mod stdio;
+mod sudo;
+mod svc;
+mod sysinfo;
+mod term;
+mod trace;
+mod transfer;
+mod trash;
+mod tunnel;
+mod vault;
+mod wol;
 
-use crate::cli::{Cli, Commands};
-use crate::config::Config;
-use crate::credentials::delete_password;
+use crate::cli::{AgentCommand, AuditCommand, Cli, CmdCommand, Commands, CompleteCommand, ConfigCommand, HookCommand, HostkeyCommand, QueueSettings, RecipeCommand, SecretsCommand, TemplateCommand, TrashCommand, VaultCommand};
+use crate::config::{Config, Connection, CryptoPrefs, Hook, RecipeStep, Template};
+use crate::cp::Location;
+use crate::credentials::{delete_password, delete_sudo_password};
+use crate::deploy::Put;
+use crate::error::RsshError;
+use crate::hooks::HookEvent;
+use crate::hostmeta::HostMeta;
+use crate::progress::{BatchProgress, ProgressMode, TransferStats};
+use crate::queue::{Job, JobResult, Outcome, RetryPolicy, TransferQueue};
+use crate::secret_env::SecretEnv;
+use crate::share::ShareOptions;
+use crate::ssh::ShellOptions;
+use crate::svc::{SvcAction, SvcOptions};
+use crate::transfer::{DirectTarget, Extract, OverwritePolicy, TransferOptions};
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
-use inquire::{Confirm, Select, Text};
-use std::path::PathBuf;
+use inquire::{Confirm, MultiSelect, Select, Text};
+use serde_json::json;
+use ssh2::{Session, Sftp};
+use std::env;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-fn main() -> Result<()> {
+fn main() {
+    // 被 ssh-add 作为 SSH_ASKPASS 调用时只输出 PIN
+    if let Some(secret) = pkcs11::askpass_secret() {
+        println!("{}", secret);
+        return;
+    }
+    // 解锁密码库后在后台保存密钥的进程
+    if vault::is_agent() {
+        vault::run_agent();
+        return;
+    }
+
+    term::install_panic_hook();
     let cli = Cli::parse();
-    let mut config = Config::load()?;
+    output::set_format(cli.format);
+    output::set_verbose(cli.verbose);
+    credentials::set_no_store(cli.no_store);
+    guard::set_allow_protected(cli.allow_protected);
+    prompt::set_non_interactive(cli.non_interactive);
+
+    if let Err(e) = run(cli) {
+        trace::log(format!("error: {:#}", e));
+        let kind = error::classify(&e);
+        let code = match kind {
+            _ if interrupt::is_interrupted() => interrupt::EXIT_CODE,
+            Some(kind) => kind.exit_code(),
+            None => error::EXIT_OTHER,
+        };
+        if output::is_json() {
+            output::emit(
+                "error",
+                json!({
+                    "kind": kind.map_or("other", RsshError::kind),
+                    "exit_code": code,
+                    "message": format!("{:#}", e),
+                }),
+            );
+        } else if !matches!(kind, Some(RsshError::RemoteExit(_))) {
+            // 远程命令失败时它自己的输出已经说明了原因
+            eprintln!("Error: {:?}", e);
+        }
+        std::process::exit(code);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    if let Some(path) = &cli.debug_ssh {
+        trace::start(path)?;
+    }
+    let mut config = match &cli.command {
+        Some(command) if command.changes_config() => Config::load_for_update()?,
+        _ => Config::load()?,
+    };
 
     match cli.command {
         Some(Commands::Add {
             alias,
             connection_string,
+            wol_mac,
+            wol_broadcast,
+            identities,
+            auth_methods,
+            pkcs11_provider,
+            agent_key,
+            fallback_hosts,
+            connect_timeout,
+            sshfp,
+            idle_timeout,
+            keepalive,
+            scrollback,
+            osc52,
+            osc52_max_size,
+            signals,
+            term,
+            lang,
+            lc_all,
+            banner,
+            banner_color,
+            prompt_banner,
+            show_info,
+            quiet_login,
+            confirm_connect,
+            require_biometrics,
+            store_credentials,
+            template,
+            settings,
+            crypto,
         }) => {
-            config.connections.insert(alias.clone(), connection_string);
+            if let Some(mac) = &wol_mac {
+                wol::parse_mac(mac)?;
+            }
+            let template = match &template {
+                Some(name) => Some(
+                    config
+                        .templates
+                        .get(name)
+                        .cloned()
+                        .context(format!("Template '{}' not found.", name))?,
+                ),
+                None => None,
+            };
+            config.check_editable(&alias)?;
+            let (target, target_port) = config::split_port(&connection_string)?;
+            // 重新添加时保留已有的其他设置
+            let conn = config
+                .connections
+                .entry(alias.clone())
+                .and_modify(|conn| conn.target = target.clone())
+                .or_insert_with(|| Connection::new(target));
+            if let Some(template) = &template {
+                conn.apply_template(template);
+            }
+            // 显式给出的参数覆盖模板，--port 又覆盖连接字符串里的端口
+            if let Some(port) = settings.port.or(target_port) {
+                conn.port = Some(port);
+            }
+            if settings.identity.is_some() {
+                conn.identity = settings.identity;
+            }
+            if settings.jump.is_some() {
+                conn.jump_host = settings.jump;
+            }
+            if !settings.tags.is_empty() {
+                conn.tags = settings.tags;
+            }
+            if !identities.is_empty() {
+                conn.identities = identities;
+            }
+            if !auth_methods.is_empty() {
+                conn.auth_methods = auth_methods;
+            }
+            if pkcs11_provider.is_some() {
+                conn.pkcs11_provider = pkcs11_provider;
+            }
+            if let Some(fingerprint) = agent_key {
+                conn.agent_key_fingerprint = Some(fingerprint).filter(|fingerprint| !fingerprint.is_empty());
+            }
+            if !fallback_hosts.is_empty() {
+                conn.fallback_hosts = fallback_hosts;
+            }
+            if connect_timeout.is_some() {
+                conn.connect_timeout = connect_timeout.filter(|&secs| secs > 0);
+            }
+            if sshfp {
+                conn.sshfp = true;
+            }
+            if idle_timeout.is_some() {
+                conn.idle_timeout = idle_timeout.filter(|&secs| secs > 0);
+            }
+            // 0 也保存，用来关闭 defaults 里的 keepalive
+            if keepalive.is_some() {
+                conn.keepalive = keepalive;
+            }
+            if scrollback.is_some() {
+                conn.scrollback = scrollback.filter(|&lines| lines > 0);
+            }
+            if let Some(mode) = osc52 {
+                conn.osc52 = mode;
+            }
+            if let Some(size) = &osc52_max_size {
+                conn.osc52_max_bytes = Some(remote_fs::parse_size(size)?);
+            }
+            if let Some(mode) = signals {
+                conn.signals = mode;
+            }
+            if term.is_some() {
+                conn.term = term;
+            }
+            if lang.is_some() {
+                conn.lang = lang;
+            }
+            if lc_all.is_some() {
+                conn.lc_all = lc_all;
+            }
+            if let Some(text) = banner {
+                conn.banner = Some(text).filter(|text| !text.is_empty());
+            }
+            if banner_color.is_some() {
+                conn.banner_color = banner_color;
+            }
+            if let Some(prompt) = prompt_banner {
+                conn.prompt_banner = prompt;
+            }
+            if let Some(show) = show_info {
+                conn.show_info = show;
+            }
+            if let Some(quiet) = quiet_login {
+                conn.quiet_login = quiet;
+            }
+            if let Some(mode) = confirm_connect {
+                conn.confirm_connect = mode;
+            }
+            if require_biometrics {
+                conn.require_biometrics = true;
+            }
+            if let Some(store) = store_credentials {
+                conn.store_credentials = (!store).then_some(false);
+            }
+            if crypto.insecure_legacy {
+                conn.crypto = CryptoPrefs::insecure_legacy();
+            }
+            if crypto.kex.is_some() {
+                conn.crypto.kex = crypto.kex;
+            }
+            if crypto.ciphers.is_some() {
+                conn.crypto.ciphers = crypto.ciphers;
+            }
+            if crypto.macs.is_some() {
+                conn.crypto.macs = crypto.macs;
+            }
+            if crypto.host_key_algorithms.is_some() {
+                conn.crypto.host_key_algorithms = crypto.host_key_algorithms;
+            }
+            if wol_mac.is_some() {
+                conn.wol_mac = wol_mac;
+                conn.wol_broadcast = wol_broadcast;
+            }
+            // 跳板机必须是已保存的别名且不能成环
+            let chain = config.jump_chain(&alias)?;
             config.save()?;
-            println!("Connection '{}' added.", alias);
+            if chain.len() > 1 {
+                output::status(format!("Route: {}", chain.join(" -> ")));
+            }
+            output::status(format!("Connection '{}' added.", alias));
+            output::event("connection_added", json!({ "alias": alias }));
         }
-        Some(Commands::List) => {
-            if config.connections.is_empty() {
+        Some(Commands::List { probe: true, jobs, .. }) => probe::handle_list_probe(&config, jobs as usize)?,
+        Some(Commands::List { details, .. }) => {
+            // 远程系统信息在交互登录时记录
+            let metadata = if details { hostmeta::load_all() } else { Default::default() };
+            if output::is_json() {
+                let connections: Vec<_> = config
+                    .connections
+                    .iter()
+                    .map(|(alias, conn)| {
+                        let mut entry = json!({
+                            "alias": alias,
+                            "connection": conn.target,
+                            "port": config.effective_port(alias, None),
+                            "tags": config.effective_tags(conn),
+                        });
+                        if details {
+                            entry["metadata"] = json!(metadata.get(alias));
+                        }
+                        entry
+                    })
+                    .collect();
+                output::emit("connections", json!({ "connections": connections }));
+            } else if config.connections.is_empty() {
                 println!("No connections saved. Use 'rssh add <alias> <user@host>' to add one.");
             } else {
                 println!("Saved connections:");
                 for (alias, conn) in &config.connections {
-                    println!("  {} -> {}", alias, conn);
+                    let target = config::format_endpoint(&conn.target, config.effective_port(alias, None));
+                    let tags = config.effective_tags(conn);
+                    if tags.is_empty() {
+                        println!("  {} -> {}", alias, target);
+                    } else {
+                        println!("  {} -> {} [{}]", alias, target, tags.join(", "));
+                    }
+                    if details {
+                        match metadata.get(alias) {
+                            Some(meta) => {
+                                let hostname = format!("host {}", meta.hostname);
+                                let mut parts: Vec<&str> = [meta.os.as_str(), meta.kernel.as_str(), meta.arch.as_str()]
+                                    .into_iter()
+                                    .filter(|part| !part.is_empty())
+                                    .collect();
+                                if !meta.hostname.is_empty() {
+                                    parts.push(&hostname);
+                                }
+                                println!("      {} | updated {}", parts.join(" | "), meta.age());
+                            }
+                            None => println!("      (no details yet; they are collected on the next interactive login)"),
+                        }
+                    }
                 }
             }
         }
-        Some(Commands::Remove { alias }) => {
-            if config.connections.remove(&alias).is_some() {
-                config.save()?;
-                delete_password(&alias)?;
-                println!("Connection '{}' removed.", alias);
-            } else {
-                return Err(anyhow!("Alias '{}' not found.", alias));
+        Some(Commands::SetDefault { alias: None, clear: false }) => {
+            let from_env = env::var(config::DEFAULT_ALIAS_ENV).is_ok_and(|alias| !alias.is_empty());
+            let alias = config.effective_default_alias();
+            let source = alias.as_ref().map(|_| if from_env { config::DEFAULT_ALIAS_ENV } else { "config" });
+            match (&alias, source) {
+                (Some(alias), Some(source)) => output::status(format!("Default alias: {} (from {})", alias, source)),
+                _ => output::status("No default alias set. Running 'rssh' without arguments shows the connection picker."),
+            }
+            output::event("default_alias", json!({ "alias": alias, "source": source }));
+        }
+        Some(Commands::SetDefault { alias, .. }) => {
+            if let Some(alias) = &alias
+                && !config.connections.contains_key(alias)
+            {
+                return Err(error::alias_not_found(alias));
+            }
+            config.default_alias = alias.clone();
+            config.save()?;
+            match &alias {
+                Some(alias) => output::status(format!("'{}' is now the default alias.", alias)),
+                None => output::status("Default alias cleared."),
+            }
+            if let Ok(env_alias) = env::var(config::DEFAULT_ALIAS_ENV)
+                && !env_alias.is_empty()
+            {
+                eprintln!("Warning: {} is set to '{}' and takes precedence in this shell.", config::DEFAULT_ALIAS_ENV, env_alias);
             }
+            output::event("default_alias", json!({ "alias": alias, "source": alias.as_ref().map(|_| "config") }));
+        }
+        Some(Commands::Show { alias, as_, copy }) => show::handle_show(&config, &alias, as_, copy)?,
+        Some(Commands::Doctor { fix_perms }) => doctor::handle_doctor(&config, fix_perms)?,
+        Some(Commands::Setup) => setup::run(&mut config)?,
+        Some(Commands::Remove { aliases, tag, yes }) => handle_remove(&mut config, aliases, tag.as_deref(), yes)?,
+        Some(Commands::Clone {
+            source,
+            alias,
+            host,
+            user,
+            settings,
+        }) => {
+            if config.connections.contains_key(&alias) {
+                return Err(anyhow!("Alias '{}' already exists.", alias));
+            }
+            let mut conn = config
+                .connections
+                .get(&source)
+                .cloned()
+                .ok_or_else(|| error::alias_not_found(&source))?;
+            if host.is_some() || user.is_some() {
+                let (old_user, old_host) = conn.target.split_once('@').unwrap_or(("", conn.target.as_str()));
+                let user = user.as_deref().unwrap_or(old_user);
+                let (host, host_port) = match &host {
+                    Some(host) => config::split_port(host)?,
+                    None => (old_host.to_string(), None),
+                };
+                conn.target = if user.is_empty() { host } else { format!("{}@{}", user, host) };
+                if host_port.is_some() {
+                    conn.port = host_port;
+                }
+            }
+            // 备用地址和 Wake-on-LAN 属于原来的主机
+            if host.is_some() {
+                conn.fallback_hosts.clear();
+                conn.wol_mac = None;
+                conn.wol_broadcast = None;
+            }
+            if settings.port.is_some() {
+                conn.port = settings.port;
+            }
+            if settings.identity.is_some() {
+                conn.identity = settings.identity;
+            }
+            if settings.jump.is_some() {
+                conn.jump_host = settings.jump;
+            }
+            if !settings.tags.is_empty() {
+                conn.tags = settings.tags;
+            }
+            let target = conn.target.clone();
+            config.connections.insert(alias.clone(), conn);
+            config.jump_chain(&alias)?;
+            config.save()?;
+            output::status(format!("Connection '{}' -> {} cloned from '{}'.", alias, target, source));
+            output::event("connection_added", json!({ "alias": alias, "cloned_from": source }));
         }
         Some(Commands::Connect {
             alias,
             port,
             identity,
+            merge_stderr,
+            term,
+            info,
+            share,
+            share_input,
+            signals,
+        }) => {
+            let alias = match alias {
+                Some(alias) => alias,
+                None => default_alias(&config)?.ok_or_else(|| {
+                    error::config(anyhow!(
+                        "No alias given and no default set. Use 'rssh set-default <alias>' or set {}.",
+                        config::DEFAULT_ALIAS_ENV
+                    ))
+                })?,
+            };
+            let mut options = ShellOptions {
+                merge_stderr,
+                ..ShellOptions::for_connection(&alias, config.resolved(&alias).as_ref())
+            };
+            options.info |= info;
+            options.share = share.map(|port| ShareOptions { port, allow_input: share_input });
+            if let Some(term) = term {
+                options.term = term;
+            }
+            if let Some(mode) = signals {
+                options.signals = mode;
+            }
+            run_shell(&config, &alias, port, identity.as_deref(), &options)?;
+        }
+        Some(Commands::Exec {
+            alias,
+            command,
+            last,
+            history,
+            port,
+            identity,
+            merge_stderr,
+            sudo,
+            sudo_user,
+            secret_env,
+            timeout,
+            signal,
+            yes,
+        }) => {
+            output::use_stderr();
+            if (last || history) && multi::is_pattern(&alias) {
+                return Err(anyhow!("--last and --history need a single alias, not a pattern."));
+            }
+            let command = if last {
+                history::last(&alias)?
+            } else if history {
+                history::pick(&alias)?
+            } else {
+                command.join(" ")
+            };
+            if last || history {
+                output::status(format!("Running: {}", command));
+            }
+            let sudo_user = sudo_user.as_deref();
+            let exec = ExecOptions {
+                merge_stderr,
+                sudo: sudo || sudo_user.is_some(),
+                sudo_user,
+                secret_env: &secret_env,
+                timeout,
+                signal,
+            };
+            if multi::is_pattern(&alias) {
+                let aliases = multi::match_pattern(&config, &alias)?;
+                multi::confirm_matches(&config, &alias, &aliases, "run the command on", yes)?;
+                for_each_matched(&aliases, |alias| run_exec(&config, alias, &command, port, identity.as_deref(), exec.clone()))?;
+            } else {
+                run_exec(&config, &alias, &command, port, identity.as_deref(), exec)?;
+            }
+        }
+        Some(Commands::Docker {
+            alias,
+            args,
+            socket,
+            port,
+            identity,
+        }) => run_docker(&config, &alias, &args, &socket, port, identity.as_deref())?,
+        Some(Commands::Svc {
+            alias,
+            action,
+            unit,
+            sudo,
+            no_sudo,
+            lines,
+            follow,
+            since,
+            port,
+            identity,
+        }) => {
+            let opts = SvcOptions { sudo, no_sudo, lines, follow, since };
+            run_svc(&config, &alias, action, &unit, &opts, port, identity.as_deref())?;
+        }
+        Some(Commands::Deploy {
+            alias,
+            put,
+            run,
+            preserve,
+            force,
+            no_clobber,
+            backup,
+            recursive,
+            progress,
+            port,
+            identity,
+        }) => {
+            let opts = TransferOptions {
+                preserve,
+                policy: OverwritePolicy::from_flags(force, no_clobber, backup),
+                recursive,
+                tar: false,
+                progress: effective_progress(progress),
+                buffer_size: transfer::DEFAULT_BUFFER_SIZE,
+                checksum: false,
+                resume: false,
+                walk: Default::default(),
+                skip_space_check: false,
+                whole_file: false,
+            };
+            run_deploy(&config, &alias, &put, &run, port, identity.as_deref(), opts)?;
+        }
+        Some(Commands::Stdio {
+            alias,
+            target,
+            port,
+            identity,
+        }) => {
+            // stdout 只传数据
+            output::use_stderr();
+            wol::wake_if_needed(&config, &alias, config.effective_port(&alias, port))?;
+            match target {
+                Some(target) => {
+                    let (host, target_port) = stdio::parse_target(&target)?;
+                    with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                        stdio::handle_channel(sess, &host, target_port)
+                    })?;
+                }
+                None => with_hooks(&config, &alias, port, || {
+                    ssh::connect_raw(&config, &alias, port).and_then(stdio::handle_raw)
+                })?,
+            }
+        }
+        Some(Commands::Proxy {
+            alias,
+            listen,
+            reverse,
+            port,
+            identity,
+        }) => {
+            wol::wake_if_needed(&config, &alias, config.effective_port(&alias, port))?;
+            with_session(&config, &alias, port, identity.as_deref(), |sess| match reverse {
+                Some(remote_port) => proxy::handle_proxy(sess, &alias, remote_port, true),
+                None => proxy::handle_proxy(sess, &alias, listen, false),
+            })?;
+        }
+        Some(Commands::Tunnel {
+            alias,
+            forwards,
+            auto,
+            port,
+            identity,
+        }) => {
+            wol::wake_if_needed(&config, &alias, config.effective_port(&alias, port))?;
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                tunnel::handle_tunnel(sess, &alias, forwards, auto)
+            })?;
+        }
+        Some(Commands::PortCheck {
+            alias,
+            remote_port,
+            port,
+            identity,
+        }) => {
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                portcheck::handle_port_check(&sess, &alias, remote_port)
+            })?;
+        }
+        Some(Commands::Fanout {
+            aliases,
+            tag,
+            script,
+            collect,
+            jobs,
+            port,
+            identity,
+        }) => {
+            let aliases = multi::select_aliases(&config, aliases, tag.as_deref())?;
+            fanout::handle_fanout(&config, &aliases, &script, collect.as_deref(), jobs as usize, port, identity.as_deref())?;
+        }
+        Some(Commands::AuditCrypto {
+            aliases,
+            all,
+            tag,
+            jobs,
+            port,
+        }) => {
+            let aliases = match all {
+                true => config.connections.keys().cloned().collect(),
+                false => multi::select_aliases(&config, aliases, tag.as_deref())?,
+            };
+            crypto_audit::handle_audit_crypto(&config, &aliases, port, jobs as usize)?;
+        }
+        Some(Commands::Status {
+            aliases,
+            tag,
+            jobs,
+            port,
+            identity,
+        }) => {
+            let aliases = multi::select_aliases(&config, aliases, tag.as_deref())?;
+            status::handle_status(&config, &aliases, port, identity.as_deref(), jobs as usize)?;
+        }
+        Some(Commands::Multiconnect {
+            aliases,
+            tag,
+            port,
+            identity,
         }) => {
-            let sess = ssh::create_session(&config, &alias, port, identity.as_deref())?;
-            ssh::handle_interactive_shell(sess)?;
+            let aliases = multi::select_aliases(&config, aliases, tag.as_deref())?;
+            multi::handle_multiconnect(&config, &aliases, port, identity.as_deref())?;
         }
         Some(Commands::Upload {
             alias,
-            local_path,
+            local_paths,
             remote_path,
+            also,
+            extract,
+            remove_archive,
+            yes,
+            preserve,
+            force,
+            no_clobber,
+            backup,
+            recursive,
+            walk,
+            tar,
+            checksum,
+            resume,
+            skip_space_check,
+            whole_file,
+            progress,
+            buffer_size,
+            queue,
             port,
             identity,
         }) => {
-            let sess = ssh::create_session(&config, &alias, port, identity.as_deref())?;
-            ssh::handle_upload(sess, &local_path, &remote_path)?;
+            let opts = TransferOptions {
+                preserve,
+                policy: OverwritePolicy::from_flags(force, no_clobber, backup),
+                recursive,
+                tar,
+                progress: effective_progress(progress),
+                buffer_size: remote_fs::parse_size(&buffer_size)? as usize,
+                checksum,
+                resume,
+                walk: walk.options(),
+                skip_space_check,
+                whole_file,
+            };
+            // 每个目标目录依次上传全部文件，共用同一个连接
+            let jobs: Vec<Job> = iter::once(remote_path)
+                .chain(also)
+                .flat_map(|to| local_paths.iter().map(move |from| Job { from: from.clone(), to: to.clone() }))
+                .collect();
+            let extract = extract.then_some(Extract { remove_archive });
+            if multi::is_pattern(&alias) {
+                let aliases = multi::match_pattern(&config, &alias)?;
+                multi::confirm_matches(&config, &alias, &aliases, "upload to", yes)?;
+                for_each_matched(&aliases, |alias| {
+                    run_upload(&config, alias, jobs.clone(), &queue, port, identity.as_deref(), opts, extract)
+                })?;
+            } else {
+                run_upload(&config, &alias, jobs, &queue, port, identity.as_deref(), opts, extract)?;
+            }
         }
         Some(Commands::Download {
             alias,
-            remote_path,
+            remote_paths,
+            local_path,
+            preserve,
+            force,
+            no_clobber,
+            backup,
+            recursive,
+            walk,
+            tar,
+            checksum,
+            resume,
+            whole_file,
+            progress,
+            buffer_size,
+            queue,
+            port,
+            identity,
+        }) => {
+            let opts = TransferOptions {
+                preserve,
+                policy: OverwritePolicy::from_flags(force, no_clobber, backup),
+                recursive,
+                tar,
+                progress: effective_progress(progress),
+                buffer_size: remote_fs::parse_size(&buffer_size)? as usize,
+                checksum,
+                resume,
+                walk: walk.options(),
+                skip_space_check: false,
+                whole_file,
+            };
+            let jobs = remote_paths
+                .into_iter()
+                .map(|from| Job { from, to: local_path.clone() })
+                .collect();
+            run_download(&config, &alias, jobs, &queue, port, identity.as_deref(), opts)?;
+        }
+        Some(Commands::Append {
+            alias,
             local_path,
+            remote_path,
+            offset,
+            port,
+            identity,
+        }) => {
+            guard::confirm(&config, &alias, "upload to")?;
+            let started = Instant::now();
+            let result = with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                transfer::handle_append(&sess, &local_path, &remote_path, offset)
+            });
+            audit_transfer(&config, "append", &alias, port, started, &result, &local_path, &remote_path);
+            result.map_err(error::transfer)?;
+        }
+        Some(Commands::Copy {
+            source,
+            destination,
+            preserve,
+            force,
+            no_clobber,
+            backup,
+            recursive,
+            walk,
+            direct,
+            progress,
+            buffer_size,
+        }) => {
+            let (src_alias, src_path) = parse_remote_spec(&source)?;
+            let (dst_alias, dst_path) = parse_remote_spec(&destination)?;
+            let opts = TransferOptions {
+                preserve,
+                policy: OverwritePolicy::from_flags(force, no_clobber, backup),
+                recursive,
+                tar: false,
+                progress: effective_progress(progress),
+                buffer_size: remote_fs::parse_size(&buffer_size)? as usize,
+                checksum: false,
+                resume: false,
+                walk: walk.options(),
+                skip_space_check: false,
+                whole_file: false,
+            };
+            check_walk(opts, direct)?;
+            guard::confirm(&config, dst_alias, "copy to")?;
+            let direct = match direct {
+                true => Some(direct_target(&config, dst_alias)?),
+                false => None,
+            };
+            let started = Instant::now();
+            let result = with_session(&config, src_alias, None, None, |src| {
+                with_session(&config, dst_alias, None, None, |dst| {
+                    let src_sftp = src.sftp().context("Failed to create SFTP session on the source")?;
+                    let dst_sftp = dst.sftp().context("Failed to create SFTP session on the destination")?;
+                    transfer::handle_copy(&src, &src_sftp, &src_path, &dst, &dst_sftp, &dst_path, opts, direct)
+                })
+            });
+            audit_transfer(&config, "copy", src_alias, None, started, &result, Path::new(&source), Path::new(&destination));
+            result.map_err(error::transfer)?;
+        }
+        Some(Commands::Cp {
+            paths,
+            preserve,
+            force,
+            no_clobber,
+            backup,
+            recursive,
+            walk,
+            tar,
+            checksum,
+            resume,
+            skip_space_check,
+            whole_file,
+            direct,
+            progress,
+            buffer_size,
+            queue,
+            port,
+            identity,
+        }) => {
+            let opts = TransferOptions {
+                preserve,
+                policy: OverwritePolicy::from_flags(force, no_clobber, backup),
+                recursive,
+                tar,
+                progress: effective_progress(progress),
+                buffer_size: remote_fs::parse_size(&buffer_size)? as usize,
+                checksum,
+                resume,
+                walk: walk.options(),
+                skip_space_check,
+                whole_file,
+            };
+            let mut locations = paths
+                .iter()
+                .map(|path| cp::parse_location(&config, path))
+                .collect::<Result<Vec<_>>>()?;
+            let destination = locations.pop().context("Missing destination")?;
+            // 所有源都在本地，或都在同一台主机上
+            let mut source_alias = None;
+            let mut sources = Vec::new();
+            for location in locations {
+                let (alias, path) = match location {
+                    Location::Local(path) => (None, path),
+                    Location::Remote { alias, path } => (Some(alias), path),
+                };
+                if !sources.is_empty() && alias != source_alias {
+                    return Err(anyhow!("All sources must be on the same side: local paths, or paths on one alias."));
+                }
+                source_alias = alias;
+                sources.push(path);
+            }
+            let remote_to_remote = source_alias.is_some() && matches!(destination, Location::Remote { .. });
+            if direct && !remote_to_remote {
+                return Err(anyhow!("--direct only applies when copying between two hosts."));
+            }
+            if tar && (remote_to_remote || source_alias.is_none() && matches!(destination, Location::Local(_))) {
+                return Err(anyhow!("--tar only applies to uploads and downloads."));
+            }
+            if (checksum || resume) && remote_to_remote {
+                return Err(anyhow!("--checksum and --continue don't apply when copying between two hosts."));
+            }
+            match (source_alias, destination) {
+                (None, Location::Local(dst_dir)) => {
+                    for source in &sources {
+                        transfer::handle_local_copy(source, &dst_dir, opts).map_err(error::transfer)?;
+                    }
+                }
+                (None, Location::Remote { alias, path }) => {
+                    let jobs = sources.into_iter().map(|from| Job { from, to: path.clone() }).collect();
+                    run_upload(&config, &alias, jobs, &queue, port, identity.as_deref(), opts, None)?;
+                }
+                (Some(alias), Location::Local(dst_dir)) => {
+                    let jobs = sources.into_iter().map(|from| Job { from, to: dst_dir.clone() }).collect();
+                    run_download(&config, &alias, jobs, &queue, port, identity.as_deref(), opts)?;
+                }
+                (Some(src_alias), Location::Remote { alias: dst_alias, path: dst_dir }) => {
+                    if port.is_some() || identity.is_some() {
+                        return Err(anyhow!("--port and --identity can't be used when both sides are remote."));
+                    }
+                    check_walk(opts, direct)?;
+                    guard::confirm(&config, &dst_alias, "copy to")?;
+                    let direct = match direct {
+                        true => Some(direct_target(&config, &dst_alias)?),
+                        false => None,
+                    };
+                    with_session(&config, &src_alias, None, None, |src| {
+                        with_session(&config, &dst_alias, None, None, |dst| {
+                            let src_sftp = src.sftp().context("Failed to create SFTP session on the source")?;
+                            let dst_sftp = dst.sftp().context("Failed to create SFTP session on the destination")?;
+                            for source in &sources {
+                                let started = Instant::now();
+                                let result = cp::for_each_match(&src_sftp, source, |path| {
+                                    let direct = direct.clone();
+                                    transfer::handle_copy(&src, &src_sftp, path, &dst, &dst_sftp, &dst_dir, opts, direct)
+                                });
+                                audit_transfer(&config, "copy", &src_alias, None, started, &result, source, &dst_dir);
+                                result.map_err(error::transfer)?;
+                            }
+                            Ok(())
+                        })
+                    })?;
+                }
+            }
+        }
+        Some(Commands::Ls {
+            alias,
+            path,
+            all,
+            port,
+            identity,
+        }) => {
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                remote_fs::handle_ls(sess, &path, all)
+            })?;
+        }
+        Some(Commands::Rm {
+            alias,
+            path,
+            recursive,
+            trash,
+            port,
+            identity,
+        }) => {
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                match trash {
+                    true => trash::handle_rm(&sess.sftp().context("Failed to create SFTP session")?, &alias, &path, recursive),
+                    false => remote_fs::handle_rm(sess, &path, recursive),
+                }
+            })?;
+        }
+        Some(Commands::Mkdir {
+            alias,
+            path,
+            parents,
+            port,
+            identity,
+        }) => {
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                remote_fs::handle_mkdir(sess, &path, parents)
+            })?;
+        }
+        Some(Commands::Mv {
+            alias,
+            from,
+            to,
             port,
             identity,
         }) => {
-            let sess = ssh::create_session(&config, &alias, port, identity.as_deref())?;
-            ssh::handle_download(sess, &remote_path, &local_path)?;
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                remote_fs::handle_mv(sess, &from, &to)
+            })?;
+        }
+        Some(Commands::Stat {
+            alias,
+            path,
+            port,
+            identity,
+        }) => {
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                remote_fs::handle_stat(sess, &path)
+            })?;
+        }
+        Some(Commands::Fingerprint { alias, port }) => {
+            hostkey::handle_fingerprint(&config, &alias, port)?;
+        }
+        Some(Commands::Pin { alias, port }) => {
+            hostkey::handle_pin(&config, &alias, port)?;
+        }
+        Some(Commands::Keygen {
+            key_type,
+            output,
+            comment,
+            no_passphrase,
+        }) => {
+            keys::handle_keygen(key_type, output, comment, no_passphrase)?;
+        }
+        Some(Commands::CopyId {
+            alias,
+            key,
+            port,
+            identity,
+        }) => {
+            let public_key = keys::resolve_public_key(&config, &alias, key.as_deref())?;
+            let added = with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                keys::copy_id(&sess, &public_key)
+            })?;
+            if added {
+                output::status(format!("Installed {} on '{}'.", public_key.display(), alias));
+            } else {
+                output::status(format!("{} is already installed on '{}'.", public_key.display(), alias));
+            }
+            let identity = keys::use_identity(&mut config, &alias, &public_key)?;
+            if let Some(identity) = &identity {
+                output::status(format!("'{}' will now log in with {}.", alias, identity.display()));
+            }
+            output::event(
+                "key_installed",
+                json!({ "alias": alias, "public_key": public_key, "added": added, "identity": identity }),
+            );
+        }
+        Some(Commands::Ping {
+            alias,
+            count,
+            port,
+            identity,
+        }) => {
+            with_hooks(&config, &alias, port, || {
+                ping::handle_ping(&config, &alias, port, identity.as_deref(), count)
+            })?;
+        }
+        Some(Commands::Bench {
+            alias,
+            size,
+            direction,
+            port,
+            identity,
+        }) => {
+            let size = remote_fs::parse_size(&size)?;
+            with_session(&config, &alias, port, identity.as_deref(), |sess| {
+                bench::handle_bench(sess, size, direction)
+            })?;
+        }
+        Some(Commands::Wake {
+            alias,
+            wait,
+            timeout,
+            port,
+        }) => {
+            let port = config.effective_port(&alias, port);
+            wol::handle_wake(&config, &alias, port, wait.then_some(timeout))?;
         }
+        Some(Commands::Hostkey { action }) => match action {
+            HostkeyCommand::List { host, port, file } => {
+                hostkey::handle_list(&config, host.as_deref(), port, file)?;
+            }
+            HostkeyCommand::Remove { host, port, file } => {
+                hostkey::handle_remove(&config, &host, port, file)?;
+            }
+            HostkeyCommand::Export { host, port, file } => {
+                hostkey::handle_export(&config, host.as_deref(), port, file)?;
+            }
+        },
+        Some(Commands::Undo) => backup::handle_undo()?,
+        Some(Commands::Config { action }) => match action {
+            ConfigCommand::Backups => backup::handle_list()?,
+            ConfigCommand::Restore { timestamp } => backup::handle_restore(&timestamp)?,
+            ConfigCommand::HistorySize { size: None } => {
+                let size = config.history_size.unwrap_or(history::DEFAULT_HISTORY_SIZE);
+                output::status(match size {
+                    0 => "Command history is off.".to_string(),
+                    size => format!("Up to {} commands are remembered per alias.", size),
+                });
+                output::event("history_size", json!({ "size": size }));
+            }
+            ConfigCommand::HistorySize { size: Some(size) } => {
+                config.history_size = Some(size);
+                config.save()?;
+                history::truncate(size)?;
+                output::status(match size {
+                    0 => "Command history turned off and cleared.".to_string(),
+                    size => format!("Up to {} commands will be remembered per alias.", size),
+                });
+                output::event("history_size", json!({ "size": size }));
+            }
+            ConfigCommand::ConfirmTags { tags: None } => {
+                output::status(match config.confirm_tags.is_empty() {
+                    true => "No protected tags.".to_string(),
+                    false => format!("Protected tags: {}", config.confirm_tags.join(", ")),
+                });
+                output::event("confirm_tags", json!({ "tags": config.confirm_tags }));
+            }
+            ConfigCommand::ConfirmTags { tags: Some(tags) } => {
+                config.confirm_tags = tags.into_iter().filter(|tag| !tag.is_empty()).collect();
+                config.save()?;
+                output::status(match config.confirm_tags.is_empty() {
+                    true => "Removed all protected tags.".to_string(),
+                    false => format!("Hosts tagged {} now need their alias typed first.", config.confirm_tags.join(", ")),
+                });
+                output::event("confirm_tags", json!({ "tags": config.confirm_tags }));
+            }
+        },
+        Some(Commands::Vault { action }) => match action {
+            VaultCommand::Init => vault::handle_init(&config)?,
+            VaultCommand::Unlock { timeout } => vault::handle_unlock(timeout)?,
+            VaultCommand::Lock => vault::handle_lock()?,
+            VaultCommand::Status => vault::handle_status()?,
+            VaultCommand::Passwd => vault::handle_passwd()?,
+            VaultCommand::Disable => vault::handle_disable()?,
+        },
+        Some(Commands::Secrets { action }) => match action {
+            SecretsCommand::List => secrets::handle_list(&config)?,
+            SecretsCommand::Set { name } => secret_env::handle_set(&name)?,
+            SecretsCommand::Delete { alias } => secrets::handle_delete(&config, &alias)?,
+            SecretsCommand::Purge { orphaned, yes } => secrets::handle_purge(&config, orphaned, yes)?,
+        },
+        Some(Commands::Audit { action }) => match action {
+            AuditCommand::Show { since, alias } => {
+                audit::handle_show(&config, since.as_deref(), alias.as_deref())?;
+            }
+            AuditCommand::Path { path: None } => {
+                let path = audit::audit_path(&config)?;
+                output::status(path.display());
+                output::event("audit_path", json!({ "path": path }));
+            }
+            AuditCommand::Path { path: Some(path) } => {
+                config.audit_log = Some(path.clone());
+                config.save()?;
+                output::status(format!("Audit log will be written to {}.", path.display()));
+                output::event("audit_path", json!({ "path": path }));
+            }
+        },
+        Some(Commands::Template { action }) => match action {
+            TemplateCommand::Add { name, settings } => {
+                config.templates.insert(
+                    name.clone(),
+                    Template {
+                        port: settings.port,
+                        identity: settings.identity,
+                        jump_host: settings.jump,
+                        tags: settings.tags,
+                    },
+                );
+                config.save()?;
+                output::status(format!("Template '{}' added.", name));
+                output::event("template_added", json!({ "name": name }));
+            }
+            TemplateCommand::List => {
+                if output::is_json() {
+                    output::emit("templates", json!({ "templates": config.templates }));
+                } else if config.templates.is_empty() {
+                    println!("No templates saved. Use 'rssh template add <name>' to add one.");
+                } else {
+                    println!("Saved templates:");
+                    for (name, template) in &config.templates {
+                        println!("  {}: {}", name, describe_template(template));
+                    }
+                }
+            }
+            TemplateCommand::Remove { name } => {
+                if config.templates.remove(&name).is_none() {
+                    return Err(anyhow!("Template '{}' not found.", name));
+                }
+                config.save()?;
+                output::status(format!("Template '{}' removed.", name));
+                output::event("template_removed", json!({ "name": name }));
+            }
+        },
+        Some(Commands::Cmd { action }) => match action {
+            CmdCommand::Add { name, command, alias } => cmds::handle_add(&mut config, &name, &command, alias.as_deref())?,
+            CmdCommand::Edit { name, alias } => cmds::handle_edit(&mut config, &name, alias.as_deref())?,
+            CmdCommand::Remove { name, alias } => cmds::handle_remove(&mut config, &name, alias.as_deref())?,
+            CmdCommand::List { alias } => cmds::handle_list(&config, alias.as_deref())?,
+            CmdCommand::Run {
+                alias,
+                name,
+                args,
+                port,
+                identity,
+                merge_stderr,
+                timeout,
+                signal,
+            } => {
+                let command = cmds::resolve(&config, &alias, &name, &args)?;
+                let exec = ExecOptions { merge_stderr, sudo: false, sudo_user: None, secret_env: &[], timeout, signal };
+                run_exec(&config, &alias, &command, port, identity.as_deref(), exec)?;
+            }
+        },
+        Some(Commands::Agent { action }) => match action {
+            AgentCommand::List => agent::handle_list(&config)?,
+        },
+        Some(Commands::Recipe { action }) => match action {
+            RecipeCommand::List => recipe::handle_list(&config)?,
+            RecipeCommand::Run {
+                name,
+                alias,
+                vars,
+                force,
+                dry_run,
+                progress,
+                port,
+                identity,
+            } => {
+                let steps = recipe::prepare(&config, &name, &alias, &vars)?;
+                if dry_run {
+                    for (index, step) in steps.iter().enumerate() {
+                        println!("{}. {}", index + 1, recipe::describe(step));
+                    }
+                    return Ok(());
+                }
+                let opts = TransferOptions {
+                    preserve: false,
+                    policy: OverwritePolicy::from_flags(force, false, false),
+                    recursive: false,
+                    tar: false,
+                    progress: effective_progress(progress),
+                    buffer_size: transfer::DEFAULT_BUFFER_SIZE,
+                    checksum: false,
+                    resume: false,
+                    walk: Default::default(),
+                    skip_space_check: false,
+                    whole_file: false,
+                };
+                run_recipe(&config, &name, &alias, &steps, port, identity.as_deref(), opts)?;
+            }
+        },
+        Some(Commands::Trash { action }) => match action {
+            TrashCommand::List { alias, port, identity } => {
+                with_sftp(&config, &alias, port, identity.as_deref(), |sftp| trash::handle_list(sftp, &alias))?;
+            }
+            TrashCommand::Restore { alias, id, port, identity } => {
+                with_sftp(&config, &alias, port, identity.as_deref(), |sftp| trash::handle_restore(sftp, &alias, &id))?;
+            }
+            TrashCommand::Empty { alias, yes, port, identity } => {
+                with_sftp(&config, &alias, port, identity.as_deref(), |sftp| trash::handle_empty(sftp, &alias, yes))?;
+            }
+        },
+        Some(Commands::Hook { action }) => match action {
+            HookCommand::Set {
+                alias,
+                event,
+                command,
+                timeout,
+                on_failure,
+            } => {
+                let conn = config
+                    .connections
+                    .get_mut(&alias)
+                    .ok_or_else(|| error::alias_not_found(&alias))?;
+                let hook = Some(Hook {
+                    command,
+                    timeout,
+                    on_failure,
+                });
+                match event {
+                    HookEvent::PreConnect => conn.pre_connect = hook,
+                    HookEvent::PostDisconnect => conn.post_disconnect = hook,
+                }
+                config.save()?;
+                output::status(format!("{} hook set for '{}'.", event.name(), alias));
+                output::event("hook_set", json!({ "alias": alias, "hook": event.name() }));
+            }
+            HookCommand::Clear { alias, event } => {
+                let conn = config
+                    .connections
+                    .get_mut(&alias)
+                    .ok_or_else(|| error::alias_not_found(&alias))?;
+                match event {
+                    HookEvent::PreConnect => conn.pre_connect = None,
+                    HookEvent::PostDisconnect => conn.post_disconnect = None,
+                }
+                config.save()?;
+                output::status(format!("{} hook cleared for '{}'.", event.name(), alias));
+                output::event("hook_cleared", json!({ "alias": alias, "hook": event.name() }));
+            }
+        },
+        Some(Commands::Completions { shell }) => completion::print_script(shell),
+        Some(Commands::Complete { command }) => match command {
+            CompleteCommand::Aliases => completion::complete_aliases(&config),
+            CompleteCommand::Remote { alias, prefix } => completion::complete_remote(config, &alias, &prefix)?,
+        },
         None => {
+            // 设置了默认别名时直接连接，不显示选择器
+            if let Some(alias) = default_alias(&config)? {
+                let options = ShellOptions::for_connection(&alias, config.resolved(&alias).as_ref());
+                return run_shell(&config, &alias, None, None, &options);
+            }
             // Interactive mode
-            let aliases: Vec<String> = config.connections.keys().cloned().collect();
-            if aliases.is_empty() {
+            if !prompt::can_ask() {
+                return Err(prompt::unavailable("No command given, and the connection picker needs a terminal. See 'rssh --help'."));
+            }
+            if setup::is_first_run(&config) {
+                config = Config::load_for_update()?;
+                setup::run(&mut config)?;
+            }
+            if config.connections.is_empty() {
                 println!("No connections saved. Use 'add' command first.");
                 return Ok(());
             }
-            let choice = Select::new("Select a connection to open:", aliases).prompt()?;
-            let port_str = Text::new("Enter port:").with_default("22").prompt()?;
+            let choice = select_connection(&config)?;
+            let default_port = config.effective_port(&choice, None);
+            let port_str = Text::new("Enter port:").with_default(&default_port.to_string()).prompt()?;
             let port = port_str.parse::<u16>().context("Invalid port number")?;
 
             let use_identity = Confirm::new("Use identity file (private key)?")
@@ -92,10 +1330,650 @@ fn main() -> Result<()> {
                 None
             };
 
-            let sess = ssh::create_session(&config, &choice, port, identity_path.as_deref())?;
-            ssh::handle_interactive_shell(sess)?;
+            // 保留默认值时不算显式指定
+            let port = (port != default_port).then_some(port);
+            let options = ShellOptions::for_connection(&choice, config.resolved(&choice).as_ref());
+            run_shell(&config, &choice, port, identity_path.as_deref(), &options)?;
+        }
+    }
+
+    Ok(())
+}
+
+// connect 和交互模式共用：确认、唤醒、打开交互 shell 并写审计日志
+fn run_shell(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>, options: &ShellOptions) -> Result<()> {
+    let _quiet = output::quiet_session(options.quiet_login);
+    guard::confirm(config, alias, "connect to")?;
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| {
+        hostmeta::refresh_if_stale(&sess, alias);
+        ssh::handle_interactive_shell(sess, options)
+    });
+    audit_connect(config, alias, port, started, &result);
+    result.map(|_| ())
+}
+
+// 别名通配符匹配到的主机依次执行，一台失败不影响其余的，最后汇总失败的主机
+fn for_each_matched(aliases: &[String], mut run: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut failed = Vec::new();
+    for alias in aliases {
+        output::status(format!("==> {} <==", alias));
+        if let Err(e) = run(alias) {
+            match error::classify(&e) {
+                Some(RsshError::RemoteExit(code)) => eprintln!("'{}': exited with status {}", alias, code),
+                _ => eprintln!("'{}': {:#}", alias, e),
+            }
+            failed.push(alias.as_str());
+        }
+    }
+    match failed.len() {
+        0 => Ok(()),
+        n => Err(anyhow!("Failed on {} of {} hosts: {}", n, aliases.len(), failed.join(", "))),
+    }
+}
+
+// 默认别名；指向不存在的连接时报错，而不是悄悄退回选择器
+fn default_alias(config: &Config) -> Result<Option<String>> {
+    match config.effective_default_alias() {
+        Some(alias) if !config.connections.contains_key(&alias) => Err(error::config(anyhow!(
+            "The default alias '{}' does not exist. Change it with 'rssh set-default' or {}.",
+            alias,
+            config::DEFAULT_ALIAS_ENV
+        ))),
+        alias => Ok(alias),
+    }
+}
+
+// 一次删除一个或多个连接，同时删除保存的密码
+fn handle_remove(config: &mut Config, aliases: Vec<String>, tag: Option<&str>, yes: bool) -> Result<()> {
+    let interactive = aliases.is_empty() && tag.is_none();
+    let selected = if interactive {
+        if !prompt::can_ask() {
+            return Err(prompt::unavailable("Pass the aliases to remove or use --tag <tag>."));
+        }
+        let mut choices: Vec<String> = config.connections.keys().cloned().collect();
+        if choices.is_empty() {
+            println!("No connections saved.");
+            return Ok(());
+        }
+        choices.sort();
+        let selected = MultiSelect::new("Select connections to remove:", choices).prompt()?;
+        if selected.is_empty() {
+            return Ok(());
+        }
+        selected
+    } else {
+        multi::select_aliases(config, aliases, tag)?
+    };
+
+    for alias in &selected {
+        config.check_editable(alias)?;
+    }
+
+    // 单个别名照旧直接删除，批量删除先确认
+    if (interactive || selected.len() > 1) && !yes {
+        if !prompt::can_ask() {
+            return Err(prompt::unavailable(format!("Refusing to remove {} connections without --yes.", selected.len())));
+        }
+        println!("About to remove {} connection(s):", selected.len());
+        for alias in &selected {
+            println!("  {} -> {}", alias, config.connections[alias].target);
+        }
+        if !Confirm::new("Remove them and their saved passwords?").with_default(false).prompt()? {
+            return Ok(());
         }
     }
 
+    for alias in &selected {
+        config.connections.remove(alias);
+    }
+    let cleared_default = config.default_alias.take_if(|alias| selected.contains(alias));
+    config.save()?;
+    if let Some(alias) = cleared_default {
+        output::status(format!("'{}' was the default alias; the default has been cleared.", alias));
+    }
+    for alias in &selected {
+        hostmeta::forget(alias);
+        history::forget(alias);
+        // 连接已经删除，密码删不掉只给出警告
+        if let Err(e) = delete_password(alias).and_then(|_| delete_sudo_password(alias)) {
+            eprintln!("Warning: could not delete the saved passwords for '{}': {:#}", alias, e);
+        }
+        output::status(format!("Connection '{}' removed.", alias));
+        output::event("connection_removed", json!({ "alias": alias }));
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+const LAST_GROUP_FILE: &str = "last-group";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Group<'a> {
+    All,
+    Tag(&'a str),
+    Untagged,
+}
+
+impl Group<'_> {
+    fn contains(&self, tags: &[String]) -> bool {
+        match self {
+            Group::All => true,
+            Group::Tag(tag) => tags.iter().any(|t| t == tag),
+            Group::Untagged => tags.is_empty(),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Group::All => "all".to_string(),
+            Group::Tag(tag) => format!("tag:{}", tag),
+            Group::Untagged => "untagged".to_string(),
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Group::All => "All",
+            Group::Tag(tag) => tag,
+            Group::Untagged => "Untagged",
+        }
+    }
+}
+
+// 有标签时先选分组（标签），再选其中的主机；默认停在上次选的分组上
+fn select_connection(config: &Config) -> Result<String> {
+    let mut aliases: Vec<String> = config.connections.keys().cloned().collect();
+    aliases.sort();
+    let mut tags: Vec<&str> = config
+        .connections
+        .values()
+        .flat_map(|conn| config.effective_tags(conn).iter().map(String::as_str))
+        .collect();
+    tags.sort();
+    tags.dedup();
+    if tags.is_empty() {
+        return pick_alias("Select a connection to open:", aliases);
+    }
+
+    let mut groups = vec![Group::All];
+    groups.extend(tags.into_iter().map(Group::Tag));
+    groups.push(Group::Untagged);
+    let count = |group: &Group| {
+        config
+            .connections
+            .values()
+            .filter(|conn| group.contains(config.effective_tags(conn)))
+            .count()
+    };
+    groups.retain(|group| count(group) > 0);
+
+    let last_path = config::state_dir().map(|dir| dir.join(LAST_GROUP_FILE));
+    let last = last_path.as_ref().ok().and_then(|path| std::fs::read_to_string(path).ok());
+    let cursor = last
+        .and_then(|last| groups.iter().position(|group| group.name() == last.trim()))
+        .unwrap_or(0);
+    let labels: Vec<String> = groups
+        .iter()
+        .map(|group| format!("{} ({})", group.label(), count(group)))
+        .collect();
+    let picked = Select::new("Select a group:", labels).with_starting_cursor(cursor).raw_prompt()?;
+    let group = groups[picked.index];
+    // 记不住上次的分组不影响连接
+    if let Ok(path) = &last_path
+        && std::fs::create_dir_all(path.parent().unwrap()).is_ok()
+    {
+        let _ = std::fs::write(path, group.name());
+    }
+
+    let members: Vec<String> = aliases
+        .into_iter()
+        .filter(|alias| group.contains(config.effective_tags(&config.connections[alias])))
+        .collect();
+    pick_alias(&format!("Select a connection in {}:", group.label()), members)
+}
+
+// 登录时记录过系统信息的主机在别名后面显示系统和架构，输入时也可以按它过滤
+fn pick_alias(message: &str, aliases: Vec<String>) -> Result<String> {
+    let metadata = hostmeta::load_all();
+    let width = aliases.iter().map(|alias| alias.chars().count()).max().unwrap_or(0);
+    let labels: Vec<String> = aliases
+        .iter()
+        .map(|alias| match metadata.get(alias).map(HostMeta::summary).filter(|summary| !summary.is_empty()) {
+            Some(summary) => format!("{:<width$}  {}", alias, summary),
+            None => alias.clone(),
+        })
+        .collect();
+    let picked = Select::new(message, labels).raw_prompt()?;
+    Ok(aliases[picked.index].clone())
+}
+
+// 在钩子之间打开会话并执行操作：连接前运行 pre_connect，结束后运行 post_disconnect
+fn with_session<T, F>(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>, f: F) -> Result<T>
+where
+    F: FnOnce(Session) -> Result<T>,
+{
+    // 会话在 f 返回时被释放，即已断开
+    with_hooks(config, alias, port, || ssh::create_session(config, alias, port, identity).and_then(f))
+}
+
+fn with_sftp<T, F>(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>, f: F) -> Result<T>
+where
+    F: FnOnce(&Sftp) -> Result<T>,
+{
+    with_session(config, alias, port, identity, |sess| f(&sess.sftp().context("Failed to create SFTP session")?))
+}
+
+// 在连接前后执行别名配置的钩子
+fn with_hooks<T, F>(config: &Config, alias: &str, port: Option<u16>, f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let hook_port = config.effective_port(alias, port);
+    hooks::run(config, alias, hook_port, HookEvent::PreConnect)?;
+    let result = f();
+    let post = hooks::run(config, alias, hook_port, HookEvent::PostDisconnect);
+    match (result, post) {
+        (Err(e), Err(post_err)) => {
+            eprintln!("Warning: {:#}", post_err);
+            Err(e)
+        }
+        (result, post) => post.and(result),
+    }
+}
+
+#[derive(Clone)]
+struct ExecOptions<'a> {
+    merge_stderr: bool,
+    sudo: bool,
+    sudo_user: Option<&'a str>,
+    secret_env: &'a [SecretEnv],
+    timeout: Option<u64>,
+    signal: Option<String>,
+}
+
+// exec 和 cmd run 共用：执行一条命令，以远程命令的退出码退出，便于脚本判断
+fn run_exec(
+    config: &Config,
+    alias: &str,
+    command: &str,
+    port: Option<u16>,
+    identity: Option<&Path>,
+    exec: ExecOptions,
+) -> Result<()> {
+    output::use_stderr();
+    // 设置了 quiet_login 的别名执行命令时不打印连接信息，只对这一台主机生效
+    let _quiet = output::quiet_session(config.connections.get(alias).is_some_and(|conn| conn.quiet_login));
+    guard::confirm(config, alias, "run a command on")?;
+    // 连接前问完，提示不会和登录提示交错
+    let env = secret_env::resolve(exec.secret_env)?;
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let timeout = exec.timeout.map(|seconds| ssh::ExecTimeout {
+        after: Duration::from_secs(seconds),
+        signal: exec.signal.clone(),
+    });
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| {
+        if exec.sudo {
+            let after = timeout.as_ref().map(|timeout| timeout.after);
+            sudo::handle_sudo_exec(sess, alias, config.connections.get(alias), command, exec.sudo_user, after)
+        } else {
+            ssh::handle_exec(sess, command, exec.merge_stderr, &env, timeout.as_ref())
+        }
+    });
+    let secret_env: Vec<_> = exec.secret_env.iter().map(|spec| &spec.name).collect();
+    audit::record(
+        config,
+        "exec",
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({
+            "command": command,
+            "sudo": exec.sudo,
+            "sudo_user": exec.sudo_user,
+            "secret_env": secret_env,
+            "timeout": exec.timeout,
+            "exit_code": result.as_ref().ok(),
+        }),
+    );
+    history::record(config, alias, command);
+    match result? {
+        0 => Ok(()),
+        code => Err(RsshError::RemoteExit(code).into()),
+    }
+}
+
+// 本机的 docker 命令经转发的套接字操作服务器上的 Docker，以 docker 的退出码退出
+fn run_docker(
+    config: &Config,
+    alias: &str,
+    args: &[String],
+    socket: &str,
+    port: Option<u16>,
+    identity: Option<&Path>,
+) -> Result<()> {
+    // stdout 留给 docker 的输出
+    output::use_stderr();
+    guard::confirm(config, alias, "use Docker on")?;
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| docker::handle_docker(sess, alias, socket, args));
+    audit::record(
+        config,
+        "docker",
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({ "args": args, "socket": socket, "exit_code": result.as_ref().ok() }),
+    );
+    match result? {
+        0 => Ok(()),
+        code => Err(RsshError::RemoteExit(code).into()),
+    }
+}
+
+// svc 的状态、重启和日志；以远程命令的退出码退出，服务没有运行时为 3
+fn run_svc(
+    config: &Config,
+    alias: &str,
+    action: SvcAction,
+    unit: &str,
+    opts: &SvcOptions,
+    port: Option<u16>,
+    identity: Option<&Path>,
+) -> Result<()> {
+    output::use_stderr();
+    if action == SvcAction::Restart {
+        guard::confirm(config, alias, "restart a service on")?;
+    }
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| {
+        svc::handle_svc(sess, alias, config.connections.get(alias), action, unit, opts)
+    });
+    // 只读的 status 和 logs 不记审计日志
+    if action == SvcAction::Restart {
+        audit::record(
+            config,
+            "svc",
+            alias,
+            config.effective_port(alias, port),
+            started,
+            result.as_ref().err(),
+            json!({ "action": action.name(), "unit": unit, "exit_code": result.as_ref().ok() }),
+        );
+    }
+    match result? {
+        0 => Ok(()),
+        code => Err(RsshError::RemoteExit(code).into()),
+    }
+}
+
+// 上传和命令共用一个连接，只握手、认证一次
+fn run_deploy(
+    config: &Config,
+    alias: &str,
+    puts: &[Put],
+    commands: &[String],
+    port: Option<u16>,
+    identity: Option<&Path>,
+    opts: TransferOptions,
+) -> Result<()> {
+    // stdout 留给远程命令的输出
+    output::use_stderr();
+    guard::confirm(config, alias, "deploy to")?;
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| {
+        deploy::handle_deploy(sess, alias, puts, commands, opts)
+    });
+    let uploads: Vec<_> = puts.iter().map(|put| json!({ "from": put.local, "to": put.remote })).collect();
+    audit::record(
+        config,
+        "deploy",
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({ "uploads": uploads, "commands": commands, "exit_code": result.as_ref().ok() }),
+    );
+    match result? {
+        0 => Ok(()),
+        code => Err(RsshError::RemoteExit(code).into()),
+    }
+}
+
+// 和 deploy 一样所有步骤共用一个连接
+fn run_recipe(
+    config: &Config,
+    name: &str,
+    alias: &str,
+    steps: &[RecipeStep],
+    port: Option<u16>,
+    identity: Option<&Path>,
+    opts: TransferOptions,
+) -> Result<()> {
+    // stdout 留给远程命令的输出
+    output::use_stderr();
+    guard::confirm(config, alias, "run a recipe on")?;
+    wol::wake_if_needed(config, alias, config.effective_port(alias, port))?;
+    let started = Instant::now();
+    let result = with_session(config, alias, port, identity, |sess| recipe::run_steps(sess, alias, steps, opts));
+    let described: Vec<String> = steps.iter().map(recipe::describe).collect();
+    audit::record(
+        config,
+        "recipe",
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({ "recipe": name, "steps": described, "exit_code": result.as_ref().ok() }),
+    );
+    match result? {
+        0 => Ok(()),
+        code => Err(RsshError::RemoteExit(code).into()),
+    }
+}
+
+fn audit_connect(config: &Config, alias: &str, port: Option<u16>, started: Instant, result: &Result<Option<i32>>) {
+    let exit_code = result.as_ref().ok().copied().flatten();
+    audit::record(
+        config,
+        "connect",
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({ "exit_code": exit_code }),
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_upload(
+    config: &Config,
+    alias: &str,
+    jobs: Vec<Job>,
+    settings: &QueueSettings,
+    port: Option<u16>,
+    identity: Option<&Path>,
+    opts: TransferOptions,
+    extract: Option<Extract>,
+) -> Result<()> {
+    check_walk(opts, false)?;
+    // 上传前就检查，不是压缩包的文件不会白传
+    if extract.is_some() {
+        for job in &jobs {
+            transfer::archive_command(&job.from)?;
+        }
+    }
+    guard::confirm(config, alias, "upload to")?;
+    let sources: Vec<&Path> = jobs.iter().map(|job| job.from.as_path()).collect();
+    let _total = match sources.len() {
+        1 => None,
+        _ => BatchProgress::start(opts.progress, Some(transfer::local_totals(&sources, opts))),
+    };
+    let results = with_hooks(config, alias, port, || {
+        Ok(queue_settings(settings, "Upload", jobs)?.run(
+            || ssh::create_session(config, alias, port, identity),
+            |sess, job| {
+                let stats = transfer::handle_upload(sess, alias, &job.from, &job.to, opts)?;
+                // 目标已存在而跳过上传时也不解压
+                if let Some(extract) = extract
+                    && !stats.all_skipped()
+                {
+                    transfer::extract_remote(&sess.sess, &job.from, &job.to, extract)?;
+                }
+                Ok(stats)
+            },
+        ))
+    })?;
+    audit_queue(config, "upload", alias, port, &results);
+    queue::into_result(results).map_err(error::transfer)
+}
+
+// 远程路径的最后一段可以带通配符，匹配到的每一项都下载
+fn run_download(
+    config: &Config,
+    alias: &str,
+    jobs: Vec<Job>,
+    settings: &QueueSettings,
+    port: Option<u16>,
+    identity: Option<&Path>,
+    opts: TransferOptions,
+) -> Result<()> {
+    check_walk(opts, false)?;
+    // 远程文件的大小要连上之后才知道，总进度随每次下载累加
+    let _total = match jobs.len() {
+        1 => None,
+        _ => BatchProgress::start(opts.progress, None),
+    };
+    let results = with_hooks(config, alias, port, || {
+        Ok(queue_settings(settings, "Download", jobs)?.run(
+            || ssh::create_session(config, alias, port, identity),
+            |conn, job| {
+                cp::for_each_match(conn.sftp()?, &job.from, |path| {
+                    transfer::handle_download(conn, alias, path, &job.to, opts)
+                })
+            },
+        ))
+    })?;
+    audit_queue(config, "download", alias, port, &results);
+    queue::into_result(results).map_err(error::transfer)
+}
+
+// tar 和服务器间直接 scp 自己遍历目录，不支持隐藏文件和符号链接的选项
+fn check_walk(opts: TransferOptions, direct: bool) -> Result<()> {
+    if opts.walk.is_default() {
+        return Ok(());
+    }
+    match (opts.tar, direct) {
+        (true, _) => Err(anyhow!("--tar can't be combined with the hidden-file, symlink or --one-file-system flags.")),
+        (_, true) => Err(anyhow!("--direct can't be combined with the hidden-file, symlink or --one-file-system flags.")),
+        _ => Ok(()),
+    }
+}
+
+fn queue_settings(settings: &QueueSettings, name: &'static str, jobs: Vec<Job>) -> Result<TransferQueue> {
+    let delay = Duration::try_from_secs_f64(settings.retry_delay)
+        .map_err(|_| anyhow!("Invalid --retry-delay '{}'", settings.retry_delay))?;
+    let retry = RetryPolicy { retries: settings.retries, delay };
+    Ok(TransferQueue::new(name, jobs, settings.jobs as usize, retry))
+}
+
+// 每个源路径记一条审计日志
+fn audit_queue(config: &Config, action: &str, alias: &str, port: Option<u16>, results: &[JobResult]) {
+    for result in results {
+        let (error, bytes) = match &result.outcome {
+            Outcome::Done(stats) => (None, Some(stats.bytes)),
+            Outcome::Skipped => (None, Some(0)),
+            Outcome::Failed(e) => (Some(e), None),
+        };
+        audit::record(
+            config,
+            action,
+            alias,
+            config.effective_port(alias, port),
+            result.started,
+            error,
+            json!({ "from": result.job.from, "to": result.job.to, "bytes": bytes, "attempts": result.attempts }),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn audit_transfer(
+    config: &Config,
+    action: &str,
+    alias: &str,
+    port: Option<u16>,
+    started: Instant,
+    result: &Result<TransferStats>,
+    from: &Path,
+    to: &Path,
+) {
+    audit::record(
+        config,
+        action,
+        alias,
+        config.effective_port(alias, port),
+        started,
+        result.as_ref().err(),
+        json!({ "from": from, "to": to, "bytes": result.as_ref().ok().map(|stats| stats.bytes) }),
+    );
+}
+
+// "alias:path" 形式的远程路径
+fn parse_remote_spec(spec: &str) -> Result<(&str, PathBuf)> {
+    match spec.split_once(':') {
+        Some((alias, path)) if !alias.is_empty() && !path.is_empty() => Ok((alias, PathBuf::from(path))),
+        _ => Err(anyhow!("Invalid remote path '{}'. Use <alias>:<path>.", spec)),
+    }
+}
+
+// 直接复制时由源主机连接目标，经跳板机的目标无法直连
+fn direct_target(config: &Config, alias: &str) -> Result<DirectTarget> {
+    let conn = config
+        .connections
+        .get(alias)
+        .ok_or_else(|| error::alias_not_found(alias))?;
+    if conn.jump_host.is_some() {
+        return Err(anyhow!("--direct can't be used with '{}' because it is reached through a jump host.", alias));
+    }
+    Ok(DirectTarget {
+        target: conn.target.clone(),
+        port: config.effective_port(alias, None),
+    })
+}
+
+fn describe_template(template: &Template) -> String {
+    let mut parts = Vec::new();
+    if let Some(port) = template.port {
+        parts.push(format!("port {}", port));
+    }
+    if let Some(identity) = &template.identity {
+        parts.push(format!("identity {}", identity.display()));
+    }
+    if let Some(jump) = &template.jump_host {
+        parts.push(format!("via {}", jump));
+    }
+    if !template.tags.is_empty() {
+        parts.push(format!("tags {}", template.tags.join(",")));
+    }
+    if parts.is_empty() {
+        "(empty)".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+// 全局 JSON 输出时，传输进度也使用 JSON 事件
+fn effective_progress(progress: ProgressMode) -> ProgressMode {
+    if output::is_json() {
+        ProgressMode::Json
+    } else {
+        progress
+    }
+}
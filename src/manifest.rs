@@ -0,0 +1,76 @@
+use crate::config::state_dir;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_DIR: &str = "transfers";
+
+/// 已经传完的一个文件；源文件的大小或修改时间变了就要重新传
+#[derive(Serialize, Deserialize)]
+struct Record {
+    path: PathBuf,
+    size: u64,
+    mtime: Option<u64>,
+}
+
+/// 递归传输时记录已完成的文件，中断后用 --continue 跳过它们。
+/// 每传完一个文件追加一行，整个目录传完后删除
+pub struct Manifest {
+    path: PathBuf,
+    file: File,
+    done: HashMap<PathBuf, (u64, Option<u64>)>,
+}
+
+impl Manifest {
+    /// 同一别名、方向、源和目标共用一个清单；不是 --continue 时从头记录
+    pub fn open(alias: &str, direction: &str, from: &Path, to: &Path, resume: bool) -> Result<Manifest> {
+        let key = format!("{}\0{}\0{}\0{}", alias, direction, from.display(), to.display());
+        let hash: String = Sha256::digest(key.as_bytes()).iter().take(8).map(|b| format!("{:02x}", b)).collect();
+        let dir = state_dir()?.join(MANIFEST_DIR);
+        fs::create_dir_all(&dir).context(format!("Failed to create {:?}", dir))?;
+        let path = dir.join(format!("{}.jsonl", hash));
+
+        let mut done = HashMap::new();
+        if resume && let Ok(existing) = File::open(&path) {
+            // 最后一行可能只写了一半，读不出来的行忽略
+            for line in BufReader::new(existing).lines().map_while(|line| line.ok()) {
+                if let Ok(record) = serde_json::from_str::<Record>(&line) {
+                    done.insert(record.path, (record.size, record.mtime));
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .truncate(false)
+            .open(&path)
+            .context(format!("Failed to open transfer manifest {:?}", path))?;
+        if !resume {
+            file.set_len(0).context(format!("Failed to reset transfer manifest {:?}", path))?;
+        }
+        Ok(Manifest { path, file, done })
+    }
+
+    /// 上次已完整传过，并且源文件没有变化
+    pub fn is_done(&self, dest: &Path, size: u64, mtime: Option<u64>) -> bool {
+        self.done.get(dest) == Some(&(size, mtime))
+    }
+
+    pub fn record(&self, dest: &Path, size: u64, mtime: Option<u64>) -> Result<()> {
+        let record = Record { path: dest.to_path_buf(), size, mtime };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        (&self.file)
+            .write_all(line.as_bytes())
+            .context(format!("Failed to update transfer manifest {:?}", self.path))
+    }
+
+    /// 整个目录传完，不再需要清单
+    pub fn finish(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::config::Config;
+use crate::cp;
+use crate::error;
+use crate::hooks::{self, HookEvent};
+use crate::output;
+use crate::prompt;
+use crate::ssh;
+use crate::term;
+use anyhow::{anyhow, Result};
+use inquire::Confirm;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use ssh2::{Channel, Session};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// 前缀键 Ctrl+]，之后的按键作为 multiconnect 自身的命令
+const PREFIX_KEY: char = ']';
+const REDRAW_INTERVAL: Duration = Duration::from_millis(30);
+const HELP_LINE: &str = " Ctrl+] then: a = type into all panes, Tab/1-9 = focus one pane, q = quit ";
+
+struct Pane {
+    alias: String,
+    sess: Session,
+    channel: Channel,
+    parser: vt100::Parser,
+    closed: bool,
+    dirty: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+}
+
+/// 按标签或别名选出要连接的主机；别名可以是通配符，如 'web-*'
+pub fn select_aliases(config: &Config, aliases: Vec<String>, tag: Option<&str>) -> Result<Vec<String>> {
+    let mut selected = Vec::new();
+    for alias in aliases {
+        if is_pattern(&alias) {
+            selected.extend(match_pattern(config, &alias)?);
+        } else if config.connections.contains_key(&alias) {
+            selected.push(alias);
+        } else {
+            return Err(error::alias_not_found(&alias));
+        }
+    }
+    if let Some(tag) = tag {
+        selected.extend(
+            config
+                .connections
+                .iter()
+                .filter(|(_, conn)| config.effective_tags(conn).iter().any(|t| t == tag))
+                .map(|(alias, _)| alias.clone()),
+        );
+    }
+    selected.sort();
+    selected.dedup();
+    if selected.is_empty() {
+        return Err(anyhow!("No connections matched. Pass aliases or use --tag <tag>."));
+    }
+    Ok(selected)
+}
+
+/// 别名里含 * ? [ 时按通配符匹配已保存的别名
+pub fn is_pattern(alias: &str) -> bool {
+    alias.contains(['*', '?', '['])
+}
+
+/// 匹配通配符的别名，按名字排序；一个都没有时报错
+pub fn match_pattern(config: &Config, pattern: &str) -> Result<Vec<String>> {
+    let mut matched: Vec<String> = config
+        .connections
+        .keys()
+        .filter(|alias| cp::glob_match(pattern, alias))
+        .cloned()
+        .collect();
+    if matched.is_empty() {
+        return Err(error::config(anyhow!("No saved aliases match '{}'.", pattern)));
+    }
+    matched.sort();
+    Ok(matched)
+}
+
+/// 对通配符匹配到的主机执行操作前列出它们并确认；`yes` 时不问
+pub fn confirm_matches(config: &Config, pattern: &str, aliases: &[String], action: &str, yes: bool) -> Result<()> {
+    if yes {
+        return Ok(());
+    }
+    if !prompt::can_ask() || output::is_json() {
+        return Err(prompt::unavailable(format!(
+            "'{}' matches {} hosts. Pass --yes to {} them without confirming.",
+            pattern,
+            aliases.len(),
+            action
+        )));
+    }
+    eprintln!("'{}' matches {} host(s):", pattern, aliases.len());
+    for alias in aliases {
+        eprintln!("  {} -> {}", alias, config.connections[alias].target);
+    }
+    if !Confirm::new(&format!("{} all of them?", capitalize(action))).with_default(false).prompt()? {
+        return Err(anyhow!("Cancelled; nothing was done."));
+    }
+    Ok(())
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// 同时打开多个交互会话，分屏显示，并把键盘输入同步发送给所有会话
+pub fn handle_multiconnect(
+    config: &Config,
+    aliases: &[String],
+    port: Option<u16>,
+    identity: Option<&Path>,
+) -> Result<()> {
+    let mut sessions = Vec::new();
+    for alias in aliases {
+        let hook_port = config.effective_port(alias, port);
+        let connected = hooks::run(config, alias, hook_port, HookEvent::PreConnect)
+            .and_then(|_| ssh::create_session(config, alias, port, identity));
+        match connected {
+            Ok(sess) => sessions.push((alias.clone(), sess)),
+            Err(e) => eprintln!("Warning: skipping '{}': {:#}", alias, e),
+        }
+    }
+    if sessions.is_empty() {
+        return Err(anyhow!("Could not connect to any of the selected hosts."));
+    }
+
+    let (width, height) = terminal::size()?;
+    let rects = layout(sessions.len(), width, height)?;
+    let mut panes = Vec::new();
+    for ((alias, sess), rect) in sessions.into_iter().zip(&rects) {
+        let mut channel = sess.channel_session()?;
+        let conn = config.resolved(&alias);
+        let term = conn.as_ref().and_then(|c| c.term.as_deref()).unwrap_or(ssh::DEFAULT_TERM);
+        let env = conn.as_ref().map(|c| c.locale_env()).unwrap_or_default();
+        ssh::request_pty(&mut channel, term, &env, rect.width, rect.height)?;
+        channel.shell()?;
+        sess.set_blocking(false);
+        panes.push(Pane {
+            alias,
+            sess,
+            channel,
+            parser: vt100::Parser::new(rect.height, rect.width, 0),
+            closed: false,
+            dirty: true,
+        });
+    }
+
+    let mut stdout = io::stdout();
+    term::prepare();
+    let aliases: Vec<&str> = panes.iter().map(|pane| pane.alias.as_str()).collect();
+    term::push_title(&format!("multiconnect — {}", aliases.join(", ")))?;
+    let raw_mode = term::RawMode::enable()?;
+    queue!(stdout, terminal::EnterAlternateScreen)?;
+    let result = run_panes(&mut panes, rects, &mut stdout);
+    let _ = queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = stdout.flush();
+    drop(raw_mode);
+    let _ = term::pop_title();
+
+    // 会话在这里释放后再执行断开钩子
+    let closed: Vec<String> = panes.into_iter().map(|pane| pane.alias).collect();
+    for alias in &closed {
+        let hook_port = config.effective_port(alias, port);
+        if let Err(e) = hooks::run(config, alias, hook_port, HookEvent::PostDisconnect) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+    result
+}
+
+fn run_panes(panes: &mut [Pane], mut rects: Vec<Rect>, stdout: &mut io::Stdout) -> Result<()> {
+    // None 表示广播到所有窗格
+    let mut focus: Option<usize> = None;
+    let mut prefix = false;
+    let mut full_redraw = true;
+    let mut last_draw = Instant::now() - REDRAW_INTERVAL;
+    let mut buf = [0u8; 4096];
+
+    loop {
+        if event::poll(Duration::from_millis(10))? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    // 在 Unix 终端里 Ctrl+] 会被解析成 Ctrl+5
+                    let is_prefix = matches!(key.code, KeyCode::Char(PREFIX_KEY) | KeyCode::Char('5'))
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if prefix {
+                        prefix = false;
+                        match key.code {
+                            KeyCode::Char('q') => return Ok(()),
+                            KeyCode::Char('a') => focus = None,
+                            KeyCode::Tab | KeyCode::Char('n') => {
+                                focus = Some(focus.map_or(0, |i| (i + 1) % panes.len()));
+                            }
+                            KeyCode::Char(c @ '1'..='9') => {
+                                let index = c as usize - '1' as usize;
+                                if index < panes.len() {
+                                    focus = Some(index);
+                                }
+                            }
+                            // 连按两次前缀键发送字面的 Ctrl+]
+                            _ if is_prefix => send_input(panes, focus, &[0x1d])?,
+                            _ => {}
+                        }
+                        full_redraw = true;
+                    } else if is_prefix {
+                        prefix = true;
+                    } else {
+                        send_input(panes, focus, &ssh::key_to_bytes(&key))?;
+                    }
+                }
+                Event::Resize(width, height) => {
+                    let (width, height) = term::resized_size(width, height);
+                    rects = layout(panes.len(), width, height)?;
+                    for (pane, rect) in panes.iter_mut().zip(&rects) {
+                        pane.parser.set_size(rect.height, rect.width);
+                        if !pane.closed {
+                            pane.sess.set_blocking(true);
+                            pane.channel
+                                .request_pty_size(rect.width as u32, rect.height as u32, None, None)?;
+                            pane.sess.set_blocking(false);
+                        }
+                    }
+                    full_redraw = true;
+                }
+                _ => {}
+            }
+        }
+
+        for pane in panes.iter_mut().filter(|pane| !pane.closed) {
+            loop {
+                match pane.channel.read(&mut buf) {
+                    Ok(0) => {
+                        if pane.channel.eof() {
+                            pane.closed = true;
+                            pane.dirty = true;
+                        }
+                        break;
+                    }
+                    Ok(n) => {
+                        pane.parser.process(&buf[..n]);
+                        pane.dirty = true;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        pane.closed = true;
+                        pane.dirty = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if panes.iter().all(|pane| pane.closed) {
+            return Ok(());
+        }
+
+        if last_draw.elapsed() >= REDRAW_INTERVAL && (full_redraw || panes.iter().any(|p| p.dirty)) {
+            draw(stdout, panes, &rects, focus, full_redraw)?;
+            full_redraw = false;
+            last_draw = Instant::now();
+        }
+    }
+}
+
+fn send_input(panes: &mut [Pane], focus: Option<usize>, bytes: &[u8]) -> Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    for (index, pane) in panes.iter_mut().enumerate() {
+        if pane.closed || focus.is_some_and(|f| f != index) {
+            continue;
+        }
+        pane.sess.set_blocking(true);
+        let written = pane.channel.write_all(bytes).and_then(|_| pane.channel.flush());
+        pane.sess.set_blocking(false);
+        if written.is_err() {
+            pane.closed = true;
+            pane.dirty = true;
+        }
+    }
+    Ok(())
+}
+
+// 按网格平铺窗格，每个窗格顶部留一行标题，最后一行是帮助信息
+fn layout(count: usize, width: u16, height: u16) -> Result<Vec<Rect>> {
+    let cols = (count as f64).sqrt().ceil() as u16;
+    let rows = (count as u16).div_ceil(cols);
+    let pane_width = width.saturating_sub(cols - 1) / cols;
+    let pane_height = height.saturating_sub(1) / rows;
+    if pane_width < 10 || pane_height < 3 {
+        return Err(anyhow!(
+            "Terminal is too small for {} panes ({}x{}).",
+            count,
+            width,
+            height
+        ));
+    }
+
+    Ok((0..count as u16)
+        .map(|i| Rect {
+            x: (i % cols) * (pane_width + 1),
+            y: (i / cols) * pane_height + 1,
+            width: pane_width,
+            height: pane_height - 1,
+        })
+        .collect())
+}
+
+fn draw(
+    stdout: &mut io::Stdout,
+    panes: &mut [Pane],
+    rects: &[Rect],
+    focus: Option<usize>,
+    full: bool,
+) -> Result<()> {
+    queue!(stdout, cursor::Hide)?;
+    if full {
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+    }
+
+    for (index, (pane, rect)) in panes.iter_mut().zip(rects).enumerate() {
+        if !full && !pane.dirty {
+            continue;
+        }
+        pane.dirty = false;
+
+        let active = focus.is_none_or(|f| f == index);
+        let mut title = format!(" [{}] {}", index + 1, pane.alias);
+        if pane.closed {
+            title.push_str(" (closed)");
+        } else if focus.is_none() {
+            title.push_str(" *");
+        }
+        let title: String = format!("{:<width$}", title, width = rect.width as usize)
+            .chars()
+            .take(rect.width as usize)
+            .collect();
+        let background = if active { Color::DarkGreen } else { Color::DarkGrey };
+        queue!(
+            stdout,
+            cursor::MoveTo(rect.x, rect.y - 1),
+            SetBackgroundColor(background),
+            SetForegroundColor(Color::White),
+            Print(title),
+            SetAttribute(Attribute::Reset),
+        )?;
+
+        let blank = " ".repeat(rect.width as usize);
+        for (row, content) in pane.parser.screen().rows_formatted(0, rect.width).enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(rect.x, rect.y + row as u16),
+                Print(&blank),
+                cursor::MoveTo(rect.x, rect.y + row as u16),
+            )?;
+            stdout.write_all(&content)?;
+            queue!(stdout, SetAttribute(Attribute::Reset))?;
+        }
+
+        // 窗格之间的竖线分隔
+        if rect.x + rect.width + 1 < terminal::size()?.0 {
+            for row in 0..=rect.height {
+                queue!(stdout, cursor::MoveTo(rect.x + rect.width, rect.y - 1 + row), Print('│'))?;
+            }
+        }
+    }
+
+    let (_, height) = terminal::size()?;
+    queue!(
+        stdout,
+        cursor::MoveTo(0, height - 1),
+        SetAttribute(Attribute::Reverse),
+        Print(HELP_LINE),
+        SetAttribute(Attribute::Reset),
+    )?;
+
+    // 光标放在当前（或第一个）活动窗格里
+    let cursor_pane = focus.unwrap_or(0);
+    let screen = panes[cursor_pane].parser.screen();
+    let (row, col) = screen.cursor_position();
+    let rect = rects[cursor_pane];
+    queue!(stdout, cursor::MoveTo(rect.x + col, rect.y + row))?;
+    if !screen.hide_cursor() {
+        queue!(stdout, cursor::Show)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Connection;
+
+    fn config(aliases: &[&str]) -> Config {
+        let mut config = Config::default();
+        for alias in aliases {
+            config.connections.insert(alias.to_string(), Connection::new(format!("root@{}", alias)));
+        }
+        config
+    }
+
+    #[test]
+    fn is_pattern_looks_for_glob_characters() {
+        for alias in ["web-*", "web-?", "db-0[1-3]", "*"] {
+            assert!(is_pattern(alias), "{}", alias);
+        }
+        for alias in ["web-1", "prod.eu", "db_01", ""] {
+            assert!(!is_pattern(alias), "{}", alias);
+        }
+    }
+
+    #[test]
+    fn match_pattern_returns_sorted_matches() {
+        let config = config(&["web-1", "web-2", "web-10", "db-01", "db-02", "db-03", "db-04", "prod.eu"]);
+        let matches = |pattern: &str| match_pattern(&config, pattern).unwrap();
+        assert_eq!(matches("web-*"), ["web-1", "web-10", "web-2"]);
+        assert_eq!(matches("web-?"), ["web-1", "web-2"]);
+        assert_eq!(matches("*-1*"), ["web-1", "web-10"]);
+        assert_eq!(matches("db-0[1-3]"), ["db-01", "db-02", "db-03"]);
+        assert_eq!(matches("db-0[!1-3]"), ["db-04"]);
+        assert_eq!(matches("db-0[^12]"), ["db-03", "db-04"]);
+        assert_eq!(matches("db-0[24]"), ["db-02", "db-04"]);
+        assert_eq!(matches("*.eu"), ["prod.eu"]);
+        assert_eq!(matches("*").len(), 8);
+    }
+
+    #[test]
+    fn match_pattern_rejects_patterns_without_matches() {
+        let config = config(&["web-1", "web-[2]"]);
+        assert!(match_pattern(&config, "db-*").is_err());
+        // 整个别名都要匹配，不是子串
+        assert!(match_pattern(&config, "web").is_err());
+        assert!(match_pattern(&config, "web-?0").is_err());
+        // 没有闭合的 [ 按字面匹配
+        assert_eq!(match_pattern(&config, "web-[*").unwrap(), ["web-[2]"]);
+        assert!(match_pattern(&Config::default(), "*").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use base64::Engine;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// 远程程序写剪贴板的默认上限
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+const PREFIX: &[u8] = b"\x1b]52;";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Osc52Mode {
+    /// Pass OSC 52 sequences through to the local terminal unchanged
+    #[default]
+    Off,
+    /// Let remote programs set the local clipboard
+    Copy,
+    /// Also answer clipboard read requests with the local clipboard
+    CopyPaste,
+}
+
+impl Osc52Mode {
+    pub fn is_off(&self) -> bool {
+        *self == Osc52Mode::Off
+    }
+}
+
+/// 从远程输出中取出的剪贴板请求
+pub enum Request {
+    Copy(String),
+    // 读剪贴板：回复时带上原来的剪贴板参数和结束符
+    Paste { selection: String, terminator: &'static [u8] },
+}
+
+enum State {
+    Normal,
+    // 已匹配的前缀字节数
+    Prefix(usize),
+    Body { data: Vec<u8>, esc: bool, overflow: bool },
+}
+
+/// 在输出流中识别 `ESC ] 52 ; <selection> ; <base64> (BEL | ESC \)`，序列可以跨越多次读取
+pub struct Osc52Filter {
+    state: State,
+    max_bytes: usize,
+}
+
+impl Osc52Filter {
+    pub fn new(max_bytes: u64) -> Self {
+        Osc52Filter {
+            state: State::Normal,
+            max_bytes: max_bytes as usize,
+        }
+    }
+
+    /// 返回去掉 OSC 52 序列后的输出和其中的请求
+    pub fn feed(&mut self, input: &[u8]) -> (Vec<u8>, Vec<Request>) {
+        let mut out = Vec::with_capacity(input.len());
+        let mut requests = Vec::new();
+        let mut i = 0;
+        while i < input.len() {
+            let byte = input[i];
+            match &mut self.state {
+                State::Normal => match input[i..].iter().position(|&b| b == 0x1b) {
+                    Some(pos) => {
+                        out.extend_from_slice(&input[i..i + pos]);
+                        self.state = State::Prefix(1);
+                        i += pos;
+                    }
+                    None => {
+                        out.extend_from_slice(&input[i..]);
+                        break;
+                    }
+                },
+                State::Prefix(matched) => {
+                    if byte == PREFIX[*matched] {
+                        *matched += 1;
+                        if *matched == PREFIX.len() {
+                            self.state = State::Body { data: Vec::new(), esc: false, overflow: false };
+                        }
+                    } else {
+                        // 不是 OSC 52，原样输出，并重新处理当前字节
+                        out.extend_from_slice(&PREFIX[..*matched]);
+                        self.state = State::Normal;
+                        continue;
+                    }
+                }
+                State::Body { data, esc, overflow } => {
+                    let terminator: Option<&'static [u8]> = match (byte, *esc) {
+                        (0x07, _) => Some(b"\x07"),
+                        (b'\\', true) => Some(b"\x1b\\"),
+                        _ => None,
+                    };
+                    if let Some(terminator) = terminator {
+                        let data = std::mem::take(data);
+                        let overflow = *overflow;
+                        self.state = State::Normal;
+                        if let Some(request) = self.parse(data, overflow, terminator) {
+                            requests.push(request);
+                        }
+                    } else if *esc {
+                        // ESC 后面不是 `\`，序列不完整，丢弃
+                        self.state = State::Normal;
+                        continue;
+                    } else if byte == 0x1b {
+                        *esc = true;
+                    } else if !(0x20..=0x7e).contains(&byte) {
+                        // base64 内容只有可见字符，遇到其他字节说明不是有效序列
+                        self.state = State::Normal;
+                        continue;
+                    } else if *overflow {
+                        // 超过上限后只等结束符
+                    } else if data.len() <= self.max_bytes * 4 / 3 + 64 {
+                        data.push(byte);
+                    } else {
+                        *overflow = true;
+                        data.clear();
+                    }
+                }
+            }
+            i += 1;
+        }
+        (out, requests)
+    }
+
+    // 超过大小上限的内容直接丢弃
+    fn parse(&self, data: Vec<u8>, overflow: bool, terminator: &'static [u8]) -> Option<Request> {
+        if overflow {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&data);
+        let (selection, payload) = text.split_once(';')?;
+        if payload == "?" {
+            return Some(Request::Paste { selection: selection.to_string(), terminator });
+        }
+        let decoded = base64::engine::general_purpose::STANDARD.decode(payload.trim()).ok()?;
+        if decoded.len() > self.max_bytes {
+            return None;
+        }
+        Some(Request::Copy(String::from_utf8_lossy(&decoded).into_owned()))
+    }
+}
+
+/// 回复读剪贴板请求的序列
+pub fn paste_reply(selection: &str, terminator: &[u8], text: &str) -> Vec<u8> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut reply = format!("\x1b]52;{};{}", selection, encoded).into_bytes();
+    reply.extend_from_slice(terminator);
+    reply
+}
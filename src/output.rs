@@ -0,0 +1,109 @@
+use crate::trace;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON event per line on stdout
+    Json,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static VERBOSE: OnceLock<bool> = OnceLock::new();
+static STDERR: OnceLock<bool> = OnceLock::new();
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+thread_local! {
+    static SESSION_QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn set_verbose(verbose: bool) {
+    let _ = VERBOSE.set(verbose);
+}
+
+/// -v：打印连接耗时等诊断信息
+pub fn is_verbose() -> bool {
+    VERBOSE.get().copied().unwrap_or(false)
+}
+
+/// 命令输出占用 stdout 时（如 exec），状态信息和事件改写到 stderr
+pub fn use_stderr() {
+    let _ = STDERR.set(true);
+}
+
+fn to_stderr() -> bool {
+    STDERR.get().copied().unwrap_or(false)
+}
+
+/// stdout 被 JSON 事件或命令输出占用
+pub fn stdout_reserved() -> bool {
+    is_json() || to_stderr()
+}
+
+/// 同时连接多台主机时（如 status），不打印每个连接的进度信息
+pub fn quiet() {
+    let _ = QUIET.set(true);
+}
+
+/// 只在一个会话期间不打印状态信息（如设置了 quiet_login 的连接），返回的守卫释放时恢复；
+/// 依次连接多台主机时不影响其他主机
+pub fn quiet_session(quiet: bool) -> QuietSession {
+    QuietSession { previous: SESSION_QUIET.replace(quiet) }
+}
+
+pub struct QuietSession {
+    previous: bool,
+}
+
+impl Drop for QuietSession {
+    fn drop(&mut self) {
+        SESSION_QUIET.set(self.previous);
+    }
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get().copied().unwrap_or_default() == OutputFormat::Json
+}
+
+/// 面向用户的状态信息，JSON 模式下不输出
+pub fn status(message: impl Display) {
+    trace::log(&message);
+    if is_json() || QUIET.get().copied().unwrap_or(false) || SESSION_QUIET.get() {
+        return;
+    }
+    if to_stderr() {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// 输出一条结构化事件，`fields` 必须是 JSON 对象
+pub fn emit(event: &str, fields: Value) {
+    let mut object = json!({ "event": event });
+    if let (Some(target), Value::Object(source)) = (object.as_object_mut(), fields) {
+        target.extend(source);
+    }
+    if to_stderr() {
+        eprintln!("{}", object);
+    } else {
+        println!("{}", object);
+    }
+}
+
+/// 仅在 JSON 模式下输出事件
+pub fn event(event: &str, fields: Value) {
+    if is_json() {
+        emit(event, fields);
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 私钥和配置目录不应让组和其他用户访问
+pub const PRIVATE_MASK: u32 = 0o077;
+/// known_hosts 可以被读，但不能被别人改
+pub const WRITE_MASK: u32 = 0o022;
+
+/// 创建只有当前用户能访问的目录（0700）；已存在的目录不改动，由 doctor 检查
+#[cfg(unix)]
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+/// 新建文件的选项，Unix 上权限为 0600
+pub fn private_file() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// 权限中被 mask 禁止的位；文件不存在或不是 Unix 时返回 None
+#[cfg(unix)]
+pub fn exposed_bits(path: &Path, mask: u32) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o7777;
+    Some(mode).filter(|mode| mode & mask != 0)
+}
+
+#[cfg(not(unix))]
+pub fn exposed_bits(_path: &Path, _mask: u32) -> Option<u32> {
+    None
+}
+
+/// 去掉 mask 中的权限位
+#[cfg(unix)]
+pub fn restrict(path: &Path, mask: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)?.permissions().mode() & 0o7777;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & !mask))
+}
+
+#[cfg(not(unix))]
+pub fn restrict(_path: &Path, _mask: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// 和 OpenSSH 一样提醒组或其他用户可读的私钥；OpenSSH 会拒绝使用，这里只警告
+pub fn warn_if_exposed(key: &Path) {
+    if let Some(mode) = exposed_bits(key, PRIVATE_MASK) {
+        eprintln!(
+            "Warning: permissions {:04o} for {:?} are too open; other users can read this private key. Run 'rssh doctor --fix-perms' to fix it.",
+            mode, key
+        );
+    }
+}
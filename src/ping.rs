@@ -0,0 +1,110 @@
+use crate::config::Config;
+use crate::error;
+use crate::output;
+use crate::ssh::{self, millis};
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Samples(Vec<Duration>);
+
+impl Samples {
+    fn stats(&self) -> Option<(f64, f64, f64)> {
+        let min = self.0.iter().min()?;
+        let max = self.0.iter().max()?;
+        let avg = self.0.iter().sum::<Duration>() / self.0.len() as u32;
+        Some((millis(*min), millis(avg), millis(*max)))
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self.stats() {
+            Some((min, avg, max)) => json!({ "samples": self.0.len(), "min_ms": min, "avg_ms": avg, "max_ms": max }),
+            None => json!(null),
+        }
+    }
+}
+
+/// 分阶段测量：TCP 连接、SSH 握手、认证，以及已建立通道上的往返延迟
+pub fn handle_ping(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>, count: u32) -> Result<()> {
+    if count == 0 {
+        return Err(anyhow!("--count must be at least 1"));
+    }
+    let conn = config
+        .connections
+        .get(alias)
+        .ok_or_else(|| error::alias_not_found(alias))?;
+    let host = conn
+        .target
+        .split_once('@')
+        .map(|(_, host)| host)
+        .context("Invalid connection string format. Use 'user@host'.")?;
+    let port = config.effective_port(alias, port);
+
+    let mut tcp = Samples::default();
+    let mut handshake = Samples::default();
+    let mut auth = Samples::default();
+    let mut rtt = Samples::default();
+
+    // 经跳板机时每次握手都要重新登录跳板机，只取完整连接这一个样本
+    if conn.jump_host.is_none() {
+        for _ in 1..count {
+            let transport = ssh::open_transport(config, conn, host, port, conn.connect_timeout())?;
+            tcp.0.push(transport.tcp_time);
+            handshake.0.push(transport.handshake_time);
+        }
+    }
+
+    let (sess, timings) = ssh::create_session_timed(config, alias, Some(port), identity)?;
+    tcp.0.push(timings.tcp);
+    handshake.0.push(timings.handshake);
+    auth.0.push(timings.auth);
+
+    // 在一个 cat 通道上逐字节回显，测量往返时间
+    let mut channel = sess.channel_session()?;
+    channel.exec("cat")?;
+    let mut byte = [0u8; 1];
+    for _ in 0..count {
+        let started = Instant::now();
+        channel.write_all(b"x")?;
+        channel.flush()?;
+        channel.read_exact(&mut byte).context("Echo channel closed unexpectedly")?;
+        rtt.0.push(started.elapsed());
+    }
+    let _ = channel.send_eof();
+    let _ = channel.close();
+
+    let phases = [("tcp", &tcp), ("handshake", &handshake), ("auth", &auth), ("rtt", &rtt)];
+    if output::is_json() {
+        let mut fields = json!({ "alias": alias, "host": host, "port": port });
+        for (name, samples) in phases {
+            fields[name] = samples.to_json();
+        }
+        output::emit("ping", fields);
+        return Ok(());
+    }
+
+    println!();
+    println!("{:<12} {:>8} {:>10} {:>10} {:>10}", "PHASE", "SAMPLES", "MIN", "AVG", "MAX");
+    for (name, samples) in phases {
+        let label = match name {
+            "tcp" => "TCP connect",
+            "handshake" => "Handshake",
+            "auth" => "Auth",
+            _ => "Echo RTT",
+        };
+        if let Some((min, avg, max)) = samples.stats() {
+            println!(
+                "{:<12} {:>8} {:>8.2}ms {:>8.2}ms {:>8.2}ms",
+                label,
+                samples.0.len(),
+                min,
+                avg,
+                max
+            );
+        }
+    }
+    Ok(())
+}
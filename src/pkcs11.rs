@@ -0,0 +1,56 @@
+use crate::prompt;
+use anyhow::{anyhow, Context, Result};
+use inquire::Password;
+use std::env;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// ssh-add 通过 SSH_ASKPASS 回调 rssh 自己来获取 PIN 或口令
+pub const ASKPASS_SECRET_ENV: &str = "RSSH_ASKPASS_SECRET";
+
+/// 作为 SSH_ASKPASS 被调用时返回要输出的 PIN
+pub fn askpass_secret() -> Option<String> {
+    env::var(ASKPASS_SECRET_ENV).ok()
+}
+
+/// libssh2 不能直接使用 PKCS#11，由 ssh-agent 加载硬件令牌上的密钥，再通过 agent 认证
+pub fn load_provider(provider: &Path) -> Result<()> {
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Err(anyhow!(
+            "PKCS#11 keys are used through ssh-agent, but SSH_AUTH_SOCK is not set. Start one with 'eval $(ssh-agent)'."
+        ));
+    }
+    if !provider.exists() {
+        return Err(anyhow!("PKCS#11 provider {:?} does not exist.", provider));
+    }
+
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable(format!(
+            "The token PIN for {} can't be asked for. Load the token with 'ssh-add -s' beforehand.",
+            provider.display()
+        )));
+    }
+    let pin = Password::new(&format!("Enter PIN for {}:", provider.display()))
+        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .prompt()?;
+
+    let askpass = env::current_exe().context("Failed to locate the rssh executable")?;
+    let output = Command::new("ssh-add")
+        .arg("-s")
+        .arg(provider)
+        .env("SSH_ASKPASS", askpass)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env(ASKPASS_SECRET_ENV, pin)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ssh-add could not load {:?}: {}",
+            provider,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::output;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use ssh2::Session;
+
+// 每个端口最多等这么久，避免服务器不回应时卡住
+const PROBE_TIMEOUT_MS: u32 = 3000;
+// 向后找空闲端口的个数
+const SCAN_LIMIT: u16 = 100;
+const PROBE_ADDRESS: &str = "127.0.0.1";
+
+/// 服务器上一个端口的占用情况
+pub enum PortState {
+    Free,
+    InUse,
+    // 服务器不允许 TCP 转发等原因，无法判断
+    Unknown(String),
+}
+
+/// 经 direct-tcpip 从服务器一侧连接 127.0.0.1:port：连得上说明已有程序在监听。
+/// 不在服务器上执行命令，也不会留下监听的端口
+pub fn probe(sess: &Session, port: u16) -> PortState {
+    let timeout = sess.timeout();
+    sess.set_timeout(PROBE_TIMEOUT_MS);
+    let result = sess.channel_direct_tcpip(PROBE_ADDRESS, port, None);
+    sess.set_timeout(timeout);
+    match result {
+        Ok(mut channel) => {
+            let _ = channel.close();
+            PortState::InUse
+        }
+        // "administratively prohibited" 等说明服务器没有去连接
+        Err(e) if e.message().contains("prohibited") || e.message().contains("Timed out") => {
+            PortState::Unknown(e.message().to_string())
+        }
+        Err(_) => PortState::Free,
+    }
+}
+
+/// port 之后第一个空闲的端口；遇到无法判断的端口就不再往后找
+pub fn next_free(sess: &Session, port: u16) -> Option<u16> {
+    for candidate in (1..=SCAN_LIMIT).filter_map(|offset| port.checked_add(offset)) {
+        match probe(sess, candidate) {
+            PortState::Free => return Some(candidate),
+            PortState::InUse => continue,
+            PortState::Unknown(_) => return None,
+        }
+    }
+    None
+}
+
+/// 在服务器上监听前检查端口；已被占用时报错，retry 给出改用下一个空闲端口的提示；无法判断时照常继续
+pub fn preflight(sess: &Session, alias: &str, port: u16, retry: impl Fn(u16) -> String) -> Result<()> {
+    if port == 0 || !matches!(probe(sess, port), PortState::InUse) {
+        return Ok(());
+    }
+    Err(anyhow!("{}", in_use_message(sess, alias, port, retry)))
+}
+
+fn in_use_message(sess: &Session, alias: &str, port: u16, retry: impl Fn(u16) -> String) -> String {
+    match next_free(sess, port) {
+        Some(free) => format!("Port {} on '{}' is already in use. {}", port, alias, retry(free)),
+        None => format!("Port {} on '{}' is already in use, and no free port was found after it.", port, alias),
+    }
+}
+
+pub fn handle_port_check(sess: &Session, alias: &str, port: u16) -> Result<()> {
+    match probe(sess, port) {
+        PortState::Free => {
+            output::status(format!("Port {} on '{}' is free.", port, alias));
+            output::event("port_check", json!({ "alias": alias, "port": port, "in_use": false }));
+            Ok(())
+        }
+        PortState::InUse => {
+            let next = next_free(sess, port);
+            output::event("port_check", json!({ "alias": alias, "port": port, "in_use": true, "next_free": next }));
+            Err(anyhow!("{}", in_use_message(sess, alias, port, |free| format!("The next free port is {}.", free))))
+        }
+        PortState::Unknown(reason) => Err(anyhow!(
+            "Could not check port {} on '{}': {} (is AllowTcpForwarding off?)",
+            port,
+            alias,
+            reason
+        )),
+    }
+}
@@ -0,0 +1,250 @@
+use crate::agent;
+use crate::auth;
+use crate::config::{self, Config};
+use crate::credentials::get_password;
+use crate::hooks::{self, HookEvent};
+use crate::hostkey;
+use crate::keys;
+use crate::output;
+use crate::ssh::{self, millis};
+use anyhow::{Context, Result};
+use crossterm::style::Stylize;
+use serde_json::json;
+use ssh2::{CheckResult, ErrorCode, Session};
+use std::env;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// 没有设置 connect_timeout 时每台主机最多等这么久
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Reach {
+    Ok(Duration),
+    Timeout,
+    Failed(String),
+    /// 经跳板机的主机要先登录跳板机，不探测
+    Skipped(String),
+}
+
+enum Auth {
+    /// 认证成功时用的方式
+    Ok(String),
+    Rejected,
+    HostKeyChanged,
+    /// 没有可以不询问就尝试的凭据，或主机不可达
+    Skipped(String),
+}
+
+struct Probe {
+    reach: Reach,
+    auth: Auth,
+}
+
+/// list --probe：并发检查每台主机的 TCP 可达性，以及保存的凭据是否还能登录；
+/// 只用 agent、不带口令的私钥和保存的密码，不会询问任何东西
+pub fn handle_list_probe(config: &Config, jobs: usize) -> Result<()> {
+    let mut aliases: Vec<&String> = config.connections.keys().collect();
+    aliases.sort();
+    if !output::is_verbose() {
+        output::quiet();
+    }
+    // 密码在开始前依次取出，密码库最多询问一次主密码；需要 Touch ID 的密码不用
+    let passwords: Vec<Option<String>> = aliases
+        .iter()
+        .map(|alias| match config.connections[*alias].require_biometrics {
+            true => None,
+            false => get_password(alias).ok(),
+        })
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, Probe)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, aliases.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(alias) = aliases.get(index) else { break };
+                    let probe = probe_host(config, alias, passwords[index].as_deref());
+                    results.lock().unwrap().push((index, probe));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+
+    if output::is_json() {
+        let hosts: Vec<_> = results
+            .iter()
+            .map(|(index, probe)| host_json(config, aliases[*index], probe))
+            .collect();
+        output::emit("connections", json!({ "connections": hosts }));
+        return Ok(());
+    }
+    if aliases.is_empty() {
+        println!("No connections saved. Use 'rssh add <alias> <user@host>' to add one.");
+        return Ok(());
+    }
+    let width = aliases.iter().map(|alias| alias.chars().count()).max().unwrap_or(0).max(5);
+    let targets: Vec<String> = aliases
+        .iter()
+        .map(|alias| config::format_endpoint(&config.connections[*alias].target, config.effective_port(alias, None)))
+        .collect();
+    let target_width = targets.iter().map(|target| target.chars().count()).max().unwrap_or(0).max(6);
+    println!("{:<width$}  {:<target_width$}  {:<16}  AUTH", "ALIAS", "TARGET", "TCP");
+    for (index, probe) in &results {
+        // 先按列宽补齐再上色，颜色代码不影响对齐
+        let reach = match &probe.reach {
+            Reach::Ok(time) => format!("{:<16}", format!("✅ {:.0}ms", millis(*time))).green(),
+            Reach::Timeout => format!("{:<16}", "⏱  timeout").yellow(),
+            Reach::Failed(_) => format!("{:<16}", "❌ unreachable").red(),
+            Reach::Skipped(reason) => format!("{:<16}", format!("-  {}", reason)).dim(),
+        };
+        let auth = match &probe.auth {
+            Auth::Ok(method) => format!("✅ {}", method).green(),
+            Auth::Rejected => "❌ rejected".to_string().red(),
+            Auth::HostKeyChanged => "❌ host key changed".to_string().red(),
+            Auth::Skipped(reason) => format!("-  {}", reason).dim(),
+        };
+        println!("{:<width$}  {:<target_width$}  {}  {}", aliases[*index], targets[*index], reach, auth);
+    }
+    for (index, probe) in &results {
+        if let Reach::Failed(error) = &probe.reach {
+            eprintln!("{}: {}", aliases[*index], error);
+        }
+    }
+    Ok(())
+}
+
+fn probe_host(config: &Config, alias: &str, password: Option<&str>) -> Probe {
+    let conn = &config.connections[alias];
+    if let Some(jump) = &conn.jump_host {
+        let reason = format!("via {}", jump);
+        return Probe { reach: Reach::Skipped(reason.clone()), auth: Auth::Skipped(reason) };
+    }
+    let Some((user, host)) = conn.target.split_once('@') else {
+        let reason = "invalid target".to_string();
+        return Probe { reach: Reach::Skipped(reason.clone()), auth: Auth::Skipped(reason) };
+    };
+    let port = config.effective_port(alias, None);
+    if let Err(e) = hooks::run(config, alias, port, HookEvent::PreConnect) {
+        return Probe { reach: Reach::Failed(format!("{:#}", e)), auth: Auth::Skipped("unreachable".to_string()) };
+    }
+
+    let timeout = conn.connect_timeout().unwrap_or(PROBE_TIMEOUT);
+    let probe = match ssh::open_transport(config, conn, host, port, Some(timeout)) {
+        Ok(transport) => {
+            // 认证也不能无限等
+            transport.sess.set_timeout(timeout.as_millis().try_into().unwrap_or(u32::MAX));
+            let auth = probe_auth(&transport.sess, config, alias, user, host, port, password)
+                .unwrap_or_else(|e| Auth::Skipped(format!("{:#}", e).lines().next().unwrap_or_default().to_string()));
+            Probe { reach: Reach::Ok(transport.tcp_time), auth }
+        }
+        Err(e) => {
+            let reach = match is_timeout(&e) {
+                true => Reach::Timeout,
+                false => Reach::Failed(format!("{:#}", e)),
+            };
+            Probe { reach, auth: Auth::Skipped("unreachable".to_string()) }
+        }
+    };
+    if let Err(e) = hooks::run(config, alias, port, HookEvent::PostDisconnect) {
+        eprintln!("Warning: {:#}", e);
+    }
+    probe
+}
+
+// TCP 连接超时，或 libssh2 握手超时（LIBSSH2_ERROR_TIMEOUT）
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::TimedOut)
+            || cause.downcast_ref::<ssh2::Error>().is_some_and(|e| e.code() == ErrorCode::Session(-9))
+    })
+}
+
+// 签名不会泄露私钥，未固定的主机也可以试 agent 和私钥；密码只发给已固定密钥的主机
+fn probe_auth(
+    sess: &Session,
+    config: &Config,
+    alias: &str,
+    user: &str,
+    host: &str,
+    port: u16,
+    password: Option<&str>,
+) -> Result<Auth> {
+    let pinned = match hostkey::check(sess, host, port)? {
+        CheckResult::Match => true,
+        CheckResult::Mismatch => return Ok(Auth::HostKeyChanged),
+        _ => false,
+    };
+    let offered = sess.auth_methods(user).context("Failed to ask the server for its authentication methods")?.to_string();
+    if sess.authenticated() {
+        return Ok(Auth::Ok("none".to_string()));
+    }
+    let mut tried = false;
+    if offered.contains("publickey") {
+        if env::var_os("SSH_AUTH_SOCK").is_some() {
+            tried = true;
+            if agent::userauth(sess, user, config.connections[alias].agent_key_fingerprint.as_deref()).is_ok() {
+                return Ok(Auth::Ok("agent".to_string()));
+            }
+        }
+        let conn = &config.defaults.apply(&config.connections[alias]);
+        let mut identities: Vec<_> = conn.identity.iter().cloned().collect();
+        identities.extend(auth::candidate_identities(conn));
+        for path in identities.iter().filter(|path| path.exists() && !keys::is_security_key(path)) {
+            // 加密的私钥需要口令，直接跳过
+            tried = true;
+            if sess.userauth_pubkey_file(user, None, path, None).is_ok() {
+                let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().to_string());
+                return Ok(Auth::Ok(format!("key {}", name)));
+            }
+        }
+    }
+    if let Some(password) = password
+        && offered.contains("password")
+    {
+        if !pinned {
+            return Ok(Auth::Skipped("host key not pinned, saved password not sent".to_string()));
+        }
+        tried = true;
+        if sess.userauth_password(user, password).is_ok() {
+            return Ok(Auth::Ok("saved password".to_string()));
+        }
+    }
+    Ok(match tried {
+        true => Auth::Rejected,
+        false => Auth::Skipped("no saved credentials".to_string()),
+    })
+}
+
+fn host_json(config: &Config, alias: &str, probe: &Probe) -> serde_json::Value {
+    let conn = &config.connections[alias];
+    let (reachable, tcp_ms, reach_error) = match &probe.reach {
+        Reach::Ok(time) => (json!(true), Some(millis(*time)), None),
+        Reach::Timeout => (json!("timeout"), None, None),
+        Reach::Failed(error) => (json!(false), None, Some(error.as_str())),
+        Reach::Skipped(reason) => (json!(null), None, Some(reason.as_str())),
+    };
+    let (auth, method) = match &probe.auth {
+        Auth::Ok(method) => ("ok", Some(method.as_str())),
+        Auth::Rejected => ("rejected", None),
+        Auth::HostKeyChanged => ("host_key_changed", None),
+        Auth::Skipped(reason) => ("skipped", Some(reason.as_str())),
+    };
+    json!({
+        "alias": alias,
+        "connection": conn.target,
+        "port": config.effective_port(alias, None),
+        "tags": config.effective_tags(conn),
+        "reachable": reachable,
+        "tcp_ms": tcp_ms,
+        "reach_error": reach_error,
+        "auth": auth,
+        "auth_detail": method,
+    })
+}
@@ -0,0 +1,406 @@
+use crate::interrupt;
+use crate::output;
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde_json::json;
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// JSON 模式下两次进度事件之间的最小间隔
+const JSON_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// 并发传输时所有进度条画在同一个 MultiProgress 里，不会互相覆盖
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+// 一条命令传输多个源路径时，所有传输共用的总进度条
+static BATCH: Mutex<Option<Batch>> = Mutex::new(None);
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Interactive progress bars on stderr
+    Bar,
+    /// Machine-readable JSON lines on stdout
+    Json,
+}
+
+/// 一次传输的结果
+#[derive(Clone, Copy, Default)]
+pub struct TransferStats {
+    /// 实际传输的字节数，不含跳过的文件
+    pub bytes: u64,
+    pub files: u64,
+    pub skipped: u64,
+}
+
+impl TransferStats {
+    /// 所有文件都因目标已存在而跳过
+    pub fn all_skipped(&self) -> bool {
+        self.files > 0 && self.skipped == self.files
+    }
+
+    pub fn add(&mut self, other: TransferStats) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+        self.skipped += other.skipped;
+    }
+}
+
+struct Batch {
+    bar: ProgressBar,
+    files_total: u64,
+    files_done: u64,
+    // 事先不知道大小（如下载）时，每次传输开始再把它的大小加进来
+    grow: bool,
+}
+
+impl Batch {
+    fn update_message(&self) {
+        self.bar.set_message(format!("{}/{} files", self.files_done, self.files_total));
+    }
+}
+
+/// 多个源路径共用的总进度，在它被丢弃时结束
+pub struct BatchProgress;
+
+impl BatchProgress {
+    /// totals 是事先算好的文件数和字节数；为 None 时随各次传输累加。只在进度条模式下显示
+    pub fn start(mode: ProgressMode, totals: Option<(u64, u64)>) -> Option<BatchProgress> {
+        if mode != ProgressMode::Bar {
+            return None;
+        }
+        let (files_total, bytes_total) = totals.unwrap_or_default();
+        let multi = MULTI.get_or_init(MultiProgress::new);
+        let bar = multi.add(ProgressBar::new(bytes_total));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] Total {msg} [{bar:40.green/white}] {bytes}/{total_bytes} ({bytes_per_sec}, eta {eta})")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        let batch = Batch { bar, files_total, files_done: 0, grow: totals.is_none() };
+        batch.update_message();
+        *BATCH.lock().unwrap() = Some(batch);
+        Some(BatchProgress)
+    }
+}
+
+impl Drop for BatchProgress {
+    fn drop(&mut self) {
+        finish_batch();
+    }
+}
+
+/// 结束总进度条，之后打印的汇总不会和它交错
+pub fn finish_batch() {
+    if let Some(batch) = BATCH.lock().unwrap().take() {
+        batch.bar.finish_with_message(format!("{}/{} files", batch.files_done, batch.files_total));
+    }
+}
+
+fn with_batch(f: impl FnOnce(&mut Batch)) -> bool {
+    match BATCH.lock().unwrap().as_mut() {
+        Some(batch) => {
+            f(batch);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 一次传输（可能包含多个文件）的整体进度
+pub struct TransferProgress {
+    mode: ProgressMode,
+    multi: MultiProgress,
+    overall: Option<ProgressBar>,
+    // 属于多个源路径的一次传输，总进度画在共用的进度条上
+    in_batch: bool,
+    files_total: u64,
+    files_done: Cell<u64>,
+    files_skipped: Cell<u64>,
+    bytes_total: u64,
+    bytes_done: Cell<u64>,
+    // 实际传输的字节数，不含跳过的文件
+    bytes_copied: Cell<u64>,
+    started: Instant,
+    // 传输期间接管 Ctrl+C，读写时检查，以便清理未完成的文件
+    _interrupt: interrupt::Guard,
+}
+
+impl TransferProgress {
+    pub fn new(mode: ProgressMode, files_total: u64, bytes_total: u64) -> Self {
+        let multi = MULTI.get_or_init(MultiProgress::new).clone();
+        let in_batch = mode == ProgressMode::Bar
+            && with_batch(|batch| {
+                if batch.grow {
+                    batch.files_total += files_total;
+                    batch.bar.inc_length(bytes_total);
+                    batch.update_message();
+                }
+            });
+        // 只有多个文件时才显示总进度条
+        let overall = (mode == ProgressMode::Bar && files_total > 1 && !in_batch).then(|| {
+            let bar = multi.add(ProgressBar::new(bytes_total));
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {msg} [{bar:40.green/white}] {bytes}/{total_bytes} ({bytes_per_sec}, eta {eta})")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.set_message(format!("0/{} files", files_total));
+            bar
+        });
+        if mode == ProgressMode::Json {
+            output::emit("transfer_start", json!({
+                "files": files_total,
+                "bytes": bytes_total,
+            }));
+        }
+        TransferProgress {
+            mode,
+            multi,
+            overall,
+            in_batch,
+            files_total,
+            files_done: Cell::new(0),
+            files_skipped: Cell::new(0),
+            bytes_total,
+            bytes_done: Cell::new(0),
+            bytes_copied: Cell::new(0),
+            started: Instant::now(),
+            _interrupt: interrupt::Guard::install(),
+        }
+    }
+
+    pub fn start_file(&self, name: &str, size: Option<u64>) -> FileProgress<'_> {
+        let bar = match self.mode {
+            ProgressMode::Bar => {
+                let bar = match size {
+                    Some(size) => {
+                        let bar = ProgressBar::new(size);
+                        bar.set_style(ProgressStyle::default_bar()
+                            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")
+                            .unwrap()
+                            .progress_chars("#>-"));
+                        bar
+                    }
+                    None => {
+                        let bar = ProgressBar::new_spinner();
+                        bar.set_style(
+                            ProgressStyle::default_spinner()
+                                .template("{spinner:.green} [{elapsed_precise}] {bytes} ({bytes_per_sec})")
+                                .unwrap(),
+                        );
+                        bar
+                    }
+                };
+                Some(self.multi.add(bar))
+            }
+            ProgressMode::Json => {
+                output::emit("file_start", json!({"file": name, "size": size}));
+                None
+            }
+        };
+        FileProgress {
+            parent: self,
+            bar,
+            name: name.to_string(),
+            size,
+            done: Cell::new(0),
+            last_emit: Cell::new(Instant::now()),
+        }
+    }
+
+    /// 跳过的文件也计入总进度
+    pub fn skip_file(&self, name: &str, size: u64) {
+        if self.mode == ProgressMode::Json {
+            output::emit("file_skipped", json!({"file": name, "size": size}));
+        }
+        self.files_skipped.set(self.files_skipped.get() + 1);
+        self.add_bytes(size);
+        self.file_finished();
+    }
+
+    /// 打印一行信息，不打乱进度条；JSON 模式下不输出
+    pub fn println(&self, message: &str) {
+        if self.mode == ProgressMode::Bar {
+            if self.multi.is_hidden() {
+                println!("{}", message);
+            } else {
+                let _ = self.multi.println(message);
+            }
+        }
+    }
+
+    /// 暂停进度条绘制，用于交互式提示
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// 结束传输，返回传输结果
+    pub fn finish(&self) -> TransferStats {
+        match self.mode {
+            ProgressMode::Bar => {
+                if let Some(overall) = &self.overall {
+                    overall.finish_with_message(format!(
+                        "{}/{} files",
+                        self.files_done.get(),
+                        self.files_total
+                    ));
+                }
+            }
+            ProgressMode::Json => output::emit("transfer_complete", json!({
+                "files": self.files_done.get(),
+                "bytes": self.bytes_done.get(),
+                "total_bytes": self.bytes_total,
+                "duration_ms": self.started.elapsed().as_millis() as u64,
+            })),
+        }
+        TransferStats {
+            bytes: self.bytes_copied.get(),
+            files: self.files_done.get(),
+            skipped: self.files_skipped.get(),
+        }
+    }
+
+    fn add_bytes(&self, n: u64) {
+        self.bytes_done.set(self.bytes_done.get() + n);
+        if let Some(overall) = &self.overall {
+            overall.inc(n);
+        }
+        if self.in_batch {
+            with_batch(|batch| batch.bar.inc(n));
+        }
+    }
+
+    fn file_finished(&self) {
+        self.files_done.set(self.files_done.get() + 1);
+        if let Some(overall) = &self.overall {
+            overall.set_message(format!("{}/{} files", self.files_done.get(), self.files_total));
+        }
+        if self.in_batch {
+            with_batch(|batch| {
+                batch.files_done += 1;
+                batch.update_message();
+            });
+        }
+    }
+}
+
+/// 单个文件的进度
+pub struct FileProgress<'a> {
+    parent: &'a TransferProgress,
+    bar: Option<ProgressBar>,
+    name: String,
+    size: Option<u64>,
+    done: Cell<u64>,
+    last_emit: Cell<Instant>,
+}
+
+impl FileProgress<'_> {
+    pub fn inc(&self, n: u64) {
+        self.done.set(self.done.get() + n);
+        self.parent.bytes_copied.set(self.parent.bytes_copied.get() + n);
+        self.parent.add_bytes(n);
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+        } else if self.last_emit.get().elapsed() >= JSON_PROGRESS_INTERVAL {
+            self.last_emit.set(Instant::now());
+            output::emit("progress", json!({
+                "file": self.name,
+                "bytes": self.done.get(),
+                "size": self.size,
+                "total_bytes_done": self.parent.bytes_done.get(),
+            }));
+        }
+    }
+
+    /// 续传时目标里已有的部分：计入进度，不算作这次传输的字节
+    pub fn skip_bytes(&self, n: u64) {
+        self.done.set(self.done.get() + n);
+        self.parent.add_bytes(n);
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+        }
+    }
+
+    pub fn wrap_read<R: Read>(&self, inner: R) -> ProgressReader<'_, R> {
+        ProgressReader {
+            inner,
+            progress: self,
+        }
+    }
+
+    pub fn wrap_write<W: Write>(&self, inner: W) -> ProgressWriter<'_, W> {
+        ProgressWriter {
+            inner,
+            progress: self,
+        }
+    }
+
+    pub fn finish(self, message: &'static str) {
+        match &self.bar {
+            // 多文件时清掉单文件进度条，只保留总进度
+            Some(bar) if self.parent.overall.is_some() || self.parent.in_batch => bar.finish_and_clear(),
+            Some(bar) => bar.finish_with_message(message),
+            None => output::emit("file_complete", json!({
+                "file": self.name,
+                "bytes": self.done.get(),
+            })),
+        }
+        self.parent.file_finished();
+    }
+}
+
+// 出错或中断时没有 finish 的进度条清掉，不留在屏幕上
+impl Drop for FileProgress<'_> {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar
+            && !bar.is_finished()
+        {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl Drop for TransferProgress {
+    fn drop(&mut self) {
+        if let Some(overall) = &self.overall
+            && !overall.is_finished()
+        {
+            overall.finish_and_clear();
+        }
+    }
+}
+
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a FileProgress<'a>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        interrupt::check()?;
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+}
+
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    progress: &'a FileProgress<'a>,
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        interrupt::check()?;
+        let n = self.inner.write(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,26 @@
+use crate::error;
+use anyhow::anyhow;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+static NON_INTERACTIVE: OnceLock<bool> = OnceLock::new();
+
+/// --non-interactive：不弹出任何询问，用于 CI
+pub fn set_non_interactive(non_interactive: bool) {
+    let _ = NON_INTERACTIVE.set(non_interactive);
+}
+
+pub fn is_non_interactive() -> bool {
+    NON_INTERACTIVE.get().copied().unwrap_or(false)
+}
+
+/// 能否询问用户：需要终端，且没有 --non-interactive
+pub fn can_ask() -> bool {
+    !is_non_interactive() && io::stdin().is_terminal()
+}
+
+/// 需要输入却不能询问时的错误；message 说明怎样事先提供
+pub fn unavailable(message: impl Display) -> anyhow::Error {
+    error::input(anyhow!("{}", message))
+}
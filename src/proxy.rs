@@ -0,0 +1,584 @@
+use crate::output;
+use crate::portcheck;
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use ssh2::{Channel, ErrorCode, Listener, Session};
+use std::io::{self, Read, Write};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const BUFFER_SIZE: usize = 16 * 1024;
+// 代理只在回环地址上监听
+const BIND_ADDRESS: &str = "127.0.0.1";
+
+// SOCKS5 回复码
+const REPLY_OK: u8 = 0;
+const REPLY_FAILURE: u8 = 1;
+const REPLY_HOST_UNREACHABLE: u8 = 4;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+/// 本地 SOCKS5 代理，经服务器访问目标（ssh -D）；reverse 时在服务器上监听，经本机访问目标（ssh -R 的动态转发）
+pub fn handle_proxy(sess: Session, alias: &str, port: u16, reverse: bool) -> Result<()> {
+    if reverse {
+        portcheck::preflight(&sess, alias, port, |free| {
+            format!("Port {} is free: rssh proxy {} --reverse {}", free, alias, free)
+        })?;
+        let (listener, bound) = sess
+            .channel_forward_listen(port, Some(BIND_ADDRESS), None)
+            .context(format!(
+                "'{}' refused to listen on port {} (the port may be in use, or AllowTcpForwarding is off)",
+                alias, port
+            ))?;
+        output::status(format!(
+            "SOCKS5 proxy listening on {}:{} on '{}'; connections go out through this machine.",
+            BIND_ADDRESS, bound, alias
+        ));
+        output::status(format!(
+            "On the server, use e.g. ALL_PROXY=socks5h://{}:{} or curl --proxy socks5h://{}:{}. Press Ctrl+C to stop.",
+            BIND_ADDRESS, bound, BIND_ADDRESS, bound
+        ));
+        output::event("proxy_listening", json!({ "alias": alias, "reverse": true, "port": bound }));
+        sess.set_blocking(false);
+        run(&sess, Acceptor::Remote(listener), &mut || true)
+    } else {
+        let listener = TcpListener::bind((BIND_ADDRESS, port))
+            .context(format!("Failed to listen on {}:{}", BIND_ADDRESS, port))?;
+        let bound = listener.local_addr()?.port();
+        listener.set_nonblocking(true)?;
+        output::status(format!(
+            "SOCKS5 proxy listening on {}:{}; connections go out through '{}'. Press Ctrl+C to stop.",
+            BIND_ADDRESS, bound, alias
+        ));
+        output::event("proxy_listening", json!({ "alias": alias, "reverse": false, "port": bound }));
+        sess.set_blocking(false);
+        run(&sess, Acceptor::Local(listener), &mut || true)
+    }
+}
+
+/// 固定转发在服务器一侧的目标：host:port 经 direct-tcpip，Unix 套接字经 direct-streamlocal
+#[derive(Clone, Debug)]
+pub enum Target {
+    Tcp(String, u16),
+    Socket(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Tcp(host, port) => write!(f, "{}:{}", host, port),
+            Target::Socket(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+/// 固定转发在本机一侧监听的端口或 Unix 套接字
+pub enum ForwardListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl ForwardListener {
+    fn set_nonblocking(&self) -> io::Result<()> {
+        match self {
+            ForwardListener::Tcp(listener) => listener.set_nonblocking(true),
+            #[cfg(unix)]
+            ForwardListener::Unix(listener) => listener.set_nonblocking(true),
+        }
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            ForwardListener::Tcp(listener) => accept_tcp(listener),
+            #[cfg(unix)]
+            ForwardListener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(true)?;
+                Ok(Stream::Unix(stream))
+            }
+        }
+    }
+}
+
+/// 固定目标的本地转发（ssh -L）：每个本地端口或套接字的连接都通到服务器一侧各自的目标
+pub fn handle_forwards(sess: Session, forwards: Vec<(ForwardListener, Target)>) -> Result<()> {
+    handle_forwards_while(&sess, forwards, || true)
+}
+
+/// 和 handle_forwards 一样，但 running 返回 false 时关闭所有连接并返回
+pub fn handle_forwards_while(
+    sess: &Session,
+    forwards: Vec<(ForwardListener, Target)>,
+    mut running: impl FnMut() -> bool,
+) -> Result<()> {
+    for (listener, _) in &forwards {
+        listener.set_nonblocking()?;
+    }
+    sess.set_blocking(false);
+    let result = run(sess, Acceptor::Forward(forwards), &mut running);
+    sess.set_blocking(true);
+    result
+}
+
+enum Acceptor {
+    // 服务器转发过来的连接，目标在本机一侧打开
+    Remote(Listener),
+    // 本地的连接，目标通过 direct-tcpip 通道打开
+    Local(TcpListener),
+    // 本地端口和各自固定的目标，不走 SOCKS 握手
+    Forward(Vec<(ForwardListener, Target)>),
+}
+
+impl Acceptor {
+    fn accept(&mut self) -> io::Result<Relay> {
+        match self {
+            Acceptor::Remote(listener) => {
+                listener.accept().map(|channel| Relay::new(Stream::Channel(channel))).map_err(io::Error::from)
+            }
+            Acceptor::Local(listener) => Ok(Relay::new(accept_tcp(listener)?)),
+            Acceptor::Forward(forwards) => {
+                for (listener, target) in forwards.iter() {
+                    match listener.accept() {
+                        Ok(client) => return Ok(Relay::forward(client, target.clone())),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+}
+
+fn accept_tcp(listener: &TcpListener) -> io::Result<Stream> {
+    let (stream, _) = listener.accept()?;
+    stream.set_nonblocking(true)?;
+    stream.set_nodelay(true)?;
+    Ok(Stream::Tcp(stream))
+}
+
+// 所有连接在一个线程里轮询，会话处于非阻塞模式
+fn run(sess: &Session, mut acceptor: Acceptor, running: &mut dyn FnMut() -> bool) -> Result<()> {
+    let mut relays: Vec<Relay> = Vec::new();
+    let mut buf = [0u8; BUFFER_SIZE];
+    loop {
+        if !running() {
+            relays.iter_mut().for_each(Relay::close);
+            return Ok(());
+        }
+        let mut idle = true;
+        match acceptor.accept() {
+            Ok(relay) => {
+                relays.push(relay);
+                idle = false;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(anyhow!("The proxy stopped: {}", e)),
+        }
+        relays.retain_mut(|relay| match relay.step(sess, &mut buf) {
+            Ok(progress) => {
+                idle &= !progress;
+                !relay.is_done()
+            }
+            Err(e) => {
+                if output::is_verbose() {
+                    eprintln!("Proxy connection closed: {}", e);
+                }
+                relay.close();
+                false
+            }
+        });
+        if idle {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// 连接的一端：SSH 通道、TCP 连接或本地 Unix 套接字连接
+enum Stream {
+    Channel(Channel),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    // Ok(0) 表示对端已关闭，没有数据时返回 WouldBlock
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Channel(channel) => match channel.read(buf) {
+                Ok(0) if !channel.eof() => Err(io::ErrorKind::WouldBlock.into()),
+                result => result,
+            },
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Channel(channel) => channel.write(buf),
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn send_eof(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Channel(channel) => channel.send_eof().map_err(io::Error::from),
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Write),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Write),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Stream::Channel(channel) => {
+                let _ = channel.close();
+            }
+            Stream::Tcp(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+}
+
+enum State {
+    Greeting,
+    Request,
+    // 本机一侧在后台线程里连接目标
+    ConnectingTcp(Receiver<io::Result<TcpStream>>),
+    // 服务器一侧打开 direct-tcpip / direct-streamlocal 通道，非阻塞模式下需要重试
+    OpeningChannel(Target),
+    Relaying,
+    // 发完错误回复后关闭
+    Failed,
+}
+
+struct Relay {
+    client: Stream,
+    // 固定目标的转发没有 SOCKS 握手，也不发回复
+    socks: bool,
+    upstream: Option<Stream>,
+    state: State,
+    // 握手阶段收到的数据
+    request: Vec<u8>,
+    to_client: Vec<u8>,
+    to_upstream: Vec<u8>,
+    client_eof: bool,
+    upstream_eof: bool,
+    client_eof_sent: bool,
+    upstream_eof_sent: bool,
+}
+
+impl Relay {
+    fn new(client: Stream) -> Self {
+        Relay {
+            client,
+            socks: true,
+            upstream: None,
+            state: State::Greeting,
+            request: Vec::new(),
+            to_client: Vec::new(),
+            to_upstream: Vec::new(),
+            client_eof: false,
+            upstream_eof: false,
+            client_eof_sent: false,
+            upstream_eof_sent: false,
+        }
+    }
+
+    fn forward(client: Stream, target: Target) -> Self {
+        Relay { socks: false, state: State::OpeningChannel(target), ..Relay::new(client) }
+    }
+
+    fn is_done(&self) -> bool {
+        match self.state {
+            State::Failed => self.to_client.is_empty(),
+            State::Relaying => self.client_eof_sent && self.upstream_eof_sent,
+            State::Greeting | State::Request => self.client_eof,
+            State::ConnectingTcp(_) | State::OpeningChannel(..) => false,
+        }
+    }
+
+    fn close(&mut self) {
+        self.client.close();
+        if let Some(upstream) = &mut self.upstream {
+            upstream.close();
+        }
+    }
+
+    // 返回是否有进展
+    fn step(&mut self, sess: &Session, buf: &mut [u8]) -> io::Result<bool> {
+        let mut progress = false;
+
+        // 客户端 -> 握手缓冲区或目标
+        if !self.client_eof && self.to_upstream.len() < BUFFER_SIZE && !matches!(self.state, State::Failed) {
+            match self.client.read(buf) {
+                Ok(0) => {
+                    self.client_eof = true;
+                    progress = true;
+                }
+                Ok(n) => {
+                    if matches!(self.state, State::Greeting | State::Request) {
+                        self.request.extend_from_slice(&buf[..n]);
+                    } else {
+                        self.to_upstream.extend_from_slice(&buf[..n]);
+                    }
+                    progress = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        progress |= self.advance_handshake(sess)?;
+
+        if let Some(upstream) = &mut self.upstream {
+            if !self.to_upstream.is_empty() {
+                match upstream.write(&self.to_upstream) {
+                    Ok(n) => {
+                        self.to_upstream.drain(..n);
+                        progress = true;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if self.client_eof && self.to_upstream.is_empty() && !self.upstream_eof_sent {
+                let _ = upstream.send_eof();
+                self.upstream_eof_sent = true;
+                progress = true;
+            }
+            if !self.upstream_eof && self.to_client.len() < BUFFER_SIZE {
+                match upstream.read(buf) {
+                    Ok(0) => {
+                        self.upstream_eof = true;
+                        progress = true;
+                    }
+                    Ok(n) => {
+                        self.to_client.extend_from_slice(&buf[..n]);
+                        progress = true;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if !self.to_client.is_empty() {
+            match self.client.write(&self.to_client) {
+                Ok(n) => {
+                    self.to_client.drain(..n);
+                    progress = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.upstream_eof && self.to_client.is_empty() && !self.client_eof_sent {
+            let _ = self.client.send_eof();
+            self.client_eof_sent = true;
+            progress = true;
+        }
+        if self.is_done() {
+            self.close();
+        }
+        Ok(progress)
+    }
+
+    fn advance_handshake(&mut self, sess: &Session) -> io::Result<bool> {
+        match &mut self.state {
+            State::Greeting => match parse_greeting(&self.request) {
+                Parsed::Incomplete => Ok(false),
+                Parsed::Done(len, no_auth) => {
+                    self.request.drain(..len);
+                    if no_auth {
+                        self.to_client.extend_from_slice(&[5, 0]);
+                        self.state = State::Request;
+                    } else {
+                        // 只支持无认证
+                        self.to_client.extend_from_slice(&[5, 0xff]);
+                        self.state = State::Failed;
+                    }
+                    Ok(true)
+                }
+                Parsed::Invalid => Err(io::Error::new(io::ErrorKind::InvalidData, "not a SOCKS5 client")),
+            },
+            State::Request => match parse_request(&self.request) {
+                Parsed::Incomplete => Ok(false),
+                Parsed::Done(len, target) => {
+                    self.request.drain(..len);
+                    // 请求之后已经发来的数据交给目标
+                    self.to_upstream.append(&mut self.request);
+                    match target {
+                        Ok((host, port)) => {
+                            if output::is_verbose() {
+                                eprintln!("Proxy: connecting to {}:{}", host, port);
+                            }
+                            self.state = match self.client {
+                                Stream::Channel(_) => State::ConnectingTcp(connect_in_background(host, port)),
+                                _ => State::OpeningChannel(Target::Tcp(host, port)),
+                            };
+                        }
+                        Err(code) => self.fail(code),
+                    }
+                    Ok(true)
+                }
+                Parsed::Invalid => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid SOCKS5 request")),
+            },
+            State::ConnectingTcp(receiver) => match receiver.try_recv() {
+                Ok(Ok(stream)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    self.connected(Stream::Tcp(stream));
+                    Ok(true)
+                }
+                Ok(Err(e)) => {
+                    if output::is_verbose() {
+                        eprintln!("Proxy: {}", e);
+                    }
+                    self.fail(REPLY_HOST_UNREACHABLE);
+                    Ok(true)
+                }
+                Err(TryRecvError::Empty) => Ok(false),
+                Err(TryRecvError::Disconnected) => {
+                    self.fail(REPLY_FAILURE);
+                    Ok(true)
+                }
+            },
+            State::OpeningChannel(target) => match open_channel(sess, target) {
+                Ok(channel) => {
+                    self.connected(Stream::Channel(channel));
+                    Ok(true)
+                }
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => Ok(false),
+                Err(e) => {
+                    if output::is_verbose() {
+                        eprintln!("Proxy: the server could not reach {}: {}", target, e);
+                    }
+                    self.fail(REPLY_HOST_UNREACHABLE);
+                    Ok(true)
+                }
+            },
+            State::Relaying | State::Failed => Ok(false),
+        }
+    }
+
+    fn connected(&mut self, upstream: Stream) {
+        if self.socks {
+            self.to_client.extend_from_slice(&reply(REPLY_OK));
+        }
+        self.upstream = Some(upstream);
+        self.state = State::Relaying;
+    }
+
+    fn fail(&mut self, code: u8) {
+        if self.socks {
+            self.to_client.extend_from_slice(&reply(code));
+        }
+        self.to_upstream.clear();
+        self.state = State::Failed;
+    }
+}
+
+fn open_channel(sess: &Session, target: &Target) -> Result<Channel, ssh2::Error> {
+    match target {
+        Target::Tcp(host, port) => sess.channel_direct_tcpip(host, *port, None),
+        Target::Socket(path) => sess.channel_direct_streamlocal(path, None),
+    }
+}
+
+fn connect_in_background(host: String, port: u16) -> Receiver<io::Result<TcpStream>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = (|| {
+            let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("{} has no addresses", host));
+            for addr in (host.as_str(), port).to_socket_addrs()? {
+                match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => last_error = e,
+                }
+            }
+            Err(io::Error::new(last_error.kind(), format!("could not reach {}:{}: {}", host, port, last_error)))
+        })();
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+enum Parsed<T> {
+    Incomplete,
+    Done(usize, T),
+    Invalid,
+}
+
+// VER NMETHODS METHODS...
+fn parse_greeting(data: &[u8]) -> Parsed<bool> {
+    match data {
+        [] | [5] => Parsed::Incomplete,
+        [5, count, methods @ ..] => {
+            let count = *count as usize;
+            if methods.len() < count {
+                return Parsed::Incomplete;
+            }
+            Parsed::Done(2 + count, methods[..count].contains(&0))
+        }
+        _ => Parsed::Invalid,
+    }
+}
+
+// VER CMD RSV ATYP DST.ADDR DST.PORT；目标无法处理时给出回复码
+fn parse_request(data: &[u8]) -> Parsed<Result<(String, u16), u8>> {
+    if data.len() < 5 {
+        return Parsed::Incomplete;
+    }
+    if data[0] != 5 {
+        return Parsed::Invalid;
+    }
+    let (host, addr_len) = match data[3] {
+        1 => (data.get(4..8).map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]).to_string()), 4),
+        3 => {
+            let len = data[4] as usize;
+            (data.get(5..5 + len).map(|name| String::from_utf8_lossy(name).into_owned()), 1 + len)
+        }
+        4 => (
+            data.get(4..20).map(|a| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(a);
+                Ipv6Addr::from(octets).to_string()
+            }),
+            16,
+        ),
+        _ => return Parsed::Done(data.len(), Err(REPLY_ADDRESS_NOT_SUPPORTED)),
+    };
+    let len = 4 + addr_len + 2;
+    let (Some(host), Some(port)) = (host, data.get(len - 2..len)) else {
+        return Parsed::Incomplete;
+    };
+    let port = u16::from_be_bytes([port[0], port[1]]);
+    // 只支持 CONNECT
+    if data[1] != 1 {
+        return Parsed::Done(len, Err(REPLY_COMMAND_NOT_SUPPORTED));
+    }
+    Parsed::Done(len, Ok((host, port)))
+}
+
+fn reply(code: u8) -> [u8; 10] {
+    [5, code, 0, 1, 0, 0, 0, 0, 0, 0]
+}
@@ -0,0 +1,280 @@
+use crate::error;
+use crate::interrupt;
+use crate::output;
+use crate::progress::{self, TransferStats};
+use crate::remote_fs::format_size;
+use crate::transfer::SftpSession;
+use anyhow::{anyhow, Error, Result};
+use serde_json::json;
+use ssh2::{ErrorCode, Session};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 重试等待时间每次翻倍，最多等这么久
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+// 连接断开、超时一类的 libssh2 错误码，重新连接后可能成功
+const TRANSIENT_SESSION_ERRORS: &[i32] = &[-1, -2, -7, -9, -13, -26, -30, -43, -45];
+const SESSION_CHECK_TIMEOUT_MS: u32 = 5000;
+
+/// 失败重试的策略：只重试连接类错误，等待时间指数增长
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    fn delay_for(&self, attempt: u32) -> Duration {
+        self.delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+/// 队列中的一项：一个源路径和它的目标目录
+#[derive(Clone)]
+pub struct Job {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+pub enum Outcome {
+    Done(TransferStats),
+    Skipped,
+    Failed(Error),
+}
+
+pub struct JobResult {
+    pub job: Job,
+    pub outcome: Outcome,
+    pub attempts: u32,
+    pub started: Instant,
+}
+
+/// 用若干个并发连接执行一组传输，每个工作线程持有自己的会话和 SFTP 通道，依次领取任务
+pub struct TransferQueue {
+    name: &'static str,
+    jobs: Vec<Job>,
+    concurrency: usize,
+    retry: RetryPolicy,
+}
+
+impl TransferQueue {
+    pub fn new(name: &'static str, jobs: Vec<Job>, concurrency: usize, retry: RetryPolicy) -> Self {
+        TransferQueue { name, jobs, concurrency, retry }
+    }
+
+    /// 执行所有任务，按入队顺序返回结果；收到中断后不再领取新任务
+    pub fn run<C, F>(self, connect: C, transfer: F) -> Vec<JobResult>
+    where
+        C: Fn() -> Result<Session> + Sync,
+        F: Fn(&SftpSession, &Job) -> Result<TransferStats> + Sync,
+    {
+        let workers = self.concurrency.clamp(1, self.jobs.len().max(1));
+        let next = AtomicUsize::new(0);
+        let slots: Vec<Mutex<Option<Job>>> = self.jobs.into_iter().map(|job| Mutex::new(Some(job))).collect();
+        let results = Mutex::new(Vec::new());
+        let retry = self.retry;
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    let mut sess = None;
+                    while !interrupt::is_interrupted() {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(slot) = slots.get(index) else { break };
+                        let job = slot.lock().unwrap().take().unwrap();
+                        let result = run_job(job, &mut sess, retry, &connect, &transfer);
+                        results.lock().unwrap().push((index, result));
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        let results: Vec<JobResult> = results.into_iter().map(|(_, result)| result).collect();
+        if slots.len() > 1 {
+            progress::finish_batch();
+            report(self.name, &results);
+        }
+        results
+    }
+}
+
+fn run_job<C, F>(job: Job, sess: &mut Option<SftpSession>, retry: RetryPolicy, connect: &C, transfer: &F) -> JobResult
+where
+    C: Fn() -> Result<Session>,
+    F: Fn(&SftpSession, &Job) -> Result<TransferStats>,
+{
+    let started = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = match sess {
+            Some(sess) => transfer(sess, &job),
+            None => connect().and_then(|opened| transfer(sess.insert(SftpSession::new(opened)), &job)),
+        };
+        let mut transient = false;
+        if let Err(e) = &result {
+            // SFTP 读写的错误只剩一句描述，看连接是否还能用来判断是不是断线
+            let dropped = sess.as_ref().is_some_and(|sess| !session_alive(&sess.sess));
+            if dropped {
+                *sess = None;
+            }
+            transient = dropped || is_transient(e);
+        }
+        let outcome = match result {
+            Ok(stats) if stats.all_skipped() => Outcome::Skipped,
+            Ok(stats) => Outcome::Done(stats),
+            Err(e) if transient && attempts <= retry.retries && !interrupt::is_interrupted() => {
+                let delay = retry.delay_for(attempts);
+                eprintln!(
+                    "Warning: {} failed ({:#}); retrying in {}s ({}/{})",
+                    job.from.display(),
+                    e,
+                    delay.as_secs_f64(),
+                    attempts,
+                    retry.retries
+                );
+                output::event("transfer_retry", json!({
+                    "path": job.from,
+                    "attempt": attempts,
+                    "delay_ms": delay.as_millis() as u64,
+                    "error": format!("{:#}", e),
+                }));
+                sleep_interruptible(delay);
+                continue;
+            }
+            Err(e) => Outcome::Failed(e),
+        };
+        return JobResult { job, outcome, attempts, started };
+    }
+}
+
+fn sleep_interruptible(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !interrupt::is_interrupted() && Instant::now() < until {
+        thread::sleep(Duration::from_millis(100).min(until.saturating_duration_since(Instant::now())));
+    }
+}
+
+// 能打开新通道说明连接还在；最多等 5 秒
+fn session_alive(sess: &Session) -> bool {
+    let timeout = sess.timeout();
+    sess.set_timeout(SESSION_CHECK_TIMEOUT_MS);
+    let alive = sess.channel_session().map(|mut channel| {
+        let _ = channel.close();
+    });
+    sess.set_timeout(timeout);
+    alive.is_ok()
+}
+
+// 连接断开、超时等可以重试；文件不存在、没有权限等重试也没用
+fn is_transient(error: &Error) -> bool {
+    error::chain(error).into_iter().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<ssh2::Error>() {
+            return is_transient_ssh(e);
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            if let Some(inner) = e.get_ref().and_then(|inner| inner.downcast_ref::<ssh2::Error>()) {
+                return is_transient_ssh(inner);
+            }
+            return matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        false
+    })
+}
+
+fn is_transient_ssh(error: &ssh2::Error) -> bool {
+    match error.code() {
+        ErrorCode::Session(code) => TRANSIENT_SESSION_ERRORS.contains(&code),
+        ErrorCode::SFTP(_) => false,
+    }
+}
+
+fn report(name: &str, results: &[JobResult]) {
+    let count = |f: fn(&Outcome) -> bool| results.iter().filter(|result| f(&result.outcome)).count();
+    let succeeded = count(|outcome| matches!(outcome, Outcome::Done(_)));
+    let skipped = count(|outcome| matches!(outcome, Outcome::Skipped));
+    let failed = count(|outcome| matches!(outcome, Outcome::Failed(_)));
+
+    output::status(format!("{} report: {} succeeded, {} skipped, {} failed", name, succeeded, skipped, failed));
+    // 上传到多个目标目录时同一个文件会出现多次，带上目标区分
+    let several_destinations = results.iter().any(|result| result.job.to != results[0].job.to);
+    for result in results {
+        let path = match several_destinations {
+            true => format!("{} -> {}", result.job.from.display(), result.job.to.display()),
+            false => result.job.from.display().to_string(),
+        };
+        let retried = match result.attempts {
+            1 => String::new(),
+            n => format!(" after {} attempts", n),
+        };
+        let line = match &result.outcome {
+            Outcome::Done(stats) => format!("  ok       {} ({}){}", path, format_size(stats.bytes), retried),
+            Outcome::Skipped => format!("  skipped  {} (already exists)", path),
+            Outcome::Failed(e) => format!("  failed   {}{}: {:#}", path, retried, e),
+        };
+        output::status(line);
+    }
+
+    let entries: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let (status, bytes, error) = match &result.outcome {
+                Outcome::Done(stats) => ("succeeded", Some(stats.bytes), None),
+                Outcome::Skipped => ("skipped", None, None),
+                Outcome::Failed(e) => ("failed", None, Some(format!("{:#}", e))),
+            };
+            json!({
+                "path": result.job.from,
+                "destination": result.job.to,
+                "status": status,
+                "bytes": bytes,
+                "attempts": result.attempts,
+                "error": error,
+            })
+        })
+        .collect();
+    output::event("transfer_report", json!({
+        "queue": name.to_lowercase(),
+        "succeeded": succeeded,
+        "skipped": skipped,
+        "failed": failed,
+        "items": entries,
+    }));
+}
+
+/// 只有一项时直接返回它的错误；多项时有失败就返回汇总错误（报告已打印）
+pub fn into_result(results: Vec<JobResult>) -> Result<()> {
+    if interrupt::is_interrupted() {
+        return Err(anyhow!("Interrupted"));
+    }
+    let total = results.len();
+    let mut failures: Vec<Error> = results
+        .into_iter()
+        .filter_map(|result| match result.outcome {
+            Outcome::Failed(e) => Some(e),
+            _ => None,
+        })
+        .collect();
+    match failures.len() {
+        0 => Ok(()),
+        1 if total == 1 => Err(failures.remove(0)),
+        n => Err(anyhow!("{} of {} transfers failed", n, total)),
+    }
+}
@@ -0,0 +1,147 @@
+use crate::config::{Config, RecipeStep};
+use crate::error;
+use crate::output;
+use crate::ssh;
+use crate::transfer::{self, SftpSession, TransferOptions};
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use serde_json::json;
+use ssh2::Session;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// clap 的 value_parser：--var NAME=VALUE
+pub fn parse_var(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{}'", spec)),
+    }
+}
+
+/// 取出配置里的步骤并代入变量：--var 优先于配置中的默认值，另有内置的 alias、date、time；
+/// 连接前就检查完，缺少变量时不会执行到一半才失败
+pub fn prepare(config: &Config, name: &str, alias: &str, overrides: &[(String, String)]) -> Result<Vec<RecipeStep>> {
+    if !config.connections.contains_key(alias) {
+        return Err(error::alias_not_found(alias));
+    }
+    let recipe = config
+        .recipes
+        .get(name)
+        .with_context(|| format!("No recipe '{}'. See 'rssh recipe list'.", name))
+        .map_err(error::config)?;
+    let now = Local::now();
+    let mut vars = BTreeMap::from([
+        ("alias".to_string(), alias.to_string()),
+        ("date".to_string(), now.format("%Y%m%d").to_string()),
+        ("time".to_string(), now.format("%H%M%S").to_string()),
+    ]);
+    vars.extend(recipe.vars.clone());
+    vars.extend(overrides.iter().cloned());
+
+    let expand = |text: &str| expand(text, &vars).with_context(|| format!("In recipe '{}'", name));
+    recipe
+        .steps
+        .iter()
+        .map(|step| {
+            Ok(match step {
+                RecipeStep::Exec(command) => RecipeStep::Exec(expand(command)?),
+                RecipeStep::Upload { from, to, recursive } => RecipeStep::Upload {
+                    from: expand(from)?,
+                    to: expand(to)?,
+                    recursive: *recursive,
+                },
+                RecipeStep::Download { from, to, recursive } => RecipeStep::Download {
+                    from: expand(from)?,
+                    to: expand(to)?,
+                    recursive: *recursive,
+                },
+            })
+        })
+        .collect()
+}
+
+// 把 {{name}} 换成变量的值；值原样插入，不加引号
+fn expand(text: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed '{{{{' in {:?}", text))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| anyhow!("{{{{{}}}}} has no value; pass --var {}=VALUE", name, name))?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// 在同一个连接上依次执行；传输失败时停下并返回错误，命令失败时停下并返回它的退出码
+pub fn run_steps(sess: Session, alias: &str, steps: &[RecipeStep], opts: TransferOptions) -> Result<i32> {
+    let conn = SftpSession::new(sess);
+    for (index, step) in steps.iter().enumerate() {
+        output::status(format!("==> [{}/{}] {}", index + 1, steps.len(), describe(step)));
+        output::event("recipe_step", json!({ "step": index + 1, "steps": steps.len(), "description": describe(step) }));
+        match step {
+            RecipeStep::Exec(command) => match ssh::handle_exec(conn.sess.clone(), command, false, &[], None)? {
+                0 => {}
+                code => return Ok(code),
+            },
+            RecipeStep::Upload { from, to, recursive } => {
+                let opts = TransferOptions { recursive: *recursive, ..opts };
+                transfer::handle_upload(&conn, alias, Path::new(from), Path::new(to), opts)?;
+            }
+            RecipeStep::Download { from, to, recursive } => {
+                let opts = TransferOptions { recursive: *recursive, ..opts };
+                transfer::handle_download(&conn, alias, Path::new(from), Path::new(to), opts)?;
+            }
+        }
+    }
+    Ok(0)
+}
+
+pub fn describe(step: &RecipeStep) -> String {
+    match step {
+        RecipeStep::Exec(command) => format!("exec: {}", command),
+        RecipeStep::Upload { from, to, .. } => format!("upload: {} -> {}", from, to),
+        RecipeStep::Download { from, to, .. } => format!("download: {} -> {}", from, to),
+    }
+}
+
+pub fn handle_list(config: &Config) -> Result<()> {
+    let mut names: Vec<_> = config.recipes.iter().collect();
+    names.sort_by_key(|(name, _)| *name);
+    if output::is_json() {
+        let recipes: Vec<_> = names
+            .iter()
+            .map(|(name, recipe)| {
+                let steps: Vec<String> = recipe.steps.iter().map(describe).collect();
+                json!({ "name": name, "description": recipe.description, "vars": recipe.vars, "steps": steps })
+            })
+            .collect();
+        output::emit("recipes", json!({ "recipes": recipes }));
+        return Ok(());
+    }
+    if names.is_empty() {
+        println!("No recipes saved. Add them under \"recipes\" in the config file.");
+        return Ok(());
+    }
+    for (name, recipe) in names {
+        match &recipe.description {
+            Some(description) => println!("{} - {}", name, description),
+            None => println!("{}", name),
+        }
+        if !recipe.vars.is_empty() {
+            let vars: Vec<String> = recipe.vars.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            println!("  vars: {}", vars.join(", "));
+        }
+        for (index, step) in recipe.steps.iter().enumerate() {
+            println!("  {}. {}", index + 1, describe(step));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+use anyhow::{anyhow, Context, Result};
+use crate::output;
+use chrono::{Local, TimeZone};
+use serde_json::json;
+use ssh2::{FileStat, Session, Sftp};
+use std::path::Path;
+
+// 以 `ls -l` 风格列出远程目录
+pub fn handle_ls(sess: Session, path: &Path, all: bool) -> Result<()> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    let stat = sftp
+        .stat(path)
+        .context(format!("Failed to stat remote path {:?}", path))?;
+
+    if !stat.is_dir() {
+        if output::is_json() {
+            output::emit("listing", json!({ "path": path, "entries": [entry_json(path, &stat)] }));
+        } else {
+            println!("{}", format_long_entry(path, &stat));
+        }
+        return Ok(());
+    }
+
+    let mut entries = sftp
+        .readdir(path)
+        .context(format!("Failed to read remote directory {:?}", path))?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    entries.retain(|(entry_path, _)| {
+        all || !entry_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .starts_with('.')
+    });
+
+    if output::is_json() {
+        let entries: Vec<_> = entries.iter().map(|(p, s)| entry_json(p, s)).collect();
+        output::emit("listing", json!({ "path": path, "entries": entries }));
+        return Ok(());
+    }
+
+    let mut total = 0u64;
+    for (entry_path, entry_stat) in &entries {
+        total += entry_stat.size.unwrap_or(0);
+        println!("{}", format_long_entry(entry_path, entry_stat));
+    }
+    println!("total {}", format_size(total));
+    Ok(())
+}
+
+pub fn handle_rm(sess: Session, path: &Path, recursive: bool) -> Result<()> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    let stat = sftp
+        .lstat(path)
+        .context(format!("Failed to stat remote path {:?}", path))?;
+
+    if stat.is_dir() {
+        if !recursive {
+            return Err(anyhow!(
+                "Remote path {:?} is a directory. Use --recursive to remove it.",
+                path
+            ));
+        }
+        remove_dir_all(&sftp, path)?;
+    } else {
+        sftp.unlink(path)
+            .context(format!("Failed to remove remote file {:?}", path))?;
+    }
+    output::status(format!("Removed {:?}", path));
+    output::event("removed", json!({ "path": path }));
+    Ok(())
+}
+
+pub fn handle_mkdir(sess: Session, path: &Path, parents: bool) -> Result<()> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    if parents {
+        create_dir_all(&sftp, path)?;
+    } else {
+        sftp.mkdir(path, 0o755)
+            .context(format!("Failed to create remote directory {:?}", path))?;
+    }
+    output::status(format!("Created {:?}", path));
+    output::event("created", json!({ "path": path }));
+    Ok(())
+}
+
+pub fn handle_mv(sess: Session, from: &Path, to: &Path) -> Result<()> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    // 如果目标是已存在的目录，则移动到该目录下并保留文件名
+    let target = match sftp.stat(to) {
+        Ok(stat) if stat.is_dir() => {
+            let file_name = from
+                .file_name()
+                .ok_or_else(|| anyhow!("Invalid source path {:?}", from))?;
+            to.join(file_name)
+        }
+        _ => to.to_path_buf(),
+    };
+    sftp.rename(from, &target, None)
+        .context(format!("Failed to move {:?} to {:?}", from, target))?;
+    output::status(format!("Moved {:?} -> {:?}", from, target));
+    output::event("moved", json!({ "from": from, "to": target }));
+    Ok(())
+}
+
+pub fn handle_stat(sess: Session, path: &Path) -> Result<()> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    let stat = sftp
+        .lstat(path)
+        .context(format!("Failed to stat remote path {:?}", path))?;
+
+    if output::is_json() {
+        let mut entry = entry_json(path, &stat);
+        entry["atime"] = json!(stat.atime);
+        if stat.file_type().is_symlink() {
+            entry["target"] = json!(sftp.readlink(path).ok());
+        }
+        output::emit("stat", entry);
+        return Ok(());
+    }
+
+    println!("  Path: {}", path.display());
+    println!("  Type: {}", file_type_name(&stat));
+    println!(
+        "  Size: {} ({} bytes)",
+        format_size(stat.size.unwrap_or(0)),
+        stat.size.unwrap_or(0)
+    );
+    match stat.perm {
+        Some(perm) => println!("  Mode: {:04o} ({})", perm & 0o7777, format_mode(perm)),
+        None => println!("  Mode: -"),
+    }
+    println!(
+        "   Uid: {}  Gid: {}",
+        stat.uid.map_or("-".to_string(), |u| u.to_string()),
+        stat.gid.map_or("-".to_string(), |g| g.to_string())
+    );
+    println!("Access: {}", format_time(stat.atime));
+    println!("Modify: {}", format_time(stat.mtime));
+    if stat.file_type().is_symlink()
+        && let Ok(target) = sftp.readlink(path)
+    {
+        println!("Target: {}", target.display());
+    }
+    Ok(())
+}
+
+fn entry_json(path: &Path, stat: &FileStat) -> serde_json::Value {
+    json!({
+        "path": path,
+        "name": path.file_name().map(|n| n.to_string_lossy()),
+        "type": file_type_name(stat),
+        "size": stat.size,
+        "mode": stat.perm.map(|p| format!("{:04o}", p & 0o7777)),
+        "uid": stat.uid,
+        "gid": stat.gid,
+        "mtime": stat.mtime,
+    })
+}
+
+fn format_long_entry(path: &Path, stat: &FileStat) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    format!(
+        "{} {:>6} {:>6} {:>8} {} {}",
+        stat.perm.map_or("?---------".to_string(), format_mode),
+        stat.uid.map_or("-".to_string(), |u| u.to_string()),
+        stat.gid.map_or("-".to_string(), |g| g.to_string()),
+        format_size(stat.size.unwrap_or(0)),
+        format_time(stat.mtime),
+        name
+    )
+}
+
+// 把 st_mode 转成 `drwxr-xr-x` 形式
+pub fn format_mode(mode: u32) -> String {
+    let kind = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '-',
+    };
+    let mut out = String::with_capacity(10);
+    out.push(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
+
+/// 解析 "100M"、"1G"、"512K" 或字节数
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 1024),
+        Some((i, 'm' | 'M')) => (&size[..i], 1024 * 1024),
+        Some((i, 'g' | 'G')) => (&size[..i], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n * multiplier),
+        _ => Err(anyhow!("Invalid size '{}'. Use e.g. 512K, 100M or 1G.", size)),
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+fn format_time(timestamp: Option<u64>) -> String {
+    timestamp
+        .and_then(|t| Local.timestamp_opt(t as i64, 0).single())
+        .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
+
+fn file_type_name(stat: &FileStat) -> &'static str {
+    let file_type = stat.file_type();
+    if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symbolic link"
+    } else if file_type.is_file() {
+        "regular file"
+    } else {
+        "other"
+    }
+}
+
+pub fn create_dir_all(sftp: &Sftp, path: &Path) -> Result<()> {
+    let mut current = std::path::PathBuf::new();
+    for component in path.components() {
+        current.push(component);
+        if sftp.stat(&current).is_ok() {
+            continue;
+        }
+        sftp.mkdir(&current, 0o755)
+            .context(format!("Failed to create remote directory {:?}", current))?;
+    }
+    Ok(())
+}
+
+pub fn remove_dir_all(sftp: &Sftp, path: &Path) -> Result<()> {
+    let entries = sftp
+        .readdir(path)
+        .context(format!("Failed to read remote directory {:?}", path))?;
+    for (entry_path, entry_stat) in entries {
+        if entry_stat.file_type().is_dir() {
+            remove_dir_all(sftp, &entry_path)?;
+        } else {
+            sftp.unlink(&entry_path)
+                .context(format!("Failed to remove remote file {:?}", entry_path))?;
+        }
+    }
+    sftp.rmdir(path)
+        .context(format!("Failed to remove remote directory {:?}", path))?;
+    Ok(())
+}
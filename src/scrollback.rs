@@ -0,0 +1,304 @@
+use crate::clipboard;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::{cursor, queue, terminal};
+use std::io::{self, Write};
+
+// 复制模式期间暂存的远程输出上限，超过后退出时改为按终端状态重绘
+const MAX_HELD_BYTES: usize = 4 * 1024 * 1024;
+const HELP: &str = "k/j scroll  v select  y copy  / ? search  n/N next  q quit";
+
+/// 本地回滚缓冲区：所有远程输出都经过一个终端模拟器，复制模式下可以翻看和搜索
+pub struct Scrollback {
+    parser: vt100::Parser,
+    copy: Option<CopyMode>,
+    // 复制模式期间收到的输出，退出时再写到终端
+    held: Vec<u8>,
+    overflowed: bool,
+}
+
+struct CopyMode {
+    // 视图顶部和光标所在的行号（0 为最早的一行）
+    top: usize,
+    cursor: usize,
+    anchor: Option<usize>,
+    // 正在输入的搜索词和方向（true 为向上）
+    search: Option<(String, bool)>,
+    last_search: Option<(String, bool)>,
+    message: Option<String>,
+}
+
+/// 复制模式中按键的结果
+pub enum CopyAction {
+    Stay,
+    // 退出复制模式，需要写到终端以恢复画面的数据
+    Exit(Vec<u8>),
+}
+
+impl Scrollback {
+    pub fn new(width: u16, height: u16, lines: usize) -> Self {
+        Scrollback {
+            parser: vt100::Parser::new(height, width, lines),
+            copy: None,
+            held: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.parser.set_size(height, width);
+        let view = view_height(&self.parser);
+        let total = total_lines(&mut self.parser);
+        if let Some(copy) = &mut self.copy {
+            copy.clamp(view, total);
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.copy.is_some()
+    }
+
+    /// 记录远程输出；返回 true 表示调用方应照常写到终端
+    pub fn feed(&mut self, data: &[u8]) -> bool {
+        self.parser.process(data);
+        if self.copy.is_none() {
+            return true;
+        }
+        if !self.overflowed {
+            self.held.extend_from_slice(data);
+            if self.held.len() > MAX_HELD_BYTES {
+                self.held = Vec::new();
+                self.overflowed = true;
+            }
+        }
+        false
+    }
+
+    pub fn enter(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let total = total_lines(&mut self.parser);
+        let view = view_height(&self.parser);
+        let (cursor_row, _) = self.parser.screen().cursor_position();
+        let screen_top = total.saturating_sub(self.parser.screen().size().0 as usize);
+        self.copy = Some(CopyMode {
+            top: total.saturating_sub(view),
+            cursor: (screen_top + cursor_row as usize).min(total.saturating_sub(1)),
+            anchor: None,
+            search: None,
+            last_search: None,
+            message: None,
+        });
+        queue!(stdout, terminal::EnterAlternateScreen)?;
+        self.render(stdout)
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent, stdout: &mut io::Stdout) -> io::Result<CopyAction> {
+        let total = total_lines(&mut self.parser);
+        let view = view_height(&self.parser);
+        let Some(copy) = &mut self.copy else {
+            return Ok(CopyAction::Stay);
+        };
+        copy.message = None;
+
+        // 正在输入搜索词
+        if let Some((query, _)) = &mut copy.search {
+            match key.code {
+                KeyCode::Esc => copy.search = None,
+                KeyCode::Enter => {
+                    if let Some((query, backward)) = copy.search.take().filter(|(query, _)| !query.is_empty()) {
+                        copy.last_search = Some((query, backward));
+                        copy.find_next(&mut self.parser, false, view, total);
+                    }
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => query.push(c),
+                _ => {}
+            }
+            self.render(stdout)?;
+            return Ok(CopyAction::Stay);
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return self.exit(stdout).map(CopyAction::Exit),
+            KeyCode::Char('c') if ctrl => return self.exit(stdout).map(CopyAction::Exit),
+            KeyCode::Up | KeyCode::Char('k') => copy.move_cursor(-1, view, total),
+            KeyCode::Down | KeyCode::Char('j') => copy.move_cursor(1, view, total),
+            KeyCode::PageUp | KeyCode::Char('b') => copy.move_cursor(-(view as isize), view, total),
+            KeyCode::PageDown | KeyCode::Char('f') => copy.move_cursor(view as isize, view, total),
+            KeyCode::Char('u') if ctrl => copy.move_cursor(-(view as isize / 2), view, total),
+            KeyCode::Char('d') if ctrl => copy.move_cursor(view as isize / 2, view, total),
+            KeyCode::Home | KeyCode::Char('g') => copy.move_cursor(isize::MIN / 2, view, total),
+            KeyCode::End | KeyCode::Char('G') => copy.move_cursor(isize::MAX / 2, view, total),
+            KeyCode::Char('v') | KeyCode::Char(' ') => {
+                copy.anchor = match copy.anchor {
+                    Some(_) => None,
+                    None => Some(copy.cursor),
+                };
+            }
+            // `/` 向上（更早的输出）搜索，`?` 向下搜索
+            KeyCode::Char(c @ ('/' | '?')) => copy.search = Some((String::new(), c == '/')),
+            KeyCode::Char('n') => copy.find_next(&mut self.parser, false, view, total),
+            KeyCode::Char('N') => copy.find_next(&mut self.parser, true, view, total),
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let (start, end) = match copy.anchor {
+                    Some(anchor) => (anchor.min(copy.cursor), anchor.max(copy.cursor)),
+                    None => (copy.cursor, copy.cursor),
+                };
+                let text = lines(&mut self.parser, start, end - start + 1)
+                    .iter()
+                    .map(|line| line.trim_end())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                copy.anchor = None;
+                copy.message = Some(match clipboard::copy(&text) {
+                    Ok(method) => format!("Copied {} line(s) via {}", end - start + 1, method),
+                    Err(e) => format!("Copy failed: {:#}", e),
+                });
+            }
+            _ => {}
+        }
+        self.render(stdout)?;
+        Ok(CopyAction::Stay)
+    }
+
+    /// 数据到达时刷新复制模式的画面（例如行数变化）
+    pub fn refresh(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        if self.copy.is_some() {
+            self.render(stdout)?;
+        }
+        Ok(())
+    }
+
+    fn exit(&mut self, stdout: &mut io::Stdout) -> io::Result<Vec<u8>> {
+        self.copy = None;
+        queue!(stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
+        let held = std::mem::take(&mut self.held);
+        // 远程程序自己在用备用屏幕（如 vim），或暂存溢出时，按模拟器状态整屏重绘
+        if self.overflowed || self.parser.screen().alternate_screen() {
+            self.overflowed = false;
+            if self.parser.screen().alternate_screen() {
+                queue!(stdout, terminal::EnterAlternateScreen)?;
+            }
+            return Ok(self.parser.screen().state_formatted());
+        }
+        Ok(held)
+    }
+
+    fn render(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let total = total_lines(&mut self.parser);
+        let view = view_height(&self.parser);
+        let width = self.parser.screen().size().1;
+        let Some(copy) = &self.copy else {
+            return Ok(());
+        };
+        let selection = copy.anchor.map(|anchor| (anchor.min(copy.cursor), anchor.max(copy.cursor)));
+        let rows = lines(&mut self.parser, copy.top, view);
+
+        queue!(stdout, cursor::Hide)?;
+        for i in 0..view {
+            let line = copy.top + i;
+            let highlighted = line == copy.cursor || selection.is_some_and(|(start, end)| (start..=end).contains(&line));
+            queue!(stdout, cursor::MoveTo(0, i as u16), terminal::Clear(terminal::ClearType::CurrentLine))?;
+            if highlighted {
+                queue!(stdout, SetAttribute(Attribute::Reverse))?;
+            }
+            let text = rows.get(i).map(String::as_str).unwrap_or("");
+            // 高亮整行，包括行尾空白
+            queue!(stdout, Print(format!("{:<width$}", text, width = width as usize)))?;
+            if highlighted {
+                queue!(stdout, SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        let status = match (&copy.search, &copy.message) {
+            (Some((query, backward)), _) => format!("{}{}", if *backward { '/' } else { '?' }, query),
+            (None, Some(message)) => message.clone(),
+            (None, None) => format!("[copy mode] line {}/{}  {}", copy.cursor + 1, total, HELP),
+        };
+        let status: String = status.chars().take(width as usize).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, view as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            SetAttribute(Attribute::Reverse),
+            Print(format!("{:<width$}", status, width = width as usize)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        stdout.flush()
+    }
+}
+
+impl CopyMode {
+    fn move_cursor(&mut self, delta: isize, view: usize, total: usize) {
+        let last = total.saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize).saturating_add(delta).clamp(0, last) as usize;
+        self.clamp(view, total);
+    }
+
+    // 让光标保持在视图内
+    fn clamp(&mut self, view: usize, total: usize) {
+        self.cursor = self.cursor.min(total.saturating_sub(1));
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + view {
+            self.top = self.cursor + 1 - view;
+        }
+        self.top = self.top.min(total.saturating_sub(view));
+    }
+
+    fn find_next(&mut self, parser: &mut vt100::Parser, reverse: bool, view: usize, total: usize) {
+        let Some((query, backward)) = &self.last_search else {
+            return;
+        };
+        let needle = query.to_lowercase();
+        let backward = *backward != reverse;
+        let candidates: Box<dyn Iterator<Item = usize>> = if backward {
+            Box::new((0..self.cursor).rev())
+        } else {
+            Box::new(self.cursor + 1..total)
+        };
+        for line in candidates {
+            let text = lines(parser, line, 1).pop().unwrap_or_default();
+            if text.to_lowercase().contains(&needle) {
+                self.cursor = line;
+                self.clamp(view, total);
+                return;
+            }
+        }
+        self.message = Some(format!("Not found: {}", query));
+    }
+}
+
+// 最后一行留给状态栏
+fn view_height(parser: &vt100::Parser) -> usize {
+    (parser.screen().size().0 as usize).saturating_sub(1).max(1)
+}
+
+// 回滚缓冲区加上当前屏幕的总行数
+fn total_lines(parser: &mut vt100::Parser) -> usize {
+    parser.set_scrollback(usize::MAX);
+    let history = parser.screen().scrollback();
+    parser.set_scrollback(0);
+    history + parser.screen().size().0 as usize
+}
+
+// 取出从 start 开始的若干行纯文本
+fn lines(parser: &mut vt100::Parser, start: usize, count: usize) -> Vec<String> {
+    parser.set_scrollback(usize::MAX);
+    let history = parser.screen().scrollback();
+    // 把 start 所在的行滚到视图顶部（或尽量靠上）
+    let offset = history.saturating_sub(start);
+    parser.set_scrollback(offset);
+    let first = history - offset;
+    let width = parser.screen().size().1;
+    let rows = parser
+        .screen()
+        .rows(0, width)
+        .skip(start - first)
+        .take(count)
+        .collect();
+    parser.set_scrollback(0);
+    rows
+}
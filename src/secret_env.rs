@@ -0,0 +1,101 @@
+use crate::credentials::{get_password, is_no_store, secret_account, set_password, store_name};
+use crate::output;
+use crate::prompt;
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, Password};
+use serde_json::json;
+
+/// --secret-env 的一项：NAME=keyring:<secret> 或 NAME=prompt
+#[derive(Clone)]
+pub struct SecretEnv {
+    pub name: String,
+    source: Source,
+}
+
+#[derive(Clone)]
+enum Source {
+    Keyring(String),
+    Prompt,
+}
+
+/// clap 的 value_parser；命令行里只有来源，没有值
+pub fn parse(spec: &str) -> Result<SecretEnv, String> {
+    let (name, source) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=keyring:<secret> or NAME=prompt, got '{}'", spec))?;
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("'{}' is not a valid environment variable name", name));
+    }
+    let source = match source.strip_prefix("keyring:") {
+        Some("") => return Err(format!("missing the secret name after 'keyring:' for {}", name)),
+        Some(secret) => Source::Keyring(secret.to_string()),
+        None if source == "prompt" => Source::Prompt,
+        None => return Err(format!("unknown source '{}' for {}; use keyring:<secret> or prompt", source, name)),
+    };
+    Ok(SecretEnv { name: name.to_string(), source })
+}
+
+/// 连接前取出所有值；keyring 里没有时在终端上询问，并可以保存起来下次使用
+pub fn resolve(specs: &[SecretEnv]) -> Result<Vec<(String, String)>> {
+    let mut values = Vec::new();
+    for spec in specs {
+        let value = match &spec.source {
+            Source::Keyring(secret) => match get_password(&secret_account(secret)) {
+                Ok(value) => value,
+                Err(_) if prompt::can_ask() => {
+                    let value = ask(&format!("Secret '{}' is not saved. Enter the value for {}:", secret, spec.name))?;
+                    let question = format!("Save it as '{}' in the {}?", secret, store_name());
+                    if !is_no_store() && Confirm::new(&question).with_default(true).prompt()? {
+                        set_password(&secret_account(secret), &value)?;
+                    }
+                    value
+                }
+                Err(_) => {
+                    return Err(prompt::unavailable(format!(
+                        "Secret '{}' is not saved and can't be asked for. Save it with 'rssh secrets set {}'.",
+                        secret, secret
+                    )));
+                }
+            },
+            Source::Prompt if prompt::can_ask() => ask(&format!("Value for {}:", spec.name))?,
+            Source::Prompt => {
+                return Err(prompt::unavailable(format!("{}=prompt needs a terminal to ask for the value.", spec.name)));
+            }
+        };
+        // 值按行传给远程 shell，多行的值会被截断
+        if value.contains(['\n', '\r']) {
+            return Err(anyhow!("The value for {} contains a line break, which can't be passed this way.", spec.name));
+        }
+        values.push((spec.name.clone(), value));
+    }
+    Ok(values)
+}
+
+fn ask(prompt: &str) -> Result<String> {
+    Ok(Password::new(prompt)
+        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .prompt()?)
+}
+
+/// secrets set：保存一个给 --secret-env keyring:<name> 用的值
+pub fn handle_set(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(anyhow!("Secret names can't be empty or contain spaces."));
+    }
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("'rssh secrets set' needs a terminal to ask for the value."));
+    }
+    let value = Password::new(&format!("Value for secret '{}':", name))
+        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+        .prompt()?;
+    if value.contains(['\n', '\r']) {
+        return Err(anyhow!("Secret values can't contain line breaks."));
+    }
+    set_password(&secret_account(name), &value)?;
+    output::status(format!("Saved secret '{}' in the {}.", name, store_name()));
+    output::event("secret_saved", json!({ "name": name }));
+    Ok(())
+}
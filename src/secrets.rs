@@ -0,0 +1,193 @@
+use crate::config::{state_dir, write_atomic, Config};
+use crate::credentials::{delete_password, keychain_exists, namespace, store_name, sudo_account, SECRET_PREFIX};
+use crate::output;
+use crate::prompt;
+use crate::vault;
+use anyhow::{anyhow, Context, Result};
+use inquire::Confirm;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+// keychain 不能列举条目，rssh 自己记下保存过的条目名称（不含密码）
+const INDEX_FILE: &str = "keychain-index.json";
+const SUDO_SUFFIX: &str = "#sudo";
+
+fn index_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join(INDEX_FILE))
+}
+
+// 按 keychain 命名空间分组；旧版本的索引是一个数组，视为当前命名空间的
+fn read_all() -> BTreeMap<String, BTreeSet<String>> {
+    let value: Option<Value> = index_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|content| serde_json::from_slice(&content).ok());
+    match value {
+        Some(Value::Array(accounts)) => {
+            let accounts = accounts.iter().filter_map(|account| account.as_str().map(String::from)).collect();
+            BTreeMap::from([(namespace().to_string(), accounts)])
+        }
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => BTreeMap::new(),
+    }
+}
+
+/// 当前命名空间下记下的 keychain 条目名称
+pub fn read_index() -> BTreeSet<String> {
+    read_all().remove(namespace()).unwrap_or_default()
+}
+
+fn write_index(index: &BTreeSet<String>) -> Result<()> {
+    let mut all = read_all();
+    if index.is_empty() {
+        all.remove(namespace());
+    } else {
+        all.insert(namespace().to_string(), index.clone());
+    }
+    let path = index_path()?;
+    fs::create_dir_all(path.parent().unwrap()).context("Failed to create state directory")?;
+    write_atomic(&path, &serde_json::to_vec_pretty(&all)?).context("Failed to write the keychain index")?;
+    Ok(())
+}
+
+/// 保存到 keychain 后记下条目名称；索引写入失败不影响保存本身
+pub fn remember(account: &str) {
+    let mut index = read_index();
+    if index.insert(account.to_string()) {
+        let _ = write_index(&index);
+    }
+}
+
+pub fn forget(account: &str) {
+    let mut index = read_index();
+    if index.remove(account) {
+        let _ = write_index(&index);
+    }
+}
+
+struct Secret {
+    account: String,
+    alias: String,
+    kind: &'static str,
+}
+
+impl Secret {
+    fn new(account: String) -> Self {
+        // --secret-env 的值不属于哪个别名，按完整名称（secret:<name>）显示和删除
+        let (alias, kind) = match account.strip_suffix(SUDO_SUFFIX) {
+            Some(alias) => (alias.to_string(), "sudo password"),
+            None if account.starts_with(SECRET_PREFIX) => (account.clone(), "secret"),
+            None => (account.clone(), "password"),
+        };
+        Secret { account, alias, kind }
+    }
+
+    fn orphaned(&self, config: &Config) -> bool {
+        self.kind != "secret" && !config.connections.contains_key(&self.alias)
+    }
+}
+
+// 密码库可以直接列出；keychain 则检查索引里的条目和所有已配置别名可能有的条目
+fn stored_secrets(config: &Config) -> Result<Vec<Secret>> {
+    let accounts = if vault::is_enabled() {
+        vault::accounts()?
+    } else {
+        let mut candidates = read_index();
+        for alias in config.connections.keys() {
+            candidates.insert(alias.clone());
+            candidates.insert(sudo_account(alias));
+        }
+        let mut found = Vec::new();
+        for account in candidates {
+            if keychain_exists(&account).context("Failed to query the keychain")? {
+                remember(&account);
+                found.push(account);
+            } else {
+                // 在 rssh 之外被删除的条目
+                forget(&account);
+            }
+        }
+        found
+    };
+    let mut secrets: Vec<Secret> = accounts.into_iter().map(Secret::new).collect();
+    secrets.sort_by(|a, b| a.alias.cmp(&b.alias).then(a.kind.cmp(b.kind)));
+    Ok(secrets)
+}
+
+pub fn handle_list(config: &Config) -> Result<()> {
+    let secrets = stored_secrets(config)?;
+    if output::is_json() {
+        let entries: Vec<_> = secrets
+            .iter()
+            .map(|secret| json!({ "alias": secret.alias, "kind": secret.kind, "orphaned": secret.orphaned(config) }))
+            .collect();
+        let namespace = (!vault::is_enabled()).then(namespace);
+        output::emit("secrets", json!({ "store": store_name(), "namespace": namespace, "secrets": entries }));
+        return Ok(());
+    }
+    // keychain 按配置目录分开保存，显示当前用的是哪个
+    let store = match vault::is_enabled() {
+        true => store_name().to_string(),
+        false => format!("{} ({})", store_name(), namespace()),
+    };
+    if secrets.is_empty() {
+        println!("No saved passwords in the {}.", store);
+        return Ok(());
+    }
+    println!("Saved in the {}:", store);
+    for secret in &secrets {
+        let note = if secret.orphaned(config) { "  (alias no longer exists)" } else { "" };
+        println!("  {:<24} {}{}", secret.alias, secret.kind, note);
+    }
+    Ok(())
+}
+
+/// 删除别名的登录密码和 sudo 密码，别名不需要还在配置里
+pub fn handle_delete(config: &Config, alias: &str) -> Result<()> {
+    let targets: Vec<Secret> = stored_secrets(config)?
+        .into_iter()
+        .filter(|secret| secret.alias == alias)
+        .collect();
+    if targets.is_empty() {
+        return Err(anyhow!("No saved passwords for '{}'.", alias));
+    }
+    delete_all(&targets)
+}
+
+pub fn handle_purge(config: &Config, orphaned: bool, yes: bool) -> Result<()> {
+    let targets: Vec<Secret> = stored_secrets(config)?
+        .into_iter()
+        .filter(|secret| !orphaned || secret.orphaned(config))
+        .collect();
+    if targets.is_empty() {
+        output::status("Nothing to purge.");
+        output::event("secrets_deleted", json!({ "deleted": [] }));
+        return Ok(());
+    }
+    // 删除全部时需要确认
+    if !orphaned && !yes {
+        if !prompt::can_ask() {
+            return Err(prompt::unavailable("Refusing to delete every saved password without --yes."));
+        }
+        let question = format!("Delete all {} saved password(s) from the {}?", targets.len(), store_name());
+        if !Confirm::new(&question).with_default(false).prompt()? {
+            return Ok(());
+        }
+    }
+    delete_all(&targets)
+}
+
+fn delete_all(targets: &[Secret]) -> Result<()> {
+    for secret in targets {
+        delete_password(&secret.account)?;
+        output::status(format!("Deleted the {} for '{}'.", secret.kind, secret.alias));
+    }
+    let deleted: Vec<_> = targets
+        .iter()
+        .map(|secret| json!({ "alias": secret.alias, "kind": secret.kind }))
+        .collect();
+    output::event("secrets_deleted", json!({ "deleted": deleted }));
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use crate::config::{self, config_dir, config_path, Config, Connection};
+use crate::credentials;
+use crate::keys;
+use crate::output;
+use crate::perms::{self, PRIVATE_MASK};
+use crate::prompt;
+use crate::vault;
+use anyhow::{anyhow, Context, Result};
+use inquire::{Confirm, MultiSelect, Text};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 第一次运行：还没有配置文件，并且可以在终端上提问
+pub fn is_first_run(config: &Config) -> bool {
+    config.connections.is_empty()
+        && config_path().is_ok_and(|path| !path.exists())
+        && prompt::can_ask()
+        && !output::is_json()
+}
+
+/// 引导设置：创建配置目录、检查密码存储、导入 ~/.ssh/config 里的主机、添加第一个连接
+pub fn run(config: &mut Config) -> Result<()> {
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("The setup asks questions and needs a terminal."));
+    }
+    println!("Welcome to rssh! A few steps to get started (Ctrl+C to skip).");
+
+    let dir = config_dir()?;
+    perms::create_private_dir(&dir).context(format!("Failed to create {:?}", dir))?;
+    // 已经存在的目录也收紧权限，其他用户看不到别名和主机
+    perms::restrict(&dir, PRIVATE_MASK).context(format!("Failed to set permissions on {:?}", dir))?;
+    println!("Config directory: {}", dir.display());
+
+    if vault::is_enabled() {
+        println!("Passwords are saved in the encrypted vault.");
+    } else {
+        match credentials::keychain_probe() {
+            Ok(()) => println!("Passwords will be saved in the system keychain."),
+            Err(e) => println!(
+                "The system keychain is not available ({:#}).\nRun 'rssh vault init' to save passwords in an encrypted file instead.",
+                e
+            ),
+        }
+    }
+
+    let mut added = Vec::new();
+    let ssh_config = keys::ssh_dir()?.join("config");
+    if ssh_config.is_file() {
+        added.extend(import_ssh_config(config, &ssh_config)?);
+    }
+    if added.is_empty() && Confirm::new("Add a connection now?").with_default(true).prompt()? {
+        added.push(add_connection(config)?);
+    }
+
+    // 什么都没添加也写入配置文件，下次不再进入引导
+    config.save()?;
+    if !added.is_empty() {
+        println!("Saved {}. Run 'rssh connect <alias>' or just 'rssh' to pick one.", added.join(", "));
+    }
+    output::event("setup_finished", json!({ "added": added }));
+    Ok(())
+}
+
+fn import_ssh_config(config: &mut Config, path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+    let hosts: Vec<(String, Connection)> = parse_ssh_config(&content)
+        .into_iter()
+        .filter(|(alias, _)| !config.connections.contains_key(alias))
+        .collect();
+    if hosts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let labels: Vec<String> = hosts
+        .iter()
+        .map(|(alias, conn)| format!("{} ({})", alias, config::format_endpoint(&conn.target, conn.port.unwrap_or(config::DEFAULT_PORT))))
+        .collect();
+    let question = format!("Found {} hosts in {}. Import which?", hosts.len(), path.display());
+    let picked = MultiSelect::new(&question, labels).with_all_selected_by_default().raw_prompt()?;
+    let picked: Vec<usize> = picked.into_iter().map(|option| option.index).collect();
+
+    let names: Vec<&str> = picked.iter().map(|&i| hosts[i].0.as_str()).collect();
+    let mut added = Vec::new();
+    for &i in &picked {
+        let (alias, mut conn) = hosts[i].clone();
+        // 跳板机只有也被导入（或已保存）时才保留
+        if let Some(jump) = &conn.jump_host
+            && !names.contains(&jump.as_str())
+            && !config.connections.contains_key(jump)
+        {
+            eprintln!("Warning: '{}' uses ProxyJump {}, which was not imported; it will connect directly.", alias, jump);
+            conn.jump_host = None;
+        }
+        config.connections.insert(alias.clone(), conn);
+        added.push(alias);
+    }
+    Ok(added)
+}
+
+// Host 行的模式和其下的 (小写的选项名, 值)
+type HostBlock = (Vec<String>, Vec<(String, String)>);
+
+/// 取出 ~/.ssh/config 里不带通配符的 Host，按 OpenSSH 的规则以先出现的值为准，
+/// 所以后面 `Host *` 中的设置作为默认值；Match 块和 Include 不处理
+fn parse_ssh_config(content: &str) -> Vec<(String, Connection)> {
+    let mut blocks: Vec<HostBlock> = vec![(vec!["*".to_string()], Vec::new())];
+    let mut in_match = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((key, value)) => (key.to_ascii_lowercase(), value.trim_start_matches(|c: char| c.is_whitespace() || c == '=').trim()),
+            None => continue,
+        };
+        match key.as_str() {
+            "host" => {
+                in_match = false;
+                blocks.push((value.split_whitespace().map(str::to_string).collect(), Vec::new()));
+            }
+            "match" => in_match = true,
+            _ if !in_match => blocks.last_mut().unwrap().1.push((key, value.trim_matches('"').to_string())),
+            _ => {}
+        }
+    }
+
+    let mut aliases: Vec<&str> = Vec::new();
+    for (patterns, _) in &blocks {
+        for pattern in patterns {
+            if !pattern.contains(['*', '?', '!']) && !aliases.contains(&pattern.as_str()) {
+                aliases.push(pattern);
+            }
+        }
+    }
+
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+    aliases
+        .into_iter()
+        .map(|alias| {
+            let get = |name: &str| {
+                blocks
+                    .iter()
+                    .filter(|(patterns, _)| host_matches(patterns, alias))
+                    .flat_map(|(_, options)| options)
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            let host = get("hostname").unwrap_or(alias);
+            let mut conn = Connection::new(format!("{}@{}", get("user").unwrap_or(&user), host));
+            conn.port = get("port").and_then(|port| port.parse().ok()).filter(|&port| port != config::DEFAULT_PORT);
+            conn.identity = get("identityfile").map(expand_home);
+            // 只支持单个、以别名写的跳板机
+            conn.jump_host = get("proxyjump")
+                .filter(|jump| !jump.contains([',', '@', ':']) && *jump != "none")
+                .map(str::to_string);
+            (alias.to_string(), conn)
+        })
+        .collect()
+}
+
+// 任一肯定的模式匹配且没有否定的模式匹配
+fn host_matches(patterns: &[String], alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(negated) if glob_match(negated.as_bytes(), alias.as_bytes()) => return false,
+            Some(_) => {}
+            None => matched |= glob_match(pattern.as_bytes(), alias.as_bytes()),
+        }
+    }
+    matched
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..])),
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p.eq_ignore_ascii_case(t) => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn add_connection(config: &mut Config) -> Result<String> {
+    let alias = Text::new("Alias (a short name for the server):").prompt()?;
+    let alias = alias.trim().to_string();
+    if alias.is_empty() {
+        return Err(anyhow!("The alias can't be empty."));
+    }
+    let target = Text::new("Connection (user@host or user@host:port):").prompt()?;
+    let (target, port) = config::split_port(target.trim())?;
+    if !target.contains('@') {
+        return Err(anyhow!("Invalid connection string format. Use 'user@host'."));
+    }
+    let identity = Text::new("Private key (leave empty to use a password or the default ~/.ssh keys):").prompt()?;
+
+    let mut conn = Connection::new(target);
+    conn.port = port;
+    conn.identity = Some(identity.trim()).filter(|path| !path.is_empty()).map(expand_home);
+    config.connections.insert(alias.clone(), conn);
+    Ok(alias)
+}
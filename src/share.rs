@@ -0,0 +1,173 @@
+use crate::input::Waker;
+use crate::output;
+use anyhow::{Context, Result};
+use ssh_key::rand_core::{OsRng, RngCore};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+// 观看者的网络太慢时断开它，不拖慢自己的会话
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// connect --share 的设置
+#[derive(Clone, Copy)]
+pub struct ShareOptions {
+    pub port: u16,
+    // 观看者发送令牌后可以输入
+    pub allow_input: bool,
+}
+
+/// 已经在监听、还没开始转发的共享端口
+pub struct ShareListener {
+    listener: TcpListener,
+    token: Option<String>,
+}
+
+/// 把交互会话的输出转发给连到本地端口的观看者
+pub struct Share {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    // 记录当前屏幕，新来的观看者先看到完整画面
+    screen: Arc<Mutex<vt100::Parser>>,
+    input: Receiver<Vec<u8>>,
+    stop: Arc<AtomicBool>,
+}
+
+/// 只监听 127.0.0.1，在进入原始模式前调用，好把地址和令牌告诉用户
+pub fn listen(options: ShareOptions) -> Result<ShareListener> {
+    let listener = TcpListener::bind(("127.0.0.1", options.port))
+        .context(format!("Failed to listen on 127.0.0.1:{}", options.port))?;
+    listener.set_nonblocking(true)?;
+    let token = options.allow_input.then(|| format!("{:016x}", OsRng.next_u64()));
+
+    output::status(format!(
+        "Sharing this session on 127.0.0.1:{} (watch with: nc localhost {})",
+        options.port, options.port
+    ));
+    match &token {
+        Some(token) => output::status(format!("Watchers who send the token {} as their first line can type.", token)),
+        None => output::status("Watchers can only see the session."),
+    }
+    Ok(ShareListener { listener, token })
+}
+
+impl ShareListener {
+    /// 开始接受观看者；观看者的输入通过 waker 唤醒会话主循环
+    pub fn start(self, title: &str, width: u16, height: u16, waker: Waker) -> Share {
+        let ShareListener { listener, token } = self;
+        let (sender, input) = mpsc::channel();
+        let share = Share {
+            clients: Arc::new(Mutex::new(Vec::new())),
+            screen: Arc::new(Mutex::new(vt100::Parser::new(height.max(1), width.max(1), 0))),
+            input,
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        let greeting = format!("[rssh] Watching {}. {}\r\n", title, match token {
+            Some(_) => "Send the token to type.",
+            None => "Read-only.",
+        });
+        let clients = share.clients.clone();
+        let screen = share.screen.clone();
+        let stop = share.stop.clone();
+        let waker = Arc::new(waker);
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = accept(stream, &greeting, &clients, &screen, token.as_deref(), &sender, &waker);
+                    }
+                    Err(_) => thread::sleep(ACCEPT_INTERVAL),
+                }
+            }
+        });
+        share
+    }
+}
+
+impl Share {
+    /// 转发一段远程输出；写不进去的观看者被断开
+    pub fn send(&self, data: &[u8]) {
+        self.screen.lock().unwrap().process(data);
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|client| client.write_all(data).and_then(|_| client.flush()).is_ok());
+    }
+
+    pub fn resize(&self, width: u16, height: u16) {
+        self.screen.lock().unwrap().set_size(height.max(1), width.max(1));
+    }
+
+    /// 有权限的观看者输入的数据
+    pub fn take_input(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        while let Ok(chunk) = self.input.try_recv() {
+            data.extend(chunk);
+        }
+        data
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for mut client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.write_all(b"\r\n[rssh] The shared session has ended.\r\n");
+            let _ = client.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+fn accept(
+    mut stream: TcpStream,
+    greeting: &str,
+    clients: &Mutex<Vec<TcpStream>>,
+    screen: &Mutex<vt100::Parser>,
+    token: Option<&str>,
+    sender: &Sender<Vec<u8>>,
+    waker: &Arc<Waker>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+    stream.write_all(greeting.as_bytes())?;
+    {
+        // 拿着锁发送当前画面，之后的输出不会插到画面前面
+        let mut clients = clients.lock().unwrap();
+        stream.write_all(b"\x1b[H\x1b[2J")?;
+        stream.write_all(&screen.lock().unwrap().screen().contents_formatted())?;
+        clients.push(stream.try_clone()?);
+    }
+
+    let Some(token) = token else { return Ok(()) };
+    let token = token.to_string();
+    let sender = sender.clone();
+    let waker = waker.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() || line.trim() != token {
+            // 令牌不对的观看者仍然可以看，输入全部丢弃
+            let _ = std::io::copy(&mut reader, &mut std::io::sink());
+            return;
+        }
+        let _ = reader.get_mut().write_all(b"[rssh] Input enabled.\r\n");
+        let mut buf = [0u8; 1024];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if sender.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                    waker.wake();
+                }
+            }
+        }
+    });
+    Ok(())
+}
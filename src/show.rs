@@ -0,0 +1,175 @@
+use crate::clipboard;
+use crate::config::{self, Config, Connection, DEFAULT_PORT};
+use crate::error;
+use crate::output;
+use crate::ssh::shell_quote;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::json;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ShowAs {
+    /// The connection string, user@host:port
+    Target,
+    /// An equivalent ssh command line
+    Openssh,
+}
+
+/// show：打印连接的完整设置和等价的 ssh 命令；as_ 只打印其中一行，copy 时放到剪贴板
+pub fn handle_show(config: &Config, alias: &str, as_: Option<ShowAs>, copy: bool) -> Result<()> {
+    // 显示填充了 defaults 之后实际使用的设置
+    let conn = &config.resolved(alias).ok_or_else(|| error::alias_not_found(alias))?;
+    // 只打印一行时留给管道，提示信息写到 stderr
+    if as_.is_some() {
+        output::use_stderr();
+    }
+    let port = config.effective_port(alias, None);
+    let target = config::format_endpoint(&conn.target, port);
+    let command = openssh_command(config, alias, conn)?;
+    let chain = config.jump_chain(alias)?;
+    let jumps = &chain[..chain.len() - 1];
+
+    // 默认复制 ssh 命令
+    let text = match as_ {
+        Some(ShowAs::Target) => &target,
+        Some(ShowAs::Openssh) | None => &command,
+    };
+    let copied = match copy {
+        true => Some(clipboard::copy(text)?),
+        false => None,
+    };
+
+    if output::is_json() {
+        output::emit(
+            "connection",
+            json!({
+                "alias": alias,
+                "target": target,
+                "port": port,
+                "jump_chain": jumps,
+                "settings": conn,
+                "included_from": config.included_from(alias),
+                "ssh_command": command,
+                "copied_with": copied,
+            }),
+        );
+        return Ok(());
+    }
+    match as_ {
+        Some(_) => println!("{}", text),
+        None => {
+            println!("Alias:            {}", alias);
+            println!("Target:           {}", target);
+            if let Some(source) = config.included_from(alias) {
+                println!("Included from:    {}", source.display());
+            }
+            if let Some(identity) = &conn.identity {
+                println!("Identity:         {}", identity.display());
+            }
+            if !conn.identities.is_empty() {
+                let identities: Vec<String> = conn.identities.iter().map(|path| path.display().to_string()).collect();
+                println!("Identities:       {}", identities.join(", "));
+            }
+            if let Some(fingerprint) = &conn.agent_key_fingerprint {
+                println!("Agent key:        {}", fingerprint);
+            }
+            if !jumps.is_empty() {
+                println!("Jump hosts:       {}", jumps.join(" -> "));
+            }
+            if !conn.fallback_hosts.is_empty() {
+                println!("Fallback hosts:   {}", conn.fallback_hosts.join(", "));
+            }
+            if !conn.auth_methods.is_empty() {
+                let methods: Vec<String> = conn
+                    .auth_methods
+                    .iter()
+                    .filter_map(|method| method.to_possible_value().map(|value| value.get_name().to_string()))
+                    .collect();
+                println!("Auth methods:     {}", methods.join(", "));
+            }
+            if let Some(timeout) = conn.connect_timeout {
+                println!("Connect timeout:  {}s", timeout);
+            }
+            if let Some(interval) = conn.keepalive() {
+                println!("Keepalive:        {}s", interval);
+            }
+            if let Some(term) = &conn.term {
+                println!("TERM:             {}", term);
+            }
+            if !conn.tags.is_empty() {
+                println!("Tags:             {}", conn.tags.join(", "));
+            }
+            println!();
+            println!("{}", command);
+        }
+    }
+    if let Some(method) = copied {
+        output::status(format!("Copied to the clipboard ({}).", method));
+    }
+    Ok(())
+}
+
+/// 等价的 OpenSSH 命令；密码、auth_methods、钩子等 ssh 没有对应参数的设置不包含在内
+fn openssh_command(config: &Config, alias: &str, conn: &Connection) -> Result<String> {
+    let mut args: Vec<String> = vec!["ssh".to_string()];
+    for identity in conn.identity.iter().chain(&conn.identities) {
+        args.push("-i".to_string());
+        args.push(identity.display().to_string());
+    }
+    let chain = config.jump_chain(alias)?;
+    if chain.len() > 1 {
+        let jumps: Vec<String> = chain[..chain.len() - 1]
+            .iter()
+            .map(|jump| jump_spec(&config.connections[jump].target, config.effective_port(jump, None)))
+            .collect();
+        args.push("-J".to_string());
+        args.push(jumps.join(","));
+    }
+    let port = config.effective_port(alias, None);
+    if port != DEFAULT_PORT {
+        args.push("-p".to_string());
+        args.push(port.to_string());
+    }
+    let mut options: Vec<(&str, String)> = Vec::new();
+    if let Some(timeout) = conn.connect_timeout {
+        options.push(("ConnectTimeout", timeout.to_string()));
+    }
+    if let Some(interval) = conn.keepalive() {
+        options.push(("ServerAliveInterval", interval.to_string()));
+    }
+    if let Some(provider) = &conn.pkcs11_provider {
+        options.push(("PKCS11Provider", provider.display().to_string()));
+    }
+    let crypto = [
+        ("KexAlgorithms", &conn.crypto.kex),
+        ("Ciphers", &conn.crypto.ciphers),
+        ("MACs", &conn.crypto.macs),
+        ("HostKeyAlgorithms", &conn.crypto.host_key_algorithms),
+    ];
+    options.extend(crypto.into_iter().filter_map(|(name, value)| value.clone().map(|value| (name, value))));
+    for (name, value) in options {
+        args.push("-o".to_string());
+        args.push(format!("{}={}", name, value));
+    }
+    // IPv6 地址不加方括号，端口已经用 -p 给出
+    args.push(conn.target.clone());
+    Ok(args.iter().map(|arg| quote_if_needed(arg)).collect::<Vec<_>>().join(" "))
+}
+
+// -J 的格式：user@host[:port]，IPv6 地址加方括号
+fn jump_spec(target: &str, port: u16) -> String {
+    match port {
+        DEFAULT_PORT if !target.contains(':') => target.to_string(),
+        _ => config::format_endpoint(target, port),
+    }
+}
+
+// 只有含特殊字符的参数才加引号，便于阅读
+fn quote_if_needed(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-~".contains(c));
+    match safe {
+        true => arg.to_string(),
+        false => shell_quote(arg),
+    }
+}
@@ -1,252 +1,1160 @@
-use crate::config::Config;
-use crate::credentials::{get_password, set_password};
+use crate::auth;
+use crate::banner::Banner;
+use crate::clipboard;
+use crate::config::{Config, Connection, CryptoPrefs};
+use crate::error;
+use crate::hostkey;
+use crate::input::{self, InputThread};
+use crate::osc52::{self, Osc52Filter, Osc52Mode};
+use crate::output;
+use crate::scrollback::{CopyAction, Scrollback};
+use crate::interrupt;
+use crate::term::{self, BufferedOutput, SignalMode, TermOutput};
+use crate::trace;
+use crate::share::{self, ShareOptions};
+use crate::sysinfo;
 use anyhow::{anyhow, Context, Result};
-use crossterm::terminal;
-use indicatif::{ProgressBar, ProgressStyle};
-use inquire::{Confirm, Password};
-use ssh2::Session;
-use std::fs;
+use crossterm::{queue, terminal};
+use serde_json::json;
+use ssh2::{Channel, ExtendedData, MethodType, Session};
 use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
 use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_FALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+// 批量传输用的通道窗口，比 libssh2 默认的 2MB 大，高延迟链路上不会等窗口调整
+const BULK_WINDOW_SIZE: u32 = 16 * 1024 * 1024;
+const BULK_PACKET_SIZE: u32 = 32 * 1024;
+// 安静登录时代替 shell 请求执行的命令；SHELL 由 sshd 设置
+const QUIET_LOGIN_COMMAND: &str = "exec \"$SHELL\" -l";
+const SHELL_BUFFER_SIZE: usize = 64 * 1024;
+// Ctrl+] ? 显示的说明
+const ESCAPE_HELP: &str = "Ctrl+] then: [ = copy mode, c = toggle where Ctrl+C/Ctrl+Z go, z = suspend rssh, . = disconnect, Ctrl+] = send Ctrl+]";
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// 已完成握手、尚未认证的连接
+pub struct Transport {
+    pub sess: Session,
+    pub host: String,
+    pub port: u16,
+    pub tcp_time: Duration,
+    pub handshake_time: Duration,
+}
+
+/// 建立会话各阶段的耗时
+pub struct ConnectTimings {
+    pub tcp: Duration,
+    pub handshake: Duration,
+    pub auth: Duration,
+    pub total: Duration,
+}
+
+impl ConnectTimings {
+    pub fn summary(&self) -> String {
+        format!(
+            "TCP connect {:.1}ms, handshake {:.1}ms, auth {:.1}ms, total {:.1}ms",
+            millis(self.tcp),
+            millis(self.handshake),
+            millis(self.auth),
+            millis(self.total)
+        )
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
 
 pub fn create_session(
     config: &Config,
     alias: &str,
-    port: u16,
+    port: Option<u16>,
     identity_path: Option<&Path>,
 ) -> Result<Session> {
-    let conn_str = config
-        .connections
-        .get(alias)
-        .context(format!("Alias '{}' not found.", alias))?;
+    create_session_timed(config, alias, port, identity_path).map(|(sess, _)| sess)
+}
+
+/// 同 create_session，并返回各阶段耗时；-v 时打印出来
+pub fn create_session_timed(
+    config: &Config,
+    alias: &str,
+    port: Option<u16>,
+    identity_path: Option<&Path>,
+) -> Result<(Session, ConnectTimings)> {
+    let chain = config.jump_chain(alias)?;
+    if chain.len() > 1 {
+        output::status(format!("Route: {}", chain.join(" -> ")));
+        output::event("route", json!({ "hops": chain }));
+    }
+    let (sess, timings) = connect_alias(config, alias, port, identity_path)?;
+    if output::is_verbose() {
+        output::status(format!("Timing: {}", timings.summary()));
+    }
+    Ok((sess, timings))
+}
+
+// 跳板机链已在 create_session 中检查过，这里按链逐层递归连接
+fn connect_alias(
+    config: &Config,
+    alias: &str,
+    port: Option<u16>,
+    identity_path: Option<&Path>,
+) -> Result<(Session, ConnectTimings)> {
+    let conn = &config.resolved(alias).ok_or_else(|| error::alias_not_found(alias))?;
+    let conn_str = &conn.target;
+    let port = config.effective_port(alias, port);
+    let identity_path = identity_path.or(conn.identity.as_deref());
 
     let parts: Vec<&str> = conn_str.split('@').collect();
     if parts.len() != 2 {
-        return Err(anyhow!(
+        return Err(error::config(anyhow!(
             "Invalid connection string format. Use 'user@host'."
-        ));
+        )));
     }
     let user = parts[0];
     let host = parts[1];
 
-    println!("Connecting to {}@{}:{}", user, host, port);
+    output::status(format!("Connecting to {}@{}:{}", user, host, port));
+    let started = Instant::now();
+
+    let transport = connect_endpoints(config, conn, host, port).map_err(error::network)?;
+    let (sess, host, port) = (transport.sess, transport.host.as_str(), transport.port);
+    hostkey::verify(&sess, host, port, conn.sshfp)
+        .inspect_err(|e| trace::log(format!("host key check failed: {:#}", e)))
+        .map_err(error::auth)?;
+
+    let auth_started = Instant::now();
+    trace::log(format!("authentication as {} started", user));
+    auth::authenticate(&sess, alias, conn, user, identity_path)
+        .inspect_err(|e| trace::log(format!("authentication failed after {:.1}ms: {:#}", millis(auth_started.elapsed()), e)))
+        .map_err(error::auth)?;
+    trace::log(format!("authenticated in {:.1}ms", millis(auth_started.elapsed())));
+    if let Some(interval) = conn.keepalive() {
+        sess.set_keepalive(false, interval);
+    }
+    let timings = ConnectTimings {
+        tcp: transport.tcp_time,
+        handshake: transport.handshake_time,
+        auth: auth_started.elapsed(),
+        total: started.elapsed(),
+    };
+
+    output::status("Successfully connected!");
+    output::event(
+        "connected",
+        json!({
+            "alias": alias,
+            "user": user,
+            "host": host,
+            "port": port,
+            "duration_ms": timings.total.as_millis() as u64,
+            "tcp_ms": millis(timings.tcp),
+            "handshake_ms": millis(timings.handshake),
+            "auth_ms": millis(timings.auth),
+        }),
+    );
+    Ok((sess, timings))
+}
+
+/// 依次尝试主地址和 fallback_hosts，返回第一个连上的地址
+pub fn connect_endpoints(config: &Config, conn: &Connection, host: &str, port: u16) -> Result<Transport> {
+    try_endpoints(conn, host, port, |host, port, timeout| open_transport(config, conn, host, port, timeout))
+}
+
+/// 只建立到别名 SSH 端口的 TCP（或经跳板机的）连接，不握手，供 stdio 桥接给其他 SSH 客户端
+pub fn connect_raw(config: &Config, alias: &str, port: Option<u16>) -> Result<TcpStream> {
+    let conn = config
+        .connections
+        .get(alias)
+        .ok_or_else(|| error::alias_not_found(alias))?;
+    let host = conn
+        .target
+        .split_once('@')
+        .map(|(_, host)| host)
+        .context("Invalid connection string format. Use 'user@host'.")?;
+    let port = config.effective_port(alias, port);
+    output::status(format!("Connecting to {}:{}", host, port));
+    try_endpoints(conn, host, port, |host, port, timeout| open_tcp(config, conn, host, port, timeout)).map_err(error::network)
+}
+
+fn try_endpoints<T>(
+    conn: &Connection,
+    host: &str,
+    port: u16,
+    mut open: impl FnMut(&str, u16, Option<Duration>) -> Result<T>,
+) -> Result<T> {
+    if conn.fallback_hosts.is_empty() {
+        return open(host, port, conn.connect_timeout());
+    }
+
+    // 有备用地址时每个地址默认最多等 5 秒
+    let timeout = conn.connect_timeout().or(Some(DEFAULT_FALLBACK_TIMEOUT));
+    let mut endpoints = vec![(host.to_string(), port)];
+    endpoints.extend(conn.fallback_hosts.iter().map(|endpoint| parse_endpoint(endpoint, port)));
+
+    let mut failures = Vec::new();
+    for (index, (host, port)) in endpoints.into_iter().enumerate() {
+        if index > 0 {
+            output::status(format!("Trying fallback {}:{}", host, port));
+        }
+        match open(&host, port, timeout) {
+            Ok(opened) => {
+                if index > 0 {
+                    output::status(format!("Connected via fallback {}:{}", host, port));
+                }
+                output::event("endpoint", json!({ "host": host, "port": port, "fallback": index > 0 }));
+                return Ok(opened);
+            }
+            Err(e) => failures.push(format!("{}:{}: {:#}", host, port, e)),
+        }
+    }
+    Err(anyhow!("Could not reach any endpoint:\n  {}", failures.join("\n  ")))
+}
+
+// "host"、"host:port" 或 "[ipv6]:port"，没写端口时用主地址的端口
+fn parse_endpoint(endpoint: &str, default_port: u16) -> (String, u16) {
+    if let Some(rest) = endpoint.strip_prefix('[')
+        && let Some((host, port)) = rest.split_once("]:")
+        && let Ok(port) = port.parse()
+    {
+        return (host.to_string(), port);
+    }
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (host.to_string(), port),
+            Err(_) => (endpoint.to_string(), default_port),
+        },
+        _ => (endpoint.trim_matches(|c| c == '[' || c == ']').to_string(), default_port),
+    }
+}
+
+/// 建立 TCP（或经跳板机的）连接并完成 SSH 握手，不做认证
+pub fn open_transport(
+    config: &Config,
+    conn: &Connection,
+    host: &str,
+    port: u16,
+    timeout: Option<Duration>,
+) -> Result<Transport> {
+    let started = Instant::now();
+    trace::log(format!("TCP connect to {}:{}", host, port));
+    let tcp = open_tcp(config, conn, host, port, timeout)
+        .inspect_err(|e| trace::log(format!("TCP connect failed: {:#}", e)))?;
+    let tcp_time = started.elapsed();
+    trace::log(format!("TCP connected in {:.1}ms", millis(tcp_time)));
 
-    let tcp = TcpStream::connect(format!("{}:{}", host, port))
-        .context(format!("Failed to connect to {}:{}", host, port))?;
     let mut sess = Session::new()?;
+    trace::attach(&sess);
+    apply_crypto(&sess, &conn.crypto)?;
     sess.set_tcp_stream(tcp);
-    sess.handshake()?;
+    // 连接超时也限制握手，端口上不是 SSH 服务时不会一直等
+    if let Some(timeout) = timeout {
+        sess.set_timeout(timeout.as_millis().try_into().unwrap_or(u32::MAX));
+    }
+    let handshake_started = Instant::now();
+    trace::log("SSH handshake started");
+    sess.handshake()
+        .inspect_err(|e| trace::log(format!("SSH handshake failed after {:.1}ms: {}", millis(handshake_started.elapsed()), e)))?;
+    sess.set_timeout(0);
+    trace::log(format!("SSH handshake finished in {:.1}ms", millis(handshake_started.elapsed())));
+    trace::log_methods(&sess);
+    Ok(Transport {
+        sess,
+        host: host.to_string(),
+        port,
+        tcp_time,
+        handshake_time: handshake_started.elapsed(),
+    })
+}
 
-    if let Some(private_key_path) = identity_path {
-        let mut attempts = 0;
-        loop {
-            let auth_result = sess.userauth_pubkey_file(user, None, private_key_path, None);
+fn open_tcp(config: &Config, conn: &Connection, host: &str, port: u16, timeout: Option<Duration>) -> Result<TcpStream> {
+    match &conn.jump_host {
+        Some(jump) => {
+            output::status(format!("Using jump host '{}'", jump));
+            connect_via_jump(config, jump, host, port)
+        }
+        None => connect_tcp(host, port, timeout),
+    }
+}
 
-            match auth_result {
-                Ok(_) => break,
-                Err(e) => {
-                    if e.to_string().contains("passphrase") && attempts < 1 {
-                        let passphrase = Password::new("Enter passphrase for key:")
-                            .with_display_mode(inquire::PasswordDisplayMode::Masked)
-                            .prompt()?;
-                        if sess
-                            .userauth_pubkey_file(user, None, private_key_path, Some(&passphrase))
-                            .is_ok()
-                        {
-                            break;
-                        }
-                        attempts += 1;
-                    } else {
-                        return Err(anyhow!("Authentication failed with key: {}", e));
-                    }
+fn connect_tcp(host: &str, port: u16, timeout: Option<Duration>) -> Result<TcpStream> {
+    let Some(timeout) = timeout else {
+        return TcpStream::connect((host, port)).context(format!("Failed to connect to {}:{}", host, port));
+    };
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .context(format!("Failed to resolve {}", host))?;
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(e).context(format!("Failed to connect to {}:{}", host, port)),
+        None => Err(anyhow!("{} did not resolve to any address", host)),
+    }
+}
+
+// 握手前设置算法偏好，加密和 MAC 两个方向使用同一列表
+fn apply_crypto(sess: &Session, crypto: &CryptoPrefs) -> Result<()> {
+    let prefs = [
+        (&crypto.kex, &[MethodType::Kex][..], "key exchange"),
+        (&crypto.ciphers, &[MethodType::CryptCs, MethodType::CryptSc][..], "cipher"),
+        (&crypto.macs, &[MethodType::MacCs, MethodType::MacSc][..], "MAC"),
+        (&crypto.host_key_algorithms, &[MethodType::HostKey][..], "host key"),
+    ];
+    for (value, methods, name) in prefs {
+        let Some(value) = value else { continue };
+        let value: String = value.split_whitespace().collect();
+        for &method in methods {
+            sess.method_pref(method, &value)
+                .context(format!("None of the {} algorithms '{}' are supported", name, value))?;
+        }
+    }
+    Ok(())
+}
+
+// 通过跳板机的 direct-tcpip 通道连接目标，并在本地回环端口上桥接成 TcpStream
+fn connect_via_jump(config: &Config, jump_alias: &str, host: &str, port: u16) -> Result<TcpStream> {
+    let (jump, _) = connect_alias(config, jump_alias, None, None)
+        .context(format!("Failed to connect to jump host '{}'", jump_alias))?;
+    let channel = jump
+        .channel_direct_tcpip(host, port, None)
+        .context(format!("Jump host '{}' could not reach {}:{}", jump_alias, host, port))?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let local = TcpStream::connect(listener.local_addr()?)?;
+    let (bridge, peer) = listener.accept()?;
+    // 只接受我们自己发起的那个连接
+    if peer != local.local_addr()? {
+        return Err(anyhow!("Unexpected connection on jump host bridge from {}", peer));
+    }
+    local.set_nodelay(true)?;
+    bridge.set_nodelay(true)?;
+
+    thread::spawn(move || {
+        if let Err(e) = pump_channel(&jump, channel, bridge) {
+            eprintln!("Jump host connection error: {}", e);
+        }
+    });
+    Ok(local)
+}
+
+// 在本地 socket 和 SSH 通道之间双向转发数据，直到任一方关闭
+fn pump_channel(sess: &Session, mut channel: Channel, mut stream: TcpStream) -> io::Result<()> {
+    sess.set_blocking(false);
+    stream.set_nonblocking(true)?;
+
+    let mut buf = [0u8; 16 * 1024];
+    let mut to_channel: Vec<u8> = Vec::new();
+    let mut to_stream: Vec<u8> = Vec::new();
+    loop {
+        let mut idle = true;
+
+        if to_channel.is_empty() {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    to_channel.extend_from_slice(&buf[..n]);
+                    idle = false;
                 }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
             }
         }
-    } else {
-        let password = match get_password(alias) {
-            Ok(pass) => pass,
-            Err(_) => {
-                let pass = Password::new(&format!("Enter password for {}:", conn_str))
-                    .with_display_mode(inquire::PasswordDisplayMode::Masked)
-                    .prompt()?;
-                if Confirm::new("Save password to keychain?")
-                    .with_default(true)
-                    .prompt()? {
-                    set_password(alias, &pass)?;
-                }
-                pass
+        if !to_channel.is_empty() {
+            match channel.write(&to_channel) {
+                Ok(n) => {
+                    to_channel.drain(..n);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
             }
-        };
-        sess.userauth_password(user, &password)
-            .context("Authentication failed. Please check your username/password.")?;
+        }
+
+        if to_stream.is_empty() {
+            match channel.read(&mut buf) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => {}
+                Ok(n) => {
+                    to_stream.extend_from_slice(&buf[..n]);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !to_stream.is_empty() {
+            match stream.write(&to_stream) {
+                Ok(n) => {
+                    to_stream.drain(..n);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if idle {
+            thread::sleep(Duration::from_millis(1));
+        }
     }
+    Ok(())
+}
 
-    println!("Successfully connected!");
-    Ok(sess)
+/// 交互会话的选项
+#[derive(Default)]
+pub struct ShellOptions {
+    // 无输入输出超过该时长后自动断开
+    pub idle_timeout: Option<Duration>,
+    // 远程 stderr 并入 stdout，否则单独写到本地 stderr
+    pub merge_stderr: bool,
+    // 本地回滚缓冲区行数，为 0 时不启用复制模式
+    pub scrollback: usize,
+    pub osc52: Osc52Mode,
+    pub osc52_max_bytes: u64,
+    // 本地终端窗口标题，会话结束后恢复
+    pub title: Option<String>,
+    pub term: String,
+    pub env: Vec<(String, String)>,
+    pub banner: Option<Banner>,
+    // 进入 shell 前探测并显示服务器概况
+    pub info: bool,
+    // 把会话输出转发给本地端口上的观看者
+    pub share: Option<ShareOptions>,
+    // Ctrl+C / Ctrl+Z 发给远程还是由本地处理，会话中可以用 Ctrl+] c 切换
+    pub signals: SignalMode,
+    // 不显示 MOTD 和上次登录信息
+    pub quiet_login: bool,
 }
 
-pub fn handle_interactive_shell(sess: Session) -> Result<()> {
+impl ShellOptions {
+    pub fn for_connection(alias: &str, conn: Option<&Connection>) -> Self {
+        ShellOptions {
+            title: conn.map(|c| format!("{} — {}", alias, c.target)),
+            term: conn
+                .and_then(|c| c.term.clone())
+                .unwrap_or_else(|| DEFAULT_TERM.to_string()),
+            env: conn.map(Connection::locale_env).unwrap_or_default(),
+            idle_timeout: conn.and_then(|c| c.idle_timeout()),
+            scrollback: conn.and_then(|c| c.scrollback).unwrap_or(0),
+            osc52: conn.map(|c| c.osc52).unwrap_or_default(),
+            osc52_max_bytes: conn
+                .and_then(|c| c.osc52_max_bytes)
+                .unwrap_or(osc52::DEFAULT_MAX_BYTES),
+            banner: conn.and_then(|c| {
+                Some(Banner {
+                    text: c.banner.clone()?,
+                    color: c.banner_color.unwrap_or_default(),
+                    prompt: c.prompt_banner,
+                })
+            }),
+            info: conn.is_some_and(|c| c.show_info),
+            signals: conn.map(|c| c.signals).unwrap_or_default(),
+            quiet_login: conn.is_some_and(|c| c.quiet_login),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn handle_interactive_shell(sess: Session, options: &ShellOptions) -> Result<Option<i32>> {
+    let idle_timeout = options.idle_timeout;
+    // 探测失败只警告，不影响进入 shell
+    let info = options.info.then(|| sysinfo::probe(&sess)).and_then(|result| {
+        result
+            .map_err(|e| eprintln!("Warning: could not read the server summary: {:#}", e))
+            .ok()
+    });
     let mut channel = sess.channel_session()?;
+    if options.merge_stderr {
+        channel.handle_extended_data(ExtendedData::Merge)?;
+    }
     let (width, height) = terminal::size()?;
-    channel.request_pty(
-        "xterm-256color",
-        None,
-        Some((width as u32, height as u32, 0, 0)),
-    )?;
-    channel.shell()?;
-
-    terminal::enable_raw_mode()?;
+    request_pty(&mut channel, &options.term, &options.env, width, height)?;
+    // sshd 只在请求 shell 时显示 MOTD（包括 PAM 的）和上次登录信息，执行命令时不显示，
+    // 所以安静登录改为执行用户的登录 shell
+    match options.quiet_login {
+        true => channel.exec(QUIET_LOGIN_COMMAND)?,
+        false => channel.shell()?,
+    }
+    if let Some(banner) = &options.banner
+        && banner.prompt
+    {
+        channel.write_all(banner.prompt_command().as_bytes())?;
+    }
+
+    term::prepare();
+    if let Some(banner) = &options.banner {
+        banner.print()?;
+    }
+    if let Some(info) = &info {
+        info.print();
+    }
+    let share_listener = options.share.map(share::listen).transpose()?;
+    if let Some(title) = &options.title {
+        term::push_title(title)?;
+    }
+    let raw_mode = term::RawMode::enable()?;
     sess.set_blocking(false);
 
     let mut stdout = io::stdout();
-    let mut channel_buf = [0; 1024];
+    let mut term_output = TermOutput::default();
+    let mut output = BufferedOutput::new();
+    let mut channel_buf = [0; SHELL_BUFFER_SIZE];
+    let mut last_activity = Instant::now();
+    let mut warned = false;
+    let mut timed_out = false;
+    let mut input = InputThread::spawn()?;
+    let share = match share_listener {
+        Some(listener) => Some(listener.start(
+            options.title.as_deref().unwrap_or("a shared session"),
+            width,
+            height,
+            input.waker()?,
+        )),
+        None => None,
+    };
+    // Ctrl+] 之后按 [ 进入复制模式，其他转义键见 ESCAPE_HELP
+    let mut scrollback = (options.scrollback > 0).then(|| Scrollback::new(width, height, options.scrollback));
+    let mut prefix = false;
+    let mut signals = options.signals;
+    // 用 Ctrl+] . 或本地模式的 Ctrl+C 断开
+    let mut disconnected = false;
+    let mut interrupted = false;
+    let mut osc52_filter = (!options.osc52.is_off()).then(|| Osc52Filter::new(options.osc52_max_bytes));
+    // 回复远程读剪贴板请求的数据
+    let mut replies = Vec::new();
 
     'main_loop: loop {
-        if crossterm::event::poll(std::time::Duration::from_millis(10))? {
-            if let Ok(event) = crossterm::event::read() {
-                match event {
-                    crossterm::event::Event::Key(key_event) => {
-                        if key_event.kind != crossterm::event::KeyEventKind::Press {
-                            continue;
+        // 把已到达的按键合并成一次写入（例如粘贴时），减少数据包数量
+        let mut key_bytes = Vec::new();
+        let mut resized = None;
+        while let Some(event) = input.try_next() {
+            last_activity = Instant::now();
+            warned = false;
+            match event? {
+                crossterm::event::Event::Key(key_event)
+                    if key_event.kind == crossterm::event::KeyEventKind::Press =>
+                {
+                    if let Some(scrollback) = scrollback.as_mut()
+                        && scrollback.is_active()
+                    {
+                        if let CopyAction::Exit(restore) = scrollback.handle_key(&key_event, &mut stdout)? {
+                            term_output.write(&mut stdout, &restore)?;
+                            stdout.flush()?;
                         }
-                        let mut key_bytes = Vec::new();
-                        match key_event.code {
-                            crossterm::event::KeyCode::Char(c) => {
-                                if key_event
-                                    .modifiers
-                                    .contains(crossterm::event::KeyModifiers::CONTROL)
-                                {
-                                    if ('a'..='z').contains(&c) {
-                                        key_bytes.push((c as u8) - b'a' + 1);
-                                    }
-                                } else {
-                                    key_bytes.push(c as u8);
+                        continue;
+                    }
+                    if prefix {
+                        prefix = false;
+                        // 连按两次前缀键发送字面的 Ctrl+]，其他键照常发送
+                        let handled = match key_event.code {
+                            crossterm::event::KeyCode::Char('[') => match scrollback.as_mut() {
+                                Some(scrollback) => {
+                                    scrollback.enter(&mut stdout)?;
+                                    true
                                 }
+                                None => false,
+                            },
+                            crossterm::event::KeyCode::Char('c') => {
+                                signals = signals.toggled();
+                                notice(&mut stdout, signals_notice(signals))?;
+                                true
                             }
-                            crossterm::event::KeyCode::Enter => key_bytes.push(b'\r'),
-                            crossterm::event::KeyCode::Backspace => key_bytes.push(8),
-                            crossterm::event::KeyCode::Left => {
-                                key_bytes.extend_from_slice(b"\x1b[D")
+                            crossterm::event::KeyCode::Char('z') => {
+                                resized = suspend(&mut stdout)?.or(resized);
+                                true
                             }
-                            crossterm::event::KeyCode::Right => {
-                                key_bytes.extend_from_slice(b"\x1b[C")
+                            crossterm::event::KeyCode::Char('.') => {
+                                disconnected = true;
+                                break 'main_loop;
                             }
-                            crossterm::event::KeyCode::Up => key_bytes.extend_from_slice(b"\x1b[A"),
-                            crossterm::event::KeyCode::Down => {
-                                key_bytes.extend_from_slice(b"\x1b[B")
+                            crossterm::event::KeyCode::Char('?') => {
+                                notice(&mut stdout, ESCAPE_HELP)?;
+                                true
                             }
-                            crossterm::event::KeyCode::Tab => key_bytes.push(b'\t'),
-                            crossterm::event::KeyCode::Esc => key_bytes.push(0x1b),
-                            _ => {}
+                            _ => false,
+                        };
+                        if handled {
+                            continue;
                         }
-                        if !key_bytes.is_empty() {
-                            channel.write_all(&key_bytes)?;
-                            channel.flush()?;
+                    } else if is_prefix_key(&key_event) {
+                        prefix = true;
+                        continue;
+                    }
+                    if !signals.is_remote() {
+                        match local_signal(&key_event) {
+                            Some(LocalSignal::Interrupt) => {
+                                interrupted = true;
+                                break 'main_loop;
+                            }
+                            Some(LocalSignal::Suspend) => {
+                                resized = suspend(&mut stdout)?.or(resized);
+                                continue;
+                            }
+                            None => {}
                         }
                     }
-                    crossterm::event::Event::Resize(width, height) => {
-                        channel.request_pty_size(width as u32, height as u32, None, None)?;
+                    key_bytes.extend(key_to_bytes(&key_event));
+                }
+                crossterm::event::Event::Resize(width, height) => {
+                    let (width, height) = term::resized_size(width, height);
+                    if let Some(scrollback) = scrollback.as_mut() {
+                        scrollback.resize(width, height);
+                        scrollback.refresh(&mut stdout)?;
                     }
-                    _ => {}
+                    if let Some(share) = &share {
+                        share.resize(width, height);
+                    }
+                    resized = Some((width, height));
                 }
+                _ => {}
             }
         }
+        // 拖动窗口时会连续收到很多 Resize 事件，只发送最后一个尺寸
+        if let Some((width, height)) = resized {
+            sess.set_blocking(true);
+            let requested = channel.request_pty_size(width as u32, height as u32, None, None);
+            sess.set_blocking(false);
+            requested?;
+        }
+        key_bytes.append(&mut replies);
+        if let Some(share) = &share {
+            key_bytes.extend(share.take_input());
+        }
+        if !key_bytes.is_empty() {
+            // 写入时切回阻塞模式，大段粘贴超过通道窗口也不会报 WouldBlock
+            sess.set_blocking(true);
+            let written = channel.write_all(&key_bytes).and_then(|_| channel.flush());
+            sess.set_blocking(false);
+            written?;
+        }
 
+        if drain_stderr(&mut channel, &mut channel_buf)? {
+            last_activity = Instant::now();
+            warned = false;
+        }
         loop {
             match channel.read(&mut channel_buf) {
-                Ok(0) => break 'main_loop,
+                Ok(0) => {
+                    output.flush()?;
+                    break 'main_loop;
+                }
                 Ok(n) => {
-                    stdout.write_all(&channel_buf[..n])?;
-                    stdout.flush()?;
+                    let filtered;
+                    let mut data = &channel_buf[..n];
+                    if let Some(filter) = osc52_filter.as_mut() {
+                        let (output, requests) = filter.feed(data);
+                        for request in requests {
+                            replies.extend(handle_osc52(request, options.osc52));
+                        }
+                        filtered = output;
+                        data = &filtered;
+                    }
+                    if let Some(share) = &share {
+                        share.send(data);
+                    }
+                    if scrollback.as_mut().is_none_or(|scrollback| scrollback.feed(data)) {
+                        term_output.write(output.writer(), data)?;
+                        output.flush_if_due()?;
+                    }
+                    last_activity = Instant::now();
+                    warned = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // 暂时没有数据了，把攒下的输出写出去
+                    output.flush()?;
+                    if let Some(scrollback) = scrollback.as_mut() {
+                        scrollback.refresh(&mut stdout)?;
+                    }
+                    break;
                 }
-                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
                 Err(e) => {
+                    output.flush()?;
                     eprintln!("Channel read error: {}", e);
                     break 'main_loop;
                 }
             }
         }
+
+        // 到期前提醒一次，最多提前 60 秒
+        let warn_before = idle_timeout.map(|timeout| (timeout / 2).min(Duration::from_secs(60)));
+        if let (Some(timeout), Some(warn_before)) = (idle_timeout, warn_before) {
+            let idle = last_activity.elapsed();
+            if idle >= timeout {
+                write!(stdout, "\r\n[rssh] Idle for {}s, disconnecting.\r\n", timeout.as_secs())?;
+                stdout.flush()?;
+                timed_out = true;
+                break 'main_loop;
+            }
+            if !warned && idle + warn_before >= timeout {
+                write!(
+                    stdout,
+                    "\r\n[rssh] Session idle, disconnecting in {}s unless there is activity.\r\n",
+                    (timeout - idle).as_secs_f64().ceil() as u64
+                )?;
+                stdout.flush()?;
+                warned = true;
+            }
+        }
+
+        // 阻塞到有数据、有按键或到了下一个空闲提醒/断开/keepalive 的时间点
+        let wait = match (idle_timeout, warn_before) {
+            (Some(timeout), Some(warn_before)) => {
+                let next = if warned { timeout } else { timeout - warn_before };
+                Some(next.saturating_sub(last_activity.elapsed()))
+            }
+            _ => None,
+        };
+        let wait = match (wait, send_keepalive(&sess)) {
+            (Some(wait), Some(keepalive)) => Some(wait.min(keepalive)),
+            (wait, keepalive) => wait.or(keepalive),
+        };
+        input.wait(&sess, wait)?;
     }
+    drop(input);
+    drop(share);
+    let _ = drain_stderr(&mut channel, &mut channel_buf);
+    if scrollback.as_ref().is_some_and(Scrollback::is_active) {
+        let _ = queue!(stdout, terminal::LeaveAlternateScreen, crossterm::cursor::Show);
+        let _ = stdout.flush();
+    }
+
+    drop(raw_mode);
+    if options.title.is_some() {
+        let _ = term::pop_title();
+    }
+
+    sess.set_blocking(true);
+    if timed_out || disconnected || interrupted {
+        let _ = channel.close();
+    }
+    let _ = channel.wait_close();
+    let exit_status = channel.exit_status().ok();
+    output::event(
+        "session_closed",
+        json!({ "exit_status": exit_status, "idle_timeout": timed_out, "disconnected": disconnected || interrupted }),
+    );
+    if interrupted {
+        interrupt::mark();
+        return Err(anyhow!("Disconnected with Ctrl+C; the remote session was closed."));
+    }
+    Ok(exit_status)
+}
 
-    terminal::disable_raw_mode()?;
+/// 设置环境变量后请求 PTY
+/// 服务器的 AcceptEnv 不允许的变量会被拒绝，这不影响会话，只在 -v 时提示
+pub fn request_pty(channel: &mut Channel, term: &str, env: &[(String, String)], width: u16, height: u16) -> Result<()> {
+    for (name, value) in env {
+        if let Err(e) = channel.setenv(name, value)
+            && output::is_verbose()
+        {
+            eprintln!("Server did not accept {}={}: {}", name, value, e);
+        }
+    }
+    channel
+        .request_pty(term, None, Some((width as u32, height as u32, 0, 0)))
+        .context(format!("Failed to request a PTY with TERM={}", term))?;
     Ok(())
 }
 
-pub fn handle_upload(sess: Session, local_path: &Path, remote_dir: &Path) -> Result<()> {
-    if !local_path.is_file() {
-        return Err(anyhow!(
-            "Local path {:?} is not a file. Please provide a path to a file to upload.",
-            local_path
-        ));
+/// exec --timeout：到时关闭通道；signal 给出时先向远程命令的进程组发送这个信号
+pub struct ExecTimeout {
+    pub after: Duration,
+    pub signal: Option<String>,
+}
+
+/// clap 的 value_parser：TERM、SIGKILL、9 等，去掉 SIG 前缀后交给远程的 kill -s
+pub fn parse_signal(text: &str) -> Result<String, String> {
+    let name = text.trim().trim_start_matches("SIG").trim_start_matches("sig").to_ascii_uppercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("'{}' is not a signal name like TERM or KILL", text));
     }
+    Ok(name)
+}
 
-    let file_name = local_path.file_name().unwrap(); // Safe due to is_file check
-    let remote_path = remote_dir.join(file_name);
+/// 执行远程命令，stdout 和 stderr 分别写到本地对应的流，返回远程退出码
+/// env 里的值先用 setenv 设置；服务器不接受（sshd 默认只接受 AcceptEnv 列出的）时
+/// 由远程 shell 从标准输入逐行读入再 export，值不会出现在任何命令行里
+pub fn handle_exec(
+    sess: Session,
+    command: &str,
+    merge_stderr: bool,
+    env: &[(String, String)],
+    timeout: Option<&ExecTimeout>,
+) -> Result<i32> {
+    // 要发信号时标准输入留着，后台等着读一行信号名，收到后发给命令所在的进程组。命令本身的标准输入仍是空的。
+    // 监听脚本是 POSIX sh 的写法，和 sudo 一样经 sh -c 执行，登录 shell 是 fish、csh 时也能用
+    let signal_on_timeout = timeout.is_some_and(|timeout| timeout.signal.is_some());
+    let command = match signal_on_timeout {
+        true => format!("sh -c {}", shell_quote(&format!("{}\n{}", SIGNAL_LISTENER, command))),
+        false => command.to_string(),
+    };
+    let command = command.as_str();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout.after);
 
-    let mut local_file = fs::File::open(local_path)
-        .context(format!("Failed to open local file: {:?}", local_path))?;
-    let file_size = local_file.metadata()?.len();
+    let mut channel = sess.channel_session()?;
+    if merge_stderr {
+        channel.handle_extended_data(ExtendedData::Merge)?;
+    }
+    let mut piped = Vec::new();
+    for (name, value) in env {
+        if channel.setenv(name, value).is_err() {
+            piped.push((name, value));
+        }
+    }
+    if piped.is_empty() {
+        channel.exec(command)?;
+    } else {
+        let reads: Vec<String> = piped
+            .iter()
+            .map(|(name, _)| format!("IFS= read -r {} && export {}", name, name))
+            .collect();
+        // 命令放在新的一行，末尾的注释不会吞掉结尾的括号
+        channel.exec(&format!("{} && {{\n{}\n}}", reads.join(" && "), command))?;
+        for (_, value) in &piped {
+            channel.write_all(format!("{}\n", value).as_bytes())?;
+        }
+        channel.flush()?;
+    }
+    if !signal_on_timeout {
+        channel.send_eof()?;
+    }
 
-    println!("Uploading {:?} to {:?}...", local_path, remote_path);
+    // 非阻塞地交替读取两个流，任何一个写满通道窗口都不会卡住另一个
+    sess.set_blocking(false);
+    let mut stdout = io::stdout();
+    let mut buf = [0; SHELL_BUFFER_SIZE];
+    loop {
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => {
+                    let _ = stdout.flush();
+                    sess.set_blocking(true);
+                    return Err(stop_timed_out(&mut channel, timeout.unwrap()));
+                }
+            },
+            None => None,
+        };
+        let written = match channel.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => stdout.write_all(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if drain_stderr(&mut channel, &mut buf)? {
+                    continue;
+                }
+                let flushed = stdout.flush();
+                let wait = match (remaining, send_keepalive(&sess)) {
+                    (Some(remaining), Some(keepalive)) => Some(remaining.min(keepalive)),
+                    (remaining, keepalive) => remaining.or(keepalive),
+                };
+                input::wait_session(&sess, wait)?;
+                flushed
+            }
+            Err(e) => return Err(e).context("Failed to read command output"),
+        };
+        match written {
+            Ok(()) => {}
+            // 本地读端已关闭（如管道给 head），不再读取剩余输出
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                sess.set_blocking(true);
+                let _ = channel.close();
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    drain_stderr(&mut channel, &mut buf)?;
+    let _ = stdout.flush();
 
-    let pb = ProgressBar::new(file_size);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")
-        .unwrap()
-        .progress_chars("#>-"));
+    sess.set_blocking(true);
+    channel.wait_close()?;
+    Ok(channel.exit_status()?)
+}
 
-    let sftp = sess.sftp().context("Failed to create SFTP session")?;
-    let mut remote_file = sftp.create(&remote_path)
-        .context(format!("Failed to create remote file: {:?}", remote_path))?;
+// 到时间时发送 keepalive，返回距下一次的时间；没有设置 keepalive 时返回 None
+fn send_keepalive(sess: &Session) -> Option<Duration> {
+    match sess.keepalive_send() {
+        Ok(0) | Err(_) => None,
+        Ok(seconds) => Some(Duration::from_secs(seconds.into())),
+    }
+}
 
-    let mut reader = pb.wrap_read(&mut local_file);
-    io::copy(&mut reader, &mut remote_file)?;
+// 远程后台读标准输入的一行，按它给出的信号名结束整个进程组；标准输入关闭时什么也不做。
+// 没有作业控制时后台命令和命令留在同一个进程组里，kill 0 就能连同子进程一起结束；
+// 这时后台命令的标准输入默认是 /dev/null，所以经 3 号描述符交给它；
+// 输出重定向到 /dev/null，免得它让通道在命令结束后还开着
+const SIGNAL_LISTENER: &str = "exec 3<&0 </dev/null\n\
+    { IFS= read -r signal && kill -s \"$signal\" 0; } <&3 >/dev/null 2>&1 &\n\
+    exec 3<&-";
 
-    pb.finish_with_message("Upload complete");
-    Ok(())
+// 超时：按要求经标准输入让远程发信号，然后关闭通道；没有 PTY 时关闭通道不会结束远程命令
+fn stop_timed_out(channel: &mut Channel, timeout: &ExecTimeout) -> anyhow::Error {
+    let seconds = timeout.after.as_secs_f64();
+    let note = match &timeout.signal {
+        Some(signal) => {
+            let sent = channel
+                .write_all(format!("{}\n", signal).as_bytes())
+                .and_then(|_| channel.flush());
+            match sent {
+                Ok(()) => format!("sent SIG{} to it", signal),
+                Err(_) => format!("could not send SIG{} to it", signal),
+            }
+        }
+        None => "it may still be running on the server (use --signal to stop it)".to_string(),
+    };
+    let _ = channel.send_eof();
+    let _ = channel.close();
+    error::timeout(anyhow!("The command did not finish within {}s; {}.", seconds, note))
 }
 
-pub fn handle_download(sess: Session, remote_path: &Path, local_dir: &Path) -> Result<()> {
-    let file_name = remote_path.file_name().ok_or_else(|| {
-        anyhow!(
-            "Remote path {:?} is a directory or invalid. Please provide a path to a file to download.",
-            remote_path
-        )
-    })?;
+// 把当前可读的远程 stderr 写到本地 stderr，返回是否读到了数据
+fn drain_stderr(channel: &mut Channel, buf: &mut [u8]) -> io::Result<bool> {
+    let mut stderr = io::stderr();
+    let mut got = false;
+    loop {
+        match channel.stderr().read(buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                stderr.write_all(&buf[..n])?;
+                got = true;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if got {
+        stderr.flush()?;
+    }
+    Ok(got)
+}
 
-    if local_dir.is_file() {
-        return Err(anyhow!(
-            "Local destination {:?} is a file. Please provide a directory path.",
-            local_dir
-        ));
+// 处理远程程序的剪贴板请求，返回要发回远程的数据
+// 会话处于原始模式，失败时不打印信息，以免打乱远程程序的画面
+fn handle_osc52(request: osc52::Request, mode: Osc52Mode) -> Vec<u8> {
+    match request {
+        osc52::Request::Copy(text) => {
+            let _ = clipboard::copy(&text);
+            Vec::new()
+        }
+        osc52::Request::Paste { selection, terminator } if mode == Osc52Mode::CopyPaste => clipboard::paste()
+            .map(|text| osc52::paste_reply(&selection, terminator, &text))
+            .unwrap_or_default(),
+        osc52::Request::Paste { .. } => Vec::new(),
     }
-    fs::create_dir_all(local_dir)
-        .context(format!("Failed to create local directory {:?}", local_dir))?;
+}
 
-    let local_path = local_dir.join(file_name);
+// 会话中间输出的一行提示；原始模式下需要 \r\n
+fn notice(stdout: &mut io::Stdout, message: &str) -> io::Result<()> {
+    write!(stdout, "\r\n[rssh] {}\r\n", message)?;
+    stdout.flush()
+}
+
+fn signals_notice(signals: SignalMode) -> &'static str {
+    match signals {
+        SignalMode::Remote => "Ctrl+C and Ctrl+Z now go to the remote program.",
+        SignalMode::Local => "Ctrl+C now disconnects and Ctrl+Z suspends rssh.",
+    }
+}
 
-    println!("Downloading {:?} to {:?}...", remote_path, local_path);
+enum LocalSignal {
+    Interrupt,
+    Suspend,
+}
+
+// 本地模式下由 rssh 自己处理的按键；Ctrl+\ 在 Unix 终端里会被解析成 Ctrl+4
+fn local_signal(key_event: &crossterm::event::KeyEvent) -> Option<LocalSignal> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    if key_event.modifiers != KeyModifiers::CONTROL {
+        return None;
+    }
+    match key_event.code {
+        KeyCode::Char('c' | '\\' | '4') => Some(LocalSignal::Interrupt),
+        KeyCode::Char('z') => Some(LocalSignal::Suspend),
+        _ => None,
+    }
+}
 
-    let sftp = sess.sftp().context("Failed to create SFTP session")?;
-    let mut remote_file = sftp.open(remote_path)
-        .context(format!("Failed to open remote file: {:?}", remote_path))?;
-    
-    let stat = remote_file.stat()?;
-    let file_size = stat.size.unwrap_or(0);
+// 挂起 rssh；fg 回来时窗口大小可能变了，返回要告诉远程 PTY 的尺寸
+fn suspend(stdout: &mut io::Stdout) -> Result<Option<(u16, u16)>> {
+    if !cfg!(unix) {
+        notice(stdout, "Suspending rssh is only supported on Unix.")?;
+        return Ok(None);
+    }
+    notice(stdout, "Suspended; run 'fg' to return to the session.")?;
+    term::suspend()?;
+    Ok(Some(terminal::size()?))
+}
 
-    let pb = ProgressBar::new(file_size);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec})")
-        .unwrap()
-        .progress_chars("#>-"));
+// Ctrl+]；Unix 终端里会被解析成 Ctrl+5
+fn is_prefix_key(key_event: &crossterm::event::KeyEvent) -> bool {
+    matches!(key_event.code, crossterm::event::KeyCode::Char(']') | crossterm::event::KeyCode::Char('5'))
+        && key_event.modifiers.contains(crossterm::event::KeyModifiers::CONTROL)
+}
 
-    let mut local_file = fs::File::create(&local_path)
-        .context(format!("Failed to create local file: {:?}", local_path))?;
+// 把按键事件转换成发送给远程终端的字节（xterm 序列）
+// Windows 上按键来自控制台 API 而不是终端序列，所以每种键都要在这里翻译
+pub fn key_to_bytes(key_event: &crossterm::event::KeyEvent) -> Vec<u8> {
+    use crossterm::event::{KeyCode, KeyModifiers};
 
-    let mut reader = pb.wrap_read(&mut remote_file);
-    io::copy(&mut reader, &mut local_file)?;
+    let modifiers = key_event.modifiers;
+    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+    let alt = modifiers.contains(KeyModifiers::ALT);
+    // xterm 的修饰键参数：1 + Shift(1) + Alt(2) + Ctrl(4)
+    let modifier_param = 1
+        + modifiers.contains(KeyModifiers::SHIFT) as u8
+        + 2 * alt as u8
+        + 4 * ctrl as u8;
 
-    pb.finish_with_message("Download complete");
-    Ok(())
-}
\ No newline at end of file
+    let mut key_bytes = Vec::new();
+    match key_event.code {
+        KeyCode::Char(c) => {
+            // Alt 组合键以 ESC 开头
+            if alt {
+                key_bytes.push(0x1b);
+            }
+            if ctrl {
+                match c {
+                    'a'..='z' | 'A'..='Z' => key_bytes.push((c.to_ascii_lowercase() as u8) - b'a' + 1),
+                    ' ' | '@' | '2' => key_bytes.push(0),
+                    '[' | '3' => key_bytes.push(0x1b),
+                    '\\' | '4' => key_bytes.push(0x1c),
+                    ']' | '5' => key_bytes.push(0x1d),
+                    '^' | '6' => key_bytes.push(0x1e),
+                    '_' | '-' | '7' => key_bytes.push(0x1f),
+                    '?' | '8' => key_bytes.push(0x7f),
+                    _ => {}
+                }
+            } else {
+                let mut utf8 = [0u8; 4];
+                key_bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            }
+        }
+        KeyCode::Enter => key_bytes.push(b'\r'),
+        KeyCode::Backspace => key_bytes.push(8),
+        KeyCode::Tab => key_bytes.push(b'\t'),
+        KeyCode::BackTab => key_bytes.extend_from_slice(b"\x1b[Z"),
+        KeyCode::Esc => key_bytes.push(0x1b),
+        KeyCode::Up | KeyCode::Down | KeyCode::Right | KeyCode::Left | KeyCode::Home | KeyCode::End => {
+            let letter = match key_event.code {
+                KeyCode::Up => 'A',
+                KeyCode::Down => 'B',
+                KeyCode::Right => 'C',
+                KeyCode::Left => 'D',
+                KeyCode::Home => 'H',
+                _ => 'F',
+            };
+            if modifier_param > 1 {
+                key_bytes.extend_from_slice(format!("\x1b[1;{}{}", modifier_param, letter).as_bytes());
+            } else {
+                key_bytes.extend_from_slice(format!("\x1b[{}", letter).as_bytes());
+            }
+        }
+        KeyCode::Insert => key_bytes.extend(tilde_sequence(2, modifier_param)),
+        KeyCode::Delete => key_bytes.extend(tilde_sequence(3, modifier_param)),
+        KeyCode::PageUp => key_bytes.extend(tilde_sequence(5, modifier_param)),
+        KeyCode::PageDown => key_bytes.extend(tilde_sequence(6, modifier_param)),
+        KeyCode::F(n @ 1..=4) => {
+            let letter = (b'P' + n - 1) as char;
+            if modifier_param > 1 {
+                key_bytes.extend_from_slice(format!("\x1b[1;{}{}", modifier_param, letter).as_bytes());
+            } else {
+                key_bytes.extend_from_slice(format!("\x1bO{}", letter).as_bytes());
+            }
+        }
+        KeyCode::F(n @ 5..=12) => {
+            let code = [15, 17, 18, 19, 20, 21, 23, 24][(n - 5) as usize];
+            key_bytes.extend(tilde_sequence(code, modifier_param));
+        }
+        _ => {}
+    }
+    key_bytes
+}
+
+// `ESC [ code ~` 形式的功能键，带修饰键时为 `ESC [ code ; m ~`
+fn tilde_sequence(code: u8, modifier_param: u8) -> Vec<u8> {
+    if modifier_param > 1 {
+        format!("\x1b[{};{}~", code, modifier_param).into_bytes()
+    } else {
+        format!("\x1b[{}~", code).into_bytes()
+    }
+}
+
+/// 用于 tar、bench 等大量数据的 exec 通道
+pub fn bulk_channel(sess: &Session) -> Result<Channel> {
+    Ok(sess.channel_open("session", BULK_WINDOW_SIZE, BULK_PACKET_SIZE, None)?)
+}
+
+// 在远程执行命令，丢弃输出，只返回退出码
+pub fn exec_status(sess: &Session, command: &str) -> Result<i32> {
+    let mut channel = sess.channel_session()?;
+    channel.exec(command)?;
+    io::copy(&mut channel, &mut io::sink())?;
+    channel.wait_close()?;
+    Ok(channel.exit_status()?)
+}
+
+/// 远程命令的输出和退出码
+pub struct Captured {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub status: i32,
+}
+
+/// 把 input 写到命令的 stdin 后关闭，分别收集 stdout 和 stderr
+pub fn exec_capture(sess: &Session, command: &str, input: &[u8]) -> Result<Captured> {
+    let mut channel = sess.channel_session()?;
+    channel.exec(command)?;
+    channel.write_all(input).context("Failed to send input to the command")?;
+    channel.send_eof()?;
+
+    // 和 handle_exec 一样非阻塞地交替读取，stderr 写满窗口不会卡住 stdout
+    let mut captured = Captured { stdout: Vec::new(), stderr: Vec::new(), status: 0 };
+    let mut buf = [0; SHELL_BUFFER_SIZE];
+    sess.set_blocking(false);
+    let result = (|| -> Result<()> {
+        loop {
+            let mut progressed = false;
+            match channel.read(&mut buf) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => {}
+                Ok(n) => {
+                    captured.stdout.extend_from_slice(&buf[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("Failed to read command output"),
+            }
+            match channel.stderr().read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    captured.stderr.extend_from_slice(&buf[..n]);
+                    progressed = true;
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("Failed to read command output"),
+            }
+            if !progressed {
+                input::wait_session(sess, None)?;
+            }
+        }
+        Ok(())
+    })();
+    sess.set_blocking(true);
+    result?;
+    channel.stderr().read_to_end(&mut captured.stderr)?;
+    channel.wait_close()?;
+    captured.status = channel.exit_status()?;
+    Ok(captured)
+}
+
+// 为远程 shell 命令转义参数
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
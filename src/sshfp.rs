@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Context, Result};
+use ssh_key::rand_core::{OsRng, RngCore};
+use ssh2::HostKeyType;
+use std::fs;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+const TYPE_SSHFP: u16 = 44;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+const EDNS_UDP_SIZE: u16 = 1232;
+const DNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+// SSHFP 指纹类型
+pub const FP_SHA1: u8 = 1;
+pub const FP_SHA256: u8 = 2;
+
+/// 一条 SSHFP 记录 (RFC 4255 / 6594)
+pub struct SshfpRecord {
+    pub algorithm: u8,
+    pub fp_type: u8,
+    pub fingerprint: Vec<u8>,
+}
+
+pub struct SshfpAnswer {
+    pub records: Vec<SshfpRecord>,
+    // 解析器设置了 AD 位，即经过 DNSSEC 验证
+    pub dnssec: bool,
+}
+
+/// 主机密钥类型对应的 SSHFP 算法编号
+pub fn algorithm_number(key_type: HostKeyType) -> Option<u8> {
+    match key_type {
+        HostKeyType::Rsa => Some(1),
+        HostKeyType::Dss => Some(2),
+        HostKeyType::Ecdsa256 | HostKeyType::Ecdsa384 | HostKeyType::Ecdsa521 => Some(3),
+        HostKeyType::Ed25519 => Some(4),
+        HostKeyType::Unknown => None,
+    }
+}
+
+/// 查询主机的 SSHFP 记录；IP 地址没有 SSHFP 记录，返回 None
+pub fn lookup(host: &str) -> Result<Option<SshfpAnswer>> {
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(None);
+    }
+    let server = nameserver()?;
+    let id = OsRng.next_u32() as u16;
+    let query = build_query(id, host)?;
+
+    let bind = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).context("Failed to open a UDP socket for DNS")?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(&query)?;
+
+    let mut buf = [0u8; 4096];
+    let n = socket
+        .recv(&mut buf)
+        .context(format!("No answer from DNS server {}", server))?;
+    parse_response(id, &buf[..n]).map(Some)
+}
+
+// 使用 /etc/resolv.conf 中的第一个 nameserver，由它负责 DNSSEC 验证
+fn nameserver() -> Result<SocketAddr> {
+    let content = fs::read_to_string("/etc/resolv.conf").context("Failed to read /etc/resolv.conf")?;
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .next()
+        .ok_or_else(|| anyhow!("No nameserver found in /etc/resolv.conf"))
+}
+
+fn build_query(id: u16, host: &str) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(64);
+    query.extend_from_slice(&id.to_be_bytes());
+    // RD + AD：请求递归查询并返回 DNSSEC 验证结果
+    query.extend_from_slice(&0x0120u16.to_be_bytes());
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1]);
+
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(anyhow!("Invalid host name for DNS lookup: {}", host));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SSHFP.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    // EDNS0 OPT 记录，设置 DO 位
+    query.push(0);
+    query.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    query.extend_from_slice(&EDNS_UDP_SIZE.to_be_bytes());
+    query.extend_from_slice(&[0, 0, 0x80, 0, 0, 0]);
+    Ok(query)
+}
+
+fn parse_response(id: u16, buf: &[u8]) -> Result<SshfpAnswer> {
+    let truncated = || anyhow!("Malformed DNS response");
+    if buf.len() < 12 || u16::from_be_bytes([buf[0], buf[1]]) != id {
+        return Err(truncated());
+    }
+    let flags = u16::from_be_bytes([buf[2], buf[3]]);
+    if flags & 0x0200 != 0 {
+        return Err(anyhow!("DNS response was truncated"));
+    }
+    match flags & 0x000f {
+        0 => {}
+        // NXDOMAIN
+        3 => return Ok(SshfpAnswer { records: Vec::new(), dnssec: flags & 0x0020 != 0 }),
+        rcode => return Err(anyhow!("DNS lookup failed (rcode {})", rcode)),
+    }
+    let questions = u16::from_be_bytes([buf[4], buf[5]]);
+    let answers = u16::from_be_bytes([buf[6], buf[7]]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(buf, pos).ok_or_else(truncated)? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(buf, pos).ok_or_else(truncated)?;
+        let header = buf.get(pos..pos + 10).ok_or_else(truncated)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        let rdata = buf.get(pos..pos + rdlen).ok_or_else(truncated)?;
+        pos += rdlen;
+        if rtype == TYPE_SSHFP && rdata.len() > 2 {
+            records.push(SshfpRecord {
+                algorithm: rdata[0],
+                fp_type: rdata[1],
+                fingerprint: rdata[2..].to_vec(),
+            });
+        }
+    }
+    Ok(SshfpAnswer { records, dnssec: flags & 0x0020 != 0 })
+}
+
+// 跳过（可能被压缩的）域名，返回其后的位置
+fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
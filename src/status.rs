@@ -0,0 +1,182 @@
+use crate::config::Config;
+use crate::hooks::{self, HookEvent};
+use crate::output;
+use crate::remote_fs::format_size;
+use crate::ssh;
+use crate::sysinfo::{self, Metrics};
+use anyhow::{anyhow, Result};
+use crossterm::style::Stylize;
+use serde_json::json;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// 超过这些值时标黄 / 标红；负载按每个 CPU 计算
+const LOAD_WARN: f64 = 0.7;
+const LOAD_CRIT: f64 = 1.0;
+const MEM_WARN: f64 = 75.0;
+const MEM_CRIT: f64 = 90.0;
+const DISK_WARN: f64 = 80.0;
+const DISK_CRIT: f64 = 90.0;
+
+#[derive(Clone, Copy)]
+enum Level {
+    Ok,
+    Warn,
+    Crit,
+}
+
+impl Level {
+    fn of(value: f64, warn: f64, crit: f64) -> Level {
+        if value >= crit {
+            Level::Crit
+        } else if value >= warn {
+            Level::Warn
+        } else {
+            Level::Ok
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Ok => "ok",
+            Level::Warn => "warn",
+            Level::Crit => "crit",
+        }
+    }
+
+    // 先按列宽补齐再上色，颜色代码不影响对齐
+    fn paint(self, text: String) -> String {
+        match self {
+            Level::Ok => text,
+            Level::Warn => text.yellow().to_string(),
+            Level::Crit => text.red().bold().to_string(),
+        }
+    }
+}
+
+/// 并发连接每台主机，收集运行时间、负载、内存和根分区用量，输出一张表
+pub fn handle_status(
+    config: &Config,
+    aliases: &[String],
+    port: Option<u16>,
+    identity: Option<&Path>,
+    jobs: usize,
+) -> Result<()> {
+    if !output::is_verbose() {
+        output::quiet();
+    }
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, Result<Metrics>)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, aliases.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(alias) = aliases.get(index) else { break };
+                    let result = probe_host(config, alias, port, identity);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+
+    let unreachable = results.iter().filter(|(_, result)| result.is_err()).count();
+    if output::is_json() {
+        let hosts: Vec<_> = results
+            .iter()
+            .map(|(index, result)| host_json(&aliases[*index], result))
+            .collect();
+        output::emit("status", json!({ "hosts": hosts, "unreachable": unreachable }));
+    } else {
+        print_table(aliases, &results);
+    }
+    match unreachable {
+        0 => Ok(()),
+        n => Err(anyhow!("{} of {} hosts could not be checked", n, aliases.len())),
+    }
+}
+
+fn probe_host(config: &Config, alias: &str, port: Option<u16>, identity: Option<&Path>) -> Result<Metrics> {
+    let hook_port = config.effective_port(alias, port);
+    hooks::run(config, alias, hook_port, HookEvent::PreConnect)?;
+    let result = ssh::create_session(config, alias, port, identity).and_then(|sess| sysinfo::probe_metrics(&sess));
+    if let Err(e) = hooks::run(config, alias, hook_port, HookEvent::PostDisconnect) {
+        eprintln!("Warning: {:#}", e);
+    }
+    result
+}
+
+fn print_table(aliases: &[String], results: &[(usize, Result<Metrics>)]) {
+    let width = aliases.iter().map(|alias| alias.chars().count()).max().unwrap_or(0).max(4);
+    println!(
+        "{:<width$}  {:<10}  {:<16}  {:<20}  DISK /",
+        "HOST", "UPTIME", "LOAD (1/5/15)", "MEMORY"
+    );
+    for (index, result) in results {
+        let alias = &aliases[*index];
+        let metrics = match result {
+            Ok(metrics) => metrics,
+            Err(e) => {
+                // 认证失败等多行错误压成一行，不打乱表格
+                let message: Vec<String> = format!("{:#}", e).lines().map(|line| line.trim().to_string()).collect();
+                println!("{:<width$}  {}", alias, format!("unreachable: {}", message.join("; ")).red());
+                continue;
+            }
+        };
+        let load = match metrics.load {
+            Some([one, five, fifteen]) => format!("{:.2} {:.2} {:.2}", one, five, fifteen),
+            None => "-".to_string(),
+        };
+        let load_level = metrics
+            .load_per_cpu()
+            .map_or(Level::Ok, |load| Level::of(load, LOAD_WARN, LOAD_CRIT));
+        let (memory, mem_level) = usage(metrics.mem_total, metrics.mem_percent(), MEM_WARN, MEM_CRIT);
+        let (disk, disk_level) = usage(metrics.disk_total, metrics.disk_percent(), DISK_WARN, DISK_CRIT);
+        println!(
+            "{:<width$}  {:<10}  {}  {}  {}",
+            alias,
+            metrics.uptime.as_deref().unwrap_or("-"),
+            load_level.paint(format!("{:<16}", load)),
+            mem_level.paint(format!("{:<20}", memory)),
+            disk_level.paint(disk),
+        );
+    }
+}
+
+// "42% of 7.8 GB" 及其告警级别
+fn usage(total: Option<u64>, percent: Option<f64>, warn: f64, crit: f64) -> (String, Level) {
+    match (total, percent) {
+        (Some(total), Some(percent)) => (
+            format!("{:.0}% of {}", percent, format_size(total)),
+            Level::of(percent, warn, crit),
+        ),
+        _ => ("-".to_string(), Level::Ok),
+    }
+}
+
+fn host_json(alias: &str, result: &Result<Metrics>) -> serde_json::Value {
+    let metrics = match result {
+        Ok(metrics) => metrics,
+        Err(e) => return json!({ "alias": alias, "reachable": false, "error": format!("{:#}", e) }),
+    };
+    let level = |value: Option<f64>, warn, crit| value.map(|value| Level::of(value, warn, crit).name());
+    json!({
+        "alias": alias,
+        "reachable": true,
+        "cpus": metrics.cpus,
+        "uptime": metrics.uptime,
+        "load": metrics.load,
+        "load_level": level(metrics.load_per_cpu(), LOAD_WARN, LOAD_CRIT),
+        "mem_total": metrics.mem_total,
+        "mem_available": metrics.mem_available,
+        "mem_level": level(metrics.mem_percent(), MEM_WARN, MEM_CRIT),
+        "disk_total": metrics.disk_total,
+        "disk_used": metrics.disk_used,
+        "disk_available": metrics.disk_available,
+        "disk_level": level(metrics.disk_percent(), DISK_WARN, DISK_CRIT),
+    })
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Context, Result};
+use ssh2::{Channel, ErrorCode, Session};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+const BUFFER_SIZE: usize = 16 * 1024;
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+/// 把到 SSH 端口的原始连接接到 stdin/stdout，供 OpenSSH 的 ProxyCommand 使用
+pub fn handle_raw(stream: TcpStream) -> Result<()> {
+    let mut writer = stream.try_clone().context("Failed to clone the connection")?;
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin().lock(), &mut writer);
+        let _ = writer.shutdown(Shutdown::Write);
+    });
+    copy_to_stdout(stream).context("Connection error")
+}
+
+/// 经服务器打开到 host:port 的 direct-tcpip 通道，接到 stdin/stdout
+pub fn handle_channel(sess: Session, host: &str, port: u16) -> Result<()> {
+    let channel = sess
+        .channel_direct_tcpip(host, port, None)
+        .context(format!("The server could not reach {}:{}", host, port))?;
+    sess.set_blocking(false);
+    pump(&sess, channel, read_stdin()).context(format!("Connection to {}:{} failed", host, port))
+}
+
+/// "host:port" 或 "[ipv6]:port"
+pub fn parse_target(target: &str) -> Result<(String, u16)> {
+    let (host, port) = target
+        .rsplit_once(':')
+        .context(format!("Invalid target '{}'. Use HOST:PORT.", target))?;
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    let port = port
+        .parse()
+        .context(format!("Invalid port in '{}'", target))?;
+    if host.is_empty() {
+        return Err(anyhow!("Invalid target '{}'. Use HOST:PORT.", target));
+    }
+    Ok((host.to_string(), port))
+}
+
+fn copy_to_stdout(mut stream: TcpStream) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut buf = [0u8; BUFFER_SIZE];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        stdout.write_all(&buf[..n])?;
+        stdout.flush()?;
+    }
+}
+
+// stdin 不能设为非阻塞，在单独的线程里读，读到末尾时关闭通道
+fn read_stdin() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = [0u8; BUFFER_SIZE];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if sender.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn pump(sess: &Session, mut channel: Channel, input: Receiver<Vec<u8>>) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut buf = [0u8; BUFFER_SIZE];
+    let mut to_channel: Vec<u8> = Vec::new();
+    let mut input_done = false;
+    let mut eof_sent = false;
+    loop {
+        let mut idle = true;
+
+        if to_channel.is_empty() && !input_done {
+            match input.try_recv() {
+                Ok(data) => {
+                    to_channel = data;
+                    idle = false;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => input_done = true,
+            }
+        }
+        if !to_channel.is_empty() {
+            match channel.write(&to_channel) {
+                Ok(n) => {
+                    to_channel.drain(..n);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        } else if input_done && !eof_sent {
+            match channel.send_eof() {
+                Ok(()) => eof_sent = true,
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        match channel.read(&mut buf) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(n) => {
+                stdout.write_all(&buf[..n])?;
+                stdout.flush()?;
+                idle = false;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if idle {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    sess.set_blocking(true);
+    let _ = channel.close();
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use crate::auth::confirm_saved_password;
+use crate::config::Connection;
+use crate::credentials::{get_password, get_sudo_password, may_store, set_sudo_password, store_name};
+use crate::error;
+use crate::prompt;
+use crate::ssh::shell_quote;
+use anyhow::{anyhow, Result};
+use crossterm::terminal;
+use inquire::{Confirm, Password};
+use ssh2::Session;
+use ssh_key::rand_core::{OsRng, RngCore};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// 输出里代替密码的文本
+const SCRUBBED: &[u8] = b"********";
+
+/// 在 PTY 上用 sudo 执行命令；看到 sudo 的密码提示时自动输入密码，返回远程退出码
+/// 依次尝试单独保存的 sudo 密码、登录密码，最后在本地询问
+/// timeout 到时关闭通道，PTY 挂断会结束远程命令
+pub fn handle_sudo_exec(
+    sess: Session,
+    alias: &str,
+    conn: Option<&Connection>,
+    command: &str,
+    user: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<i32> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    // 随机的提示文本，不会和命令本身的输出混淆
+    let marker = format!("[rssh-sudo-{:08x}]", OsRng.next_u32());
+    let mut sudo = format!("sudo -p {}", shell_quote(&marker));
+    if let Some(user) = user {
+        sudo.push_str(&format!(" -u {}", shell_quote(user)));
+    }
+    let full_command = format!("{} -- sh -c {}", sudo, shell_quote(command));
+
+    let mut channel = sess.channel_session()?;
+    let (width, height) = terminal::size().unwrap_or((80, 24));
+    channel.request_pty("xterm", None, Some((width as u32, height as u32, 0, 0)))?;
+    channel.exec(&full_command)?;
+
+    let mut stored: Vec<String> = Vec::new();
+    for password in [get_sudo_password(alias), get_password(alias)].into_iter().flatten() {
+        if !stored.contains(&password) {
+            stored.push(password);
+        }
+    }
+    stored.reverse();
+    // 需要生物识别确认时，在第一次用到保存的密码时再确认
+    let mut confirmed = conn.is_none_or(|conn| !conn.require_biometrics);
+
+    let mut stdout = io::stdout();
+    let mut sent: Vec<String> = Vec::new();
+    let mut typed = None;
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 32 * 1024];
+    loop {
+        if let Some(deadline) = deadline {
+            // libssh2 的超时以毫秒计，0 表示不限
+            let remaining = deadline.saturating_duration_since(Instant::now()).as_millis().max(1);
+            sess.set_timeout(remaining.min(u32::MAX as u128) as u32);
+        }
+        let n = match channel.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut && deadline.is_some() => {
+                stdout.write_all(&scrub(&pending, &sent))?;
+                stdout.flush()?;
+                sess.set_timeout(0);
+                let _ = channel.close();
+                return Err(error::timeout(anyhow!(
+                    "The command did not finish within {}s; its terminal was closed.",
+                    timeout.unwrap_or_default().as_secs_f64()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buf[..n]);
+
+        while let Some(pos) = find(&pending, marker.as_bytes()) {
+            stdout.write_all(&scrub(&pending[..pos], &sent))?;
+            stdout.flush()?;
+            pending.drain(..pos + marker.len());
+
+            if !confirmed && !stored.is_empty() {
+                confirmed = true;
+                if !conn.is_some_and(|conn| confirm_saved_password(alias, conn)) {
+                    stored.clear();
+                }
+            }
+            let password = match stored.pop() {
+                Some(password) => password,
+                None if prompt::can_ask() => {
+                    let password = Password::new(&format!("[sudo] password on {}:", alias))
+                        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+                        .without_confirmation()
+                        .prompt()?;
+                    typed = Some(password.clone());
+                    password
+                }
+                None => {
+                    let _ = channel.close();
+                    return Err(prompt::unavailable(format!(
+                        "sudo on '{}' asked for a password, but no stored password was accepted and none can be asked for.",
+                        alias
+                    )));
+                }
+            };
+            // sudo 在 PTY 上关闭了回显，密码不会出现在输出里
+            channel.write_all(format!("{}\n", password).as_bytes())?;
+            channel.flush()?;
+            sent.push(password);
+        }
+
+        // 保留末尾可能是提示或密码开头的部分，等下一块数据
+        let pending_scrubbed = scrub(&pending, &sent);
+        let keep = sent
+            .iter()
+            .map(String::len)
+            .chain([marker.len()])
+            .max()
+            .unwrap_or(0)
+            .saturating_sub(1)
+            .min(pending_scrubbed.len());
+        let cut = pending_scrubbed.len() - keep;
+        stdout.write_all(&pending_scrubbed[..cut])?;
+        stdout.flush()?;
+        pending = pending_scrubbed[cut..].to_vec();
+    }
+    stdout.write_all(&scrub(&pending, &sent))?;
+    stdout.flush()?;
+
+    sess.set_timeout(0);
+    channel.wait_close()?;
+    let status = channel.exit_status()?;
+
+    // 本地输入的密码被接受，询问是否保存
+    if let Some(password) = typed
+        && status == 0
+        && may_store(conn)
+        && prompt::can_ask()
+        && Confirm::new(&format!("Save this sudo password for '{}' in the {}?", alias, store_name()))
+            .with_default(false)
+            .prompt()
+            .unwrap_or(false)
+    {
+        set_sudo_password(alias, &password)?;
+    }
+    Ok(status)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// 把输出中出现的密码替换掉
+fn scrub(data: &[u8], passwords: &[String]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    for password in passwords.iter().filter(|p| !p.is_empty()) {
+        let password = password.as_bytes();
+        let mut scrubbed = Vec::with_capacity(data.len());
+        let mut rest = &data[..];
+        while let Some(pos) = find(rest, password) {
+            scrubbed.extend_from_slice(&rest[..pos]);
+            scrubbed.extend_from_slice(SCRUBBED);
+            rest = &rest[pos + password.len()..];
+        }
+        scrubbed.extend_from_slice(rest);
+        data = scrubbed;
+    }
+    data
+}
@@ -0,0 +1,189 @@
+use crate::config::Connection;
+use crate::output;
+use crate::remote_fs::format_size;
+use crate::ssh::{self, shell_quote};
+use crate::sudo;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use crossterm::style::Stylize;
+use serde_json::json;
+use ssh2::Session;
+use std::collections::HashMap;
+
+// systemctl show 读取的属性，不需要 root 权限
+const PROPERTIES: &str = "Id,Description,LoadState,ActiveState,SubState,UnitFileState,MainPID,\
+ActiveEnterTimestamp,InactiveEnterTimestamp,MemoryCurrent,NRestarts,Result";
+// 和 systemctl status 一样，服务没有运行时退出码为 3
+const EXIT_NOT_ACTIVE: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SvcAction {
+    /// Show whether the unit is running, since when, its main PID and memory
+    Status,
+    /// Restart the unit (through sudo unless logged in as root) and show its status
+    Restart,
+    /// Print the unit's recent journal lines
+    Logs,
+}
+
+impl SvcAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            SvcAction::Status => "status",
+            SvcAction::Restart => "restart",
+            SvcAction::Logs => "logs",
+        }
+    }
+}
+
+pub struct SvcOptions {
+    // logs 时用 sudo 运行 journalctl
+    pub sudo: bool,
+    // restart 时不用 sudo
+    pub no_sudo: bool,
+    pub lines: u32,
+    pub follow: bool,
+    pub since: Option<String>,
+}
+
+/// svc：在远程用 systemctl / journalctl 查看、重启服务和看日志，返回退出码
+pub fn handle_svc(
+    sess: Session,
+    alias: &str,
+    conn: Option<&Connection>,
+    action: SvcAction,
+    unit: &str,
+    opts: &SvcOptions,
+) -> Result<i32> {
+    match action {
+        SvcAction::Status => status(&sess, alias, unit),
+        SvcAction::Restart => {
+            let command = format!("systemctl restart -- {}", shell_quote(unit));
+            let is_root = conn.is_some_and(|conn| conn.target.split('@').next() == Some("root"));
+            let code = match opts.no_sudo || is_root {
+                true => ssh::handle_exec(sess.clone(), &command, false, &[], None)?,
+                false => sudo::handle_sudo_exec(sess.clone(), alias, conn, &command, None, None)?,
+            };
+            if code != 0 {
+                return Ok(code);
+            }
+            output::status(format!("Restarted {} on {}.", unit, alias));
+            output::event("service_restarted", json!({ "alias": alias, "unit": unit }));
+            let code = status(&sess, alias, unit)?;
+            if code != 0 {
+                output::status(format!("See its journal with 'rssh svc {} logs {}'.", alias, unit));
+            }
+            Ok(code)
+        }
+        SvcAction::Logs => {
+            let mut command = format!("journalctl --no-pager -o short-iso -n {} -u {}", opts.lines, shell_quote(unit));
+            if let Some(since) = &opts.since {
+                command.push_str(&format!(" --since {}", shell_quote(since)));
+            }
+            if opts.follow {
+                command.push_str(" -f");
+            }
+            match opts.sudo {
+                true => sudo::handle_sudo_exec(sess, alias, conn, &command, None, None),
+                false => ssh::handle_exec(sess, &command, false, &[], None),
+            }
+        }
+    }
+}
+
+// 读取服务状态并打印，服务没有运行时返回 EXIT_NOT_ACTIVE
+fn status(sess: &Session, alias: &str, unit: &str) -> Result<i32> {
+    let properties = show(sess, unit)?;
+    let get = |key: &str| properties.get(key).map(String::as_str).filter(|value| !value.is_empty());
+    if get("LoadState") == Some("not-found") {
+        return Err(anyhow!("Unit '{}' was not found on '{}'.", unit, alias));
+    }
+    let active = get("ActiveState").unwrap_or("unknown");
+    let since = match active {
+        "active" | "reloading" => get("ActiveEnterTimestamp"),
+        _ => get("InactiveEnterTimestamp"),
+    };
+    // 没有运行或者没有统计内存时是 0 或 [not set]
+    let main_pid = get("MainPID").filter(|&pid| pid != "0");
+    let memory = get("MemoryCurrent").and_then(|bytes| bytes.parse::<u64>().ok());
+    let code = match active {
+        "active" | "reloading" => 0,
+        _ => EXIT_NOT_ACTIVE,
+    };
+
+    if output::is_json() {
+        output::emit(
+            "service",
+            json!({
+                "alias": alias,
+                "unit": get("Id").unwrap_or(unit),
+                "description": get("Description"),
+                "load_state": get("LoadState"),
+                "active_state": active,
+                "sub_state": get("SubState"),
+                "enabled": get("UnitFileState"),
+                "since": since,
+                "main_pid": main_pid.and_then(|pid| pid.parse::<u32>().ok()),
+                "memory_bytes": memory,
+                "restarts": get("NRestarts").and_then(|count| count.parse::<u32>().ok()),
+                "result": get("Result"),
+            }),
+        );
+        return Ok(code);
+    }
+    let dot = match active {
+        "active" | "reloading" => "●".green(),
+        "failed" => "●".red(),
+        "activating" | "deactivating" => "●".yellow(),
+        _ => "●".reset(),
+    };
+    match get("Description") {
+        Some(description) => println!("{} {} - {}", dot, get("Id").unwrap_or(unit), description),
+        None => println!("{} {}", dot, get("Id").unwrap_or(unit)),
+    }
+    let mut state = active.to_string();
+    if let Some(sub_state) = get("SubState") {
+        state.push_str(&format!(" ({})", sub_state));
+    }
+    if let Some(since) = since {
+        state.push_str(&format!(" since {}", since));
+    }
+    println!("    Active:    {}", state);
+    if active == "failed"
+        && let Some(result) = get("Result")
+    {
+        println!("    Result:    {}", result);
+    }
+    if let Some(enabled) = get("UnitFileState") {
+        println!("    Enabled:   {}", enabled);
+    }
+    if let Some(pid) = main_pid {
+        println!("    Main PID:  {}", pid);
+    }
+    if let Some(bytes) = memory {
+        println!("    Memory:    {}", format_size(bytes));
+    }
+    if let Some(restarts) = get("NRestarts").filter(|&count| count != "0") {
+        println!("    Restarts:  {}", restarts);
+    }
+    Ok(code)
+}
+
+// systemctl show 的 Key=Value 输出
+fn show(sess: &Session, unit: &str) -> Result<HashMap<String, String>> {
+    let command = format!("systemctl show --no-pager --property={} -- {}", PROPERTIES, shell_quote(unit));
+    let captured = ssh::exec_capture(sess, &command, b"")?;
+    match captured.status {
+        0 => {}
+        127 => return Err(anyhow!("systemctl was not found on the server; 'rssh svc' needs systemd.")),
+        code => {
+            let stderr = String::from_utf8_lossy(&captured.stderr);
+            return Err(anyhow!("systemctl show failed with exit status {}: {}", code, stderr.trim()));
+        }
+    }
+    Ok(String::from_utf8_lossy(&captured.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+        .collect())
+}
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use crossterm::style::Stylize;
+use ssh2::Session;
+use std::io::Read;
+
+// 探测最多等这么久，慢的服务器不影响进入 shell
+const PROBE_TIMEOUT_MS: u32 = 5000;
+
+// 只用 POSIX sh 和常见命令，缺少的项输出为空
+const PROBE_SCRIPT: &str = r#"
+echo "hostname=$(hostname 2>/dev/null || uname -n)"
+echo "os=$( (. /etc/os-release 2>/dev/null && echo "$PRETTY_NAME") || uname -sr)"
+echo "uptime=$(uptime 2>/dev/null)"
+echo "disk=$(df -hP / 2>/dev/null | awk 'NR==2 {print $3 " of " $2 " used (" $5 ")"}')"
+echo "last=$(last -n 2 "$(id -un)" 2>/dev/null | sed -n 2p)"
+"#;
+
+/// 登录后显示的服务器概况
+#[derive(Default)]
+pub struct SystemInfo {
+    hostname: String,
+    os: String,
+    uptime: String,
+    load: String,
+    disk: String,
+    last_login: String,
+}
+
+/// 用一个 exec 通道收集主机名、运行时间、负载、根分区用量和上次登录
+pub fn probe(sess: &Session) -> Result<SystemInfo> {
+    let mut info = SystemInfo::default();
+    for (key, value) in run_script(sess, PROBE_SCRIPT)? {
+        match key.as_str() {
+            "hostname" => info.hostname = value,
+            "os" => info.os = value,
+            "uptime" => (info.uptime, info.load) = parse_uptime(&value),
+            "disk" => info.disk = value,
+            "last" if !value.starts_with("wtmp") => info.last_login = value,
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+/// 用于 status 表格的数值指标，缺少的项为 None
+#[derive(Default)]
+pub struct Metrics {
+    pub cpus: Option<u32>,
+    pub uptime: Option<String>,
+    pub load: Option<[f64; 3]>,
+    // 字节
+    pub mem_total: Option<u64>,
+    pub mem_available: Option<u64>,
+    pub disk_total: Option<u64>,
+    pub disk_used: Option<u64>,
+    pub disk_available: Option<u64>,
+}
+
+impl Metrics {
+    /// 1 分钟负载除以 CPU 数
+    pub fn load_per_cpu(&self) -> Option<f64> {
+        Some(self.load?[0] / self.cpus.filter(|&cpus| cpus > 0)? as f64)
+    }
+
+    pub fn mem_percent(&self) -> Option<f64> {
+        let total = self.mem_total.filter(|&total| total > 0)?;
+        Some(100.0 * total.saturating_sub(self.mem_available?) as f64 / total as f64)
+    }
+
+    /// 和 df 一样按 已用 / (已用 + 可用) 计算，不算保留给 root 的空间
+    pub fn disk_percent(&self) -> Option<f64> {
+        let used = self.disk_used?;
+        let usable = (used + self.disk_available?).max(1);
+        Some(100.0 * used as f64 / usable as f64)
+    }
+}
+
+// Linux 上读 /proc，其他系统退回解析 uptime 的输出
+const METRICS_SCRIPT: &str = r#"
+echo "cpus=$(getconf _NPROCESSORS_ONLN 2>/dev/null || nproc 2>/dev/null)"
+[ -r /proc/uptime ] && echo "uptime_secs=$(cut -d' ' -f1 /proc/uptime)"
+[ -r /proc/loadavg ] && echo "load=$(cut -d' ' -f1-3 /proc/loadavg)"
+echo "uptime=$(uptime 2>/dev/null)"
+[ -r /proc/meminfo ] && awk '/^MemTotal:/ {print "mem_total=" $2} /^MemAvailable:/ {print "mem_available=" $2}' /proc/meminfo
+df -Pk / 2>/dev/null | awk 'NR==2 {print "disk=" $2 " " $3 " " $4}'
+"#;
+
+pub fn probe_metrics(sess: &Session) -> Result<Metrics> {
+    let mut metrics = Metrics::default();
+    let mut uptime_text = String::new();
+    for (key, value) in run_script(sess, METRICS_SCRIPT)? {
+        let numbers: Vec<f64> = value
+            .split([' ', ','])
+            .filter_map(|part| part.parse().ok())
+            .collect();
+        match key.as_str() {
+            "cpus" => metrics.cpus = value.parse().ok(),
+            "uptime_secs" => metrics.uptime = numbers.first().map(|&secs| format_uptime(secs as u64)),
+            "load" => metrics.load = parse_load(&numbers),
+            "uptime" => uptime_text = value,
+            "mem_total" => metrics.mem_total = numbers.first().map(|&kb| kb as u64 * 1024),
+            "mem_available" => metrics.mem_available = numbers.first().map(|&kb| kb as u64 * 1024),
+            "disk" if numbers.len() == 3 => {
+                metrics.disk_total = Some(numbers[0] as u64 * 1024);
+                metrics.disk_used = Some(numbers[1] as u64 * 1024);
+                metrics.disk_available = Some(numbers[2] as u64 * 1024);
+            }
+            _ => {}
+        }
+    }
+    // 没有 /proc 时从 uptime 的输出里取
+    let (up, load) = parse_uptime(&uptime_text);
+    if metrics.uptime.is_none() && !up.is_empty() {
+        metrics.uptime = Some(up);
+    }
+    if metrics.load.is_none() {
+        let numbers: Vec<f64> = load.split([' ', ',']).filter_map(|part| part.parse().ok()).collect();
+        metrics.load = parse_load(&numbers);
+    }
+    Ok(metrics)
+}
+
+fn parse_load(numbers: &[f64]) -> Option<[f64; 3]> {
+    match numbers {
+        [one, five, fifteen, ..] => Some([*one, *five, *fifteen]),
+        _ => None,
+    }
+}
+
+fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+// 在 exec 通道里执行脚本，解析 key=value 行；最多等 PROBE_TIMEOUT_MS
+pub fn run_script(sess: &Session, script: &str) -> Result<Vec<(String, String)>> {
+    let timeout = sess.timeout();
+    sess.set_timeout(PROBE_TIMEOUT_MS);
+    let result = read_script(sess, script);
+    sess.set_timeout(timeout);
+    let output = result?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .collect())
+}
+
+fn read_script(sess: &Session, script: &str) -> Result<String> {
+    let mut channel = sess.channel_session().context("Failed to open a channel")?;
+    channel.exec(script).context("Failed to run the probe")?;
+    let mut output = String::new();
+    channel.read_to_string(&mut output).context("Failed to read the probe output")?;
+    let _ = channel.wait_close();
+    Ok(output)
+}
+
+// "10:00:00 up 3 days,  2:03,  1 user,  load average: 0.00, 0.01, 0.05"
+// macOS 是 "load averages: 1.20 1.31 1.40"
+fn parse_uptime(uptime: &str) -> (String, String) {
+    let (head, load) = match uptime.split_once("load average") {
+        Some((head, rest)) => (head, rest.split_once(':').map_or("", |(_, load)| load.trim())),
+        None => (uptime, ""),
+    };
+    let up = head.split_once(" up ").map_or("", |(_, up)| up);
+    let up: Vec<&str> = up
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty() && !part.contains("user"))
+        .collect();
+    (up.join(", "), load.to_string())
+}
+
+impl SystemInfo {
+    /// 带边框的几行摘要，空的项不显示
+    pub fn print(&self) {
+        let title = match (self.hostname.is_empty(), self.os.is_empty()) {
+            (false, false) => format!("{} ({})", self.hostname, self.os),
+            (false, true) => self.hostname.clone(),
+            (true, _) => self.os.clone(),
+        };
+        let rows: Vec<(&str, &str)> = [
+            ("Uptime", self.uptime.as_str()),
+            ("Load", self.load.as_str()),
+            ("Disk /", self.disk.as_str()),
+            ("Last", self.last_login.as_str()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+        if title.is_empty() && rows.is_empty() {
+            return;
+        }
+
+        let width = rows
+            .iter()
+            .map(|(_, value)| value.chars().count() + 9)
+            .chain([title.chars().count() + 3])
+            .max()
+            .unwrap_or(0);
+        let top = format!("─ {} {}", title, "─".repeat(width.saturating_sub(title.chars().count() + 2)));
+        println!("{}", format!("┌{}", top).dark_grey());
+        for (label, value) in rows {
+            println!("{} {}{}", "│".dark_grey(), format!("{:<8}", label).bold(), value);
+        }
+        println!("{}", format!("└{}", "─".repeat(width + 1)).dark_grey());
+    }
+}
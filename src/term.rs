@@ -0,0 +1,192 @@
+use clap::ValueEnum;
+use crossterm::{cursor, queue, terminal};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::time::{Duration, Instant};
+
+/// 交互会话里 Ctrl+C、Ctrl+\ 和 Ctrl+Z 的去向
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignalMode {
+    /// Send them to the remote program, like a local terminal would
+    #[default]
+    Remote,
+    /// Ctrl+C and Ctrl+\ disconnect, Ctrl+Z suspends rssh
+    Local,
+}
+
+impl SignalMode {
+    pub fn is_remote(&self) -> bool {
+        *self == SignalMode::Remote
+    }
+
+    pub fn toggled(self) -> SignalMode {
+        match self {
+            SignalMode::Remote => SignalMode::Local,
+            SignalMode::Local => SignalMode::Remote,
+        }
+    }
+}
+
+/// 进入交互会话前调用：Windows 控制台默认不解析 VT 转义序列，需要先打开
+pub fn prepare() {
+    #[cfg(windows)]
+    {
+        if !crossterm::ansi_support::supports_ansi() {
+            eprintln!("Warning: this console does not support VT escape sequences; remote output may not display correctly.");
+        }
+    }
+}
+
+/// 原始模式的作用域守卫：提前返回或出错时也会恢复终端
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// 像本地程序一样按 Ctrl+Z 挂起：恢复终端后给自己发 SIGTSTP，fg 回来时重新进入原始模式
+#[cfg(unix)]
+pub fn suspend() -> io::Result<()> {
+    terminal::disable_raw_mode()?;
+    unsafe { libc::raise(libc::SIGTSTP) };
+    terminal::enable_raw_mode()
+}
+
+#[cfg(not(unix))]
+pub fn suspend() -> io::Result<()> {
+    Ok(())
+}
+
+/// panic 时先把终端恢复正常，再打印 panic 信息
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if terminal::is_raw_mode_enabled().unwrap_or(false) {
+            let mut stdout = io::stdout();
+            let _ = queue!(stdout, terminal::LeaveAlternateScreen, cursor::Show);
+            let _ = stdout.flush();
+            let _ = terminal::disable_raw_mode();
+        }
+        default_hook(info);
+    }));
+}
+
+/// 保存当前窗口标题（xterm 标题栈）并设置新标题
+pub fn push_title(title: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[22;0t")?;
+    queue!(stdout, terminal::SetTitle(title))?;
+    stdout.flush()
+}
+
+/// 恢复 push_title 之前的标题；不支持标题栈的终端会忽略
+pub fn pop_title() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[23;0t")?;
+    stdout.flush()
+}
+
+/// 窗口大小变化后要告诉远程 PTY 的尺寸
+/// Windows 的 Resize 事件报告的是屏幕缓冲区大小，而不是可见窗口大小，需要重新查询
+pub fn resized_size(width: u16, height: u16) -> (u16, u16) {
+    #[cfg(windows)]
+    {
+        if let Ok(size) = terminal::size() {
+            return size;
+        }
+    }
+    (width, height)
+}
+
+// 输出缓冲的大小，大段输出攒满后一次写给终端
+const OUTPUT_BUFFER_SIZE: usize = 256 * 1024;
+// 持续输出时至少这么久刷新一次画面
+const FLUSH_INTERVAL: Duration = Duration::from_millis(16);
+
+/// 交互会话的终端输出：连续输出时不再每读一块就刷新一次，攒到缓冲区满、
+/// 超过一帧的时间或通道暂时没有数据时才写给终端，cat 大文件、编译日志这类大量输出更快
+pub struct BufferedOutput {
+    out: BufWriter<Stdout>,
+    last_flush: Instant,
+}
+
+impl BufferedOutput {
+    pub fn new() -> Self {
+        BufferedOutput { out: BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, io::stdout()), last_flush: Instant::now() }
+    }
+
+    pub fn writer(&mut self) -> &mut BufWriter<Stdout> {
+        &mut self.out
+    }
+
+    /// 距上次刷新超过一帧时刷新，持续输出时画面也会跟着走
+    pub fn flush_if_due(&mut self) -> io::Result<()> {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 立即写给终端；在直接写 stdout 之前调用，保证先后顺序
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.out.flush()
+    }
+}
+
+/// 把远程输出写到本地终端
+/// 读取边界可能截断多字节 UTF-8 字符，不完整的尾部留到下一次写入再输出
+#[derive(Default)]
+pub struct TermOutput {
+    pending: Vec<u8>,
+}
+
+impl TermOutput {
+    pub fn write<W: Write>(&mut self, out: &mut W, data: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(data);
+        let complete = self.pending.len() - incomplete_tail(&self.pending);
+        let result = write_complete(out, &self.pending[..complete]);
+        self.pending.drain(..complete);
+        result
+    }
+}
+
+#[cfg(not(windows))]
+fn write_complete<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    out.write_all(data)
+}
+
+// Windows 控制台只接受合法的 UTF-8，非法字节替换成 U+FFFD，否则整次写入都会失败
+#[cfg(windows)]
+fn write_complete<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    out.write_all(String::from_utf8_lossy(data).as_bytes())
+}
+
+// 末尾未写完的 UTF-8 字符的字节数；不是 UTF-8 的数据返回 0，原样输出
+fn incomplete_tail(data: &[u8]) -> usize {
+    for back in 1..=data.len().min(3) {
+        let byte = data[data.len() - back];
+        // 续字节，继续往前找首字节
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+        let expected = match byte {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return 0,
+        };
+        return if expected > back { back } else { 0 };
+    }
+    0
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use ssh2::{MethodType, Session, TraceFlags};
+use std::ffi::{c_char, c_int, c_void};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+// --debug-ssh 的输出文件；每一行立即写入，卡住时文件里也有到那一刻为止的记录
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+// ssh2 没有封装这个函数；没有处理函数时 libssh2 把跟踪输出写到 stderr
+unsafe extern "C" {
+    fn libssh2_trace_sethandler(
+        session: *mut c_void,
+        context: *mut c_void,
+        callback: extern "C" fn(*mut c_void, *mut c_void, *const c_char, usize),
+    ) -> c_int;
+}
+
+/// --debug-ssh：之后的连接把 libssh2 的跟踪信息和 rssh 各阶段的耗时写到 path
+pub fn start(path: &Path) -> Result<()> {
+    let mut file = File::create(path).context(format!("Failed to create debug log {:?}", path))?;
+    STARTED.get_or_init(Instant::now);
+    let args: Vec<String> = std::env::args().collect();
+    writeln!(file, "rssh {} on {} {}", env!("CARGO_PKG_VERSION"), std::env::consts::OS, std::env::consts::ARCH)?;
+    writeln!(file, "started {}", Local::now().to_rfc3339())?;
+    writeln!(file, "command: {}", args.join(" "))?;
+    // libssh2 只有按调试配置编译时才输出跟踪信息
+    if !cfg!(debug_assertions) {
+        writeln!(file, "note: this build's libssh2 may have no tracing compiled in; only rssh's own events may appear")?;
+    }
+    *FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    FILE.lock().unwrap().is_some()
+}
+
+/// 记一行 rssh 自己的事件，前面是从启动起的毫秒数；没有 --debug-ssh 时什么也不做
+pub fn log(message: impl Display) {
+    let mut file = FILE.lock().unwrap();
+    if let Some(file) = file.as_mut() {
+        let elapsed = STARTED.get().map_or(0.0, |started| started.elapsed().as_secs_f64() * 1000.0);
+        let _ = writeln!(file, "{:>10.1}ms [rssh] {}", elapsed, message);
+    }
+}
+
+/// 握手前调用，打开这个会话的 libssh2 跟踪。
+/// 不包含 TRANS：它会转储解密后的数据包，其中有输入的密码
+pub fn attach(sess: &Session) {
+    if !is_enabled() {
+        return;
+    }
+    let flags = TraceFlags::AUTH
+        | TraceFlags::CONN
+        | TraceFlags::ERROR
+        | TraceFlags::KEX
+        | TraceFlags::PUBLICKEY
+        | TraceFlags::SCP
+        | TraceFlags::SFTP
+        | TraceFlags::SOCKET;
+    sess.trace(flags);
+    let mut raw = sess.raw();
+    unsafe {
+        libssh2_trace_sethandler(&mut *raw as *mut _ as *mut c_void, std::ptr::null_mut(), on_trace);
+    }
+}
+
+/// 握手后记下协商出的算法
+pub fn log_methods(sess: &Session) {
+    if !is_enabled() {
+        return;
+    }
+    let method = |kind| sess.methods(kind).unwrap_or("?");
+    log(format!(
+        "negotiated kex={} hostkey={} cipher={}/{} mac={}/{}",
+        method(MethodType::Kex),
+        method(MethodType::HostKey),
+        method(MethodType::CryptCs),
+        method(MethodType::CryptSc),
+        method(MethodType::MacCs),
+        method(MethodType::MacSc)
+    ));
+}
+
+extern "C" fn on_trace(_session: *mut c_void, _context: *mut c_void, message: *const c_char, len: usize) {
+    if message.is_null() {
+        return;
+    }
+    let message = unsafe { std::slice::from_raw_parts(message as *const u8, len) };
+    let mut file = FILE.lock().unwrap();
+    if let Some(file) = file.as_mut() {
+        let elapsed = STARTED.get().map_or(0.0, |started| started.elapsed().as_secs_f64() * 1000.0);
+        let _ = writeln!(file, "{:>10.1}ms {}", elapsed, String::from_utf8_lossy(message).trim_end());
+    }
+}
@@ -0,0 +1,1915 @@
+use crate::delta::{self, Piece, Signature};
+use crate::input;
+use crate::interrupt;
+use crate::manifest::Manifest;
+use crate::output;
+use crate::progress::{FileProgress, ProgressMode, TransferProgress, TransferStats};
+use crate::prompt;
+use crate::remote_fs;
+use crate::ssh::{self, exec_status, shell_quote};
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use inquire::Confirm;
+use ssh2::{FileStat, OpenFlags, OpenType, Session, Sftp};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::cell::OnceCell;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 上传/下载的公共选项
+#[derive(Clone, Copy)]
+pub struct TransferOptions {
+    pub preserve: bool,
+    pub policy: OverwritePolicy,
+    pub recursive: bool,
+    pub tar: bool,
+    pub progress: ProgressMode,
+    pub buffer_size: usize,
+    /// 目标已存在且内容相同时直接跳过，不看修改时间
+    pub checksum: bool,
+    /// --continue：跳过上次已传完的文件，接着传没传完的
+    pub resume: bool,
+    pub walk: WalkOptions,
+    /// 上传前不检查远程剩余空间
+    pub skip_space_check: bool,
+    /// 目标已有旧版本时也整个传，不做增量传输
+    pub whole_file: bool,
+}
+
+/// upload --extract：上传压缩包后在目标目录里解压
+#[derive(Clone, Copy)]
+pub struct Extract {
+    // 解压成功后删除服务器上的压缩包
+    pub remove_archive: bool,
+}
+
+// 文件名后缀和对应的解压命令；.tar 要排在 .tar.gz 等之后
+const ARCHIVE_COMMANDS: &[(&str, &str)] = &[
+    (".tar.gz", "tar -xzf"),
+    (".tgz", "tar -xzf"),
+    (".tar.bz2", "tar -xjf"),
+    (".tbz2", "tar -xjf"),
+    (".tar.xz", "tar -xJf"),
+    (".txz", "tar -xJf"),
+    (".tar.zst", "tar --zstd -xf"),
+    (".tar", "tar -xf"),
+    (".zip", "unzip -oq"),
+];
+
+/// 递归传输遇到符号链接时怎么办
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// 复制链接指向的文件，跳过指向目录的链接（默认）
+    FilesOnly,
+    /// 指向目录的链接也进入，但不进入正在复制的上级目录
+    Follow,
+    /// 在目标处重新创建同样的链接
+    Preserve,
+}
+
+/// 递归传输时对隐藏文件、符号链接和其他文件系统的处理
+#[derive(Clone, Copy)]
+pub struct WalkOptions {
+    pub hidden: bool,
+    pub symlinks: SymlinkPolicy,
+    pub one_file_system: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions { hidden: true, symlinks: SymlinkPolicy::FilesOnly, one_file_system: false }
+    }
+}
+
+impl WalkOptions {
+    pub fn is_default(&self) -> bool {
+        self.hidden && self.symlinks == SymlinkPolicy::FilesOnly && !self.one_file_system
+    }
+}
+
+// 目录传输中途失败时提示可以续传
+const CONTINUE_HINT: &str = "Finished files are recorded; run the same command with --continue to pick up where it stopped.";
+
+/// 默认的复制缓冲区；libssh2 会把一次大的 SFTP 读写拆成多个并发请求，缓冲区越大流水线越满
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+// 递归传输前先列出所有要创建的目录和要复制的文件，以便显示总进度
+#[derive(Default)]
+struct TransferPlan {
+    dirs: Vec<(PathBuf, PathBuf)>,
+    files: Vec<(PathBuf, PathBuf, u64)>,
+    // --preserve-symlinks 时要重新创建的链接：(源路径, 目标路径, 链接内容)
+    links: Vec<(PathBuf, PathBuf, PathBuf)>,
+    // --follow-symlinks 时正在列出的各级目录的真实路径，链接指回它们时会死循环
+    ancestors: HashSet<PathBuf>,
+    // --one-file-system：本地根目录的设备号，或远程和根目录在同一文件系统上的目录
+    root_dev: Option<u64>,
+    same_fs: Option<HashSet<PathBuf>>,
+}
+
+impl TransferPlan {
+    fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|(_, _, size)| size).sum()
+    }
+}
+
+/// 一个连接和它的 SFTP 通道；通道在第一次用到时打开，同一连接上的多次传输共用
+pub struct SftpSession {
+    pub sess: Session,
+    sftp: OnceCell<Sftp>,
+}
+
+impl SftpSession {
+    pub fn new(sess: Session) -> Self {
+        SftpSession { sess, sftp: OnceCell::new() }
+    }
+
+    pub fn sftp(&self) -> Result<&Sftp> {
+        if let Some(sftp) = self.sftp.get() {
+            return Ok(sftp);
+        }
+        let sftp = self.sess.sftp().context("Failed to create SFTP session")?;
+        Ok(self.sftp.get_or_init(|| sftp))
+    }
+}
+
+/// 多个源路径时本地文件的个数和总字节数，用于显示总进度；读不到的路径不计入
+pub fn local_totals(paths: &[&Path], opts: TransferOptions) -> (u64, u64) {
+    let mut plan = TransferPlan::default();
+    for path in paths {
+        if path.is_dir() && opts.recursive {
+            let _ = plan_upload_dir(path, Path::new(""), &mut plan, opts.walk);
+        } else if let Ok(meta) = fs::metadata(path)
+            && meta.is_file()
+        {
+            plan.files.push((path.to_path_buf(), PathBuf::new(), meta.len()));
+        }
+    }
+    (plan.files.len() as u64, plan.total_bytes())
+}
+
+/// alias 用来区分不同主机的传输清单
+pub fn handle_upload(
+    conn: &SftpSession,
+    alias: &str,
+    local_path: &Path,
+    remote_dir: &Path,
+    opts: TransferOptions,
+) -> Result<TransferStats> {
+    if local_path.is_dir() {
+        if !opts.recursive {
+            return Err(anyhow!(
+                "Local path {:?} is a directory. Use --recursive to upload it.",
+                local_path
+            ));
+        }
+        if opts.tar {
+            if remote_has_tar(&conn.sess) {
+                let remote_root = remote_dir.join(local_path.file_name().unwrap_or_default());
+                check_tar_policy(&remote_root, conn.sftp()?.lstat(&remote_root).is_ok(), opts)?;
+                check_remote_space(conn, remote_dir, local_totals(&[local_path], opts).1, opts)?;
+                return tar_upload(&conn.sess, local_path, remote_dir, opts);
+            }
+            eprintln!("Warning: 'tar' is not available on the remote host, falling back to SFTP.");
+        }
+        let sftp = conn.sftp()?;
+        let dir_name = local_path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid local directory {:?}", local_path))?;
+
+        let mut plan = TransferPlan::default();
+        let remote_root = remote_dir.join(dir_name);
+        plan_upload_dir(local_path, &remote_root, &mut plan, opts.walk)?;
+        check_remote_space(conn, remote_dir, plan.total_bytes(), opts)?;
+
+        let source = fs::canonicalize(local_path).unwrap_or_else(|_| local_path.to_path_buf());
+        let manifest = Manifest::open(alias, "upload", &source, &remote_root, opts.resume)?;
+        let progress = TransferProgress::new(opts.progress, plan.files.len() as u64, plan.total_bytes());
+        for (_, remote) in &plan.dirs {
+            remote_fs::create_dir_all(sftp, remote)?;
+        }
+        for (_, remote, target) in &plan.links {
+            make_remote_link(&conn.sess, sftp, target, remote, &progress)?;
+        }
+        plan.files
+            .iter()
+            .try_for_each(|(local, remote, _)| upload_file(conn, local, remote, opts, &progress, Some(&manifest)))
+            .inspect_err(|_| progress.println(CONTINUE_HINT))?;
+        if opts.preserve {
+            // 目录的时间戳要在其中的文件写完之后再设置
+            for (local, remote) in plan.dirs.iter().rev() {
+                preserve_remote_attrs(sftp, remote, &fs::metadata(local)?)?;
+            }
+        }
+        manifest.finish();
+        return Ok(progress.finish());
+    }
+
+    if !local_path.is_file() {
+        return Err(anyhow!(
+            "Local path {:?} is not a file. Please provide a path to a file to upload.",
+            local_path
+        ));
+    }
+
+    let file_name = local_path.file_name().unwrap(); // Safe due to is_file check
+    let remote_path = remote_dir.join(file_name);
+
+    let size = fs::metadata(local_path)?.len();
+    check_remote_space(conn, remote_dir, size, opts)?;
+    let progress = TransferProgress::new(opts.progress, 1, size);
+    upload_file(conn, local_path, &remote_path, opts, &progress, None)?;
+    Ok(progress.finish())
+}
+
+pub fn handle_download(
+    conn: &SftpSession,
+    alias: &str,
+    remote_path: &Path,
+    local_dir: &Path,
+    opts: TransferOptions,
+) -> Result<TransferStats> {
+    let file_name = remote_path.file_name().ok_or_else(|| {
+        anyhow!(
+            "Remote path {:?} is a directory or invalid. Please provide a path to a file to download.",
+            remote_path
+        )
+    })?;
+
+    if local_dir.is_file() {
+        return Err(anyhow!(
+            "Local destination {:?} is a file. Please provide a directory path.",
+            local_dir
+        ));
+    }
+    fs::create_dir_all(local_dir)
+        .context(format!("Failed to create local directory {:?}", local_dir))?;
+
+    let sftp = conn.sftp()?;
+    let remote_stat = sftp
+        .stat(remote_path)
+        .context(format!("Failed to stat remote path {:?}", remote_path))?;
+
+    if remote_stat.is_dir() {
+        if !opts.recursive {
+            return Err(anyhow!(
+                "Remote path {:?} is a directory. Use --recursive to download it.",
+                remote_path
+            ));
+        }
+        if opts.tar {
+            if remote_has_tar(&conn.sess) {
+                let local_root = local_dir.join(remote_path.file_name().unwrap_or_default());
+                check_tar_policy(&local_root, local_root.symlink_metadata().is_ok(), opts)?;
+                return tar_download(&conn.sess, remote_path, local_dir, opts);
+            }
+            eprintln!("Warning: 'tar' is not available on the remote host, falling back to SFTP.");
+        }
+
+        let mut plan = TransferPlan::default();
+        if opts.walk.one_file_system {
+            plan.same_fs = Some(remote_same_fs(&conn.sess, remote_path)?);
+        }
+        let local_root = local_dir.join(file_name);
+        plan_download_dir(sftp, remote_path, &local_root, &mut plan, opts.walk)?;
+
+        let source = sftp.realpath(remote_path).unwrap_or_else(|_| remote_path.to_path_buf());
+        let local_root = fs::canonicalize(local_dir).map(|dir| dir.join(file_name)).unwrap_or(local_root);
+        let manifest = Manifest::open(alias, "download", &source, &local_root, opts.resume)?;
+        let progress = TransferProgress::new(opts.progress, plan.files.len() as u64, plan.total_bytes());
+        for (_, local) in &plan.dirs {
+            fs::create_dir_all(local)
+                .context(format!("Failed to create local directory {:?}", local))?;
+        }
+        for (_, local, target) in &plan.links {
+            make_local_link(target, local, &progress)?;
+        }
+        plan.files
+            .iter()
+            .try_for_each(|(remote, local, _)| download_file(conn, remote, local, opts, &progress, Some(&manifest)))
+            .inspect_err(|_| progress.println(CONTINUE_HINT))?;
+        if opts.preserve {
+            for (remote, local) in plan.dirs.iter().rev() {
+                preserve_local_attrs(local, &sftp.stat(remote)?)?;
+            }
+        }
+        manifest.finish();
+        return Ok(progress.finish());
+    }
+
+    let progress = TransferProgress::new(opts.progress, 1, remote_stat.size.unwrap_or(0));
+    download_file(conn, remote_path, &local_dir.join(file_name), opts, &progress, None)?;
+    Ok(progress.finish())
+}
+
+/// 本机上的复制，供 cp 两端都是本地路径时使用
+pub fn handle_local_copy(src_path: &Path, dst_dir: &Path, opts: TransferOptions) -> Result<TransferStats> {
+    let file_name = src_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid source path {:?}", src_path))?;
+    if dst_dir.is_file() {
+        return Err(anyhow!(
+            "Destination {:?} is a file. Please provide a directory path.",
+            dst_dir
+        ));
+    }
+    let mut plan = TransferPlan::default();
+    if src_path.is_dir() {
+        if !opts.recursive {
+            return Err(anyhow!(
+                "Source path {:?} is a directory. Use --recursive to copy it.",
+                src_path
+            ));
+        }
+        plan_upload_dir(src_path, &dst_dir.join(file_name), &mut plan, opts.walk)?;
+    } else if src_path.is_file() {
+        plan.files.push((src_path.to_path_buf(), dst_dir.join(file_name), fs::metadata(src_path)?.len()));
+    } else {
+        return Err(anyhow!("Source path {:?} does not exist.", src_path));
+    }
+
+    fs::create_dir_all(dst_dir).context(format!("Failed to create directory {:?}", dst_dir))?;
+    let progress = TransferProgress::new(opts.progress, plan.files.len() as u64, plan.total_bytes());
+    for (_, dst) in &plan.dirs {
+        fs::create_dir_all(dst).context(format!("Failed to create directory {:?}", dst))?;
+    }
+    for (_, dst, target) in &plan.links {
+        make_local_link(target, dst, &progress)?;
+    }
+    for (src, dst, _) in &plan.files {
+        local_copy_file(src, dst, opts, &progress)?;
+    }
+    if opts.preserve {
+        for (src, dst) in plan.dirs.iter().rev() {
+            preserve_metadata(dst, &fs::metadata(src)?)?;
+        }
+    }
+    Ok(progress.finish())
+}
+
+/// 服务器之间直接 scp 时目标的地址
+#[derive(Clone)]
+pub struct DirectTarget {
+    pub target: String,
+    pub port: u16,
+}
+
+/// 从一台远程主机复制到另一台，数据经过本机中转；`direct` 时先尝试让源主机直接 scp 给目标。
+/// 两边的 SFTP 会话由调用方打开，复制多个文件时共用
+#[allow(clippy::too_many_arguments)]
+pub fn handle_copy(
+    src: &Session,
+    src_sftp: &Sftp,
+    src_path: &Path,
+    dst: &Session,
+    dst_sftp: &Sftp,
+    dst_dir: &Path,
+    opts: TransferOptions,
+    direct: Option<DirectTarget>,
+) -> Result<TransferStats> {
+    let file_name = src_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid source path {:?}", src_path))?;
+    let src_stat = src_sftp
+        .stat(src_path)
+        .context(format!("Failed to stat source path {:?}", src_path))?;
+    if src_stat.is_dir() && !opts.recursive {
+        return Err(anyhow!(
+            "Source path {:?} is a directory. Use --recursive to copy it.",
+            src_path
+        ));
+    }
+
+    let mut plan = TransferPlan::default();
+    if src_stat.is_dir() {
+        if opts.walk.one_file_system {
+            plan.same_fs = Some(remote_same_fs(src, src_path)?);
+        }
+        plan_download_dir(src_sftp, src_path, &dst_dir.join(file_name), &mut plan, opts.walk)?;
+    } else {
+        plan.files.push((src_path.to_path_buf(), dst_dir.join(file_name), src_stat.size.unwrap_or(0)));
+    }
+
+    if let Some(direct) = direct {
+        match scp_direct(src, src_path, dst_dir, &direct, opts) {
+            Ok(()) => {
+                output::status(format!("Copied directly from the source to {}.", direct.target));
+                return Ok(TransferStats {
+                    bytes: plan.total_bytes(),
+                    files: plan.files.len() as u64,
+                    skipped: 0,
+                });
+            }
+            Err(e) => eprintln!("Warning: direct copy failed ({:#}), streaming through this machine instead.", e),
+        }
+    }
+
+    remote_fs::create_dir_all(dst_sftp, dst_dir)?;
+    let progress = TransferProgress::new(opts.progress, plan.files.len() as u64, plan.total_bytes());
+    for (_, dst_path) in &plan.dirs {
+        remote_fs::create_dir_all(dst_sftp, dst_path)?;
+    }
+    for (_, dst_path, target) in &plan.links {
+        make_remote_link(dst, dst_sftp, target, dst_path, &progress)?;
+    }
+    for (src_path, dst_path, _) in &plan.files {
+        copy_file(src_sftp, src_path, dst_sftp, dst_path, opts, &progress)?;
+    }
+    if opts.preserve {
+        for (src_path, dst_path) in plan.dirs.iter().rev() {
+            copy_remote_attrs(dst_sftp, dst_path, &src_sftp.stat(src_path)?)?;
+        }
+    }
+    Ok(progress.finish())
+}
+
+// 在源主机上执行 scp；BatchMode 保证它没有可用的密钥时直接失败而不是等待输入
+fn scp_direct(src: &Session, src_path: &Path, dst_dir: &Path, direct: &DirectTarget, opts: TransferOptions) -> Result<()> {
+    let mut command = format!("scp -q -o BatchMode=yes -P {}", direct.port);
+    if opts.recursive {
+        command.push_str(" -r");
+    }
+    if opts.preserve {
+        command.push_str(" -p");
+    }
+    command.push_str(&format!(
+        " {} {}",
+        shell_quote(&src_path.to_string_lossy()),
+        shell_quote(&format!("{}:{}", direct.target, dst_dir.to_string_lossy()))
+    ));
+    match exec_status(src, &command)? {
+        0 => Ok(()),
+        code => Err(anyhow!("scp on the source host exited with status {}", code)),
+    }
+}
+
+fn copy_file(
+    src_sftp: &Sftp,
+    src_path: &Path,
+    dst_sftp: &Sftp,
+    dst_path: &Path,
+    opts: TransferOptions,
+    progress: &TransferProgress,
+) -> Result<()> {
+    let mut src_file = src_sftp
+        .open(src_path)
+        .context(format!("Failed to open source file: {:?}", src_path))?;
+    let stat = src_file.stat()?;
+    let file_size = stat.size.unwrap_or(0);
+    let name = src_path.display().to_string();
+
+    if dst_sftp.stat(dst_path).is_ok() {
+        match progress.suspend(|| opts.policy.resolve(dst_path))? {
+            ConflictAction::Skip => {
+                progress.println(&format!("Skipping {:?}: destination file already exists.", dst_path));
+                progress.skip_file(&name, file_size);
+                return Ok(());
+            }
+            ConflictAction::Backup => {
+                let backup_path = backup_path_for(dst_path);
+                let _ = dst_sftp.unlink(&backup_path);
+                dst_sftp.rename(dst_path, &backup_path, None)
+                    .context(format!("Failed to back up destination file {:?}", dst_path))?;
+                progress.println(&format!("Existing destination file backed up to {:?}", backup_path));
+            }
+            ConflictAction::Overwrite => {}
+        }
+    }
+
+    progress.println(&format!("Copying {:?} to {:?}...", src_path, dst_path));
+
+    let file_progress = progress.start_file(&name, Some(file_size));
+    let mut dst_file = dst_sftp
+        .create(dst_path)
+        .context(format!("Failed to create destination file: {:?}", dst_path))?;
+    let mut reader = file_progress.wrap_read(&mut src_file);
+    if let Err(e) = copy_buffered(&mut reader, &mut dst_file, opts.buffer_size) {
+        drop(dst_file);
+        set_aside_remote(dst_sftp, dst_path);
+        return Err(e.into());
+    }
+    drop(dst_file);
+
+    if opts.preserve {
+        copy_remote_attrs(dst_sftp, dst_path, &stat)?;
+    }
+
+    file_progress.finish("Copy complete");
+    Ok(())
+}
+
+// 把源文件的权限和时间戳设置到目标文件上，属主不同步
+fn copy_remote_attrs(sftp: &Sftp, path: &Path, stat: &FileStat) -> Result<()> {
+    let attrs = FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm: stat.perm.map(|perm| perm & 0o7777),
+        atime: stat.atime.or(stat.mtime),
+        mtime: stat.mtime,
+    };
+    sftp.setstat(path, attrs)
+        .context(format!("Failed to preserve attributes on {:?}", path))
+}
+
+fn upload_file(
+    conn: &SftpSession,
+    local_path: &Path,
+    remote_path: &Path,
+    opts: TransferOptions,
+    progress: &TransferProgress,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    let sftp = conn.sftp()?;
+    let mut local_file = fs::File::open(local_path)
+        .context(format!("Failed to open local file: {:?}", local_path))?;
+    let metadata = local_file.metadata()?;
+    let file_size = metadata.len();
+    let mtime = metadata.modified().ok().map(unix_secs);
+    let name = local_path.display().to_string();
+
+    let remote_size = sftp.stat(remote_path).ok().map(|stat| stat.size.unwrap_or(0));
+    if opts.resume
+        && remote_size == Some(file_size)
+        && manifest.is_some_and(|manifest| manifest.is_done(remote_path, file_size, mtime))
+    {
+        progress.println(&format!("Skipping {:?}: already transferred.", remote_path));
+        progress.skip_file(&name, file_size);
+        return Ok(());
+    }
+    let offset = match opts.resume {
+        true => resume_offset_remote(conn, local_path, remote_path, remote_size, file_size, progress)?,
+        false => 0,
+    };
+    if offset == 0
+        && let Some(remote_size) = remote_size
+    {
+        if (opts.checksum || opts.resume) && remote_size == file_size && same_content(conn, local_path, remote_path)? {
+            progress.println(&format!("Skipping {:?}: content is unchanged.", remote_path));
+            progress.skip_file(&name, file_size);
+            return record(manifest, remote_path, file_size, mtime);
+        }
+        match progress.suspend(|| opts.policy.resolve(remote_path))? {
+            ConflictAction::Skip => {
+                progress.println(&format!("Skipping {:?}: remote file already exists.", remote_path));
+                progress.skip_file(&name, file_size);
+                return Ok(());
+            }
+            ConflictAction::Backup => {
+                let backup_path = backup_path_for(remote_path);
+                let _ = sftp.unlink(&backup_path);
+                sftp.rename(remote_path, &backup_path, None)
+                    .context(format!("Failed to back up remote file {:?}", remote_path))?;
+                progress.println(&format!("Existing remote file backed up to {:?}", backup_path));
+            }
+            ConflictAction::Overwrite => {}
+        }
+    }
+
+    let file_progress = progress.start_file(&name, Some(file_size));
+    if offset == 0
+        && let Some(remote_size) = remote_size
+        && use_delta(opts, remote_size, file_size)
+        && delta_upload(conn, local_path, remote_path, remote_size, &file_progress, progress)?
+    {
+        if opts.preserve {
+            preserve_remote_attrs(sftp, remote_path, &metadata)?;
+        }
+        file_progress.finish("Upload complete");
+        return record(manifest, remote_path, file_size, mtime);
+    }
+    let mut remote_file = if offset > 0 {
+        progress.println(&format!("Resuming {:?} to {:?} at {}...", local_path, remote_path, remote_fs::format_size(offset)));
+        file_progress.skip_bytes(offset);
+        local_file.seek(SeekFrom::Start(offset))?;
+        let mut file = sftp.open_mode(remote_path, OpenFlags::WRITE, 0o644, OpenType::File)
+            .context(format!("Failed to open remote file: {:?}", remote_path))?;
+        file.seek(SeekFrom::Start(offset))?;
+        file
+    } else {
+        progress.println(&format!("Uploading {:?} to {:?}...", local_path, remote_path));
+        sftp.create(remote_path)
+            .context(format!("Failed to create remote file: {:?}", remote_path))?
+    };
+
+    let mut reader = file_progress.wrap_read(&mut local_file);
+    if let Err(e) = copy_buffered(&mut reader, &mut remote_file, opts.buffer_size) {
+        drop(remote_file);
+        set_aside_remote(sftp, remote_path);
+        return Err(e.into());
+    }
+    drop(remote_file);
+
+    if opts.preserve {
+        preserve_remote_attrs(sftp, remote_path, &metadata)?;
+    }
+
+    file_progress.finish("Upload complete");
+    record(manifest, remote_path, file_size, mtime)
+}
+
+fn download_file(
+    conn: &SftpSession,
+    remote_path: &Path,
+    local_path: &Path,
+    opts: TransferOptions,
+    progress: &TransferProgress,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    let sftp = conn.sftp()?;
+    let mut remote_file = sftp.open(remote_path)
+        .context(format!("Failed to open remote file: {:?}", remote_path))?;
+
+    let stat = remote_file.stat()?;
+    let file_size = stat.size.unwrap_or(0);
+    let name = remote_path.display().to_string();
+
+    let local_size = fs::metadata(local_path).ok().map(|meta| meta.len());
+    if opts.resume
+        && local_size == Some(file_size)
+        && manifest.is_some_and(|manifest| manifest.is_done(local_path, file_size, stat.mtime))
+    {
+        progress.println(&format!("Skipping {:?}: already transferred.", local_path));
+        progress.skip_file(&name, file_size);
+        return Ok(());
+    }
+    let offset = match opts.resume {
+        true => resume_offset_local(conn, remote_path, local_path, local_size, file_size, progress)?,
+        false => 0,
+    };
+    if offset == 0
+        && let Some(local_size) = local_size
+    {
+        if (opts.checksum || opts.resume) && local_size == file_size && same_content(conn, local_path, remote_path)? {
+            progress.println(&format!("Skipping {:?}: content is unchanged.", local_path));
+            progress.skip_file(&name, file_size);
+            return record(manifest, local_path, file_size, stat.mtime);
+        }
+        match progress.suspend(|| opts.policy.resolve(local_path))? {
+            ConflictAction::Skip => {
+                progress.println(&format!("Skipping {:?}: local file already exists.", local_path));
+                progress.skip_file(&name, file_size);
+                return Ok(());
+            }
+            ConflictAction::Backup => {
+                let backup_path = backup_path_for(local_path);
+                fs::rename(local_path, &backup_path)
+                    .context(format!("Failed to back up local file {:?}", local_path))?;
+                progress.println(&format!("Existing local file backed up to {:?}", backup_path));
+            }
+            ConflictAction::Overwrite => {}
+        }
+    }
+
+    let file_progress = progress.start_file(&name, Some(file_size));
+    if offset == 0
+        && let Some(local_size) = local_size
+        && use_delta(opts, local_size, file_size)
+        && delta_download(conn, &mut remote_file, remote_path, local_path, file_size, &file_progress, progress)?
+    {
+        if opts.preserve {
+            preserve_local_attrs(local_path, &stat)?;
+        }
+        file_progress.finish("Download complete");
+        return record(manifest, local_path, file_size, stat.mtime);
+    }
+    let mut local_file = if offset > 0 {
+        progress.println(&format!("Resuming {:?} to {:?} at {}...", remote_path, local_path, remote_fs::format_size(offset)));
+        file_progress.skip_bytes(offset);
+        remote_file.seek(SeekFrom::Start(offset))?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .open(local_path)
+            .context(format!("Failed to open local file: {:?}", local_path))?;
+        file.seek(SeekFrom::Start(offset))?;
+        file
+    } else {
+        progress.println(&format!("Downloading {:?} to {:?}...", remote_path, local_path));
+        fs::File::create(local_path)
+            .context(format!("Failed to create local file: {:?}", local_path))?
+    };
+
+    let mut reader = file_progress.wrap_read(&mut remote_file);
+    if let Err(e) = copy_buffered(&mut reader, &mut local_file, opts.buffer_size) {
+        drop(local_file);
+        set_aside_local(local_path);
+        return Err(e.into());
+    }
+    drop(local_file);
+
+    if opts.preserve {
+        preserve_local_attrs(local_path, &stat)?;
+    }
+
+    file_progress.finish("Download complete");
+    record(manifest, local_path, file_size, stat.mtime)
+}
+
+fn record(manifest: Option<&Manifest>, path: &Path, size: u64, mtime: Option<u64>) -> Result<()> {
+    match manifest {
+        Some(manifest) => manifest.record(path, size, mtime),
+        None => Ok(()),
+    }
+}
+
+// --continue 时找上次没传完的部分：中断留下的 .partial，或比源文件小的目标文件。
+// 它和源文件开头的内容一致才接着传，返回已有的字节数；对不上就从头传
+fn resume_offset_remote(
+    conn: &SftpSession,
+    local_path: &Path,
+    remote_path: &Path,
+    remote_size: Option<u64>,
+    file_size: u64,
+    progress: &TransferProgress,
+) -> Result<u64> {
+    let sftp = conn.sftp()?;
+    let partial = partial_path_for(remote_path);
+    let (candidate, size) = match sftp.stat(&partial) {
+        Ok(stat) if remote_size.is_none() => (partial.clone(), stat.size.unwrap_or(0)),
+        _ => match remote_size {
+            Some(size) if size < file_size => (remote_path.to_path_buf(), size),
+            _ => return Ok(0),
+        },
+    };
+    let local = fs::File::open(local_path).context(format!("Failed to open local file: {:?}", local_path))?;
+    let matches = size > 0
+        && size < file_size
+        && sha256_hex(local.take(size)).context(format!("Failed to hash {:?}", local_path))?
+            == remote_sha256(conn, &candidate, None)?;
+    if !matches {
+        if candidate == partial {
+            progress.println(&format!("Discarding {:?}: it doesn't match {:?}.", partial, local_path));
+            let _ = sftp.unlink(&partial);
+        }
+        return Ok(0);
+    }
+    if candidate == partial {
+        sftp.rename(&partial, remote_path, None)
+            .context(format!("Failed to rename {:?} to {:?}", partial, remote_path))?;
+    }
+    Ok(size)
+}
+
+fn resume_offset_local(
+    conn: &SftpSession,
+    remote_path: &Path,
+    local_path: &Path,
+    local_size: Option<u64>,
+    file_size: u64,
+    progress: &TransferProgress,
+) -> Result<u64> {
+    let partial = partial_path_for(local_path);
+    let (candidate, size) = match fs::metadata(&partial) {
+        Ok(meta) if local_size.is_none() => (partial.clone(), meta.len()),
+        _ => match local_size {
+            Some(size) if size < file_size => (local_path.to_path_buf(), size),
+            _ => return Ok(0),
+        },
+    };
+    let local = fs::File::open(&candidate).context(format!("Failed to open local file: {:?}", candidate))?;
+    let matches = size > 0
+        && size < file_size
+        && sha256_hex(local).context(format!("Failed to hash {:?}", candidate))?
+            == remote_sha256(conn, remote_path, Some(size))?;
+    if !matches {
+        if candidate == partial {
+            progress.println(&format!("Discarding {:?}: it doesn't match {:?}.", partial, remote_path));
+            let _ = fs::remove_file(&partial);
+        }
+        return Ok(0);
+    }
+    if candidate == partial {
+        fs::rename(&partial, local_path)
+            .context(format!("Failed to rename {:?} to {:?}", partial, local_path))?;
+    }
+    Ok(size)
+}
+
+// 目标的旧版本被 --backup 改名挪走了就没法做增量
+fn use_delta(opts: TransferOptions, existing_size: u64, file_size: u64) -> bool {
+    !opts.whole_file
+        && opts.policy != OverwritePolicy::Backup
+        && existing_size >= delta::MIN_SIZE
+        && file_size >= delta::MIN_SIZE
+}
+
+// 服务器上没有 cksum 或 sha256sum、签名读不懂时返回 None，照常整个传
+fn remote_signature(conn: &SftpSession, path: &Path, size: u64) -> Result<Option<Signature>> {
+    let block_size = delta::block_size_for(size);
+    let captured = ssh::exec_capture(&conn.sess, &delta::signature_command(path, block_size), &[])?;
+    let signature = match captured.status {
+        0 => delta::Signature::parse(&String::from_utf8_lossy(&captured.stdout), block_size),
+        127 => Err(anyhow!("cksum or sha256sum is not available on the server")),
+        code => Err(anyhow!("computing block signatures exited with status {}", code)),
+    };
+    match signature {
+        Ok(signature) => Ok(Some(signature)),
+        Err(e) => {
+            if output::is_verbose() {
+                eprintln!("Sending {:?} whole: {}", path, e);
+            }
+            Ok(None)
+        }
+    }
+}
+
+// 暂存增量数据的文件，和目标在同一个目录
+fn delta_path_for(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// 增量上传：服务器上的旧文件给出块签名，只把对不上的部分传过去，再在服务器上拼出新文件。
+// 没有可用的块时返回 false，由调用方整个传
+fn delta_upload(
+    conn: &SftpSession,
+    local_path: &Path,
+    remote_path: &Path,
+    remote_size: u64,
+    file_progress: &FileProgress,
+    progress: &TransferProgress,
+) -> Result<bool> {
+    let Some(signature) = remote_signature(conn, remote_path, remote_size)? else {
+        return Ok(false);
+    };
+    let local = fs::File::open(local_path).context(format!("Failed to open local file: {:?}", local_path))?;
+    let scan = delta::find_matches(local, &signature).context(format!("Failed to read {:?}", local_path))?;
+    if scan.matches.is_empty() {
+        return Ok(false);
+    }
+    let file_size = fs::metadata(local_path)?.len();
+    let block_size = signature.block_size;
+    let pieces = delta::plan(&scan.matches, file_size, block_size);
+    progress.println(&format!("Updating {:?} from {:?} (delta)...", remote_path, local_path));
+
+    // 新数据按块对齐写进暂存文件，中间的缺口是空洞，不经过网络
+    let sftp = conn.sftp()?;
+    let staged = delta_path_for(remote_path, ".rssh-delta-data");
+    let assembled = delta_path_for(remote_path, ".rssh-delta");
+    let sent = (|| -> Result<()> {
+        let mut local = fs::File::open(local_path)?;
+        let mut remote = sftp
+            .create(&staged)
+            .context(format!("Failed to create remote file: {:?}", staged))?;
+        for piece in &pieces {
+            if let Piece::Literal { offset, len, slot } = *piece {
+                local.seek(SeekFrom::Start(offset))?;
+                remote.seek(SeekFrom::Start(slot * block_size))?;
+                let mut reader = file_progress.wrap_read((&mut local).take(len));
+                copy_buffered(&mut reader, &mut remote, DEFAULT_BUFFER_SIZE)?;
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = sent {
+        let _ = sftp.unlink(&staged);
+        return Err(e);
+    }
+
+    let script = delta::assemble_script(remote_path, &staged, &assembled, &pieces, block_size, &scan.sha256);
+    let captured = ssh::exec_capture(&conn.sess, "sh", script.as_bytes())?;
+    match captured.status {
+        0 => {}
+        delta::MISMATCH => {
+            progress.println(&format!("The delta for {:?} didn't check out; sending the whole file.", remote_path));
+            return Ok(false);
+        }
+        code => {
+            let stderr = String::from_utf8_lossy(&captured.stderr);
+            return Err(anyhow!("Rebuilding {:?} on the server failed with exit status {}: {}", remote_path, code, stderr.trim()));
+        }
+    }
+    file_progress.skip_bytes(scan.matched_bytes(block_size));
+    report_delta(progress, remote_path, file_size - scan.matched_bytes(block_size), file_size);
+    Ok(true)
+}
+
+// 增量下载：服务器上的新文件给出块签名，本地旧文件里已有的块直接复制，其余部分从服务器读。
+// 拼好的文件校验通过后才替换本地文件
+fn delta_download(
+    conn: &SftpSession,
+    remote_file: &mut ssh2::File,
+    remote_path: &Path,
+    local_path: &Path,
+    file_size: u64,
+    file_progress: &FileProgress,
+    progress: &TransferProgress,
+) -> Result<bool> {
+    let Some(signature) = remote_signature(conn, remote_path, file_size)? else {
+        return Ok(false);
+    };
+    let local = fs::File::open(local_path).context(format!("Failed to open local file: {:?}", local_path))?;
+    let scan = delta::find_matches(local, &signature).context(format!("Failed to read {:?}", local_path))?;
+    if scan.matches.is_empty() {
+        return Ok(false);
+    }
+    let expected = remote_sha256(conn, remote_path, None)?;
+    progress.println(&format!("Updating {:?} from {:?} (delta)...", local_path, remote_path));
+
+    let block_size = signature.block_size;
+    let mut found = HashMap::new();
+    for m in &scan.matches {
+        found.entry(m.block as u64).or_insert(m.offset);
+    }
+    let assembled = delta_path_for(local_path, ".rssh-delta");
+    let rebuilt = (|| -> Result<bool> {
+        let mut old = fs::File::open(local_path)?;
+        let mut out = BufWriter::new(fs::File::create(&assembled)?);
+        let blocks = file_size.div_ceil(block_size);
+        let mut block = 0;
+        while block < blocks {
+            if let Some(&offset) = found.get(&block) {
+                old.seek(SeekFrom::Start(offset))?;
+                io::copy(&mut (&mut old).take(block_size), &mut out)?;
+                file_progress.skip_bytes(block_size);
+                block += 1;
+                continue;
+            }
+            let start = block;
+            while block < blocks && !found.contains_key(&block) {
+                block += 1;
+            }
+            let from = start * block_size;
+            remote_file.seek(SeekFrom::Start(from))?;
+            let mut reader = file_progress.wrap_read((&mut *remote_file).take((block * block_size).min(file_size) - from));
+            copy_buffered(&mut reader, &mut out, DEFAULT_BUFFER_SIZE)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        let actual = sha256_hex(fs::File::open(&assembled)?)?;
+        if actual != expected {
+            progress.println(&format!("The delta for {:?} didn't check out; downloading the whole file.", local_path));
+            return Ok(false);
+        }
+        fs::set_permissions(&assembled, fs::metadata(local_path)?.permissions())?;
+        fs::rename(&assembled, local_path).context(format!("Failed to replace {:?}", local_path))?;
+        Ok(true)
+    })();
+    if !matches!(rebuilt, Ok(true)) {
+        let _ = fs::remove_file(&assembled);
+    }
+    if matches!(rebuilt, Ok(true)) {
+        report_delta(progress, local_path, file_size - scan.matched_bytes(block_size).min(file_size), file_size);
+    }
+    rebuilt
+}
+
+fn report_delta(progress: &TransferProgress, path: &Path, sent: u64, file_size: u64) {
+    progress.println(&format!(
+        "{:?}: transferred {} of {}; the rest was already there.",
+        path,
+        remote_fs::format_size(sent),
+        remote_fs::format_size(file_size)
+    ));
+}
+
+// 大小相同时再比较 SHA-256
+fn same_content(conn: &SftpSession, local_path: &Path, remote_path: &Path) -> Result<bool> {
+    let local = fs::File::open(local_path).context(format!("Failed to open local file: {:?}", local_path))?;
+    let local = sha256_hex(local).context(format!("Failed to hash {:?}", local_path))?;
+    Ok(local == remote_sha256(conn, remote_path, None)?)
+}
+
+fn same_local_content(a: &Path, b: &Path) -> Result<bool> {
+    let hash = |path: &Path| -> Result<String> {
+        let file = fs::File::open(path).context(format!("Failed to open file: {:?}", path))?;
+        sha256_hex(file).context(format!("Failed to hash {:?}", path))
+    };
+    Ok(hash(a)? == hash(b)?)
+}
+
+// 优先在服务器上执行 sha256sum；没有这个命令时经 SFTP 读出内容在本地计算。limit 只算开头的这么多字节
+fn remote_sha256(conn: &SftpSession, path: &Path, limit: Option<u64>) -> Result<String> {
+    let quoted = shell_quote(&path.to_string_lossy());
+    let command = match limit {
+        Some(limit) => format!("head -c {} -- {} | sha256sum", limit, quoted),
+        None => format!("sha256sum -- {}", quoted),
+    };
+    if let Ok(captured) = ssh::exec_capture(&conn.sess, &command, &[])
+        && captured.status == 0
+        && let Some(hash) = String::from_utf8_lossy(&captured.stdout).split_whitespace().next()
+        && hash.len() == 64
+        && hash.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Ok(hash.to_ascii_lowercase());
+    }
+    let file = conn.sftp()?.open(path).context(format!("Failed to open remote file: {:?}", path))?;
+    sha256_hex(file.take(limit.unwrap_or(u64::MAX))).context(format!("Failed to hash remote file {:?}", path))
+}
+
+fn sha256_hex(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; DEFAULT_BUFFER_SIZE];
+    loop {
+        interrupt::check()?;
+        match reader.read(&mut buf)? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn local_copy_file(src_path: &Path, dst_path: &Path, opts: TransferOptions, progress: &TransferProgress) -> Result<()> {
+    let mut src_file = fs::File::open(src_path).context(format!("Failed to open file: {:?}", src_path))?;
+    let metadata = src_file.metadata()?;
+    let name = src_path.display().to_string();
+
+    if let Ok(dst_meta) = fs::metadata(dst_path) {
+        if opts.checksum && dst_meta.len() == metadata.len() && same_local_content(src_path, dst_path)? {
+            progress.println(&format!("Skipping {:?}: content is unchanged.", dst_path));
+            progress.skip_file(&name, metadata.len());
+            return Ok(());
+        }
+        match progress.suspend(|| opts.policy.resolve(dst_path))? {
+            ConflictAction::Skip => {
+                progress.println(&format!("Skipping {:?}: destination file already exists.", dst_path));
+                progress.skip_file(&name, metadata.len());
+                return Ok(());
+            }
+            ConflictAction::Backup => {
+                let backup_path = backup_path_for(dst_path);
+                fs::rename(dst_path, &backup_path)
+                    .context(format!("Failed to back up destination file {:?}", dst_path))?;
+                progress.println(&format!("Existing destination file backed up to {:?}", backup_path));
+            }
+            ConflictAction::Overwrite => {}
+        }
+    }
+
+    progress.println(&format!("Copying {:?} to {:?}...", src_path, dst_path));
+    let file_progress = progress.start_file(&name, Some(metadata.len()));
+    let mut dst_file = fs::File::create(dst_path).context(format!("Failed to create file: {:?}", dst_path))?;
+    let mut reader = file_progress.wrap_read(&mut src_file);
+    if let Err(e) = copy_buffered(&mut reader, &mut dst_file, opts.buffer_size) {
+        drop(dst_file);
+        set_aside_local(dst_path);
+        return Err(e.into());
+    }
+    drop(dst_file);
+
+    if opts.preserve {
+        preserve_metadata(dst_path, &metadata)?;
+    }
+    file_progress.finish("Copy complete");
+    Ok(())
+}
+
+// 列出本地目录下要上传的内容
+fn plan_upload_dir(local_dir: &Path, remote_dir: &Path, plan: &mut TransferPlan, walk: WalkOptions) -> Result<()> {
+    plan.dirs.push((local_dir.to_path_buf(), remote_dir.to_path_buf()));
+    if walk.one_file_system && plan.root_dev.is_none() {
+        plan.root_dev = fs::metadata(local_dir).ok().and_then(|metadata| device(&metadata));
+    }
+    let real_dir = match walk.symlinks {
+        SymlinkPolicy::Follow => fs::canonicalize(local_dir).ok(),
+        _ => None,
+    };
+    if let Some(real_dir) = &real_dir {
+        plan.ancestors.insert(real_dir.clone());
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(local_dir)
+        .context(format!("Failed to read local directory {:?}", local_dir))?
+        .collect::<io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        if !walk.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let local_path = entry.path();
+        let remote_path = remote_dir.join(entry.file_name());
+        let is_symlink = entry.file_type()?.is_symlink();
+        if is_symlink && walk.symlinks == SymlinkPolicy::Preserve {
+            let target = fs::read_link(&local_path).context(format!("Failed to read symlink {:?}", local_path))?;
+            plan.links.push((local_path, remote_path, target));
+            continue;
+        }
+        // 默认跟随指向文件的链接，但不进入指向目录的链接
+        let metadata = match fs::metadata(&local_path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                eprintln!("Warning: skipping broken symlink {:?}", local_path);
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            if is_symlink && walk.symlinks == SymlinkPolicy::FilesOnly {
+                eprintln!("Warning: skipping symlinked directory {:?} (use --follow-symlinks to copy it)", local_path);
+                continue;
+            }
+            if is_symlink
+                && let Ok(real) = fs::canonicalize(&local_path)
+                && plan.ancestors.contains(&real)
+            {
+                eprintln!("Warning: skipping {:?}: it links back to a directory being copied", local_path);
+                continue;
+            }
+            if plan.root_dev.is_some() && device(&metadata) != plan.root_dev {
+                eprintln!("Warning: skipping {:?}: it is on another filesystem", local_path);
+                continue;
+            }
+            plan_upload_dir(&local_path, &remote_path, plan, walk)?;
+        } else if metadata.is_file() {
+            plan.files.push((local_path, remote_path, metadata.len()));
+        }
+    }
+    if let Some(real_dir) = &real_dir {
+        plan.ancestors.remove(real_dir);
+    }
+    Ok(())
+}
+
+// 列出远程目录下要下载的内容
+fn plan_download_dir(sftp: &Sftp, remote_dir: &Path, local_dir: &Path, plan: &mut TransferPlan, walk: WalkOptions) -> Result<()> {
+    plan.dirs.push((remote_dir.to_path_buf(), local_dir.to_path_buf()));
+    let real_dir = match walk.symlinks {
+        SymlinkPolicy::Follow => sftp.realpath(remote_dir).ok(),
+        _ => None,
+    };
+    if let Some(real_dir) = &real_dir {
+        plan.ancestors.insert(real_dir.clone());
+    }
+
+    let mut entries = sftp
+        .readdir(remote_dir)
+        .context(format!("Failed to read remote directory {:?}", remote_dir))?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (remote_path, entry_stat) in entries {
+        let file_name = match remote_path.file_name() {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        if !walk.hidden && file_name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let local_path = local_dir.join(file_name);
+        let is_symlink = entry_stat.file_type().is_symlink();
+        if is_symlink && walk.symlinks == SymlinkPolicy::Preserve {
+            let target = sftp
+                .readlink(&remote_path)
+                .context(format!("Failed to read symlink {:?}", remote_path))?;
+            plan.links.push((remote_path, local_path, target));
+            continue;
+        }
+        let stat = if is_symlink {
+            match sftp.stat(&remote_path) {
+                Ok(stat) => stat,
+                Err(_) => {
+                    eprintln!("Warning: skipping broken symlink {:?}", remote_path);
+                    continue;
+                }
+            }
+        } else {
+            entry_stat
+        };
+        if stat.is_dir() {
+            if is_symlink && walk.symlinks == SymlinkPolicy::FilesOnly {
+                eprintln!("Warning: skipping symlinked directory {:?} (use --follow-symlinks to copy it)", remote_path);
+                continue;
+            }
+            if is_symlink
+                && let Ok(real) = sftp.realpath(&remote_path)
+                && plan.ancestors.contains(&real)
+            {
+                eprintln!("Warning: skipping {:?}: it links back to a directory being copied", remote_path);
+                continue;
+            }
+            if let Some(same_fs) = &plan.same_fs
+                && !same_fs.contains(&remote_path)
+            {
+                eprintln!("Warning: skipping {:?}: it is on another filesystem", remote_path);
+                continue;
+            }
+            plan_download_dir(sftp, &remote_path, &local_path, plan, walk)?;
+        } else if stat.is_file() {
+            plan.files.push((remote_path, local_path, stat.size.unwrap_or(0)));
+        }
+    }
+    if let Some(real_dir) = &real_dir {
+        plan.ancestors.remove(real_dir);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+// SFTP 看不到设备号，在服务器上用 find -xdev 列出和根目录在同一文件系统上的目录；
+// 挂载点本身也会被列出，所以再比较一次 stat 的设备号
+fn remote_same_fs(sess: &Session, root: &Path) -> Result<HashSet<PathBuf>> {
+    let command = format!(
+        "find {} -xdev -type d -exec stat -c '%d %n' {{}} +",
+        shell_quote(&root.to_string_lossy())
+    );
+    let captured = ssh::exec_capture(sess, &command, &[])?;
+    if captured.status != 0 {
+        return Err(anyhow!(
+            "--one-file-system needs 'find' and 'stat' on the server: {}",
+            String::from_utf8_lossy(&captured.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&captured.stdout);
+    let mut lines = stdout.lines().filter_map(|line| line.split_once(' '));
+    let Some((root_dev, _)) = lines.next() else {
+        return Ok(HashSet::new());
+    };
+    Ok(lines
+        .filter(|(dev, _)| *dev == root_dev)
+        .map(|(_, path)| PathBuf::from(path))
+        .collect())
+}
+
+// 目标处已是同样的链接时不动；是别的链接时替换；是普通文件或目录时跳过
+fn make_local_link(target: &Path, path: &Path, progress: &TransferProgress) -> Result<()> {
+    match fs::read_link(path) {
+        Ok(existing) if existing == target => return Ok(()),
+        Ok(_) => fs::remove_file(path).context(format!("Failed to replace symlink {:?}", path))?,
+        Err(_) if fs::symlink_metadata(path).is_ok() => {
+            progress.println(&format!("Skipping link {:?}: a file or directory is already there.", path));
+            return Ok(());
+        }
+        Err(_) => {}
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, path).context(format!("Failed to create symlink {:?}", path))?;
+    #[cfg(not(unix))]
+    progress.println(&format!("Skipping link {:?}: symlinks are not supported here.", path));
+    Ok(())
+}
+
+// 用 ln -s 创建：OpenSSH 的 sftp-server 把 SFTP symlink 请求的两个参数弄反了
+fn make_remote_link(sess: &Session, sftp: &Sftp, target: &Path, path: &Path, progress: &TransferProgress) -> Result<()> {
+    match sftp.readlink(path) {
+        Ok(existing) if existing == target => return Ok(()),
+        Ok(_) => {}
+        Err(_) if sftp.lstat(path).is_ok() => {
+            progress.println(&format!("Skipping link {:?}: a file or directory is already there.", path));
+            return Ok(());
+        }
+        Err(_) => {}
+    }
+    let command = format!(
+        "ln -sfn -- {} {}",
+        shell_quote(&target.to_string_lossy()),
+        shell_quote(&path.to_string_lossy())
+    );
+    match exec_status(sess, &command)? {
+        0 => Ok(()),
+        code => Err(anyhow!("Failed to create symlink {:?} (ln exited with status {})", path, code)),
+    }
+}
+
+/// 上传前检查目标所在文件系统的剩余空间，免得传到一大半才失败；--skip-space-check 时不检查。
+/// 远程没有 df（或输出看不懂）时也不检查
+fn check_remote_space(conn: &SftpSession, remote_dir: &Path, needed: u64, opts: TransferOptions) -> Result<()> {
+    if needed < SPACE_CHECK_MIN || opts.skip_space_check {
+        return Ok(());
+    }
+    let Some(available) = remote_available(conn, remote_dir)? else {
+        if output::is_verbose() {
+            eprintln!("Could not check the free space on the remote side; uploading anyway.");
+        }
+        return Ok(());
+    };
+    if needed > available {
+        return Err(anyhow!(
+            "Not enough space on the remote side for {:?}: the upload needs {} but only {} is free there. Free some space, or pass --skip-space-check to try anyway.",
+            remote_dir,
+            remote_fs::format_size(needed),
+            remote_fs::format_size(available)
+        ));
+    }
+    if needed > available / 10 * 9 {
+        eprintln!(
+            "Warning: the upload ({}) will nearly fill the remote filesystem of {:?} ({} free).",
+            remote_fs::format_size(needed),
+            remote_dir,
+            remote_fs::format_size(available)
+        );
+    }
+    Ok(())
+}
+
+// 目标目录可能还不存在，查最近的已存在的上级目录；df -P 的第二行第四列是可用的 KiB
+fn remote_available(conn: &SftpSession, remote_dir: &Path) -> Result<Option<u64>> {
+    let sftp = conn.sftp()?;
+    let mut dir = remote_dir;
+    while sftp.stat(dir).is_err() {
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => dir = parent,
+            _ => {
+                dir = Path::new(".");
+                break;
+            }
+        }
+    }
+    let command = format!("df -Pk -- {}", shell_quote(&dir.to_string_lossy()));
+    let Ok(captured) = ssh::exec_capture(&conn.sess, &command, &[]) else {
+        return Ok(None);
+    };
+    if captured.status != 0 {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&captured.stdout);
+    Ok(stdout
+        .lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|kib| kib.parse::<u64>().ok())
+        .map(|kib| kib * 1024))
+}
+
+fn remote_has_tar(sess: &Session) -> bool {
+    matches!(exec_status(sess, "command -v tar >/dev/null 2>&1"), Ok(0))
+}
+
+// tar 模式不逐个文件处理冲突，目标已存在时只有 --force 才继续
+fn check_tar_policy(destination: &Path, exists: bool, opts: TransferOptions) -> Result<()> {
+    if !exists || opts.policy == OverwritePolicy::Force {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{:?} already exists and --tar overwrites files in it without asking. Pass --force to overwrite, or drop --tar to use --no-clobber, --backup or the per-file prompt.",
+        destination
+    ))
+}
+
+// 本地打包成 tar 流，通过 exec 通道交给远程 `tar -x` 解包
+fn tar_upload(sess: &Session, local_dir: &Path, remote_dir: &Path, opts: TransferOptions) -> Result<TransferStats> {
+    let dir_name = local_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid local directory {:?}", local_dir))?;
+    let remote_dir_str = remote_dir.to_string_lossy();
+    let command = format!(
+        "mkdir -p {dir} && tar -x{p}f - -C {dir}",
+        dir = shell_quote(&remote_dir_str),
+        p = if opts.preserve { "p" } else { "" }
+    );
+
+    let total = dir_size(local_dir);
+    let progress = TransferProgress::new(opts.progress, 1, total);
+    progress.println(&format!("Uploading {:?} to {:?} as a tar stream...", local_dir, remote_dir));
+    let file_progress = progress.start_file(&local_dir.display().to_string(), Some(total));
+
+    let mut channel = ssh::bulk_channel(sess)?;
+    channel.exec(&command)?;
+    let mut stream = TarStream::new(sess, &mut channel);
+    let written = (|| -> Result<()> {
+        let writer = BufWriter::with_capacity(opts.buffer_size, file_progress.wrap_write(&mut stream));
+        let mut builder = tar::Builder::new(writer);
+        builder.follow_symlinks(false);
+        builder
+            .append_dir_all(dir_name, local_dir)
+            .context(format!("Failed to archive {:?}", local_dir))?;
+        builder.into_inner()?.flush()?;
+        Ok(())
+    })();
+    let stderr = stream.finish();
+    written?;
+    channel.send_eof()?;
+    finish_tar_channel(&mut channel, stderr)?;
+
+    file_progress.finish("Upload complete");
+    Ok(progress.finish())
+}
+
+// 远程 `tar -c` 打包，本地解包
+fn tar_download(sess: &Session, remote_dir: &Path, local_dir: &Path, opts: TransferOptions) -> Result<TransferStats> {
+    let parent = remote_dir
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = remote_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid remote directory {:?}", remote_dir))?;
+    let command = format!(
+        "tar -cf - -C {} {}",
+        shell_quote(&parent.to_string_lossy()),
+        shell_quote(&name.to_string_lossy())
+    );
+
+    let progress = TransferProgress::new(opts.progress, 1, 0);
+    progress.println(&format!("Downloading {:?} to {:?} as a tar stream...", remote_dir, local_dir));
+    // 远程目录大小未知，只显示已传输字节数
+    let file_progress = progress.start_file(&remote_dir.display().to_string(), None);
+
+    let mut channel = ssh::bulk_channel(sess)?;
+    channel.exec(&command)?;
+    let mut stream = TarStream::new(sess, &mut channel);
+    let unpacked = {
+        let reader = BufReader::with_capacity(opts.buffer_size, file_progress.wrap_read(&mut stream));
+        let mut archive = tar::Archive::new(reader);
+        archive.set_preserve_permissions(opts.preserve);
+        archive.set_preserve_mtime(true);
+        archive
+            .unpack(local_dir)
+            .context(format!("Failed to unpack archive into {:?}", local_dir))
+    };
+    let stderr = stream.finish();
+    unpacked?;
+    finish_tar_channel(&mut channel, stderr)?;
+
+    file_progress.finish("Download complete");
+    Ok(progress.finish())
+}
+
+// 等远程 tar 结束；stderr 在传输过程中已经读出来了，这里只收尾
+fn finish_tar_channel(channel: &mut ssh2::Channel, mut stderr: Vec<u8>) -> Result<()> {
+    channel.stderr().read_to_end(&mut stderr)?;
+    channel.wait_close()?;
+    let status = channel.exit_status()?;
+    if status != 0 {
+        return Err(anyhow!("Remote tar exited with status {}: {}", status, String::from_utf8_lossy(&stderr).trim()));
+    }
+    Ok(())
+}
+
+/// tar 流的读写端：会话切到非阻塞，每次读写前先把 stderr 读空，
+/// 远程 tar 报错很多时也不会因为窗口被 stderr 占满而卡住
+struct TarStream<'a> {
+    sess: &'a Session,
+    channel: &'a mut ssh2::Channel,
+    stderr: Vec<u8>,
+}
+
+impl<'a> TarStream<'a> {
+    fn new(sess: &'a Session, channel: &'a mut ssh2::Channel) -> Self {
+        sess.set_blocking(false);
+        TarStream { sess, channel, stderr: Vec::new() }
+    }
+
+    fn drain_stderr(&mut self) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.channel.stderr().read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.stderr.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.sess.set_blocking(true);
+        self.stderr
+    }
+}
+
+impl Read for TarStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.drain_stderr()?;
+            match self.channel.read(buf) {
+                // 只有 stderr 有数据时 stdout 也会读到 0，要看 eof 才知道是否真的结束了
+                Ok(0) if !self.channel.eof() => {}
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            input::wait_session(self.sess, None)?;
+        }
+    }
+}
+
+impl Write for TarStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            self.drain_stderr()?;
+            match self.channel.write(buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            input::wait_session(self.sess, None)?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            match self.channel.flush() {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            self.drain_stderr()?;
+            input::wait_session(self.sess, None)?;
+        }
+    }
+}
+
+/// 按文件名选出解压命令，不认识的格式报错
+pub fn archive_command(path: &Path) -> Result<&'static str> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    ARCHIVE_COMMANDS
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|&(_, command)| command)
+        .ok_or_else(|| anyhow!("--extract can't unpack {:?}; it handles .tar.gz, .tgz, .tar.bz2, .tar.xz, .tar.zst, .tar and .zip files.", path))
+}
+
+/// 在远程目录里解压刚上传的压缩包，文件名和本地的相同
+pub fn extract_remote(sess: &Session, local_path: &Path, remote_dir: &Path, extract: Extract) -> Result<()> {
+    let tool = archive_command(local_path)?;
+    let name = local_path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = remote_dir.to_string_lossy();
+    let mut command = format!("cd {} && {} {}", shell_quote(&dir), tool, shell_quote(&name));
+    if extract.remove_archive {
+        command.push_str(&format!(" && rm -f -- {}", shell_quote(&name)));
+    }
+    let captured = ssh::exec_capture(sess, &command, b"")?;
+    match captured.status {
+        0 => {}
+        127 => {
+            let program = tool.split(' ').next().unwrap_or(tool);
+            return Err(anyhow!("'{}' was not found on the server; install it there to use --extract.", program));
+        }
+        code => {
+            let stderr = String::from_utf8_lossy(&captured.stderr);
+            return Err(anyhow!("Unpacking {} in {} failed with exit status {}: {}", name, dir, code, stderr.trim()));
+        }
+    }
+    match extract.remove_archive {
+        true => output::status(format!("Unpacked {} in {} and removed the archive", name, dir)),
+        false => output::status(format!("Unpacked {} in {}", name, dir)),
+    }
+    output::event("extracted", json!({ "archive": remote_dir.join(&*name), "directory": remote_dir, "removed": extract.remove_archive }));
+    Ok(())
+}
+
+/// append：把本地文件（`-` 为标准输入）接到远程文件末尾，给出 `offset` 时从该位置覆盖写入；
+/// 都不会截断文件的其余部分，远程文件不存在时创建
+pub fn handle_append(sess: &Session, local_path: &Path, remote_path: &Path, offset: Option<u64>) -> Result<TransferStats> {
+    let sftp = sess.sftp().context("Failed to create SFTP session")?;
+    let mut reader: Box<dyn Read> = match local_path == Path::new("-") {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(fs::File::open(local_path).context(format!("Failed to open local file: {:?}", local_path))?),
+    };
+    let flags = match offset {
+        Some(_) => OpenFlags::WRITE | OpenFlags::CREATE,
+        None => OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::APPEND,
+    };
+    let mut remote_file = sftp
+        .open_mode(remote_path, flags, 0o644, OpenType::File)
+        .context(format!("Failed to open remote file: {:?}", remote_path))?;
+    // 有的服务器忽略 APPEND，追加时也显式定位到末尾
+    let start = match offset {
+        Some(offset) => offset,
+        None => remote_file.stat()?.size.unwrap_or(0),
+    };
+    remote_file.seek(SeekFrom::Start(start))?;
+    // 标准输入每读到一块就写出去，持续的日志流也能边读边发
+    let bytes = copy_buffered(&mut reader, &mut remote_file, DEFAULT_BUFFER_SIZE)
+        .context(format!("Failed to write to remote file: {:?}", remote_path))?;
+    drop(remote_file);
+
+    match offset {
+        Some(offset) => output::status(format!("Wrote {} to {:?} at offset {}", remote_fs::format_size(bytes), remote_path, offset)),
+        None => output::status(format!("Appended {} to {:?}", remote_fs::format_size(bytes), remote_path)),
+    }
+    output::event("appended", json!({ "path": remote_path, "offset": start, "bytes": bytes }));
+    Ok(TransferStats { bytes, files: 1, skipped: 0 })
+}
+
+/// 和 io::copy 相同，但使用指定大小的缓冲区
+pub fn copy_buffered<R: Read + ?Sized, W: Write + ?Sized>(reader: &mut R, writer: &mut W, buffer_size: usize) -> io::Result<u64> {
+    let mut buf = vec![0u8; buffer_size.max(1)];
+    let mut total = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buf[..n])?;
+        total += n as u64;
+    }
+    writer.flush()?;
+    Ok(total)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+static PROMPT: Mutex<()> = Mutex::new(());
+
+// 更小的上传不检查剩余空间，空间不够时很快就会失败
+const SPACE_CHECK_MIN: u64 = 1024 * 1024;
+
+/// 目标文件已存在时的处理策略
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    Prompt,
+    Force,
+    NoClobber,
+    Backup,
+}
+
+enum ConflictAction {
+    Overwrite,
+    Skip,
+    Backup,
+}
+
+impl OverwritePolicy {
+    pub fn from_flags(force: bool, no_clobber: bool, backup: bool) -> Self {
+        if no_clobber {
+            OverwritePolicy::NoClobber
+        } else if backup {
+            OverwritePolicy::Backup
+        } else if force {
+            OverwritePolicy::Force
+        } else {
+            OverwritePolicy::Prompt
+        }
+    }
+
+    fn resolve(self, existing: &Path) -> Result<ConflictAction> {
+        Ok(match self {
+            OverwritePolicy::Force => ConflictAction::Overwrite,
+            OverwritePolicy::NoClobber => ConflictAction::Skip,
+            OverwritePolicy::Backup => ConflictAction::Backup,
+            OverwritePolicy::Prompt => {
+                if !prompt::can_ask() {
+                    return Err(prompt::unavailable(format!(
+                        "{:?} already exists; use --force, --no-clobber or --backup when not running interactively",
+                        existing
+                    )));
+                }
+                // 并发传输时一次只问一个问题
+                let _prompt = PROMPT.lock().unwrap_or_else(|e| e.into_inner());
+                let overwrite = Confirm::new(&format!("{:?} already exists. Overwrite?", existing))
+                    .with_default(false)
+                    .prompt()
+                    .context("Destination exists; use --force, --no-clobber or --backup when not running interactively")?;
+                if overwrite {
+                    ConflictAction::Overwrite
+                } else {
+                    ConflictAction::Skip
+                }
+            }
+        })
+    }
+}
+
+fn backup_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn partial_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+// 传输中断或出错时，把写了一半的文件改名为 .partial，不和完整的文件混在一起
+fn set_aside_remote(sftp: &Sftp, path: &Path) {
+    let partial = partial_path_for(path);
+    let _ = sftp.unlink(&partial);
+    match sftp.rename(path, &partial, None) {
+        Ok(()) => eprintln!("Incomplete remote file kept as {:?}", partial),
+        Err(e) => eprintln!("Warning: could not rename the incomplete remote file {:?}: {}", path, e),
+    }
+}
+
+fn set_aside_local(path: &Path) {
+    let partial = partial_path_for(path);
+    match fs::rename(path, &partial) {
+        Ok(()) => eprintln!("Incomplete local file kept as {:?}", partial),
+        Err(e) => eprintln!("Warning: could not rename the incomplete local file {:?}: {}", path, e),
+    }
+}
+
+// 类似 `scp -p`：把本地文件的权限和时间戳同步到远程文件
+fn preserve_remote_attrs(sftp: &Sftp, remote_path: &Path, metadata: &fs::Metadata) -> Result<()> {
+    let mtime = unix_secs(metadata.modified()?);
+    let atime = metadata.accessed().map(unix_secs).unwrap_or(mtime);
+
+    #[cfg(unix)]
+    let (perm, uid, gid) = {
+        use std::os::unix::fs::MetadataExt;
+        (Some(metadata.mode() & 0o7777), Some(metadata.uid()), Some(metadata.gid()))
+    };
+    #[cfg(not(unix))]
+    let (perm, uid, gid) = (None, None, None);
+
+    let stat = FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm,
+        atime: Some(atime),
+        mtime: Some(mtime),
+    };
+    sftp.setstat(remote_path, stat)
+        .context(format!("Failed to preserve attributes on {:?}", remote_path))?;
+
+    // 属主通常只有 root 才能修改，失败时只给出警告
+    if uid.is_some() || gid.is_some() {
+        let owner = FileStat {
+            size: None,
+            uid,
+            gid,
+            perm: None,
+            atime: None,
+            mtime: None,
+        };
+        if sftp.setstat(remote_path, owner).is_err() {
+            eprintln!(
+                "Warning: could not preserve ownership of {:?}; permissions and times were kept.",
+                remote_path
+            );
+        }
+    }
+    Ok(())
+}
+
+// 本机复制：同步权限和时间戳，属主不变
+fn preserve_metadata(path: &Path, metadata: &fs::Metadata) -> Result<()> {
+    // 和 preserve_local_attrs 一样先设时间，复制过来的权限可能没有读权限
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    fs::File::open(path)
+        .and_then(|f| f.set_times(times))
+        .context(format!("Failed to preserve timestamps on {:?}", path))?;
+    fs::set_permissions(path, metadata.permissions())
+        .context(format!("Failed to preserve permissions on {:?}", path))
+}
+
+// 把远程文件的权限和时间戳同步到本地文件
+fn preserve_local_attrs(local_path: &Path, stat: &FileStat) -> Result<()> {
+    // 目录不能以写方式打开，只读句柄也能设置时间；先设时间再改权限，免得权限去掉了读
+    if let Some(mtime) = stat.mtime {
+        let to_time = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        let times = fs::FileTimes::new()
+            .set_modified(to_time(mtime))
+            .set_accessed(to_time(stat.atime.unwrap_or(mtime)));
+        fs::File::open(local_path)
+            .and_then(|f| f.set_times(times))
+            .context(format!("Failed to preserve timestamps on {:?}", local_path))?;
+    }
+
+    #[cfg(unix)]
+    if let Some(perm) = stat.perm {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(local_path, fs::Permissions::from_mode(perm & 0o7777))
+            .context(format!("Failed to preserve permissions on {:?}", local_path))?;
+    }
+
+    #[cfg(unix)]
+    if (stat.uid.is_some() || stat.gid.is_some())
+        && std::os::unix::fs::chown(local_path, stat.uid, stat.gid).is_err()
+    {
+        eprintln!(
+            "Warning: could not preserve ownership of {:?}; permissions and times were kept.",
+            local_path
+        );
+    }
+    Ok(())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rssh-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn remote_stat(perm: u32, mtime: u64) -> FileStat {
+        FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: Some(perm),
+            atime: Some(mtime),
+            mtime: Some(mtime),
+        }
+    }
+
+    // 和 download --recursive --preserve 一样，先处理子目录再处理上级目录
+    #[test]
+    fn preserve_local_attrs_on_directories() {
+        let root = scratch_dir("preserve");
+        let sub = root.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join("file"), b"data").unwrap();
+
+        preserve_local_attrs(&sub.join("file"), &remote_stat(0o600, 1_000_000)).unwrap();
+        for dir in [&sub, &root] {
+            preserve_local_attrs(dir, &remote_stat(0o750, 2_000_000)).unwrap();
+        }
+
+        for (path, mtime) in [(sub.join("file"), 1_000_000), (sub.clone(), 2_000_000), (root.clone(), 2_000_000)] {
+            let metadata = fs::metadata(&path).unwrap();
+            assert_eq!(unix_secs(metadata.modified().unwrap()), mtime, "{:?}", path);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&sub).unwrap().permissions().mode() & 0o7777, 0o750);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn preserve_local_attrs_on_unreadable_file() {
+        let root = scratch_dir("preserve-mode");
+        let file = root.join("secret");
+        fs::write(&file, b"data").unwrap();
+        preserve_local_attrs(&file, &remote_stat(0o200, 1_000_000)).unwrap();
+        assert_eq!(unix_secs(fs::metadata(&file).unwrap().modified().unwrap()), 1_000_000);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserve_metadata_on_write_only_directory() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = scratch_dir("preserve-copy");
+        let (from, to) = (root.join("from"), root.join("to"));
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&to).unwrap();
+        fs::File::open(&from)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000)))
+            .unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o300)).unwrap();
+
+        preserve_metadata(&to, &fs::metadata(&from).unwrap()).unwrap();
+        let metadata = fs::metadata(&to).unwrap();
+        assert_eq!(unix_secs(metadata.modified().unwrap()), 1_000_000);
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o300);
+        for dir in [&from, &to] {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn archive_command_picks_the_tool_by_suffix() {
+        let cases = [
+            ("site.tar.gz", "tar -xzf"),
+            ("site.tgz", "tar -xzf"),
+            ("site.tar.bz2", "tar -xjf"),
+            ("site.tbz2", "tar -xjf"),
+            ("site.tar.xz", "tar -xJf"),
+            ("site.txz", "tar -xJf"),
+            ("site.tar.zst", "tar --zstd -xf"),
+            ("site.tar", "tar -xf"),
+            ("site.zip", "unzip -oq"),
+            // 扩展名不区分大小写，目录部分不影响
+            ("/tmp/build/Site-1.2.TAR.GZ", "tar -xzf"),
+            ("release.v2.Zip", "unzip -oq"),
+        ];
+        for (name, command) in cases {
+            assert_eq!(archive_command(Path::new(name)).unwrap(), command, "{}", name);
+        }
+    }
+
+    #[test]
+    fn archive_command_rejects_other_files() {
+        for name in ["notes.txt", "dump.gz", "site.rar", "tar", "site.tar.gz.part", "zip", ""] {
+            assert!(archive_command(Path::new(name)).is_err(), "{:?} should be rejected", name);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::output;
+use crate::prompt;
+use crate::remote_fs;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use inquire::Confirm;
+use serde_json::json;
+use ssh2::Sftp;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// 回收站在远程主目录下；每次删除占一个以时间命名的子目录
+const TRASH_DIR: &str = ".rssh-trash";
+// 子目录里记录原来位置的文件
+const ORIGIN_FILE: &str = ".rssh-origin";
+
+/// 回收站里的一项
+struct Entry {
+    id: String,
+    original: PathBuf,
+    item: PathBuf,
+    is_dir: bool,
+}
+
+fn trash_root(sftp: &Sftp) -> Result<PathBuf> {
+    let home = sftp.realpath(Path::new(".")).context("Failed to find the remote home directory")?;
+    Ok(home.join(TRASH_DIR))
+}
+
+// 只解析所在目录，path 本身是符号链接时移走的是链接
+fn absolute(sftp: &Sftp, path: &Path) -> Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| anyhow!("Invalid remote path {:?}", path))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = sftp
+        .realpath(parent)
+        .context(format!("Failed to resolve remote path {:?}", path))?;
+    Ok(parent.join(name))
+}
+
+/// rm --trash：把文件或目录移进回收站，而不是删除
+pub fn handle_rm(sftp: &Sftp, alias: &str, path: &Path, recursive: bool) -> Result<()> {
+    let stat = sftp
+        .lstat(path)
+        .context(format!("Failed to stat remote path {:?}", path))?;
+    if stat.is_dir() && !recursive {
+        return Err(anyhow!(
+            "Remote path {:?} is a directory. Use --recursive to remove it.",
+            path
+        ));
+    }
+    let original = absolute(sftp, path)?;
+    let root = trash_root(sftp)?;
+    if original.starts_with(&root) {
+        return Err(anyhow!(
+            "{:?} is already in the trash. Use 'rssh trash empty {}' to delete it.",
+            path,
+            alias
+        ));
+    }
+
+    let id = new_id(sftp, &root);
+    let batch = root.join(&id);
+    remote_fs::create_dir_all(sftp, &batch)?;
+    let moved = write_origin(sftp, &batch, &original).and_then(|_| {
+        let target = batch.join(original.file_name().unwrap_or_default());
+        sftp.rename(&original, &target, None).context(format!(
+            "Failed to move {:?} to the trash (it may be on another filesystem; remove it without --trash instead)",
+            original
+        ))
+    });
+    if let Err(e) = moved {
+        let _ = remove_batch(sftp, &batch);
+        return Err(e);
+    }
+    output::status(format!(
+        "Moved {:?} to the trash. Restore it with 'rssh trash restore {} {}'.",
+        original, alias, id
+    ));
+    output::event("trashed", json!({ "path": original, "id": id }));
+    Ok(())
+}
+
+pub fn handle_list(sftp: &Sftp, alias: &str) -> Result<()> {
+    let entries = entries(sftp)?;
+    if output::is_json() {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| json!({ "id": entry.id, "path": entry.original, "dir": entry.is_dir }))
+            .collect();
+        output::emit("trash", json!({ "alias": alias, "entries": entries }));
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("The trash on '{}' is empty.", alias);
+        return Ok(());
+    }
+    println!("{:<17}  PATH", "ID");
+    for entry in &entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        println!("{:<17}  {}{}", entry.id, entry.original.display(), suffix);
+    }
+    Ok(())
+}
+
+/// 放回原来的位置；那里已有同名文件时不覆盖
+pub fn handle_restore(sftp: &Sftp, alias: &str, id: &str) -> Result<()> {
+    let entry = entries(sftp)?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| anyhow!("No trash entry '{}' on '{}'. See 'rssh trash list {}'.", id, alias, alias))?;
+    if sftp.lstat(&entry.original).is_ok() {
+        return Err(anyhow!(
+            "{:?} already exists on '{}'. Move it away first, then restore again.",
+            entry.original,
+            alias
+        ));
+    }
+    if let Some(parent) = entry.original.parent() {
+        remote_fs::create_dir_all(sftp, parent)?;
+    }
+    sftp.rename(&entry.item, &entry.original, None)
+        .context(format!("Failed to restore {:?}", entry.original))?;
+    remove_batch(sftp, &trash_root(sftp)?.join(&entry.id))?;
+    output::status(format!("Restored {:?}.", entry.original));
+    output::event("restored", json!({ "path": entry.original, "id": entry.id }));
+    Ok(())
+}
+
+pub fn handle_empty(sftp: &Sftp, alias: &str, yes: bool) -> Result<()> {
+    let entries = entries(sftp)?;
+    if entries.is_empty() {
+        output::status(format!("The trash on '{}' is already empty.", alias));
+        output::event("trash_emptied", json!({ "alias": alias, "deleted": 0 }));
+        return Ok(());
+    }
+    if !yes {
+        if !prompt::can_ask() {
+            return Err(prompt::unavailable("Refusing to empty the trash without --yes."));
+        }
+        let question = format!("Permanently delete {} item(s) in the trash on '{}'?", entries.len(), alias);
+        if !Confirm::new(&question).with_default(false).prompt()? {
+            return Ok(());
+        }
+    }
+    remote_fs::remove_dir_all(sftp, &trash_root(sftp)?)?;
+    output::status(format!("Deleted {} item(s) from the trash on '{}'.", entries.len(), alias));
+    output::event("trash_emptied", json!({ "alias": alias, "deleted": entries.len() }));
+    Ok(())
+}
+
+// 按时间排序；没有记录原位置的目录不是 rssh 放进去的，忽略
+fn entries(sftp: &Sftp) -> Result<Vec<Entry>> {
+    let root = trash_root(sftp)?;
+    let Ok(batches) = sftp.readdir(&root) else {
+        return Ok(Vec::new());
+    };
+    let mut entries = Vec::new();
+    for (batch, stat) in batches {
+        if !stat.is_dir() {
+            continue;
+        }
+        let Some(original) = read_origin(sftp, &batch) else { continue };
+        let item = batch.join(original.file_name().unwrap_or_default());
+        let Ok(item_stat) = sftp.lstat(&item) else { continue };
+        entries.push(Entry {
+            id: batch.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            original,
+            item,
+            is_dir: item_stat.is_dir(),
+        });
+    }
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(entries)
+}
+
+// 同一秒内多次删除时加上 -2、-3 区分
+fn new_id(sftp: &Sftp, root: &Path) -> String {
+    let base = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut id = base.clone();
+    let mut n = 1;
+    while sftp.lstat(&root.join(&id)).is_ok() {
+        n += 1;
+        id = format!("{}-{}", base, n);
+    }
+    id
+}
+
+fn write_origin(sftp: &Sftp, batch: &Path, original: &Path) -> Result<()> {
+    let path = batch.join(ORIGIN_FILE);
+    let mut file = sftp.create(&path).context(format!("Failed to create {:?}", path))?;
+    file.write_all(original.to_string_lossy().as_bytes())
+        .context(format!("Failed to write {:?}", path))
+}
+
+fn read_origin(sftp: &Sftp, batch: &Path) -> Option<PathBuf> {
+    let mut content = String::new();
+    sftp.open(batch.join(ORIGIN_FILE)).ok()?.read_to_string(&mut content).ok()?;
+    (!content.is_empty()).then(|| PathBuf::from(content))
+}
+
+fn remove_batch(sftp: &Sftp, batch: &Path) -> Result<()> {
+    let _ = sftp.unlink(&batch.join(ORIGIN_FILE));
+    sftp.rmdir(batch)
+        .context(format!("Failed to remove remote directory {:?}", batch))
+}
@@ -0,0 +1,249 @@
+use crate::output;
+use crate::prompt;
+use crate::proxy::{self, ForwardListener, Target};
+use anyhow::{anyhow, Context, Result};
+use inquire::MultiSelect;
+use serde_json::json;
+use ssh2::Session;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+// 本地端口只在回环地址上监听
+const BIND_ADDRESS: &str = "127.0.0.1";
+
+// Linux 上先用 ss，没有时用 net-tools 的 netstat，最后是 BSD/macOS 的 netstat
+const LISTEN_SCRIPT: &str = "ss -tlnp 2>/dev/null || netstat -tlnp 2>/dev/null || netstat -an -p tcp 2>/dev/null";
+
+/// 转发在本机一侧的端口或 Unix 套接字路径
+#[derive(Clone, Debug)]
+pub enum Local {
+    Port(u16),
+    Socket(PathBuf),
+}
+
+/// -L 的参数：PORT、LOCAL:PORT、LOCAL:HOST:PORT 或 LOCAL:/REMOTE.sock（也可以写成 LOCAL:remote:/REMOTE.sock）；
+/// LOCAL 是端口或含 / 的套接字路径，HOST 默认是服务器自己
+#[derive(Clone, Debug)]
+pub struct Forward {
+    pub local: Local,
+    pub target: Target,
+}
+
+/// clap 的 value_parser
+pub fn parse_forward(spec: &str) -> Result<Forward, String> {
+    let parse_port = |text: &str| text.parse::<u16>().map_err(|_| format!("invalid port '{}' in '{}'", text, spec));
+    let parts: Vec<&str> = spec.splitn(2, ':').collect();
+    let (local, rest) = match parts[..] {
+        [port] => {
+            let port = parse_port(port)?;
+            return Ok(Forward { local: Local::Port(port), target: Target::Tcp(BIND_ADDRESS.to_string(), port) });
+        }
+        [local, rest] if local.contains('/') => (parse_socket(local, spec)?, rest),
+        [local, rest] => (Local::Port(parse_port(local)?), rest),
+        _ => unreachable!(),
+    };
+    let rest = rest.strip_prefix("remote:").filter(|path| path.starts_with('/')).unwrap_or(rest);
+    if rest.starts_with('/') {
+        return Ok(Forward { local, target: Target::Socket(rest.to_string()) });
+    }
+    match rest.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => Ok(Forward {
+            local,
+            target: Target::Tcp(host.trim_start_matches('[').trim_end_matches(']').to_string(), parse_port(port)?),
+        }),
+        _ => Ok(Forward { local, target: Target::Tcp(BIND_ADDRESS.to_string(), parse_port(rest)?) }),
+    }
+}
+
+#[cfg(unix)]
+fn parse_socket(path: &str, _spec: &str) -> Result<Local, String> {
+    Ok(Local::Socket(PathBuf::from(path)))
+}
+
+#[cfg(not(unix))]
+fn parse_socket(_path: &str, spec: &str) -> Result<Local, String> {
+    Err(format!("local Unix sockets are not supported on this platform ('{}')", spec))
+}
+
+/// 服务器上一个正在监听的端口
+struct Service {
+    address: String,
+    port: u16,
+    process: Option<String>,
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5}  {:<16}  {}", self.port, self.address, self.process.as_deref().unwrap_or("?"))
+    }
+}
+
+/// tunnel：把服务器上的端口转发到本机；auto 时先列出服务器上在监听的端口供选择
+pub fn handle_tunnel(sess: Session, alias: &str, mut forwards: Vec<Forward>, auto: bool) -> Result<()> {
+    if auto {
+        forwards.extend(pick_services(&sess, alias)?);
+    }
+    if forwards.is_empty() {
+        output::status("Nothing to forward.");
+        return Ok(());
+    }
+
+    let mut listeners = Vec::new();
+    let mut mapped = Vec::new();
+    for forward in forwards {
+        let (listener, mut entry) = match &forward.local {
+            Local::Port(port) => {
+                let listener = bind(*port, auto)?;
+                let local = listener.local_addr()?.port();
+                output::status(format!("{}:{} -> {} on '{}'", BIND_ADDRESS, local, forward.target, alias));
+                (ForwardListener::Tcp(listener), json!({ "local_port": local }))
+            }
+            #[cfg(unix)]
+            Local::Socket(path) => {
+                let listener = bind_socket(path)?;
+                output::status(format!("{} -> {} on '{}'", path.display(), forward.target, alias));
+                (ForwardListener::Unix(listener), json!({ "local_socket": path }))
+            }
+            #[cfg(not(unix))]
+            Local::Socket(_) => unreachable!(),
+        };
+        match &forward.target {
+            Target::Tcp(host, port) => {
+                entry["host"] = json!(host);
+                entry["port"] = json!(port);
+            }
+            Target::Socket(path) => entry["socket"] = json!(path),
+        }
+        mapped.push(entry);
+        listeners.push((listener, forward.target));
+    }
+    output::event("tunnel_listening", json!({ "alias": alias, "forwards": mapped }));
+    output::status("Press Ctrl+C to stop.");
+    proxy::handle_forwards(sess, listeners)
+}
+
+// 只有自己能连接本地套接字：转发的可能是 docker.sock 这样等同 root 权限的套接字。
+// 上次没有清理掉的套接字文件（没有进程在监听）先删除，正在使用的不动
+#[cfg(unix)]
+pub fn bind_socket(path: &std::path::Path) -> Result<std::os::unix::net::UnixListener> {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("{} already exists and is not a socket.", path.display()));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!("{} is already in use by another program.", path.display()));
+        }
+        fs::remove_file(path).context(format!("Failed to remove the stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path).context(format!("Failed to listen on {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .context(format!("Failed to restrict the permissions of {}", path.display()))?;
+    Ok(listener)
+}
+
+// 端口被占用（或需要特权）时，自动发现的转发改用系统分配的端口；-L 指定的端口照常报错
+fn bind(port: u16, fallback: bool) -> Result<TcpListener> {
+    match TcpListener::bind((BIND_ADDRESS, port)) {
+        Ok(listener) => Ok(listener),
+        Err(_) if fallback => {
+            TcpListener::bind((BIND_ADDRESS, 0)).context(format!("Failed to listen on {}", BIND_ADDRESS))
+        }
+        Err(e) => Err(anyhow!("Failed to listen on {}:{}: {}", BIND_ADDRESS, port, e)),
+    }
+}
+
+fn pick_services(sess: &Session, alias: &str) -> Result<Vec<Forward>> {
+    if !prompt::can_ask() || output::is_json() {
+        return Err(prompt::unavailable("--auto asks which ports to forward and needs a terminal. Use -L PORT instead."));
+    }
+    let services = discover(sess).context(format!("Failed to list listening ports on '{}'", alias))?;
+    if services.is_empty() {
+        return Err(anyhow!(
+            "Found no listening TCP ports on '{}' (neither ss nor netstat gave any output).",
+            alias
+        ));
+    }
+    if services.iter().all(|service| service.process.is_none()) {
+        output::status("Process names are only shown for your own processes unless you log in as root.");
+    }
+    let picked = MultiSelect::new(&format!("Ports listening on '{}' to forward:", alias), services).prompt()?;
+    Ok(picked
+        .into_iter()
+        .map(|service| Forward {
+            local: Local::Port(service.port),
+            target: Target::Tcp(target_host(&service.address), service.port),
+        })
+        .collect())
+}
+
+// 监听所有地址的服务经服务器的回环地址访问，其余的用它监听的地址
+fn target_host(address: &str) -> String {
+    match address {
+        "0.0.0.0" | "*" => BIND_ADDRESS.to_string(),
+        "::" => "::1".to_string(),
+        _ => address.to_string(),
+    }
+}
+
+fn discover(sess: &Session) -> Result<Vec<Service>> {
+    let mut channel = sess.channel_session().context("Failed to open a channel")?;
+    channel.exec(LISTEN_SCRIPT).context("Failed to run ss/netstat")?;
+    let mut output = String::new();
+    channel.read_to_string(&mut output).context("Failed to read the list of ports")?;
+    let _ = channel.wait_close();
+
+    // 同一端口在 IPv4 和 IPv6 上各监听一次时只列一次，地址取先出现的那个
+    let mut services: BTreeMap<u16, Service> = BTreeMap::new();
+    for service in output.lines().filter_map(parse_line) {
+        match services.get_mut(&service.port) {
+            Some(existing) => {
+                if existing.process.is_none() {
+                    existing.process = service.process;
+                }
+            }
+            None => {
+                services.insert(service.port, service);
+            }
+        }
+    }
+    Ok(services.into_values().collect())
+}
+
+// ss:        LISTEN 0 511 0.0.0.0:80 0.0.0.0:* users:(("nginx",pid=812,fd=6))
+// netstat:   tcp 0 0 0.0.0.0:22 0.0.0.0:* LISTEN 640/sshd
+// BSD:       tcp4 0 0 *.5432 *.* LISTEN
+// 本地地址在这三种格式里都是第 4 列
+fn parse_line(line: &str) -> Option<Service> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if !fields.contains(&"LISTEN") {
+        return None;
+    }
+    let (address, port) = split_address(fields.get(3)?)?;
+    let process = match fields[0] {
+        "LISTEN" => line.split_once("((\"").and_then(|(_, rest)| rest.split_once('"')).map(|(name, _)| name.to_string()),
+        _ => fields
+            .last()
+            .and_then(|last| last.split_once('/'))
+            .map(|(_, name)| name.to_string()),
+    };
+    Some(Service { address, port, process })
+}
+
+// "[::1]:631"、"127.0.0.53%lo:53"、":::22"、"*.22"、"::1.631"
+fn split_address(text: &str) -> Option<(String, u16)> {
+    let (address, port) = text
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .or_else(|| text.rsplit_once('.'))?;
+    let port = port.parse().ok()?;
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    let address = address.split_once('%').map_or(address, |(address, _)| address);
+    Some((address.to_string(), port))
+}
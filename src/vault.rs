@@ -0,0 +1,622 @@
+use crate::config::{config_dir, write_atomic, Config};
+use crate::credentials::{is_no_store, keychain_delete, keychain_get, keychain_set, sudo_account, SECRET_PREFIX};
+use crate::output;
+use crate::perms;
+use crate::prompt;
+use crate::secrets;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use inquire::Password;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use ssh_key::rand_core::{OsRng, RngCore};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const VAULT_FILE: &str = "vault.json";
+const VAULT_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+// argon2id：64 MiB 内存，3 轮
+const KDF_M_COST: u32 = 64 * 1024;
+const KDF_T_COST: u32 = 3;
+const KDF_P_COST: u32 = 1;
+const UNLOCK_ATTEMPTS: usize = 3;
+
+type Key = Zeroizing<[u8; KEY_LEN]>;
+type Secrets = BTreeMap<String, String>;
+
+// 本进程已解锁的密钥，同一条命令里不再重复询问
+static KEY: Mutex<Option<Key>> = Mutex::new(None);
+
+/// 磁盘上的密码库：所有密码序列化后整体用 AES-256-GCM 加密，密钥由主密码经 argon2id 派生
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: Kdf,
+    nonce: String,
+    data: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Kdf {
+    algorithm: String,
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl Kdf {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Kdf {
+            algorithm: "argon2id".to_string(),
+            salt: STANDARD.encode(salt),
+            m_cost: KDF_M_COST,
+            t_cost: KDF_T_COST,
+            p_cost: KDF_P_COST,
+        }
+    }
+
+    fn derive(&self, password: &str) -> Result<Key> {
+        if self.algorithm != "argon2id" {
+            return Err(anyhow!("Unsupported vault key derivation '{}'", self.algorithm));
+        }
+        let salt = STANDARD.decode(&self.salt).context("Invalid vault salt")?;
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_LEN))
+            .map_err(|e| anyhow!("Invalid vault key derivation parameters: {}", e))?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| anyhow!("Failed to derive the vault key: {}", e))?;
+        Ok(key)
+    }
+}
+
+impl VaultFile {
+    fn seal(kdf: Kdf, key: &Key, secrets: &Secrets) -> Result<Self> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(secrets)?);
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new_from_slice(key.as_ref()).map_err(|_| anyhow!("Invalid vault key"))?;
+        let data = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt the vault"))?;
+        Ok(VaultFile {
+            version: VAULT_VERSION,
+            kdf,
+            nonce: STANDARD.encode(nonce),
+            data: STANDARD.encode(data),
+        })
+    }
+
+    // 密钥不对时返回 None
+    fn open(&self, key: &Key) -> Option<Secrets> {
+        let nonce = STANDARD.decode(&self.nonce).ok().filter(|n| n.len() == NONCE_LEN)?;
+        let data = STANDARD.decode(&self.data).ok()?;
+        let cipher = Aes256Gcm::new_from_slice(key.as_ref()).ok()?;
+        let plaintext = Zeroizing::new(cipher.decrypt(Nonce::from_slice(&nonce), data.as_slice()).ok()?);
+        serde_json::from_slice(&plaintext).ok()
+    }
+}
+
+pub fn vault_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join(VAULT_FILE))
+}
+
+/// 创建了密码库后，所有密码都存在密码库里，不再使用系统 keychain
+pub fn is_enabled() -> bool {
+    vault_path().is_ok_and(|path| path.exists())
+}
+
+fn read_vault() -> Result<VaultFile> {
+    let path = vault_path()?;
+    let content = fs::read_to_string(&path).context(format!("Failed to read vault {:?}", path))?;
+    let vault: VaultFile = serde_json::from_str(&content).context("Failed to parse the vault file")?;
+    if vault.version != VAULT_VERSION {
+        return Err(anyhow!("Unsupported vault version {}", vault.version));
+    }
+    Ok(vault)
+}
+
+fn write_vault(vault: &VaultFile) -> Result<()> {
+    let path = vault_path()?;
+    perms::create_private_dir(path.parent().unwrap()).context("Failed to create config directory")?;
+    let text = serde_json::to_string_pretty(vault)?;
+    write_atomic(&path, text.as_bytes()).context("Failed to write the vault file")?;
+    restrict_permissions(&path);
+    Ok(())
+}
+
+// 读-改-写期间持有的锁，和配置文件的锁分开
+fn lock_vault() -> Result<File> {
+    let dir = config_dir()?;
+    perms::create_private_dir(&dir).context("Failed to create config directory")?;
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(dir.join("vault.lock"))
+        .context("Failed to open vault lock file")?;
+    file.lock().context("Failed to lock the vault")?;
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) {}
+
+/// 解锁密码库：依次尝试本进程缓存、后台进程缓存的密钥，最后询问主密码
+fn unlock(vault: &VaultFile) -> Result<(Key, Secrets)> {
+    let cached = KEY.lock().unwrap().clone();
+    if let Some(key) = cached
+        && let Some(secrets) = vault.open(&key)
+    {
+        return Ok((key, secrets));
+    }
+    if let Some(key) = agent::fetch_key()
+        && let Some(secrets) = vault.open(&key)
+    {
+        *KEY.lock().unwrap() = Some(key.clone());
+        return Ok((key, secrets));
+    }
+    let (key, secrets) = prompt_unlock(vault)?;
+    *KEY.lock().unwrap() = Some(key.clone());
+    // 本次登录会话内的后续命令不再询问；--no-store 时只在本进程内有效
+    if !is_no_store()
+        && let Err(e) = agent::start(&key, None)
+        && output::is_verbose()
+    {
+        eprintln!("Warning: could not cache the vault key: {:#}", e);
+    }
+    Ok((key, secrets))
+}
+
+fn prompt_unlock(vault: &VaultFile) -> Result<(Key, Secrets)> {
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("The password vault is locked. Run 'rssh vault unlock' first."));
+    }
+    for _ in 0..UNLOCK_ATTEMPTS {
+        let password = Zeroizing::new(
+            Password::new("Vault master password:")
+                .with_display_mode(inquire::PasswordDisplayMode::Masked)
+                .without_confirmation()
+                .prompt()?,
+        );
+        let key = vault.kdf.derive(&password)?;
+        if let Some(secrets) = vault.open(&key) {
+            return Ok((key, secrets));
+        }
+        eprintln!("Wrong master password.");
+    }
+    Err(anyhow!("Failed to unlock the password vault."))
+}
+
+fn prompt_new_password() -> Result<Zeroizing<String>> {
+    if !prompt::can_ask() {
+        return Err(prompt::unavailable("Setting the master password needs a terminal."));
+    }
+    let password = Zeroizing::new(
+        Password::new("New master password:")
+            .with_display_mode(inquire::PasswordDisplayMode::Masked)
+            .with_custom_confirmation_message("Repeat the master password:")
+            .with_custom_confirmation_error_message("The passwords don't match.")
+            .prompt()?,
+    );
+    if password.is_empty() {
+        return Err(anyhow!("The master password can't be empty."));
+    }
+    Ok(password)
+}
+
+pub fn get(account: &str) -> Result<Option<String>> {
+    let vault = read_vault()?;
+    let (_, mut secrets) = unlock(&vault)?;
+    Ok(secrets.remove(account))
+}
+
+/// 密码库中所有条目的名称
+pub fn accounts() -> Result<Vec<String>> {
+    let vault = read_vault()?;
+    let (_, secrets) = unlock(&vault)?;
+    Ok(secrets.into_keys().collect())
+}
+
+pub fn set(account: &str, secret: &str) -> Result<()> {
+    let _lock = lock_vault()?;
+    let vault = read_vault()?;
+    let (key, mut secrets) = unlock(&vault)?;
+    secrets.insert(account.to_string(), secret.to_string());
+    write_vault(&VaultFile::seal(vault.kdf, &key, &secrets)?)
+}
+
+pub fn delete(account: &str) -> Result<()> {
+    let _lock = lock_vault()?;
+    let vault = read_vault()?;
+    let (key, mut secrets) = unlock(&vault)?;
+    if secrets.remove(account).is_some() {
+        write_vault(&VaultFile::seal(vault.kdf, &key, &secrets)?)?;
+    }
+    Ok(())
+}
+
+// 要移进密码库的 keychain 条目：各别名的登录密码和 sudo 密码，以及索引里记下的 --secret-env 值
+fn keychain_accounts(config: &Config, index: &BTreeSet<String>) -> BTreeSet<String> {
+    let mut accounts: BTreeSet<String> = index
+        .iter()
+        .filter(|account| account.starts_with(SECRET_PREFIX))
+        .cloned()
+        .collect();
+    for alias in config.connections.keys() {
+        accounts.insert(alias.clone());
+        accounts.insert(sudo_account(alias));
+    }
+    accounts
+}
+
+/// 创建密码库，并把 keychain 里已保存的密码移进来
+pub fn handle_init(config: &Config) -> Result<()> {
+    let _lock = lock_vault()?;
+    if is_enabled() {
+        return Err(anyhow!("A vault already exists at {:?}.", vault_path()?));
+    }
+    let password = prompt_new_password()?;
+    let kdf = Kdf::generate();
+    let key = kdf.derive(&password)?;
+
+    let mut secrets = Secrets::new();
+    for account in keychain_accounts(config, &secrets::read_index()) {
+        if let Ok(secret) = keychain_get(&account) {
+            secrets.insert(account, secret);
+        }
+    }
+    write_vault(&VaultFile::seal(kdf, &key, &secrets)?)?;
+    for account in secrets.keys() {
+        if let Err(e) = keychain_delete(account) {
+            eprintln!("Warning: could not remove '{}' from the keychain: {:#}", account, e);
+        }
+    }
+    *KEY.lock().unwrap() = Some(key.clone());
+    let cached = agent::start(&key, None).is_ok();
+
+    output::status(format!("Created the password vault at {:?}.", vault_path()?));
+    if !secrets.is_empty() {
+        output::status(format!("Moved {} password(s) from the keychain into the vault.", secrets.len()));
+    }
+    output::event("vault_created", json!({ "imported": secrets.len(), "unlocked": cached }));
+    Ok(())
+}
+
+pub fn handle_unlock(timeout: Option<u64>) -> Result<()> {
+    let vault = read_existing()?;
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("Unlocking the vault needs a terminal to ask for the master password."));
+    }
+    let timeout = timeout.filter(|minutes| *minutes > 0).map(|minutes| Duration::from_secs(minutes * 60));
+    // 已经解锁时不再询问；指定了新的超时则重启后台进程
+    let key = match agent::fetch_key().filter(|key| vault.open(key).is_some()) {
+        Some(_) if timeout.is_none() => {
+            output::status("The vault is already unlocked.");
+            return Ok(());
+        }
+        Some(key) => key,
+        None => prompt_unlock(&vault)?.0,
+    };
+    agent::start(&key, timeout)?;
+    match timeout {
+        Some(timeout) => output::status(format!(
+            "Vault unlocked; it locks again after {} minute(s) without use.",
+            timeout.as_secs() / 60
+        )),
+        None => output::status("Vault unlocked until 'rssh vault lock' or the end of this login session."),
+    }
+    output::event("vault_unlocked", json!({ "timeout_minutes": timeout.map(|t| t.as_secs() / 60) }));
+    Ok(())
+}
+
+pub fn handle_lock() -> Result<()> {
+    if agent::stop() {
+        output::status("Vault locked.");
+    } else {
+        output::status("The vault was not unlocked.");
+    }
+    output::event("vault_locked", json!({}));
+    Ok(())
+}
+
+pub fn handle_status() -> Result<()> {
+    let path = vault_path()?;
+    let enabled = path.exists();
+    let entries = if enabled {
+        let vault = read_vault()?;
+        agent::fetch_key().and_then(|key| vault.open(&key)).map(|secrets| secrets.len())
+    } else {
+        None
+    };
+    if output::is_json() {
+        output::emit("vault_status", json!({
+            "enabled": enabled,
+            "path": path,
+            "unlocked": entries.is_some(),
+            "entries": entries,
+        }));
+        return Ok(());
+    }
+    if !enabled {
+        println!("No vault; passwords are stored in the system keychain. Run 'rssh vault init' to create one.");
+        return Ok(());
+    }
+    println!("Vault: {:?}", path);
+    match entries {
+        Some(entries) => println!("Status: unlocked, {} password(s)", entries),
+        None => println!("Status: locked"),
+    }
+    Ok(())
+}
+
+/// 修改主密码：重新派生密钥并加密，已缓存的旧密钥随之替换
+pub fn handle_passwd() -> Result<()> {
+    let _lock = lock_vault()?;
+    let vault = read_existing()?;
+    let (_, secrets) = unlock(&vault)?;
+    let password = prompt_new_password()?;
+    let kdf = Kdf::generate();
+    let key = kdf.derive(&password)?;
+    write_vault(&VaultFile::seal(kdf, &key, &secrets)?)?;
+    *KEY.lock().unwrap() = Some(key.clone());
+    if agent::stop() {
+        agent::start(&key, None)?;
+    }
+    output::status("Master password changed.");
+    output::event("vault_password_changed", json!({}));
+    Ok(())
+}
+
+/// 把密码移回系统 keychain，并删除密码库
+pub fn handle_disable() -> Result<()> {
+    let _lock = lock_vault()?;
+    let vault = read_existing()?;
+    let (_, secrets) = unlock(&vault)?;
+    for (account, secret) in &secrets {
+        keychain_set(account, secret).context("Failed to move the passwords back to the keychain; the vault was kept")?;
+    }
+    fs::remove_file(vault_path()?).context("Failed to remove the vault file")?;
+    agent::stop();
+    output::status(format!(
+        "Moved {} password(s) to the system keychain and removed the vault.",
+        secrets.len()
+    ));
+    output::event("vault_removed", json!({ "exported": secrets.len() }));
+    Ok(())
+}
+
+fn read_existing() -> Result<VaultFile> {
+    if !is_enabled() {
+        return Err(anyhow!("There is no vault. Run 'rssh vault init' to create one."));
+    }
+    read_vault()
+}
+
+/// 后台进程由 rssh 自己以这个环境变量启动
+pub fn is_agent() -> bool {
+    std::env::var_os(agent::AGENT_ENV).is_some()
+}
+
+pub fn run_agent() {
+    agent::run();
+}
+
+// 解锁后在后台保存派生出的密钥，通过只有当前用户能访问的 Unix socket 提供给后续的 rssh 命令
+#[cfg(unix)]
+mod agent {
+    use super::{Key, KEY_LEN};
+    use crate::config::state_dir;
+    use anyhow::{Context, Result};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::os::unix::process::CommandExt;
+    use std::path::PathBuf;
+    use std::process::{self, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use zeroize::Zeroizing;
+
+    pub const AGENT_ENV: &str = "RSSH_VAULT_AGENT";
+    const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+    // 优先放在 $XDG_RUNTIME_DIR，登出时会被清掉
+    fn socket_path() -> Result<PathBuf> {
+        let dir = match dirs::runtime_dir() {
+            Some(dir) => dir.join("rssh"),
+            None => state_dir()?,
+        };
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .context(format!("Failed to create {:?}", dir))?;
+        Ok(dir.join("vault.sock"))
+    }
+
+    fn request(command: &str) -> Option<String> {
+        let mut stream = UnixStream::connect(socket_path().ok()?).ok()?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).ok()?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).ok()?;
+        writeln!(stream, "{}", command).ok()?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).ok()?;
+        Some(reply.trim().to_string())
+    }
+
+    pub fn fetch_key() -> Option<Key> {
+        let reply = Zeroizing::new(request("get")?);
+        let bytes = Zeroizing::new(STANDARD.decode(reply.as_bytes()).ok()?);
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        if bytes.len() != KEY_LEN {
+            return None;
+        }
+        key.copy_from_slice(&bytes);
+        Some(key)
+    }
+
+    /// 通知后台进程退出；返回是否有进程在运行
+    pub fn stop() -> bool {
+        request("lock").is_some()
+    }
+
+    pub fn start(key: &Key, timeout: Option<Duration>) -> Result<()> {
+        stop();
+        let exe = std::env::current_exe().context("Failed to locate the rssh executable")?;
+        // 单独的进程组，终端里的 Ctrl+C 不会结束它
+        let mut child = Command::new(exe)
+            .env(AGENT_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .context("Failed to start the vault agent")?;
+        // 密钥通过管道传过去，不出现在命令行参数里
+        let mut stdin = child.stdin.take().context("Failed to start the vault agent")?;
+        let message = Zeroizing::new(format!(
+            "{}\n{}\n",
+            STANDARD.encode(key.as_ref()),
+            timeout.map_or(0, |t| t.as_secs())
+        ));
+        stdin.write_all(message.as_bytes()).context("Failed to start the vault agent")?;
+        drop(stdin);
+
+        // 等 socket 就绪，后续命令才能马上用上
+        let started = Instant::now();
+        while started.elapsed() < IO_TIMEOUT {
+            if UnixStream::connect(socket_path()?).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Err(anyhow::anyhow!("The vault agent did not start"))
+    }
+
+    pub fn run() {
+        let mut input = Zeroizing::new(String::new());
+        if std::io::stdin().read_to_string(&mut input).is_err() {
+            return;
+        }
+        let mut lines = input.lines();
+        let key = Zeroizing::new(lines.next().unwrap_or_default().to_string());
+        let timeout = lines
+            .next()
+            .and_then(|t| t.parse::<u64>().ok())
+            .filter(|t| *t > 0)
+            .map(Duration::from_secs);
+        let Ok(path) = socket_path() else {
+            return;
+        };
+        let _ = fs::remove_file(&path);
+        let Ok(listener) = UnixListener::bind(&path) else {
+            return;
+        };
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+
+        let last_used = Arc::new(Mutex::new(Instant::now()));
+        if let Some(timeout) = timeout {
+            let last_used = Arc::clone(&last_used);
+            let path = path.clone();
+            thread::spawn(move || loop {
+                thread::sleep(Duration::from_secs(5));
+                if last_used.lock().unwrap().elapsed() >= timeout {
+                    let _ = fs::remove_file(&path);
+                    process::exit(0);
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+            let mut command = String::new();
+            if BufReader::new(&stream).read_line(&mut command).is_err() {
+                continue;
+            }
+            match command.trim() {
+                "get" => {
+                    *last_used.lock().unwrap() = Instant::now();
+                    let _ = writeln!(stream, "{}", key.as_str());
+                }
+                "lock" => {
+                    // 先删除 socket 再回复，随后启动的新进程不会被误删
+                    let _ = fs::remove_file(&path);
+                    let _ = writeln!(stream, "ok");
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// 没有 Unix socket 的平台上不缓存，每条命令都询问主密码
+#[cfg(not(unix))]
+mod agent {
+    use super::Key;
+    use anyhow::{anyhow, Result};
+    use std::time::Duration;
+
+    pub const AGENT_ENV: &str = "RSSH_VAULT_AGENT";
+
+    pub fn fetch_key() -> Option<Key> {
+        None
+    }
+
+    pub fn stop() -> bool {
+        false
+    }
+
+    pub fn start(_key: &Key, _timeout: Option<Duration>) -> Result<()> {
+        Err(anyhow!("Keeping the vault unlocked is not supported on this platform yet"))
+    }
+
+    pub fn run() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Connection;
+
+    #[test]
+    fn init_moves_secret_env_values_along_with_passwords() {
+        let mut config = Config::default();
+        config.connections.insert("web".to_string(), Connection::new("deploy@web".to_string()));
+        let index = BTreeSet::from(["web".to_string(), "secret:DB_PASSWORD".to_string(), "secret:API_TOKEN".to_string()]);
+        let accounts: Vec<String> = keychain_accounts(&config, &index).into_iter().collect();
+        assert_eq!(accounts, ["secret:API_TOKEN", "secret:DB_PASSWORD", "web", "web#sudo"]);
+        // 没有索引时仍然检查每个别名
+        let accounts: Vec<String> = keychain_accounts(&config, &BTreeSet::new()).into_iter().collect();
+        assert_eq!(accounts, ["web", "web#sudo"]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::{Config, Connection};
+use crate::error;
+use crate::output;
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_BROADCAST: &str = "255.255.255.255";
+const WOL_PORT: u16 = 9;
+// 等待主机启动时每次探测的间隔和单次连接超时
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_WAIT_SECS: u64 = 120;
+
+/// 解析 "aa:bb:cc:dd:ee:ff" 或 "aa-bb-cc-dd-ee-ff" 格式的 MAC 地址
+pub fn parse_mac(mac: &str) -> Result<[u8; 6]> {
+    let parts: Vec<&str> = mac.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(anyhow!("Invalid MAC address '{}'. Use aa:bb:cc:dd:ee:ff.", mac));
+    }
+    let mut bytes = [0u8; 6];
+    for (byte, part) in bytes.iter_mut().zip(parts) {
+        *byte = u8::from_str_radix(part, 16)
+            .map_err(|_| anyhow!("Invalid MAC address '{}'. Use aa:bb:cc:dd:ee:ff.", mac))?;
+    }
+    Ok(bytes)
+}
+
+/// 发送 magic packet：6 个 0xFF 后接 16 次 MAC 地址
+pub fn send_magic_packet(mac: &str, broadcast: Option<&str>) -> Result<()> {
+    let mac_bytes = parse_mac(mac)?;
+    let mut packet = vec![0xFFu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac_bytes);
+    }
+
+    let broadcast = broadcast.unwrap_or(DEFAULT_BROADCAST);
+    let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to open UDP socket")?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&packet, (broadcast, WOL_PORT))
+        .context(format!("Failed to send magic packet to {}", broadcast))?;
+    Ok(())
+}
+
+pub fn handle_wake(config: &Config, alias: &str, port: u16, wait: Option<u64>) -> Result<()> {
+    let conn = lookup(config, alias)?;
+    let mac = conn
+        .wol_mac
+        .as_deref()
+        .context(format!("No Wake-on-LAN MAC address configured for '{}'. Use 'rssh add {} <user@host> --wol-mac <mac>'.", alias, alias))?;
+
+    send_magic_packet(mac, conn.wol_broadcast.as_deref())?;
+    output::status(format!("Magic packet sent to {}.", mac));
+    output::event("wol_sent", json!({ "alias": alias, "mac": mac }));
+
+    if let Some(secs) = wait {
+        wait_for_port(host_of(conn), port, Duration::from_secs(secs))?;
+    }
+    Ok(())
+}
+
+/// 连接前调用：配置了 wol_mac 且 SSH 端口不可达时先唤醒主机并等待
+pub fn wake_if_needed(config: &Config, alias: &str, port: u16) -> Result<()> {
+    let Some(conn) = config.connections.get(alias) else {
+        return Ok(());
+    };
+    let Some(mac) = conn.wol_mac.as_deref() else {
+        return Ok(());
+    };
+    let host = host_of(conn);
+    if port_open(host, port) {
+        return Ok(());
+    }
+
+    output::status(format!("{} is not reachable, sending Wake-on-LAN packet...", host));
+    send_magic_packet(mac, conn.wol_broadcast.as_deref())?;
+    output::event("wol_sent", json!({ "alias": alias, "mac": mac }));
+    wait_for_port(host, port, Duration::from_secs(DEFAULT_WAIT_SECS))
+}
+
+fn wait_for_port(host: &str, port: u16, timeout: Duration) -> Result<()> {
+    output::status(format!("Waiting for {}:{} to come up...", host, port));
+    let started = Instant::now();
+    loop {
+        if port_open(host, port) {
+            output::event(
+                "host_up",
+                json!({ "host": host, "port": port, "waited_ms": started.elapsed().as_millis() as u64 }),
+            );
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            return Err(anyhow!(
+                "{}:{} did not come up within {}s after Wake-on-LAN",
+                host,
+                port,
+                timeout.as_secs()
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn port_open(host: &str, port: u16) -> bool {
+    let Ok(addrs) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+}
+
+fn lookup<'a>(config: &'a Config, alias: &str) -> Result<&'a Connection> {
+    config
+        .connections
+        .get(alias)
+        .ok_or_else(|| error::alias_not_found(alias))
+}
+
+fn host_of(conn: &Connection) -> &str {
+    conn.target
+        .split_once('@')
+        .map_or(conn.target.as_str(), |(_, host)| host)
+}